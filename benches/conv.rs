@@ -0,0 +1,115 @@
+// Benchmarks of the conv kernels and of the public ops using them.
+//
+// Run with `cargo bench --bench conv`. With `--features c-kernels`, each Rust kernel of
+// `CpuBackend` is followed by the original C one on the same inputs.
+extern crate autograd as ag;
+
+#[cfg(feature = "c-kernels")]
+use ag::backend::c_kernels;
+use ag::backend::{Backend, CpuBackend, Im2ColParams, MaxPoolParams};
+use std::time::Instant;
+
+fn bench<F: FnMut()>(name: &str, iters: u32, mut f: F) {
+    // warm up
+    f();
+    let start = Instant::now();
+    for _ in 0..iters {
+        f();
+    }
+    let elapsed = start.elapsed();
+    let ms = elapsed.as_secs() as f64 * 1e3 + elapsed.subsec_nanos() as f64 * 1e-6;
+    println!("{:<40} {:>10.3} ms/iter", name, ms / iters as f64);
+}
+
+// Times the kernels under `conv2d` and `max_pool2d` for one input shape.
+fn bench_kernels(batch: usize, ch: usize, h: usize, w: usize) {
+    let shape = format!("{:?}", [batch, ch, h, w]);
+    let conv = Im2ColParams {
+        channels: ch,
+        height: h,
+        width: w,
+        kernel: (3, 3),
+        pad: (1, 1, 1, 1),
+        stride: (1, 1),
+        dilation: (1, 1),
+    };
+    let (yh, yw) = conv.out_size();
+    let im = ag::ndarray_ext::standard_normal(&[ch * h * w]).into_raw_vec();
+    let col = ag::ndarray_ext::standard_normal(&[ch * 9 * yh * yw]).into_raw_vec();
+    let mut col_buf = vec![0.; col.len()];
+    let mut im_buf = vec![0.; im.len()];
+    bench(&format!("im2col {} (rust)", shape), 100, || {
+        CpuBackend.im2col(&conv, &im, &mut col_buf)
+    });
+    #[cfg(feature = "c-kernels")]
+    bench(&format!("im2col {} (c)", shape), 100, || {
+        c_kernels::im2col(&conv, &im, &mut col_buf)
+    });
+    bench(&format!("col2im {} (rust)", shape), 100, || {
+        CpuBackend.col2im(&conv, &col, &mut im_buf)
+    });
+    #[cfg(feature = "c-kernels")]
+    bench(&format!("col2im {} (c)", shape), 100, || {
+        c_kernels::col2im(&conv, &col, &mut im_buf)
+    });
+
+    let pool = MaxPoolParams {
+        batch,
+        channels: ch,
+        height: h,
+        width: w,
+        channels_last: false,
+        size: (2, 2),
+        stride: (2, 2),
+        pad: (0, 0),
+        out_size: (h / 2, w / 2),
+    };
+    let ylen = batch * ch * (h / 2) * (w / 2);
+    let x = ag::ndarray_ext::standard_normal(&[batch * ch * h * w]).into_raw_vec();
+    let gy = ag::ndarray_ext::standard_normal(&[ylen]).into_raw_vec();
+    let (mut y, mut argmax) = (vec![0.; ylen], vec![0.; ylen]);
+    let mut gx = vec![0.; x.len()];
+    bench(&format!("max_pool {} (rust)", shape), 50, || {
+        CpuBackend.max_pool(&pool, &x, &mut y, &mut argmax)
+    });
+    #[cfg(feature = "c-kernels")]
+    bench(&format!("max_pool {} (c)", shape), 50, || {
+        c_kernels::max_pool(&pool, &x, &mut y, &mut argmax)
+    });
+    bench(&format!("max_pool_grad {} (rust)", shape), 50, || {
+        CpuBackend.max_pool_grad(&gy, &argmax, &mut gx)
+    });
+    #[cfg(feature = "c-kernels")]
+    bench(&format!("max_pool_grad {} (c)", shape), 50, || {
+        c_kernels::max_pool_grad(&gy, &argmax, &mut gx)
+    });
+}
+
+fn main() {
+    // (batch, channels, height, width)
+    for &(batch, ch, h, w) in &[(32, 16, 28, 28), (8, 64, 32, 32)] {
+        bench_kernels(batch, ch, h, w);
+
+        let ref x = ag::constant(ag::ndarray_ext::standard_normal(&[batch, ch, h, w]));
+        let ref filter = ag::variable(ag::ndarray_ext::standard_normal(&[ch, ch, 3, 3]));
+        let ref conv = ag::conv2d(x, filter, 1, 1);
+        let ref pool = ag::max_pool2d(conv, 2, 0, 2);
+        let ref loss = ag::reduce_sum(pool, &[0, 1, 2, 3], false);
+        let ref grads = ag::grad(&[loss], &[x, filter]);
+
+        let shape = format!("{:?}", [batch, ch, h, w]);
+        bench(&format!("conv2d {}", shape), 20, || {
+            ag::eval(&[conv], &[]);
+        });
+        bench(&format!("conv2d + max_pool2d {}", shape), 20, || {
+            ag::eval(&[pool], &[]);
+        });
+        bench(
+            &format!("conv2d + max_pool2d (fwd+bwd) {}", shape),
+            10,
+            || {
+                ag::eval(grads, &[]);
+            },
+        );
+    }
+}
@@ -1,6 +1,9 @@
+#[cfg(feature = "c-kernels")]
 extern crate cc;
 
 fn main() {
+    // The C kernels are only used in parity tests and benchmarks against the Rust ones.
+    #[cfg(feature = "c-kernels")]
     cc::Build::new()
         .flag("-std=c99")
         .file("src/c/conv.c")
@@ -48,8 +48,6 @@ fn main() {
     let ref b1 = ag::variable(ag::ndarray_ext::zeros(&[1, 32, 28, 28]));
     let ref b2 = ag::variable(ag::ndarray_ext::zeros(&[1, 64, 14, 14]));
     let ref b3 = ag::variable(ag::ndarray_ext::zeros(&[1, 10]));
-    let params = &[w1, w2, w3, b1, b2, b3];
-    let ref params_adam = ag::gradient_descent_ops::Adam::vars_with_states(params);
     let (x, y) = inputs();
     let z1 = conv_pool(&x, w1, b1); // map to 32 channel
     let z2 = conv_pool(&z1, w2, b2); // map to 64 channel
@@ -57,6 +55,8 @@ fn main() {
     let logits = logits(&z3, w3, b3); // linear
     let loss = ag::sparse_softmax_cross_entropy(&logits, &y);
     let mean_loss = ag::reduce_mean(loss, &[0, 1], false);
+    let params = &ag::trainable_variables(&[&mean_loss]);
+    let ref params_adam = ag::gradient_descent_ops::Adam::vars_with_states(params);
     let grads = &ag::grad(&[&mean_loss], params);
     let adam = ag::gradient_descent_ops::Adam::default();
     let update_ops: &[ag::Tensor] = &adam.compute_updates(params_adam, grads);
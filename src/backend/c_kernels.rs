@@ -0,0 +1,342 @@
+//! The original C kernels in `src/c/conv.c` (`c-kernels` feature).
+//!
+//! Only used in the parity tests and benchmarks against the Rust kernels of `CpuBackend`.
+//! The wrappers take the parameters of the corresponding `Backend` kernels and panic on
+//! geometries the C kernels don't support.
+extern crate libc;
+
+use self::libc::{c_float, c_int};
+#[cfg(test)]
+use super::{Backend, CpuBackend};
+use super::{Im2ColParams, MaxPoolParams};
+use std::f32;
+
+#[link(name = "conv")]
+extern "C" {
+    fn im2col_cpu(
+        data_im: *const c_float,
+        channels: c_int,
+        height: c_int,
+        width: c_int,
+        kernel_h: c_int,
+        kernel_w: c_int,
+        pad_h: c_int,
+        pad_w: c_int,
+        stride_h: c_int,
+        stride_w: c_int,
+        dilation_h: c_int,
+        dilation_w: c_int,
+        data_col: *mut c_float,
+    );
+
+    fn col2im_cpu(
+        data_col: *const c_float,
+        channels: c_int,
+        height: c_int,
+        width: c_int,
+        kernel_h: c_int,
+        kernel_w: c_int,
+        pad_h: c_int,
+        pad_w: c_int,
+        stride_h: c_int,
+        stride_w: c_int,
+        dilation_h: c_int,
+        dilation_w: c_int,
+        data_im: *mut c_float,
+    );
+
+    fn max_pool_cpu(
+        input: *const c_float,
+        pad: c_int,
+        h: c_int,
+        w: c_int,
+        out_h: c_int,
+        out_w: c_int,
+        c: c_int,
+        batch: c_int,
+        size: c_int,
+        stride: c_int,
+        output: *mut c_float,
+        argmax: *mut c_float,
+        float_min: c_float,
+    );
+
+    fn max_pool_grad_cpu(
+        input: *const c_float,
+        h: c_int,
+        w: c_int,
+        c: c_int,
+        batch: c_int,
+        gx: *mut c_float,
+        argmax: *const c_float,
+    );
+
+    fn max_pool_grad_grad_cpu(
+        ggx: *const c_float,
+        h: c_int,
+        w: c_int,
+        c: c_int,
+        batch: c_int,
+        ggy: *mut c_float,
+        argmax: *const c_float,
+    );
+}
+
+// `(pad_h, pad_w)` of `params`, which must be the same at both ends of each axis.
+fn symmetric_pads(params: &Im2ColParams) -> (usize, usize) {
+    let (top, bottom, left, right) = params.pad;
+    assert!(
+        top == bottom && left == right,
+        "The C kernels only support symmetric pads (got {:?})",
+        params.pad
+    );
+    (top, left)
+}
+
+/// `Backend::im2col` of the C kernels.
+pub fn im2col(params: &Im2ColParams, im: &[f32], col: &mut [f32]) {
+    let (pad_h, pad_w) = symmetric_pads(params);
+    let Im2ColParams {
+        channels,
+        height,
+        width,
+        kernel,
+        stride,
+        dilation,
+        ..
+    } = *params;
+    let (out_h, out_w) = params.out_size();
+    assert!(im.len() >= channels * height * width);
+    assert!(col.len() >= channels * kernel.0 * kernel.1 * out_h * out_w);
+    unsafe {
+        im2col_cpu(
+            im.as_ptr(),
+            channels as c_int,
+            height as c_int,
+            width as c_int,
+            kernel.0 as c_int,
+            kernel.1 as c_int,
+            pad_h as c_int,
+            pad_w as c_int,
+            stride.0 as c_int,
+            stride.1 as c_int,
+            dilation.0 as c_int,
+            dilation.1 as c_int,
+            col.as_mut_ptr(),
+        )
+    }
+}
+
+/// `Backend::col2im` of the C kernels.
+pub fn col2im(params: &Im2ColParams, col: &[f32], im: &mut [f32]) {
+    let (pad_h, pad_w) = symmetric_pads(params);
+    let Im2ColParams {
+        channels,
+        height,
+        width,
+        kernel,
+        stride,
+        dilation,
+        ..
+    } = *params;
+    let (out_h, out_w) = params.out_size();
+    assert!(col.len() >= channels * kernel.0 * kernel.1 * out_h * out_w);
+    assert!(im.len() >= channels * height * width);
+    unsafe {
+        col2im_cpu(
+            col.as_ptr(),
+            channels as c_int,
+            height as c_int,
+            width as c_int,
+            kernel.0 as c_int,
+            kernel.1 as c_int,
+            pad_h as c_int,
+            pad_w as c_int,
+            stride.0 as c_int,
+            stride.1 as c_int,
+            dilation.0 as c_int,
+            dilation.1 as c_int,
+            im.as_mut_ptr(),
+        )
+    }
+}
+
+/// `Backend::max_pool` of the C kernels.
+///
+/// Only square windows, strides and pads in `NCHW` are supported.
+pub fn max_pool(params: &MaxPoolParams, x: &[f32], y: &mut [f32], argmax: &mut [f32]) {
+    let MaxPoolParams {
+        batch,
+        channels,
+        height,
+        width,
+        channels_last,
+        size,
+        stride,
+        pad,
+        out_size,
+    } = *params;
+    assert!(
+        !channels_last && size.0 == size.1 && stride.0 == stride.1 && pad.0 == pad.1,
+        "The C kernels only support square NCHW pooling (got {:?})",
+        params
+    );
+    let len = batch * channels * out_size.0 * out_size.1;
+    assert!(x.len() >= batch * channels * height * width);
+    assert!(y.len() >= len && argmax.len() >= len);
+    unsafe {
+        max_pool_cpu(
+            x.as_ptr(),
+            pad.0 as c_int,
+            height as c_int,
+            width as c_int,
+            out_size.0 as c_int,
+            out_size.1 as c_int,
+            channels as c_int,
+            batch as c_int,
+            size.0 as c_int,
+            stride.0 as c_int,
+            y.as_mut_ptr(),
+            argmax.as_mut_ptr(),
+            f32::MIN,
+        )
+    }
+}
+
+// Panics unless `argmax` has `len` indices into a buffer of length `size`.
+fn check_argmax(argmax: &[f32], len: usize, size: usize) {
+    assert!(argmax.len() >= len);
+    assert!(
+        argmax[..len]
+            .iter()
+            .all(|&i| 0. <= i && (i as usize) < size),
+        "argmax out of range"
+    );
+}
+
+/// `Backend::max_pool_grad` of the C kernels.
+pub fn max_pool_grad(gy: &[f32], argmax: &[f32], gx: &mut [f32]) {
+    check_argmax(argmax, gy.len(), gx.len());
+    unsafe {
+        max_pool_grad_cpu(
+            gy.as_ptr(),
+            gy.len() as c_int,
+            1,
+            1,
+            1,
+            gx.as_mut_ptr(),
+            argmax.as_ptr(),
+        )
+    }
+}
+
+/// `Backend::max_pool_grad_grad` of the C kernels.
+pub fn max_pool_grad_grad(ggx: &[f32], argmax: &[f32], ggy: &mut [f32]) {
+    check_argmax(argmax, ggy.len(), ggx.len());
+    unsafe {
+        max_pool_grad_grad_cpu(
+            ggx.as_ptr(),
+            ggy.len() as c_int,
+            1,
+            1,
+            1,
+            ggy.as_mut_ptr(),
+            argmax.as_ptr(),
+        )
+    }
+}
+
+// (channels, height, width, kernel_h, kernel_w, pad, stride, dilation)
+#[cfg(test)]
+const CONV_CONFIGS: &[(usize, usize, usize, usize, usize, usize, usize, usize)] = &[
+    (1, 3, 3, 2, 2, 0, 1, 1),
+    (3, 7, 5, 3, 3, 1, 1, 1),
+    (2, 8, 8, 3, 2, 2, 2, 1),
+    (4, 9, 7, 3, 3, 1, 3, 2),
+    (2, 5, 6, 1, 1, 0, 2, 1),
+    (3, 6, 6, 2, 3, 3, 1, 2),
+];
+
+#[cfg(test)]
+fn random_vec(len: usize) -> Vec<f32> {
+    ::ndarray_ext::standard_normal(&[len]).into_raw_vec()
+}
+
+#[test]
+fn test_im2col_col2im_parity() {
+    for &(channels, height, width, kh, kw, pad, stride, dilation) in CONV_CONFIGS {
+        let params = Im2ColParams {
+            channels,
+            height,
+            width,
+            kernel: (kh, kw),
+            pad: (pad, pad, pad, pad),
+            stride: (stride, stride),
+            dilation: (dilation, dilation),
+        };
+        let (yh, yw) = params.out_size();
+        let im = random_vec(channels * height * width);
+        let col = random_vec(channels * kh * kw * yh * yw);
+
+        let mut rust_col = vec![f32::NAN; col.len()];
+        let mut c_col = vec![f32::NAN; col.len()];
+        CpuBackend.im2col(&params, &im, &mut rust_col);
+        im2col(&params, &im, &mut c_col);
+        assert_eq!(rust_col, c_col);
+
+        let mut rust_im = vec![0.; im.len()];
+        let mut c_im = vec![0.; im.len()];
+        CpuBackend.col2im(&params, &col, &mut rust_im);
+        col2im(&params, &col, &mut c_im);
+        assert_eq!(rust_im, c_im);
+    }
+}
+
+#[test]
+fn test_max_pool_parity() {
+    // (batch, channels, height, width, size, pad, stride)
+    for &(batch, channels, height, width, size, pad, stride) in &[
+        (1, 1, 3, 3, 2, 0, 1),
+        (2, 3, 8, 8, 2, 0, 2),
+        (2, 2, 7, 9, 3, 1, 2),
+        (3, 2, 6, 5, 3, 2, 1),
+    ] {
+        let params = MaxPoolParams {
+            batch,
+            channels,
+            height,
+            width,
+            channels_last: false,
+            size: (size, size),
+            stride: (stride, stride),
+            pad: (pad, pad),
+            out_size: (
+                (height + 2 * pad - size) / stride + 1,
+                (width + 2 * pad - size) / stride + 1,
+            ),
+        };
+        let x = random_vec(batch * channels * height * width);
+        let ylen = batch * channels * params.out_size.0 * params.out_size.1;
+
+        let (mut rust_y, mut rust_argmax) = (vec![0.; ylen], vec![0.; ylen]);
+        let (mut c_y, mut c_argmax) = (vec![0.; ylen], vec![0.; ylen]);
+        CpuBackend.max_pool(&params, &x, &mut rust_y, &mut rust_argmax);
+        max_pool(&params, &x, &mut c_y, &mut c_argmax);
+        assert_eq!(rust_y, c_y);
+        assert_eq!(rust_argmax, c_argmax);
+
+        let gy = random_vec(ylen);
+        let mut rust_gx = vec![0.; x.len()];
+        let mut c_gx = vec![0.; x.len()];
+        CpuBackend.max_pool_grad(&gy, &rust_argmax, &mut rust_gx);
+        max_pool_grad(&gy, &c_argmax, &mut c_gx);
+        assert_eq!(rust_gx, c_gx);
+
+        let ggx = random_vec(x.len());
+        let mut rust_ggy = vec![0.; ylen];
+        let mut c_ggy = vec![0.; ylen];
+        CpuBackend.max_pool_grad_grad(&ggx, &rust_argmax, &mut rust_ggy);
+        max_pool_grad_grad(&ggx, &c_argmax, &mut c_ggy);
+        assert_eq!(rust_ggy, c_ggy);
+    }
+}
@@ -0,0 +1,1098 @@
+#[cfg(feature = "mkl")]
+extern crate intel_mkl_src;
+extern crate libc;
+#[cfg(not(any(feature = "mkl", feature = "openblas")))]
+extern crate matrixmultiply;
+#[cfg(all(feature = "openblas", not(feature = "mkl")))]
+extern crate openblas_src;
+
+use super::{AvgPoolParams, Backend, GemmParams, Im2ColNdParams, Im2ColParams, MaxPoolParams};
+use ndarray_ext::NdArray;
+use rayon::prelude::*;
+use std::f32;
+
+/// The default backend. Uses BLAS (`mkl` or `openblas` feature) or `matrixmultiply`
+/// for GEMM and parallelizes the other kernels with rayon.
+pub struct CpuBackend;
+
+impl Backend for CpuBackend {
+    fn name(&self) -> &str {
+        "CpuBackend"
+    }
+
+    #[inline]
+    fn sgemm(&self, params: &GemmParams, a: &[f32], b: &[f32], c: &mut [f32]) {
+        sgemm(params, a, b, c)
+    }
+
+    #[cfg(feature = "mkl")]
+    fn sgemm_batch(&self, params: &GemmParams, a: &[&[f32]], b: &[&[f32]], c: &mut [&mut [f32]]) {
+        cblas_sgemm_batch_wrapper(params, a, b, c);
+    }
+
+    #[cfg(not(feature = "mkl"))]
+    fn sgemm_batch(&self, params: &GemmParams, a: &[&[f32]], b: &[&[f32]], c: &mut [&mut [f32]]) {
+        // parallel mm
+        c.par_iter_mut()
+            .zip(a.par_iter().zip(b.par_iter()))
+            .for_each(|(c, (a, b))| sgemm(params, a, b, c));
+    }
+
+    #[inline]
+    fn im2col(&self, params: &Im2ColParams, im: &[f32], col: &mut [f32]) {
+        im2col(params, im, col)
+    }
+
+    #[inline]
+    fn col2im(&self, params: &Im2ColParams, col: &[f32], im: &mut [f32]) {
+        col2im(params, col, im)
+    }
+
+    #[inline]
+    fn im2col_nhwc(
+        &self,
+        params: &Im2ColParams,
+        batch: usize,
+        groups: usize,
+        im: &[f32],
+        col: &mut [f32],
+    ) {
+        im2col_nhwc(params, batch, groups, im, col)
+    }
+
+    #[inline]
+    fn col2im_nhwc(
+        &self,
+        params: &Im2ColParams,
+        batch: usize,
+        groups: usize,
+        col: &[f32],
+        im: &mut [f32],
+    ) {
+        col2im_nhwc(params, batch, groups, col, im)
+    }
+
+    #[inline]
+    fn im2col_nd(&self, params: &Im2ColNdParams, im: &[f32], col: &mut [f32]) {
+        im2col_nd(params, im, col)
+    }
+
+    #[inline]
+    fn col2im_nd(&self, params: &Im2ColNdParams, col: &[f32], im: &mut [f32]) {
+        col2im_nd(params, col, im)
+    }
+
+    #[inline]
+    fn max_pool(&self, params: &MaxPoolParams, x: &[f32], y: &mut [f32], argmax: &mut [f32]) {
+        if params.channels_last {
+            max_pool_nhwc(params, x, y, argmax)
+        } else {
+            max_pool(params, x, y, argmax)
+        }
+    }
+
+    #[inline]
+    fn max_pool_grad(&self, gy: &[f32], argmax: &[f32], gx: &mut [f32]) {
+        max_pool_grad(gy, argmax, gx)
+    }
+
+    #[inline]
+    fn max_pool_grad_grad(&self, ggx: &[f32], argmax: &[f32], ggy: &mut [f32]) {
+        max_pool_grad_grad(ggx, argmax, ggy)
+    }
+
+    #[inline]
+    fn avg_pool(&self, params: &AvgPoolParams, x: &[f32], y: &mut [f32]) {
+        if params.channels_last {
+            avg_pool_nhwc(params, x, y)
+        } else {
+            avg_pool(params, x, y)
+        }
+    }
+
+    #[inline]
+    fn avg_pool_grad(&self, params: &AvgPoolParams, gy: &[f32], gx: &mut [f32]) {
+        if params.channels_last {
+            avg_pool_grad_nhwc(params, gy, gx)
+        } else {
+            avg_pool_grad(params, gy, gx)
+        }
+    }
+
+    #[inline]
+    fn map(&self, x: &NdArray, f: &(dyn Fn(f32) -> f32 + Sync)) -> NdArray {
+        x.mapv(f)
+    }
+}
+
+// Panics unless the operands of `params` fit in `a`, `b` and `c`, which are passed
+// to GEMM as raw pointers.
+#[inline]
+fn check_gemm_operands(params: &GemmParams, a: &[f32], b: &[f32], c: &[f32]) {
+    let GemmParams { m, n, k, .. } = *params;
+    assert!(
+        a.len() >= m * k && b.len() >= k * n && c.len() >= m * n,
+        "sgemm: operands are too short for (m, n, k) = {:?}",
+        (m, n, k)
+    );
+}
+
+/// c := alpha * op(a) op(b) + beta * c, where a, b and c are row-major.
+///
+/// op(a) is (m, k) and op(b) is (k, n).
+#[cfg(not(any(feature = "mkl", feature = "openblas")))]
+#[inline]
+pub fn sgemm(params: &GemmParams, a: &[f32], b: &[f32], c: &mut [f32]) {
+    check_gemm_operands(params, a, b, c);
+    let GemmParams {
+        trans_a,
+        trans_b,
+        m,
+        n,
+        k,
+        alpha,
+        beta,
+    } = *params;
+    let rsa = if trans_a { 1 } else { k };
+    let csa = if trans_a { m } else { 1 };
+    let rsb = if trans_b { 1 } else { n };
+    let csb = if trans_b { k } else { 1 };
+    let rsc = n;
+    let csc = 1;
+    unsafe {
+        matrixmultiply::sgemm(
+            m,
+            k,
+            n,
+            alpha,
+            a.as_ptr(),
+            rsa as isize,
+            csa as isize,
+            b.as_ptr(),
+            rsb as isize,
+            csb as isize,
+            beta,
+            c.as_mut_ptr(),
+            rsc as isize,
+            csc as isize,
+        )
+    }
+}
+
+#[cfg(any(feature = "mkl", feature = "openblas"))]
+#[inline]
+fn cblas_transpose(trans: bool) -> CblasTranspose {
+    if trans {
+        CblasTranspose::CblasTrans
+    } else {
+        CblasTranspose::CblasNoTrans
+    }
+}
+
+/// c := alpha * op(a) op(b) + beta * c, where a, b and c are row-major.
+///
+/// op(a) is (m, k) and op(b) is (k, n).
+#[cfg(any(feature = "mkl", feature = "openblas"))]
+#[inline]
+pub fn sgemm(params: &GemmParams, a: &[f32], b: &[f32], c: &mut [f32]) {
+    check_gemm_operands(params, a, b, c);
+    let GemmParams {
+        trans_a,
+        trans_b,
+        m,
+        n,
+        k,
+        alpha,
+        beta,
+    } = *params;
+    let lda = if trans_a { m } else { k } as BlasInt;
+    let ldb = if trans_b { k } else { n } as BlasInt;
+    let ldc = n as BlasInt;
+    unsafe {
+        cblas_sgemm(
+            CBLAS_ROW_MAJOR,
+            cblas_transpose(trans_a),
+            cblas_transpose(trans_b),
+            m as BlasInt,
+            n as BlasInt,
+            k as BlasInt,
+            alpha,
+            a.as_ptr(),
+            lda,
+            b.as_ptr(),
+            ldb,
+            beta,
+            c.as_mut_ptr(),
+            ldc,
+        )
+    }
+}
+
+// MKL is linked with the ILP64 interface.
+#[cfg(feature = "mkl")]
+type BlasInt = i64;
+
+#[cfg(all(feature = "openblas", not(feature = "mkl")))]
+type BlasInt = libc::c_int;
+
+#[cfg(any(feature = "mkl", feature = "openblas"))]
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+enum CblasTranspose {
+    CblasNoTrans = 111,
+    CblasTrans = 112,
+    // CblasConjTrans = 113,
+}
+
+#[cfg(any(feature = "mkl", feature = "openblas"))]
+type CblasLayout = libc::c_int;
+
+#[cfg(any(feature = "mkl", feature = "openblas"))]
+const CBLAS_ROW_MAJOR: CblasLayout = 101;
+
+#[cfg(any(feature = "mkl", feature = "openblas"))]
+extern "C" {
+    fn cblas_sgemm(
+        layout: CblasLayout,
+        transa: CblasTranspose,
+        transb: CblasTranspose,
+        m: BlasInt,
+        n: BlasInt,
+        k: BlasInt,
+        alpha: libc::c_float,
+        a: *const libc::c_float,
+        lda: BlasInt,
+        b: *const libc::c_float,
+        ldb: BlasInt,
+        beta: libc::c_float,
+        c: *mut libc::c_float,
+        ldc: BlasInt,
+    );
+}
+
+#[cfg(feature = "mkl")]
+extern "C" {
+    // Batched sgemm from intel MKL
+    fn cblas_sgemm_batch(
+        layout: CblasLayout,
+        transa_array: *const CblasTranspose, // batch of CblasTranspose
+        transb_array: *const CblasTranspose, // batch of CblasTranspose
+        m_array: *const BlasInt,             // batch of m
+        n_array: *const BlasInt,             // batch of n
+        k_array: *const BlasInt,             // batch of k
+        alpha_array: *const libc::c_float,   // batch of alpha
+        a_array: *const *const libc::c_float, // a
+        lda_array: *const BlasInt,           // batch of lda
+        b_array: *const *const libc::c_float, // b
+        ldb_array: *const BlasInt,           // batch of ldb
+        beta_array: *const libc::c_float,    // batch of beta
+        c_array: *mut *mut libc::c_float,    // c
+        ldc_array: *const BlasInt,           // batch of odc
+        group_count: BlasInt,                // batch size
+        group_size: *const BlasInt,
+    ); // num of matrices in each batch
+}
+
+#[cfg(feature = "mkl")]
+#[inline]
+pub fn cblas_sgemm_batch_wrapper(
+    params: &GemmParams,
+    a: &[&[f32]],
+    b: &[&[f32]],
+    c: &mut [&mut [f32]],
+) {
+    let size = c.len();
+    for ((a, b), c) in a.iter().zip(b).zip(c.iter()) {
+        check_gemm_operands(params, a, b, c);
+    }
+    assert!(a.len() >= size && b.len() >= size);
+    let GemmParams {
+        trans_a,
+        trans_b,
+        m,
+        n,
+        k,
+        alpha,
+        beta,
+    } = *params;
+    let lda = if trans_a { m } else { k } as BlasInt;
+    let ldb = if trans_b { k } else { n } as BlasInt;
+    let ldc = n as BlasInt;
+    let a = a.iter().map(|a| a.as_ptr()).collect::<Vec<_>>();
+    let b = b.iter().map(|b| b.as_ptr()).collect::<Vec<_>>();
+    let mut c = c.iter_mut().map(|c| c.as_mut_ptr()).collect::<Vec<_>>();
+    unsafe {
+        // A single group of `size` products
+        cblas_sgemm_batch(
+            CBLAS_ROW_MAJOR,
+            &cblas_transpose(trans_a),
+            &cblas_transpose(trans_b),
+            &(m as BlasInt),
+            &(n as BlasInt),
+            &(k as BlasInt),
+            &alpha,
+            a.as_ptr(),
+            &lda,
+            b.as_ptr(),
+            &ldb,
+            &beta,
+            c.as_mut_ptr(),
+            &ldc,
+            1,
+            &(size as BlasInt),
+        );
+    }
+}
+
+// Range of output indices `o` such that `offset + o * stride` is in `[0, size)`.
+#[inline]
+fn valid_range(offset: isize, stride: usize, size: usize, out: usize) -> (usize, usize) {
+    let stride = stride as isize;
+    let lo = if offset < 0 {
+        (-offset + stride - 1) / stride
+    } else {
+        0
+    };
+    let hi = if offset < size as isize {
+        (size as isize - offset + stride - 1) / stride
+    } else {
+        0
+    };
+    let hi = (hi as usize).min(out);
+    ((lo as usize).min(hi), hi)
+}
+
+#[inline]
+fn fill_zero(a: &mut [f32]) {
+    for a in a.iter_mut() {
+        *a = 0.;
+    }
+}
+
+#[inline]
+pub fn max_pool(params: &MaxPoolParams, input: &[f32], output: &mut [f32], argmax: &mut [f32]) {
+    let MaxPoolParams {
+        height: h,
+        width: w,
+        size: (size_h, size_w),
+        stride: (stride_h, stride_w),
+        pad: (pad_top, pad_left),
+        out_size: (out_h, out_w),
+        ..
+    } = *params;
+    output
+        .par_chunks_mut(out_h * out_w)
+        .zip(argmax.par_chunks_mut(out_h * out_w))
+        .zip(input.par_chunks(h * w))
+        .enumerate()
+        .for_each(|(bc, ((output, argmax), x_ch))| {
+            // for each channel of a sample
+            for i in 0..out_h {
+                let h_start = (i * stride_h) as isize - pad_top as isize;
+                let h_end = (h_start + size_h as isize).min(h as isize) as usize;
+                let h_start = h_start.max(0) as usize;
+                for j in 0..out_w {
+                    let w_start = (j * stride_w) as isize - pad_left as isize;
+                    let w_end = (w_start + size_w as isize).min(w as isize) as usize;
+                    let w_start = w_start.max(0) as usize;
+                    let mut max = f32::MIN;
+                    let mut max_i = 0;
+                    // in a window
+                    for row in h_start..h_end {
+                        for col in w_start..w_end {
+                            let val = x_ch[row * w + col];
+                            if val > max {
+                                max_i = row * w + col;
+                                max = val;
+                            }
+                        }
+                    }
+                    output[i * out_w + j] = max;
+                    // Indices into the whole batch, as in the other kernels
+                    argmax[i * out_w + j] = (bc * h * w + max_i) as f32;
+                }
+            }
+        });
+}
+
+#[inline]
+pub fn max_pool_nhwc(
+    params: &MaxPoolParams,
+    input: &[f32],
+    output: &mut [f32],
+    argmax: &mut [f32],
+) {
+    let MaxPoolParams {
+        channels: c,
+        height: h,
+        width: w,
+        size: (size_h, size_w),
+        stride: (stride_h, stride_w),
+        pad: (pad_top, pad_left),
+        out_size: (out_h, out_w),
+        ..
+    } = *params;
+    output
+        .par_chunks_mut(out_w * c)
+        .zip(argmax.par_chunks_mut(out_w * c))
+        .enumerate()
+        .for_each(|(bi, (output, argmax))| {
+            // for each output row of a sample
+            let (b, i) = (bi / out_h, bi % out_h);
+            let h_start = (i * stride_h) as isize - pad_top as isize;
+            let h_end = (h_start + size_h as isize).min(h as isize) as usize;
+            let h_start = h_start.max(0) as usize;
+            for j in 0..out_w {
+                let w_start = (j * stride_w) as isize - pad_left as isize;
+                let w_end = (w_start + size_w as isize).min(w as isize) as usize;
+                let w_start = w_start.max(0) as usize;
+                let output = &mut output[j * c..(j + 1) * c];
+                let argmax = &mut argmax[j * c..(j + 1) * c];
+                for ch in 0..c {
+                    output[ch] = f32::MIN;
+                    argmax[ch] = (b * h * w * c + ch) as f32;
+                }
+                // in a window
+                for row in h_start..h_end {
+                    for col in w_start..w_end {
+                        let base = ((b * h + row) * w + col) * c;
+                        let x = &input[base..base + c];
+                        for ch in 0..c {
+                            if x[ch] > output[ch] {
+                                output[ch] = x[ch];
+                                argmax[ch] = (base + ch) as f32;
+                            }
+                        }
+                    }
+                }
+            }
+        });
+}
+
+#[inline]
+pub fn max_pool_grad(gy: &[f32], argmax: &[f32], gx: &mut [f32]) {
+    for (&g, &i) in gy.iter().zip(argmax) {
+        gx[i as usize] += g;
+    }
+}
+
+#[inline]
+pub fn max_pool_grad_grad(ggx: &[f32], argmax: &[f32], ggy: &mut [f32]) {
+    for (g, &i) in ggy.iter_mut().zip(argmax) {
+        *g = ggx[i as usize];
+    }
+}
+
+#[inline]
+pub fn avg_pool(params: &AvgPoolParams, x: &[f32], y: &mut [f32]) {
+    let AvgPoolParams {
+        height: h,
+        width: w,
+        rows,
+        cols,
+        ..
+    } = *params;
+    let (out_h, out_w) = (rows.len(), cols.len());
+    y.par_chunks_mut(out_h * out_w)
+        .zip(x.par_chunks(h * w))
+        .for_each(|(y, x)| {
+            for (i, r) in rows.iter().enumerate() {
+                for (j, col) in cols.iter().enumerate() {
+                    let mut sum = 0.;
+                    for row in r.begin..r.end {
+                        for a in &x[row * w + col.begin..row * w + col.end] {
+                            sum += *a;
+                        }
+                    }
+                    y[i * out_w + j] = sum / (r.divisor * col.divisor) as f32;
+                }
+            }
+        });
+}
+
+#[inline]
+pub fn avg_pool_grad(params: &AvgPoolParams, gy: &[f32], gx: &mut [f32]) {
+    let AvgPoolParams {
+        height: h,
+        width: w,
+        rows,
+        cols,
+        ..
+    } = *params;
+    let (out_h, out_w) = (rows.len(), cols.len());
+    gx.par_chunks_mut(h * w)
+        .zip(gy.par_chunks(out_h * out_w))
+        .for_each(|(gx, gy)| {
+            for (i, r) in rows.iter().enumerate() {
+                for (j, col) in cols.iter().enumerate() {
+                    let g = gy[i * out_w + j] / (r.divisor * col.divisor) as f32;
+                    for row in r.begin..r.end {
+                        for a in &mut gx[row * w + col.begin..row * w + col.end] {
+                            *a += g;
+                        }
+                    }
+                }
+            }
+        });
+}
+
+#[inline]
+pub fn avg_pool_nhwc(params: &AvgPoolParams, x: &[f32], y: &mut [f32]) {
+    let AvgPoolParams {
+        channels: c,
+        height: h,
+        width: w,
+        rows,
+        cols,
+        ..
+    } = *params;
+    let (out_h, out_w) = (rows.len(), cols.len());
+    y.par_chunks_mut(out_h * out_w * c)
+        .zip(x.par_chunks(h * w * c))
+        .for_each(|(y, x)| {
+            for (i, r) in rows.iter().enumerate() {
+                for (j, col) in cols.iter().enumerate() {
+                    let y = &mut y[(i * out_w + j) * c..(i * out_w + j + 1) * c];
+                    fill_zero(y);
+                    for row in r.begin..r.end {
+                        for x in x[(row * w + col.begin) * c..(row * w + col.end) * c].chunks(c) {
+                            for (y, x) in y.iter_mut().zip(x) {
+                                *y += *x;
+                            }
+                        }
+                    }
+                    let divisor = (r.divisor * col.divisor) as f32;
+                    for y in y.iter_mut() {
+                        *y /= divisor;
+                    }
+                }
+            }
+        });
+}
+
+#[inline]
+pub fn avg_pool_grad_nhwc(params: &AvgPoolParams, gy: &[f32], gx: &mut [f32]) {
+    let AvgPoolParams {
+        channels: c,
+        height: h,
+        width: w,
+        rows,
+        cols,
+        ..
+    } = *params;
+    let (out_h, out_w) = (rows.len(), cols.len());
+    gx.par_chunks_mut(h * w * c)
+        .zip(gy.par_chunks(out_h * out_w * c))
+        .for_each(|(gx, gy)| {
+            for (i, r) in rows.iter().enumerate() {
+                for (j, col) in cols.iter().enumerate() {
+                    let gy = &gy[(i * out_w + j) * c..(i * out_w + j + 1) * c];
+                    let divisor = (r.divisor * col.divisor) as f32;
+                    for row in r.begin..r.end {
+                        let gx = &mut gx[(row * w + col.begin) * c..(row * w + col.end) * c];
+                        for gx in gx.chunks_mut(c) {
+                            for (gx, gy) in gx.iter_mut().zip(gy) {
+                                *gx += *gy / divisor;
+                            }
+                        }
+                    }
+                }
+            }
+        });
+}
+
+/// Calls `f(dst, src)` for each run of `channels / groups` elements in a (group, batch)
+/// region of the columns of `im2col_nhwc`, where `src` is `None` if the run is in
+/// the padding.
+///
+/// `dst` and `src` are offsets relative to the region and to the image respectively.
+fn for_each_col_nhwc<F>(params: &Im2ColParams, groups: usize, group: usize, mut f: F)
+where
+    F: FnMut(usize, Option<usize>),
+{
+    let Im2ColParams {
+        channels,
+        height,
+        width,
+        kernel,
+        pad: (pad_top, _, pad_left, _),
+        stride,
+        dilation,
+    } = *params;
+    let (out_h, out_w) = params.out_size();
+    let group_ch = channels / groups;
+    let mut dst = 0;
+    for oi in 0..out_h {
+        for oj in 0..out_w {
+            for ki in 0..kernel.0 {
+                let i = (oi * stride.0 + ki * dilation.0) as isize - pad_top as isize;
+                for kj in 0..kernel.1 {
+                    let j = (oj * stride.1 + kj * dilation.1) as isize - pad_left as isize;
+                    let src = if 0 <= i && i < height as isize && 0 <= j && j < width as isize {
+                        Some((i as usize * width + j as usize) * channels + group * group_ch)
+                    } else {
+                        None
+                    };
+                    f(dst, src);
+                    dst += group_ch;
+                }
+            }
+        }
+    }
+}
+
+#[inline]
+pub fn im2col_nhwc(
+    params: &Im2ColParams,
+    batch: usize,
+    groups: usize,
+    im: &[f32],
+    col: &mut [f32],
+) {
+    let (out_h, out_w) = params.out_size();
+    let group_ch = params.channels / groups;
+    let region = out_h * out_w * params.kernel.0 * params.kernel.1 * group_ch;
+    let image = params.height * params.width * params.channels;
+    col[..groups * batch * region]
+        .par_chunks_mut(region)
+        .enumerate()
+        .for_each(|(gb, col)| {
+            // for each (group, batch) region
+            let (g, b) = (gb / batch, gb % batch);
+            let im = &im[b * image..(b + 1) * image];
+            for_each_col_nhwc(params, groups, g, |dst, src| {
+                let dst = &mut col[dst..dst + group_ch];
+                match src {
+                    Some(src) => dst.copy_from_slice(&im[src..src + group_ch]),
+                    None => fill_zero(dst),
+                }
+            });
+        });
+}
+
+#[inline]
+pub fn col2im_nhwc(
+    params: &Im2ColParams,
+    batch: usize,
+    groups: usize,
+    col: &[f32],
+    im: &mut [f32],
+) {
+    let (out_h, out_w) = params.out_size();
+    let group_ch = params.channels / groups;
+    let region = out_h * out_w * params.kernel.0 * params.kernel.1 * group_ch;
+    let image = params.height * params.width * params.channels;
+    im[..batch * image]
+        .par_chunks_mut(image)
+        .enumerate()
+        .for_each(|(b, im)| {
+            // Groups of a sample write to disjoint channels, so they are processed in turn.
+            for g in 0..groups {
+                let col = &col[(g * batch + b) * region..(g * batch + b + 1) * region];
+                for_each_col_nhwc(params, groups, g, |src, dst| {
+                    if let Some(dst) = dst {
+                        for (a, b) in im[dst..dst + group_ch]
+                            .iter_mut()
+                            .zip(&col[src..src + group_ch])
+                        {
+                            *a += *b;
+                        }
+                    }
+                });
+            }
+        });
+}
+
+#[inline]
+pub fn im2col(params: &Im2ColParams, data_im: &[f32], data_col: &mut [f32]) {
+    let Im2ColParams {
+        channels,
+        height,
+        width,
+        kernel: (kernel_h, kernel_w),
+        pad: (pad_top, _, pad_left, _),
+        stride: (stride_h, stride_w),
+        dilation: (dilation_h, dilation_w),
+    } = *params;
+    let (output_h, output_w) = params.out_size();
+    let channel_size = height * width;
+    let col_channel_size = kernel_h * kernel_w * output_h * output_w;
+    data_col[..channels * col_channel_size]
+        .par_chunks_mut(col_channel_size)
+        .zip(data_im[..channels * channel_size].par_chunks(channel_size))
+        .for_each(|(col, im)| {
+            let mut rows = col.chunks_mut(output_w);
+            for kernel_row in 0..kernel_h {
+                let row_offset = (kernel_row * dilation_h) as isize - pad_top as isize;
+                for kernel_col in 0..kernel_w {
+                    let col_offset = (kernel_col * dilation_w) as isize - pad_left as isize;
+                    let (lo, hi) = valid_range(col_offset, stride_w, width, output_w);
+                    for output_row in 0..output_h {
+                        // unwrap is safe
+                        let dst = rows.next().unwrap();
+                        let input_row = row_offset + (output_row * stride_h) as isize;
+                        if input_row < 0 || input_row >= height as isize || lo == hi {
+                            fill_zero(dst);
+                            continue;
+                        }
+                        let src = &im[input_row as usize * width..(input_row as usize + 1) * width];
+                        fill_zero(&mut dst[..lo]);
+                        fill_zero(&mut dst[hi..]);
+                        let start = (col_offset + (lo * stride_w) as isize) as usize;
+                        if stride_w == 1 {
+                            dst[lo..hi].copy_from_slice(&src[start..start + hi - lo]);
+                        } else {
+                            for (d, s) in dst[lo..hi]
+                                .iter_mut()
+                                .zip(src[start..].iter().step_by(stride_w))
+                            {
+                                *d = *s;
+                            }
+                        }
+                    }
+                }
+            }
+        });
+}
+
+// `data_im` must be initialized (with zeros in most cases) since the results are accumulated.
+#[inline]
+pub fn col2im(params: &Im2ColParams, data_col: &[f32], data_im: &mut [f32]) {
+    let Im2ColParams {
+        channels,
+        height,
+        width,
+        kernel: (kernel_h, kernel_w),
+        pad: (pad_top, _, pad_left, _),
+        stride: (stride_h, stride_w),
+        dilation: (dilation_h, dilation_w),
+    } = *params;
+    let (output_h, output_w) = params.out_size();
+    let channel_size = height * width;
+    let col_channel_size = kernel_h * kernel_w * output_h * output_w;
+    data_im[..channels * channel_size]
+        .par_chunks_mut(channel_size)
+        .zip(data_col[..channels * col_channel_size].par_chunks(col_channel_size))
+        .for_each(|(im, col)| {
+            let mut rows = col.chunks(output_w);
+            for kernel_row in 0..kernel_h {
+                let row_offset = (kernel_row * dilation_h) as isize - pad_top as isize;
+                for kernel_col in 0..kernel_w {
+                    let col_offset = (kernel_col * dilation_w) as isize - pad_left as isize;
+                    let (lo, hi) = valid_range(col_offset, stride_w, width, output_w);
+                    for output_row in 0..output_h {
+                        // unwrap is safe
+                        let src = rows.next().unwrap();
+                        let input_row = row_offset + (output_row * stride_h) as isize;
+                        if input_row < 0 || input_row >= height as isize || lo == hi {
+                            continue;
+                        }
+                        let dst =
+                            &mut im[input_row as usize * width..(input_row as usize + 1) * width];
+                        let start = (col_offset + (lo * stride_w) as isize) as usize;
+                        if stride_w == 1 {
+                            for (d, s) in dst[start..start + hi - lo].iter_mut().zip(&src[lo..hi]) {
+                                *d += *s;
+                            }
+                        } else {
+                            for (d, s) in
+                                dst[start..].iter_mut().step_by(stride_w).zip(&src[lo..hi])
+                            {
+                                *d += *s;
+                            }
+                        }
+                    }
+                }
+            }
+        });
+}
+
+// Increments the multi-dimensional `index` in row-major order.
+#[inline]
+fn next_index(index: &mut [usize], dims: &[usize]) {
+    for (i, &d) in index.iter_mut().zip(dims).rev() {
+        *i += 1;
+        if *i < d {
+            return;
+        }
+        *i = 0;
+    }
+}
+
+// Calls `f(row, input_row, col_offset, lo, hi)` for each row of the columns of one channel,
+// where a row is the last spatial axis of the output `out`.
+//
+// `input_row` is the index of the corresponding row of the image (`None` if it is in
+// the padding), and `[lo, hi)` is the valid range of the row (see `valid_range`).
+#[inline]
+fn for_each_col_row_nd<F>(params: &Im2ColNdParams, out: &[usize], mut f: F)
+where
+    F: FnMut(usize, Option<usize>, isize, usize, usize),
+{
+    let Im2ColNdParams {
+        shape,
+        kernel,
+        pad,
+        stride,
+        dilation,
+        ..
+    } = *params;
+    let last = shape.len() - 1;
+    let kernel_size: usize = kernel.iter().product();
+    let num_out_rows: usize = out[..last].iter().product();
+    let mut k = vec![0; shape.len()];
+    let mut o = vec![0; last];
+    let mut row = 0;
+    for _ in 0..kernel_size {
+        let col_offset = (k[last] * dilation[last]) as isize - pad[last] as isize;
+        let (lo, hi) = valid_range(col_offset, stride[last], shape[last], out[last]);
+        for _ in 0..num_out_rows {
+            let mut input_row = Some(0);
+            for a in 0..last {
+                let i = (o[a] * stride[a] + k[a] * dilation[a]) as isize - pad[a] as isize;
+                input_row = match input_row {
+                    Some(r) if 0 <= i && i < shape[a] as isize => Some(r * shape[a] + i as usize),
+                    _ => None,
+                };
+            }
+            f(row, input_row, col_offset, lo, hi);
+            row += 1;
+            next_index(&mut o, &out[..last]);
+        }
+        next_index(&mut k, kernel);
+    }
+}
+
+#[inline]
+pub fn im2col_nd(params: &Im2ColNdParams, data_im: &[f32], data_col: &mut [f32]) {
+    let out = params.out_shape();
+    let (shape, stride) = (params.shape, params.stride);
+    let (width, output_w, stride_w) = (
+        shape[shape.len() - 1],
+        out[out.len() - 1],
+        stride[stride.len() - 1],
+    );
+    let channel_size: usize = shape.iter().product();
+    let col_channel_size = params.kernel.iter().product::<usize>() * out.iter().product::<usize>();
+    data_col[..params.channels * col_channel_size]
+        .par_chunks_mut(col_channel_size)
+        .zip(data_im[..params.channels * channel_size].par_chunks(channel_size))
+        .for_each(|(col, im)| {
+            for_each_col_row_nd(params, &out, |row, input_row, col_offset, lo, hi| {
+                let dst = &mut col[row * output_w..(row + 1) * output_w];
+                let input_row = match input_row {
+                    Some(r) if lo != hi => r,
+                    _ => return fill_zero(dst),
+                };
+                let src = &im[input_row * width..(input_row + 1) * width];
+                fill_zero(&mut dst[..lo]);
+                fill_zero(&mut dst[hi..]);
+                let start = (col_offset + (lo * stride_w) as isize) as usize;
+                for (d, s) in dst[lo..hi]
+                    .iter_mut()
+                    .zip(src[start..].iter().step_by(stride_w))
+                {
+                    *d = *s;
+                }
+            });
+        });
+}
+
+// `data_im` must be initialized (with zeros in most cases) since the results are accumulated.
+#[inline]
+pub fn col2im_nd(params: &Im2ColNdParams, data_col: &[f32], data_im: &mut [f32]) {
+    let out = params.out_shape();
+    let (shape, stride) = (params.shape, params.stride);
+    let (width, output_w, stride_w) = (
+        shape[shape.len() - 1],
+        out[out.len() - 1],
+        stride[stride.len() - 1],
+    );
+    let channel_size: usize = shape.iter().product();
+    let col_channel_size = params.kernel.iter().product::<usize>() * out.iter().product::<usize>();
+    data_im[..params.channels * channel_size]
+        .par_chunks_mut(channel_size)
+        .zip(data_col[..params.channels * col_channel_size].par_chunks(col_channel_size))
+        .for_each(|(im, col)| {
+            for_each_col_row_nd(params, &out, |row, input_row, col_offset, lo, hi| {
+                let input_row = match input_row {
+                    Some(r) if lo != hi => r,
+                    _ => return,
+                };
+                let src = &col[row * output_w..(row + 1) * output_w];
+                let dst = &mut im[input_row * width..(input_row + 1) * width];
+                let start = (col_offset + (lo * stride_w) as isize) as usize;
+                for (d, s) in dst[start..].iter_mut().step_by(stride_w).zip(&src[lo..hi]) {
+                    *d += *s;
+                }
+            });
+        });
+}
+
+#[cfg(test)]
+fn gemm(trans_a: bool, trans_b: bool, m: usize, n: usize, k: usize, beta: f32) -> GemmParams {
+    GemmParams {
+        trans_a,
+        trans_b,
+        m,
+        n,
+        k,
+        alpha: 1.,
+        beta,
+    }
+}
+
+#[test]
+fn test_gemm_trans_a() {
+    let a = [1., 2., 3., 4., 5., 6.];
+    let b = [1., 2., 3., 4.];
+    let mut c = [0.; 6];
+    let m = 3; // row of op(a)
+    let n = 2; // col of op(b)
+    let k = 2; // col of op(a)
+    sgemm(&gemm(true, false, m, n, k, 0.), &a, &b, &mut c);
+    assert_eq!(&c, &[13.0, 18.0, 17.0, 24.0, 21.0, 30.0]);
+}
+
+#[test]
+fn test_gemm_trans_b() {
+    let a = [1., 2., 3., 4.];
+    let b = [1., 2., 3., 4., 5., 6.];
+    let mut c = [0.; 6];
+    let m = 2; // row of op(a)
+    let n = 3; // col of op(b)
+    let k = 2; // col of op(a)
+    sgemm(&gemm(false, true, m, n, k, 0.), &a, &b, &mut c);
+    assert_eq!(&c, &[5., 11., 17., 11., 25., 39.]);
+}
+
+#[cfg(feature = "mkl")]
+#[test]
+fn test_sgemm_batch_trans_a() {
+    let batch = 2;
+    let w = vec![0., 1., 2., 3., 4., 5.]; // (2, 3)
+    let x = vec![0., 1., 2., 3., 4., 5., 6., 7.]; // (2, 2, 2)
+    let mut z = vec![0.; 12]; // (2, 2, 2)
+    let m = 3; // row of op(a)
+    let n = 2; // col of op(b)
+    let k = 2; // col of op(a)
+    cblas_sgemm_batch_wrapper(
+        &gemm(true, false, m, n, k, 0.),
+        &[&w, &w],
+        &super::split_regions(batch, &x),
+        &mut super::split_regions_mut(batch, &mut z),
+    );
+    assert_eq!(
+        z,
+        vec![6., 9., 8., 13., 10., 17., 18., 21., 28., 33., 38., 45.]
+    );
+}
+
+#[cfg(feature = "mkl")]
+#[test]
+fn test_sgemm_batch() {
+    let batch = 2;
+    let x = vec![0., 1., 2., 3.]; // (2, 2)
+    let y = vec![0., 1., 2., 3., 4., 5., 6., 7.]; // (2, 2, 2)
+    let mut z = vec![0.; 8]; // (2, 2, 2)
+
+    cblas_sgemm_batch_wrapper(
+        &gemm(false, false, 2, 2, 2, 0.),
+        &[&x, &x],
+        &super::split_regions(batch, &y),
+        &mut super::split_regions_mut(batch, &mut z),
+    );
+    assert_eq!(z, vec![2., 3., 6., 11., 6., 7., 26., 31.]);
+}
+
+#[test]
+fn test_sequential_sgemm() {
+    let x = [0., 1., 2., 3.];
+    let y = [0., 1., 2., 3.];
+    let mut z = [0.; 8];
+
+    for z in z.chunks_mut(4) {
+        sgemm(&gemm(false, false, 2, 2, 2, 0.), &x, &y, z)
+    }
+    assert_eq!([2.0, 3.0, 6.0, 11.0, 2.0, 3.0, 6.0, 11.0], z);
+}
+
+#[test]
+fn test_sgemm_acc() {
+    let x = [0., 1., 2., 3.];
+    let y = [0., 1., 2., 3.];
+    let mut z = [0.; 4];
+
+    let num_iter = 3.;
+
+    for _ in 0..num_iter as usize {
+        sgemm(&gemm(false, false, 2, 2, 2, 1.), &x, &y, &mut z)
+    }
+    assert_eq!(
+        [2. * num_iter, 3. * num_iter, 6. * num_iter, 11. * num_iter],
+        z
+    );
+}
+
+#[test]
+#[should_panic]
+fn test_sgemm_short_output() {
+    let x = [0., 1., 2., 3.];
+    let mut z = [0.; 3];
+    sgemm(&gemm(false, false, 2, 2, 2, 0.), &x, &x, &mut z)
+}
+
+#[test]
+fn test_max_pool_cpu() {
+    let x = vec![0., 1., 2., 5., 4., 3., 6., 7., 8.];
+    let mut output = vec![0.; 4];
+    let mut argmax = vec![0.; 4];
+    let params = MaxPoolParams {
+        batch: 1,
+        channels: 1,
+        height: 3,
+        width: 3,
+        channels_last: false,
+        size: (2, 2),
+        stride: (1, 1),
+        pad: (0, 0),
+        out_size: (2, 2),
+    };
+    max_pool(&params, &x, &mut output, &mut argmax);
+    assert_eq!(output, vec![5., 4., 7., 8.]);
+    assert_eq!(argmax, vec![3., 4., 7., 8.]);
+}
+
+#[test]
+fn test_im2col_nd() {
+    let x = ::ndarray_ext::standard_normal(&[3, 7, 6]).into_raw_vec();
+    let (kernel, pad, stride, dilation) = ([3, 2], [1, 1], [2, 2], [1, 2]);
+    let params = Im2ColParams {
+        channels: 3,
+        height: 7,
+        width: 6,
+        kernel: (3, 2),
+        pad: (1, 1, 1, 1),
+        stride: (2, 2),
+        dilation: (1, 2),
+    };
+    let params_nd = Im2ColNdParams {
+        channels: 3,
+        shape: &[7, 6],
+        kernel: &kernel,
+        pad: &pad,
+        stride: &stride,
+        dilation: &dilation,
+    };
+    let mut col = vec![0.; 3 * 3 * 2 * 4 * 3];
+    let mut col_nd = vec![0.; 3 * 3 * 2 * 4 * 3];
+    im2col(&params, &x, &mut col);
+    im2col_nd(&params_nd, &x, &mut col_nd);
+    assert_eq!(col, col_nd);
+
+    let mut im = vec![0.; 3 * 7 * 6];
+    let mut im_nd = vec![0.; 3 * 7 * 6];
+    col2im(&params, &col, &mut im);
+    col2im_nd(&params_nd, &col, &mut im_nd);
+    assert_eq!(im, im_nd);
+}
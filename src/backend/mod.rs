@@ -0,0 +1,270 @@
+//! Kernels that ops dispatch to during evaluation.
+//!
+//! Heavy ops (`matmul`, `batch_matmul`, convolutions, pooling and unary math functions)
+//! don't call their kernels directly but through the `Backend` of the running evaluation,
+//! which is available as `ctx.backend()` in `Op::compute`.
+//! `CpuBackend` is used by default; another backend can be selected per evaluation with
+//! `Eval::set_backend`.
+//!
+//! ```
+//! extern crate autograd as ag;
+//!
+//! let ref a = ag::ones(&[2, 3]);
+//! let ref b = ag::ones(&[3, 2]);
+//! let ref c = ag::matmul(a, b);
+//!
+//! let fast = ag::Eval::new().push(c).run(&[]);
+//! let slow = ag::Eval::new()
+//!     .set_backend(&ag::backend::ReferenceBackend)
+//!     .push(c)
+//!     .run(&[]);
+//! assert_eq!(fast, slow);
+//! ```
+//!
+//! Inputs are passed as slices and outputs as mutable slices, both row-major.
+//! Geometries of the kernels are grouped into parameter structs such as `Im2ColParams`.
+use ndarray_ext::NdArray;
+
+mod cpu;
+mod reference;
+
+#[cfg(feature = "c-kernels")]
+#[doc(hidden)]
+pub mod c_kernels;
+
+pub use self::cpu::CpuBackend;
+pub use self::reference::ReferenceBackend;
+
+/// Set of kernels used by ops.
+///
+/// Implementors must be `Sync` since ops may call kernels from multiple threads.
+pub trait Backend: Sync {
+    /// Name of this backend.
+    fn name(&self) -> &str;
+
+    /// c := alpha * op(a) op(b) + beta * c, where a, b and c are row-major.
+    ///
+    /// op(a) is (m, k) and op(b) is (k, n).
+    fn sgemm(&self, params: &GemmParams, a: &[f32], b: &[f32], c: &mut [f32]);
+
+    /// Applies `sgemm` to each triple of `a`, `b` and `c`.
+    ///
+    /// The default implementation calls `sgemm` sequentially.
+    fn sgemm_batch(&self, params: &GemmParams, a: &[&[f32]], b: &[&[f32]], c: &mut [&mut [f32]]) {
+        for ((a, b), c) in a.iter().zip(b).zip(c) {
+            self.sgemm(params, a, b, c);
+        }
+    }
+
+    /// Unfolds an image of shape (channels, height, width) into columns of shape
+    /// (channels, kernel_h, kernel_w, out_h, out_w).
+    fn im2col(&self, params: &Im2ColParams, im: &[f32], col: &mut [f32]);
+
+    /// Adjoint of `im2col`. The results are accumulated into `im`.
+    fn col2im(&self, params: &Im2ColParams, col: &[f32], im: &mut [f32]);
+
+    /// Channels-last version of `im2col` for a batch.
+    ///
+    /// Unfolds images of shape (batch, height, width, channels) into columns of shape
+    /// (groups, batch, out_h, out_w, kernel_h, kernel_w, channels / groups).
+    fn im2col_nhwc(
+        &self,
+        params: &Im2ColParams,
+        batch: usize,
+        groups: usize,
+        im: &[f32],
+        col: &mut [f32],
+    );
+
+    /// Adjoint of `im2col_nhwc`. The results are accumulated into `im`.
+    fn col2im_nhwc(
+        &self,
+        params: &Im2ColParams,
+        batch: usize,
+        groups: usize,
+        col: &[f32],
+        im: &mut [f32],
+    );
+
+    /// N-d version of `im2col`.
+    ///
+    /// Unfolds an image of shape (channels, shape...) into columns of shape
+    /// (channels, kernel..., out...).
+    fn im2col_nd(&self, params: &Im2ColNdParams, im: &[f32], col: &mut [f32]);
+
+    /// Adjoint of `im2col_nd`. The results are accumulated into `im`.
+    fn col2im_nd(&self, params: &Im2ColNdParams, col: &[f32], im: &mut [f32]);
+
+    /// 2D max pooling over `x` of shape (batch, channels, height, width).
+    ///
+    /// `x` and `y` are channels-last if `params.channels_last` is true.
+    /// `argmax` receives the indices of the max elements in the whole `x`.
+    fn max_pool(&self, params: &MaxPoolParams, x: &[f32], y: &mut [f32], argmax: &mut [f32]);
+
+    /// Scatters `gy` into `gx` along `argmax`. The results are accumulated into `gx`.
+    fn max_pool_grad(&self, gy: &[f32], argmax: &[f32], gx: &mut [f32]);
+
+    /// Gathers `ggx` along `argmax` into `ggy`.
+    fn max_pool_grad_grad(&self, ggx: &[f32], argmax: &[f32], ggy: &mut [f32]);
+
+    /// 2D average pooling over `x` of shape (batch, channels, height, width).
+    ///
+    /// Output element `(i, j)` is the sum of `x` over `rows[i]` and `cols[j]`
+    /// divided by `rows[i].divisor * cols[j].divisor`.
+    /// `y` is of shape (batch, channels, rows.len(), cols.len()).
+    /// `x` and `y` are channels-last if `params.channels_last` is true.
+    fn avg_pool(&self, params: &AvgPoolParams, x: &[f32], y: &mut [f32]);
+
+    /// Distributes `gy` over `gx`, i.e. the transpose of `avg_pool`.
+    /// The results are accumulated into `gx`.
+    fn avg_pool_grad(&self, params: &AvgPoolParams, gy: &[f32], gx: &mut [f32]);
+
+    /// Applies `f` to each element of `x`.
+    fn map(&self, x: &NdArray, f: &(dyn Fn(f32) -> f32 + Sync)) -> NdArray;
+}
+
+/// Parameters of `Backend::sgemm`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GemmParams {
+    pub trans_a: bool,
+    pub trans_b: bool,
+    pub m: usize,
+    pub n: usize,
+    pub k: usize,
+    pub alpha: f32,
+    pub beta: f32,
+}
+
+/// Geometry of `Backend::im2col` and its variants.
+///
+/// `channels`, `height` and `width` are of the image, and the other fields are `(h, w)`
+/// except `pad`, which is `(top, bottom, left, right)`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Im2ColParams {
+    pub channels: usize,
+    pub height: usize,
+    pub width: usize,
+    pub kernel: (usize, usize),
+    pub pad: (usize, usize, usize, usize),
+    pub stride: (usize, usize),
+    pub dilation: (usize, usize),
+}
+
+impl Im2ColParams {
+    /// `(out_h, out_w)` of the columns.
+    #[inline]
+    pub fn out_size(&self) -> (usize, usize) {
+        let (pad_top, pad_bottom, pad_left, pad_right) = self.pad;
+        (
+            conv_out_len(
+                self.height,
+                self.kernel.0,
+                pad_top + pad_bottom,
+                self.stride.0,
+                self.dilation.0,
+            ),
+            conv_out_len(
+                self.width,
+                self.kernel.1,
+                pad_left + pad_right,
+                self.stride.1,
+                self.dilation.1,
+            ),
+        )
+    }
+}
+
+/// Geometry of `Backend::im2col_nd`.
+///
+/// `pad`, `stride` and `dilation` have one element per spatial axis like `shape` and
+/// `kernel`. Pads are the same at both ends of an axis.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Im2ColNdParams<'a> {
+    pub channels: usize,
+    pub shape: &'a [usize],
+    pub kernel: &'a [usize],
+    pub pad: &'a [usize],
+    pub stride: &'a [usize],
+    pub dilation: &'a [usize],
+}
+
+impl<'a> Im2ColNdParams<'a> {
+    /// Spatial shape of the columns.
+    pub fn out_shape(&self) -> Vec<usize> {
+        (0..self.shape.len())
+            .map(|i| {
+                conv_out_len(
+                    self.shape[i],
+                    self.kernel[i],
+                    2 * self.pad[i],
+                    self.stride[i],
+                    self.dilation[i],
+                )
+            })
+            .collect()
+    }
+}
+
+/// Geometry of `Backend::max_pool`.
+///
+/// `pad` is `(top, left)`; pads after the last rows and columns are implied by `out_size`.
+/// Padded elements are never selected. The other pairs are `(h, w)`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MaxPoolParams {
+    pub batch: usize,
+    pub channels: usize,
+    pub height: usize,
+    pub width: usize,
+    pub channels_last: bool,
+    pub size: (usize, usize),
+    pub stride: (usize, usize),
+    pub pad: (usize, usize),
+    pub out_size: (usize, usize),
+}
+
+/// Geometry of `Backend::avg_pool`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AvgPoolParams<'a> {
+    pub batch: usize,
+    pub channels: usize,
+    pub height: usize,
+    pub width: usize,
+    pub channels_last: bool,
+    pub rows: &'a [PoolWindow],
+    pub cols: &'a [PoolWindow],
+}
+
+/// Input range pooled into an output element along an axis.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PoolWindow {
+    pub begin: usize,
+    pub end: usize,
+    /// Number of elements the sum is divided by along the axis, which may count paddings.
+    pub divisor: usize,
+}
+
+#[inline]
+fn conv_out_len(size: usize, kernel: usize, pads: usize, stride: usize, dilation: usize) -> usize {
+    (size + pads - (dilation * (kernel - 1) + 1)) / stride + 1
+}
+
+/// Splits `slice` into `size` regions of the same length.
+#[inline]
+pub fn split_regions(size: usize, slice: &[f32]) -> Vec<&[f32]> {
+    let len = slice.len() / size;
+    (0..size).map(|i| &slice[i * len..(i + 1) * len]).collect()
+}
+
+/// Mutable version of `split_regions`.
+#[inline]
+pub fn split_regions_mut(size: usize, slice: &mut [f32]) -> Vec<&mut [f32]> {
+    let len = slice.len() / size;
+    let mut ret = Vec::with_capacity(size);
+    let mut rest = slice;
+    for _ in 0..size {
+        let (head, tail) = { rest }.split_at_mut(len);
+        ret.push(head);
+        rest = tail;
+    }
+    ret
+}
@@ -0,0 +1,367 @@
+use super::{AvgPoolParams, Backend, GemmParams, Im2ColNdParams, Im2ColParams, MaxPoolParams};
+use ndarray_ext::NdArray;
+use std::f32;
+
+/// Slow but simple backend for testing.
+///
+/// Every kernel is a straightforward sequential loop over the output elements,
+/// so results of other backends can be checked against this one.
+pub struct ReferenceBackend;
+
+// Index of the element at (b, ch, i, j) of an array of shape (batch, c, h, w),
+// or of shape (batch, h, w, c) if `channels_last`.
+#[inline]
+fn index4(
+    channels_last: bool,
+    (c, h, w): (usize, usize, usize),
+    (b, ch, i, j): (usize, usize, usize, usize),
+) -> usize {
+    if channels_last {
+        ((b * h + i) * w + j) * c + ch
+    } else {
+        ((b * c + ch) * h + i) * w + j
+    }
+}
+
+// Calls `f(col_index, im_index)` for each element of the columns of `im2col_nhwc`,
+// where `im_index` is `None` if the element is in the padding.
+fn for_each_col_nhwc<F>(params: &Im2ColParams, batch: usize, groups: usize, mut f: F)
+where
+    F: FnMut(usize, Option<usize>),
+{
+    let Im2ColParams {
+        channels,
+        height,
+        width,
+        kernel,
+        pad: (pad_top, _, pad_left, _),
+        stride,
+        dilation,
+    } = *params;
+    let (out_h, out_w) = params.out_size();
+    let group_ch = channels / groups;
+    let mut dst = 0;
+    for g in 0..groups {
+        for b in 0..batch {
+            for oi in 0..out_h {
+                for oj in 0..out_w {
+                    for ki in 0..kernel.0 {
+                        for kj in 0..kernel.1 {
+                            let i = (oi * stride.0 + ki * dilation.0) as isize - pad_top as isize;
+                            let j = (oj * stride.1 + kj * dilation.1) as isize - pad_left as isize;
+                            for c in 0..group_ch {
+                                let im_index = if 0 <= i
+                                    && i < height as isize
+                                    && 0 <= j
+                                    && j < width as isize
+                                {
+                                    let (i, j) = (i as usize, j as usize);
+                                    Some(
+                                        ((b * height + i) * width + j) * channels
+                                            + g * group_ch
+                                            + c,
+                                    )
+                                } else {
+                                    None
+                                };
+                                f(dst, im_index);
+                                dst += 1;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Converts a flat row-major index into a multi-dimensional one.
+fn unravel(mut index: usize, dims: &[usize]) -> Vec<usize> {
+    let mut ret = vec![0; dims.len()];
+    for (r, &d) in ret.iter_mut().zip(dims).rev() {
+        *r = index % d;
+        index /= d;
+    }
+    ret
+}
+
+// Calls `f(col_index, im_index)` for each element of the columns of `im2col_nd`,
+// where `im_index` is `None` if the element is in the padding.
+fn for_each_col_nd<F>(params: &Im2ColNdParams, mut f: F)
+where
+    F: FnMut(usize, Option<usize>),
+{
+    let Im2ColNdParams {
+        channels,
+        shape,
+        kernel,
+        pad,
+        stride,
+        dilation,
+    } = *params;
+    let out = params.out_shape();
+    let kernel_size: usize = kernel.iter().product();
+    let out_size: usize = out.iter().product();
+    let mut dst = 0;
+    for c in 0..channels {
+        for ki in 0..kernel_size {
+            let k = unravel(ki, kernel);
+            for oi in 0..out_size {
+                let o = unravel(oi, &out);
+                let mut im_index = Some(c);
+                for a in 0..shape.len() {
+                    let i = (o[a] * stride[a] + k[a] * dilation[a]) as isize - pad[a] as isize;
+                    im_index = match im_index {
+                        Some(idx) if 0 <= i && i < shape[a] as isize => {
+                            Some(idx * shape[a] + i as usize)
+                        }
+                        _ => None,
+                    };
+                }
+                f(dst, im_index);
+                dst += 1;
+            }
+        }
+    }
+}
+
+// Calls `f(col_index, im_index)` for each element of the columns of `im2col`,
+// where `im_index` is `None` if the element is in the padding.
+fn for_each_col<F>(params: &Im2ColParams, mut f: F)
+where
+    F: FnMut(usize, Option<usize>),
+{
+    let Im2ColParams {
+        channels,
+        height,
+        width,
+        kernel: (kernel_h, kernel_w),
+        pad: (pad_top, _, pad_left, _),
+        stride: (stride_h, stride_w),
+        dilation: (dilation_h, dilation_w),
+    } = *params;
+    let (out_h, out_w) = params.out_size();
+    let mut dst = 0;
+    for c in 0..channels {
+        for ki in 0..kernel_h {
+            for kj in 0..kernel_w {
+                for oi in 0..out_h {
+                    for oj in 0..out_w {
+                        let i = (oi * stride_h + ki * dilation_h) as isize - pad_top as isize;
+                        let j = (oj * stride_w + kj * dilation_w) as isize - pad_left as isize;
+                        let im_index =
+                            if 0 <= i && i < height as isize && 0 <= j && j < width as isize {
+                                Some((c * height + i as usize) * width + j as usize)
+                            } else {
+                                None
+                            };
+                        f(dst, im_index);
+                        dst += 1;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Backend for ReferenceBackend {
+    fn name(&self) -> &str {
+        "ReferenceBackend"
+    }
+
+    fn sgemm(&self, params: &GemmParams, a: &[f32], b: &[f32], c: &mut [f32]) {
+        let GemmParams {
+            trans_a,
+            trans_b,
+            m,
+            n,
+            k,
+            alpha,
+            beta,
+        } = *params;
+        for i in 0..m {
+            for j in 0..n {
+                let mut acc = 0.;
+                for l in 0..k {
+                    let a_il = if trans_a { a[l * m + i] } else { a[i * k + l] };
+                    let b_lj = if trans_b { b[j * k + l] } else { b[l * n + j] };
+                    acc += a_il * b_lj;
+                }
+                // `c` is ignored when beta is zero as in BLAS, even if it has NaNs.
+                c[i * n + j] = if beta == 0. {
+                    alpha * acc
+                } else {
+                    alpha * acc + beta * c[i * n + j]
+                };
+            }
+        }
+    }
+
+    fn im2col(&self, params: &Im2ColParams, im: &[f32], col: &mut [f32]) {
+        for_each_col(params, |dst, src| col[dst] = src.map_or(0., |i| im[i]));
+    }
+
+    fn col2im(&self, params: &Im2ColParams, col: &[f32], im: &mut [f32]) {
+        for_each_col(params, |src, dst| {
+            if let Some(i) = dst {
+                im[i] += col[src];
+            }
+        });
+    }
+
+    fn im2col_nhwc(
+        &self,
+        params: &Im2ColParams,
+        batch: usize,
+        groups: usize,
+        im: &[f32],
+        col: &mut [f32],
+    ) {
+        for_each_col_nhwc(params, batch, groups, |dst, src| {
+            col[dst] = src.map_or(0., |i| im[i])
+        });
+    }
+
+    fn col2im_nhwc(
+        &self,
+        params: &Im2ColParams,
+        batch: usize,
+        groups: usize,
+        col: &[f32],
+        im: &mut [f32],
+    ) {
+        for_each_col_nhwc(params, batch, groups, |src, dst| {
+            if let Some(i) = dst {
+                im[i] += col[src];
+            }
+        });
+    }
+
+    fn im2col_nd(&self, params: &Im2ColNdParams, im: &[f32], col: &mut [f32]) {
+        for_each_col_nd(params, |dst, src| col[dst] = src.map_or(0., |i| im[i]));
+    }
+
+    fn col2im_nd(&self, params: &Im2ColNdParams, col: &[f32], im: &mut [f32]) {
+        for_each_col_nd(params, |src, dst| {
+            if let Some(i) = dst {
+                im[i] += col[src];
+            }
+        });
+    }
+
+    fn max_pool(&self, params: &MaxPoolParams, x: &[f32], y: &mut [f32], argmax: &mut [f32]) {
+        let MaxPoolParams {
+            batch,
+            channels: c,
+            height: h,
+            width: w,
+            channels_last,
+            size: (size_h, size_w),
+            stride: (stride_h, stride_w),
+            pad: (pad_top, pad_left),
+            out_size: (out_h, out_w),
+        } = *params;
+        for b in 0..batch {
+            for ch in 0..c {
+                for oi in 0..out_h {
+                    for oj in 0..out_w {
+                        let mut max = f32::MIN;
+                        let mut max_i = index4(channels_last, (c, h, w), (b, ch, 0, 0));
+                        for ki in 0..size_h {
+                            for kj in 0..size_w {
+                                let i = (oi * stride_h + ki) as isize - pad_top as isize;
+                                let j = (oj * stride_w + kj) as isize - pad_left as isize;
+                                if 0 <= i && i < h as isize && 0 <= j && j < w as isize {
+                                    let (i, j) = (i as usize, j as usize);
+                                    let idx = index4(channels_last, (c, h, w), (b, ch, i, j));
+                                    if x[idx] > max {
+                                        max = x[idx];
+                                        max_i = idx;
+                                    }
+                                }
+                            }
+                        }
+                        let dst = index4(channels_last, (c, out_h, out_w), (b, ch, oi, oj));
+                        y[dst] = max;
+                        argmax[dst] = max_i as f32;
+                    }
+                }
+            }
+        }
+    }
+
+    fn max_pool_grad(&self, gy: &[f32], argmax: &[f32], gx: &mut [f32]) {
+        for (&g, &i) in gy.iter().zip(argmax) {
+            gx[i as usize] += g;
+        }
+    }
+
+    fn max_pool_grad_grad(&self, ggx: &[f32], argmax: &[f32], ggy: &mut [f32]) {
+        for (g, &i) in ggy.iter_mut().zip(argmax) {
+            *g = ggx[i as usize];
+        }
+    }
+
+    fn avg_pool(&self, params: &AvgPoolParams, x: &[f32], y: &mut [f32]) {
+        let AvgPoolParams {
+            batch,
+            channels: c,
+            height: h,
+            width: w,
+            channels_last,
+            rows,
+            cols,
+        } = *params;
+        let (out_h, out_w) = (rows.len(), cols.len());
+        for b in 0..batch {
+            for ch in 0..c {
+                for (oi, r) in rows.iter().enumerate() {
+                    for (oj, col) in cols.iter().enumerate() {
+                        let mut sum = 0.;
+                        for i in r.begin..r.end {
+                            for j in col.begin..col.end {
+                                sum += x[index4(channels_last, (c, h, w), (b, ch, i, j))];
+                            }
+                        }
+                        let dst = index4(channels_last, (c, out_h, out_w), (b, ch, oi, oj));
+                        y[dst] = sum / (r.divisor * col.divisor) as f32;
+                    }
+                }
+            }
+        }
+    }
+
+    fn avg_pool_grad(&self, params: &AvgPoolParams, gy: &[f32], gx: &mut [f32]) {
+        let AvgPoolParams {
+            batch,
+            channels: c,
+            height: h,
+            width: w,
+            channels_last,
+            rows,
+            cols,
+        } = *params;
+        let (out_h, out_w) = (rows.len(), cols.len());
+        for b in 0..batch {
+            for ch in 0..c {
+                for (oi, r) in rows.iter().enumerate() {
+                    for (oj, col) in cols.iter().enumerate() {
+                        let src = index4(channels_last, (c, out_h, out_w), (b, ch, oi, oj));
+                        for i in r.begin..r.end {
+                            for j in col.begin..col.end {
+                                gx[index4(channels_last, (c, h, w), (b, ch, i, j))] +=
+                                    gy[src] / (r.divisor * col.divisor) as f32;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn map(&self, x: &NdArray, f: &(dyn Fn(f32) -> f32 + Sync)) -> NdArray {
+        let v = x.iter().map(|&a| f(a)).collect::<Vec<f32>>();
+        // unwrap is safe
+        NdArray::from_shape_vec(x.shape(), v).unwrap()
+    }
+}
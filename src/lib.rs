@@ -58,9 +58,10 @@
 //! // }
 //! # }
 //! ```
-#[macro_use(s)]
 extern crate ndarray;
 extern crate rand;
+#[cfg(feature = "half")]
+extern crate half;
 extern crate rayon;
 
 #[macro_use]
@@ -69,6 +70,8 @@ pub mod test_helper;
 
 pub mod tensor;
 
+pub mod backend;
+
 #[doc(hidden)]
 pub mod runtime;
 
@@ -92,4 +95,4 @@ pub use ops::gradient_descent_ops;
 #[doc(hidden)]
 pub use ndarray_ext::NdArray;
 
-pub use runtime::{eval, Eval};
+pub use runtime::{eval, is_training, set_training, Eval};
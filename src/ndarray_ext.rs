@@ -1,4 +1,5 @@
 use ndarray;
+use std::borrow::Cow;
 
 pub type NdArray = ndarray::Array<f32, ndarray::IxDyn>;
 
@@ -113,6 +114,16 @@ pub fn shape_of(x: &NdArray) -> NdArray {
     NdArray::from_shape_vec(ndarray::IxDyn(&[rank]), shape).unwrap()
 }
 
+/// Elements of `x` in the standard layout, without copy if possible.
+#[doc(hidden)]
+#[inline]
+pub fn as_contiguous<'a>(x: &'a NdArray) -> Cow<'a, [f32]> {
+    match x.as_slice() {
+        Some(x) => Cow::Borrowed(x),
+        None => Cow::Owned(x.iter().cloned().collect()),
+    }
+}
+
 #[doc(hidden)]
 #[inline]
 pub fn into_mat(x: NdArray) -> ndarray::Array<f32, ndarray::Ix2> {
@@ -123,6 +134,80 @@ pub fn into_mat(x: NdArray) -> ndarray::Array<f32, ndarray::Ix2> {
     x.into_shape(ndarray::Ix2(a, b)).unwrap()
 }
 
+/// Floating point formats of `HalfArray`.
+#[cfg(feature = "half")]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HalfType {
+    /// IEEE 754 binary16
+    F16,
+    /// bfloat16 (same exponent range as f32)
+    BF16,
+}
+
+#[cfg(feature = "half")]
+impl HalfType {
+    #[inline]
+    pub fn encode(self, a: f32) -> u16 {
+        match self {
+            HalfType::F16 => ::half::f16::from_f32(a).to_bits(),
+            HalfType::BF16 => ::half::bf16::from_f32(a).to_bits(),
+        }
+    }
+
+    #[inline]
+    pub fn decode(self, a: u16) -> f32 {
+        match self {
+            HalfType::F16 => ::half::f16::from_bits(a).to_f32(),
+            HalfType::BF16 => ::half::bf16::from_bits(a).to_f32(),
+        }
+    }
+
+    /// Rounds `a` to the nearest value representable in this format.
+    #[inline]
+    pub fn round(self, a: f32) -> f32 {
+        self.decode(self.encode(a))
+    }
+}
+
+/// Half precision storage of an `NdArray`.
+///
+/// Elements are kept as raw 16-bit patterns of `ty`, and are decoded into f32 before
+/// any computation, so that arithmetic (e.g. matmul or conv) is accumulated in f32.
+#[cfg(feature = "half")]
+#[derive(Clone, Debug)]
+pub struct HalfArray {
+    pub bits: ndarray::Array<u16, ndarray::IxDyn>,
+    pub ty: HalfType,
+}
+
+#[cfg(feature = "half")]
+impl HalfArray {
+    /// Encodes `arr` into `ty` with round-to-nearest-even.
+    pub fn from_f32(arr: &NdArray, ty: HalfType) -> HalfArray {
+        HalfArray {
+            bits: arr.map(|&a| ty.encode(a)),
+            ty,
+        }
+    }
+
+    /// Decodes this into an f32 array.
+    pub fn to_f32(&self) -> NdArray {
+        let ty = self.ty;
+        self.bits.map(|&a| ty.decode(a))
+    }
+
+    /// Overwrites this with `arr` (shapes must match).
+    pub fn assign_f32(&mut self, arr: &NdArray) {
+        let ty = self.ty;
+        self.bits.zip_mut_with(arr, |a, &b| *a = ty.encode(b));
+    }
+
+    #[inline]
+    pub fn shape(&self) -> &[usize] {
+        self.bits.shape()
+    }
+}
+
 /// Generates ndarrays which can be fed to `autograd::variable()` etc.
 pub mod array_gen {
     use super::*;
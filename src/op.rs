@@ -5,6 +5,11 @@ use tensor::Tensor;
 
 pub type ComputeResult = Vec<Result<NdArray, ComputeException>>;
 
+/// Return value of `Op::static_shape`.
+///
+/// `Err` describes why the input shapes are incompatible.
+pub type StaticShapeResult = Result<Option<Vec<isize>>, String>;
+
 #[derive(Clone, Debug)]
 /// This is an `exception`, not an error.
 pub enum ComputeException {
@@ -85,4 +90,14 @@ pub trait Op {
     /// NOTE:
     /// The number of return values must match `xs.len()`.
     fn grad(&self, gy: &Tensor, xs: &[&Tensor], y: &Tensor) -> Vec<Option<Tensor>>;
+
+    /// Infers the shape of `compute`'s (first) return value at graph build time.
+    ///
+    /// Shapes of `xs` are available via `Tensor::static_shape`; unknown dimensions are `-1`.
+    /// Returns `Ok(None)` if even the rank is unknown, which is the default.
+    /// If `xs`'s shapes are known to be incompatible, return `Err`; the graph construction
+    /// then panics with this op's name.
+    fn static_shape(&self, _xs: &[&Tensor]) -> StaticShapeResult {
+        Ok(None)
+    }
 }
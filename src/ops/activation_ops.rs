@@ -65,6 +65,10 @@ impl op::Op for Softmax {
         let sum = ops::reduce_sum(&(output * gy), &[self.axis], true);
         vec![Some((gy - sum) * output)]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape())
+    }
 }
 
 impl op::Op for Softplus {
@@ -75,7 +79,8 @@ impl op::Op for Softplus {
     fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
         let xs = ctx.grab_inputs();
         let e = f32::consts::E;
-        vec![Ok(xs[0].mapv(move |a| (a.exp() + 1.).log(e)))]
+        let y = ctx.backend().map(xs[0], &move |a| (a.exp() + 1.).log(e));
+        vec![Ok(y)]
     }
 
     fn grad(&self, gy: &Tensor, xs: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
@@ -84,6 +89,10 @@ impl op::Op for Softplus {
         let gx = gy * (a / b);
         vec![Some(gx)]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape())
+    }
 }
 
 impl op::Op for Sigmoid {
@@ -93,12 +102,17 @@ impl op::Op for Sigmoid {
 
     fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
         let x = ctx.grab_inputs()[0];
-        vec![Ok(x.mapv(|a| ((a * 0.5).tanh() * 0.5) + 0.5))]
+        let y = ctx.backend().map(x, &|a| ((a * 0.5).tanh() * 0.5) + 0.5);
+        vec![Ok(y)]
     }
 
     fn grad(&self, gy: &Tensor, _: &[&Tensor], y: &Tensor) -> Vec<Option<Tensor>> {
         vec![Some(gy * (y - ops::square(y)))]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape())
+    }
 }
 
 impl op::Op for ReLU {
@@ -108,7 +122,7 @@ impl op::Op for ReLU {
 
     fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
         let x = ctx.grab_inputs()[0];
-        vec![Ok(x.map(|a| a.max(0.)))]
+        vec![Ok(ctx.backend().map(x, &|a| a.max(0.)))]
     }
 
     fn grad(&self, gy: &Tensor, inputs: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
@@ -117,6 +131,10 @@ impl op::Op for ReLU {
         // (`mul_inplace` returns `None` as an input gradient.)
         vec![Some(ops::mul_inplace(bin, gy))]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape())
+    }
 }
 
 impl op::Op for Identity {
@@ -133,6 +151,10 @@ impl op::Op for Identity {
         // use gy's array with rc increment.
         vec![Some(gy.clone())]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape())
+    }
 }
 
 impl op::Op for ELU {
@@ -142,7 +164,7 @@ impl op::Op for ELU {
 
     fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
         let x = ctx.grab_inputs()[0];
-        let ret = x.mapv(move |a| {
+        let ret = ctx.backend().map(x, &move |a| {
             if a > 0. {
                 a
             } else {
@@ -159,6 +181,10 @@ impl op::Op for ELU {
             .build(ELUGrad { alpha: self.alpha });
         vec![Some(gx)]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape())
+    }
 }
 
 impl op::Op for ELUGrad {
@@ -170,7 +196,7 @@ impl op::Op for ELUGrad {
         let xs = ctx.grab_inputs();
         let x = xs[0];
         let gy = xs[1];
-        let a = x.mapv(move |a| {
+        let a = ctx.backend().map(x, &move |a| {
             if a > 0. {
                 1.
             } else {
@@ -183,4 +209,8 @@ impl op::Op for ELUGrad {
     fn grad(&self, _: &Tensor, _: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
         vec![None, None]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape())
+    }
 }
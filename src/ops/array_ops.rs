@@ -66,6 +66,80 @@ pub struct GatherGrad {
     pub axis: isize,
 }
 
+/// Writes `updates` into the subviews of `x` picked by `indices` along `axis`;
+/// the inverse of `Gather`.
+///
+/// Inputs are `x`, `indices` and `updates`, where `updates` has the shape of
+/// `x.shape[..axis] + indices.shape + x.shape[axis+1..]`.
+/// If `accumulate` is true, `updates` are added to `x` (duplicate indices are summed up),
+/// otherwise they replace `x` (the last one wins for duplicate indices).
+pub struct Scatter {
+    pub axis: isize,
+    pub accumulate: bool,
+}
+
+// Gradient of `updates` of `Scatter` without `accumulate`, which is `gather` of `gy`
+// except for the updates overwritten by later duplicates.
+pub struct ScatterGrad {
+    pub axis: isize,
+}
+
+/// Gathers slices of `params` at the coordinates in the last axis of `indices`.
+///
+/// Inputs are `params` and `indices`.
+pub struct GatherNd;
+
+/// Adds `updates` into zeros of `shape` at the coordinates in the last axis of `indices`;
+/// the inverse of `GatherNd`.
+///
+/// Inputs are `indices`, `updates` and `shape`.
+pub struct ScatterNd;
+
+/// Picks `x` (2nd input) at `indices` (1st input) along `axis`, lane by lane:
+/// `y[.., j, ..] = x[.., indices[.., j, ..], ..]`.
+///
+/// `indices` has the shape of `x` except along `axis`.
+pub struct TakeAlongAxis {
+    pub axis: isize,
+}
+
+/// Transpose of `TakeAlongAxis`: adds `updates` (2nd input) into zeros shaped like
+/// `x` (3rd input) at `indices` (1st input) along `axis`.
+pub struct PutAlongAxis {
+    pub axis: isize,
+}
+
+/// How `ag::pad` fills the padded elements.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PadMode {
+    /// Fills with the value.
+    Constant(f32),
+    /// Mirrors the elements without repeating the edge: `[1, 2, 3]` → `[3, 2, 1, 2, 3, 2, 1]`.
+    Reflect,
+    /// Repeats the edge element: `[1, 2, 3]` → `[1, 1, 1, 2, 3, 3, 3]`.
+    Replicate,
+}
+
+/// Pads each axis of `x` with `pads[axis] = (before, after)` elements.
+pub struct Pad {
+    pub pads: Vec<(usize, usize)>,
+    pub mode: PadMode,
+}
+
+// Takes `x` as the second input to know the shape of gx.
+pub struct PadGrad {
+    pads: Vec<(usize, usize)>,
+    mode: PadMode,
+}
+
+/// Coordinates of the nonzero elements of `mask` (1st input) as a
+/// `(num_nonzero, mask.ndim)` tensor, for `GatherNd` of `x` (2nd input).
+pub struct MaskIndices;
+
+/// Concatenates pairs of `(indices, values)` of `IndexedSlices` into
+/// flat indices (1st output) and values (2nd output).
+pub struct ConcatIndexedSlices;
+
 pub struct IndexOp {
     pub index: isize,
 }
@@ -137,6 +211,10 @@ impl op::Op for Shape {
     fn grad(&self, _: &Tensor, _: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
         vec![None]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape().map(|s| vec![s.len() as isize]))
+    }
 }
 
 impl op::Op for Rank {
@@ -153,6 +231,10 @@ impl op::Op for Rank {
     fn grad(&self, _: &Tensor, _: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
         vec![None]
     }
+
+    fn static_shape(&self, _: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(Some(vec![]))
+    }
 }
 
 impl op::Op for Size {
@@ -169,6 +251,10 @@ impl op::Op for Size {
     fn grad(&self, _: &Tensor, _: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
         vec![None]
     }
+
+    fn static_shape(&self, _: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(Some(vec![]))
+    }
 }
 
 impl op::Op for Reshape {
@@ -178,7 +264,12 @@ impl op::Op for Reshape {
 
     fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
         let xs = ctx.grab_inputs();
-        let ret = xs[0].clone();
+        // `into_shape` reads a column-major array (e.g. transposed) in column-major order.
+        let ret = if xs[0].is_standard_layout() {
+            xs[0].clone()
+        } else {
+            NdArray::from_shape_fn(xs[0].shape(), |i| xs[0][i])
+        };
         let shape_arr: &NdArray = xs[1];
         let target = shape_arr
             .iter()
@@ -238,6 +329,10 @@ impl op::Op for SetDiff1D {
     fn grad(&self, _: &Tensor, _: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
         vec![None, None]
     }
+
+    fn static_shape(&self, _: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(Some(vec![-1]))
+    }
 }
 
 impl op::Op for IndexOp {
@@ -271,6 +366,10 @@ impl op::Op for IndexOp {
             .build(op);
         vec![Some(gx)]
     }
+
+    fn static_shape(&self, _: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(Some(vec![]))
+    }
 }
 
 impl op::Op for IndexOpGrad {
@@ -306,6 +405,10 @@ impl op::Op for IndexOpGrad {
     fn grad(&self, _: &Tensor, _: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
         vec![None]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape())
+    }
 }
 
 impl op::Op for Gather {
@@ -343,11 +446,40 @@ impl op::Op for Gather {
     }
 
     fn grad(&self, gy: &Tensor, inputs: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
-        let gx = Tensor::builder()
+        let mut gx = Tensor::builder()
             .set_shape(inputs[0].shape())
-            .set_inputs(vec![inputs[0], inputs[1], gy])
-            .build(GatherGrad { axis: self.axis });
-        vec![None, Some(gx)]
+            .set_inputs(vec![inputs[0], inputs[1], gy]);
+        if self.axis == 0 {
+            // Rows of `param` picked by `indices` have `gy`.
+            gx = gx.set_indexed_slices(::tensor::IndexedSlices {
+                indices: inputs[0].clone(),
+                values: gy.clone(),
+            });
+        }
+        vec![None, Some(gx.build(GatherGrad { axis: self.axis }))]
+    }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        let (indices_shape, param_shape) = match (xs[0].static_shape(), xs[1].static_shape()) {
+            (Some(a), Some(b)) => (a, b),
+            _ => return Ok(None),
+        };
+        let axis = ndarray_ext::normalize_negative_axis(self.axis, param_shape.len());
+        if axis >= param_shape.len() {
+            return Err(format!(
+                "Axis {} is out of range for {:?}",
+                self.axis, param_shape
+            ));
+        }
+        // former + indices_shape + latter
+        Ok(Some(
+            param_shape[..axis]
+                .iter()
+                .chain(&indices_shape)
+                .chain(&param_shape[axis + 1..])
+                .cloned()
+                .collect(),
+        ))
     }
 }
 
@@ -413,6 +545,602 @@ impl op::Op for GatherGrad {
     fn grad(&self, _: &Tensor, _: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
         vec![None, None, None]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[1].static_shape())
+    }
+}
+
+/// Checks and converts `indices` to be used along an axis of `size`.
+fn to_axis_indices(indices: &NdArray, size: usize, name: &str) -> Vec<usize> {
+    indices
+        .iter()
+        .map(|&i| {
+            assert!(
+                0. <= i && (i as usize) < size,
+                "ag::{}: Index {} is out of range for an axis of size {}",
+                name,
+                i,
+                size
+            );
+            i as usize
+        })
+        .collect()
+}
+
+/// `(outer, axis size, inner)` of `shape`.
+fn split_shape(shape: &[usize], axis: usize) -> (usize, usize, usize) {
+    (
+        shape[..axis].iter().product(),
+        shape[axis],
+        shape[axis + 1..].iter().product(),
+    )
+}
+
+/// `shape[..axis] + indices.shape + shape[axis+1..]`
+fn gathered_shape(shape: &[usize], indices: &[usize], axis: usize) -> Vec<usize> {
+    shape[..axis]
+        .iter()
+        .chain(indices)
+        .chain(&shape[axis + 1..])
+        .cloned()
+        .collect()
+}
+
+impl op::Op for Scatter {
+    fn name(&self) -> &str {
+        "Scatter"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        let xs = ctx.grab_inputs();
+        let name = if self.accumulate {
+            "scatter_add"
+        } else {
+            "scatter"
+        };
+        let (x, indices, updates) = (xs[0], xs[1], xs[2]);
+        let axis = ndarray_ext::normalize_negative_axis(self.axis, x.ndim());
+        assert!(
+            axis < x.ndim(),
+            "ag::{}: Invalid axis {} for {:?}",
+            name,
+            self.axis,
+            x.shape()
+        );
+        let expected = gathered_shape(x.shape(), indices.shape(), axis);
+        assert_eq!(
+            updates.shape(),
+            expected.as_slice(),
+            "ag::{}: updates must have shape {:?}",
+            name,
+            expected
+        );
+        let (outer, n, inner) = split_shape(x.shape(), axis);
+        let indices = to_axis_indices(indices, n, name);
+        let m = indices.len();
+
+        let mut y = ndarray_ext::as_contiguous(x).into_owned();
+        let updates = ndarray_ext::as_contiguous(updates);
+        for o in 0..outer {
+            for (j, &i) in indices.iter().enumerate() {
+                let src = &updates[(o * m + j) * inner..(o * m + j + 1) * inner];
+                let dst = &mut y[(o * n + i) * inner..(o * n + i + 1) * inner];
+                if self.accumulate {
+                    dst.iter_mut().zip(src).for_each(|(d, s)| *d += s);
+                } else {
+                    dst.copy_from_slice(src);
+                }
+            }
+        }
+        vec![Ok(NdArray::from_shape_vec(x.shape(), y).unwrap())]
+    }
+
+    fn grad(&self, gy: &Tensor, xs: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
+        let indices = xs[1];
+        if self.accumulate {
+            let gu = Tensor::builder()
+                .set_inputs(vec![indices, gy])
+                .build(Gather {
+                    axis: self.axis,
+                    should_normalize_negative_indices: false,
+                });
+            vec![Some(gy.clone()), None, Some(gu)]
+        } else {
+            // Overwritten elements of `x` don't affect `y`.
+            let ref zeros = ops::zeros(&ops::shape(xs[2]));
+            let gx = Tensor::builder()
+                .set_inputs(vec![gy, indices, zeros])
+                .build(Scatter {
+                    axis: self.axis,
+                    accumulate: false,
+                });
+            let gu = Tensor::builder()
+                .set_inputs(vec![gy, indices])
+                .build(ScatterGrad { axis: self.axis });
+            vec![Some(gx), None, Some(gu)]
+        }
+    }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape())
+    }
+}
+
+impl op::Op for ScatterGrad {
+    fn name(&self) -> &str {
+        "ScatterGrad"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        let xs = ctx.grab_inputs();
+        let (gy, indices) = (xs[0], xs[1]);
+        let axis = ndarray_ext::normalize_negative_axis(self.axis, gy.ndim());
+        let (outer, n, inner) = split_shape(gy.shape(), axis);
+        let shape = gathered_shape(gy.shape(), indices.shape(), axis);
+        let indices = to_axis_indices(indices, n, "scatter");
+        let m = indices.len();
+
+        // The update that each index ends up with
+        let mut winners = vec![m; n];
+        for (j, &i) in indices.iter().enumerate() {
+            winners[i] = j;
+        }
+        let gy = ndarray_ext::as_contiguous(gy);
+        let mut gu = vec![0.; outer * m * inner];
+        for o in 0..outer {
+            for (j, &i) in indices.iter().enumerate() {
+                if winners[i] == j {
+                    gu[(o * m + j) * inner..(o * m + j + 1) * inner]
+                        .copy_from_slice(&gy[(o * n + i) * inner..(o * n + i + 1) * inner]);
+                }
+            }
+        }
+        vec![Ok(NdArray::from_shape_vec(shape, gu).unwrap())]
+    }
+
+    fn grad(&self, _: &Tensor, _: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
+        vec![None, None]
+    }
+}
+
+/// Flat offsets of the slices of `shape` that the last axis of `indices` points to,
+/// and the size of each slice.
+fn nd_offsets(shape: &[usize], indices: &NdArray, name: &str) -> (Vec<usize>, usize) {
+    let k = *indices.shape().last().unwrap_or(&0);
+    assert!(
+        indices.ndim() > 0 && k <= shape.len(),
+        "ag::{}: Last axis of indices {:?} must be at most the rank of {:?}",
+        name,
+        indices.shape(),
+        shape
+    );
+    let slice_size = shape[k..].iter().product::<usize>();
+    let indices = ndarray_ext::as_contiguous(indices);
+    let offsets = if k == 0 {
+        vec![0; indices.len()]
+    } else {
+        indices
+            .chunks(k)
+            .map(|coord| {
+                coord.iter().zip(&shape[..k]).fold(0, |acc, (&i, &size)| {
+                    assert!(
+                        0. <= i && (i as usize) < size,
+                        "ag::{}: Index {:?} is out of range for {:?}",
+                        name,
+                        coord,
+                        shape
+                    );
+                    acc * size + i as usize
+                }) * slice_size
+            })
+            .collect()
+    };
+    (offsets, slice_size)
+}
+
+impl op::Op for GatherNd {
+    fn name(&self) -> &str {
+        "GatherNd"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        let xs = ctx.grab_inputs();
+        let (params, indices) = (xs[0], xs[1]);
+        let (offsets, slice_size) = nd_offsets(params.shape(), indices, "gather_nd");
+        let params_ = ndarray_ext::as_contiguous(params);
+        let mut y = Vec::with_capacity(offsets.len() * slice_size);
+        for &offset in &offsets {
+            y.extend_from_slice(&params_[offset..offset + slice_size]);
+        }
+        let k = indices.shape()[indices.ndim() - 1];
+        let shape = indices.shape()[..indices.ndim() - 1]
+            .iter()
+            .chain(&params.shape()[k..])
+            .cloned()
+            .collect::<Vec<_>>();
+        vec![Ok(NdArray::from_shape_vec(shape, y).unwrap())]
+    }
+
+    fn grad(&self, gy: &Tensor, xs: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
+        let gx = Tensor::builder()
+            .set_inputs(vec![xs[1], gy, &ops::shape(xs[0])])
+            .build(ScatterNd);
+        vec![Some(gx), None]
+    }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        let (params_shape, indices_shape) = match (xs[0].static_shape(), xs[1].static_shape()) {
+            (Some(a), Some(b)) => (a, b),
+            _ => return Ok(None),
+        };
+        match indices_shape.split_last() {
+            Some((&k, outer)) if k >= 0 && k as usize <= params_shape.len() => Ok(Some(
+                outer
+                    .iter()
+                    .chain(&params_shape[k as usize..])
+                    .cloned()
+                    .collect(),
+            )),
+            Some((&k, _)) if k < 0 => Ok(None),
+            _ => Err(format!(
+                "Invalid indices {:?} for {:?}",
+                indices_shape, params_shape
+            )),
+        }
+    }
+}
+
+impl op::Op for ScatterNd {
+    fn name(&self) -> &str {
+        "ScatterNd"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        let xs = ctx.grab_inputs();
+        let (indices, updates) = (xs[0], xs[1]);
+        let shape = ndarray_ext::arr_to_shape(xs[2]);
+        let (offsets, slice_size) = nd_offsets(&shape, indices, "scatter_nd");
+        assert_eq!(
+            updates.len(),
+            offsets.len() * slice_size,
+            "ag::scatter_nd: updates {:?} must have {} slices of {:?}",
+            updates.shape(),
+            offsets.len(),
+            &shape[indices.shape()[indices.ndim() - 1]..]
+        );
+        let updates = ndarray_ext::as_contiguous(updates);
+        let mut y = vec![0.; shape.iter().product()];
+        for (&offset, u) in offsets.iter().zip(updates.chunks(slice_size.max(1))) {
+            y[offset..offset + slice_size]
+                .iter_mut()
+                .zip(u)
+                .for_each(|(y, u)| *y += u);
+        }
+        vec![Ok(NdArray::from_shape_vec(shape, y).unwrap())]
+    }
+
+    fn grad(&self, gy: &Tensor, xs: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
+        let gu = Tensor::builder()
+            .set_inputs(vec![gy, xs[0]])
+            .build(GatherNd);
+        // `updates` may have been given in any shape with the same elements.
+        vec![None, Some(ops::reshape(gu, &ops::shape(xs[1]))), None]
+    }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(ops::const_gen_ops::static_shape_from_shape_tensor(xs[2]))
+    }
+}
+
+impl PadMode {
+    /// Index of `x` that the `i`th element of an axis of length `len` padded by `before`
+    /// comes from, or `None` for a constant.
+    fn source(&self, i: usize, before: usize, len: usize) -> Option<usize> {
+        let i = i as isize - before as isize;
+        let len = len as isize;
+        if 0 <= i && i < len {
+            return Some(i as usize);
+        }
+        match *self {
+            PadMode::Constant(_) => None,
+            PadMode::Replicate => Some(if i < 0 { 0 } else { len as usize - 1 }),
+            PadMode::Reflect if len == 1 => Some(0),
+            PadMode::Reflect => {
+                // Reflects repeatedly if the pad is longer than the axis.
+                let period = 2 * (len - 1);
+                let i = ((i % period) + period) % period;
+                Some(if i < len { i } else { period - i } as usize)
+            }
+        }
+    }
+}
+
+/// Source indices of each axis of `x` padded by `pads`.
+fn pad_sources(
+    x_shape: &[usize],
+    pads: &[(usize, usize)],
+    mode: PadMode,
+) -> Vec<Vec<Option<usize>>> {
+    assert_eq!(
+        x_shape.len(),
+        pads.len(),
+        "ag::pad: Number of pads ({}) must be the rank of the input {:?}",
+        pads.len(),
+        x_shape
+    );
+    x_shape
+        .iter()
+        .zip(pads)
+        .map(|(&len, &(before, after))| {
+            assert!(
+                len > 0
+                    || match mode {
+                        PadMode::Constant(_) => true,
+                        _ => before + after == 0,
+                    },
+                "ag::pad: Can't pad an empty axis by {:?}",
+                mode
+            );
+            (0..before + len + after)
+                .map(|i| mode.source(i, before, len))
+                .collect()
+        })
+        .collect()
+}
+
+/// Source index of `x` for the index `i` of the padded array.
+#[inline]
+fn pad_source(sources: &[Vec<Option<usize>>], i: &ndarray::IxDyn, buf: &mut Vec<usize>) -> bool {
+    buf.clear();
+    for (axis, src) in sources.iter().enumerate() {
+        match src[i[axis]] {
+            Some(j) => buf.push(j),
+            None => return false,
+        }
+    }
+    true
+}
+
+impl op::Op for Pad {
+    fn name(&self) -> &str {
+        "Pad"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        let x = ctx.grab_inputs()[0];
+        let sources = pad_sources(x.shape(), &self.pads, self.mode);
+        let value = match self.mode {
+            PadMode::Constant(a) => a,
+            _ => 0.,
+        };
+        let shape = sources.iter().map(|s| s.len()).collect::<Vec<_>>();
+        let mut buf = Vec::with_capacity(shape.len());
+        let y = NdArray::from_shape_fn(ndarray::IxDyn(&shape), |i| {
+            if pad_source(&sources, &i, &mut buf) {
+                x[ndarray::IxDyn(&buf)]
+            } else {
+                value
+            }
+        });
+        vec![Ok(y)]
+    }
+
+    fn grad(&self, gy: &Tensor, xs: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
+        let gx = Tensor::builder()
+            .set_inputs(vec![gy, xs[0]])
+            .set_shape(xs[0].shape())
+            .build(PadGrad {
+                pads: self.pads.clone(),
+                mode: self.mode,
+            });
+        vec![Some(gx)]
+    }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        let x_shape = match xs[0].static_shape() {
+            Some(a) => a,
+            None => return Ok(None),
+        };
+        if x_shape.len() != self.pads.len() {
+            return Err(format!(
+                "Number of pads ({}) must be the rank of {:?}",
+                self.pads.len(),
+                x_shape
+            ));
+        }
+        Ok(Some(
+            x_shape
+                .iter()
+                .zip(&self.pads)
+                .map(|(&a, &(b, c))| if a == -1 { -1 } else { a + (b + c) as isize })
+                .collect(),
+        ))
+    }
+}
+
+impl op::Op for PadGrad {
+    fn name(&self) -> &str {
+        "PadGrad"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        let xs = ctx.grab_inputs();
+        let (gy, x) = (xs[0], xs[1]);
+        let sources = pad_sources(x.shape(), &self.pads, self.mode);
+        let mut buf = Vec::with_capacity(x.ndim());
+        let mut gx = NdArray::zeros(x.shape());
+        // Accumulates gy of the elements copied from each element of x.
+        for (i, &g) in gy.indexed_iter() {
+            if pad_source(&sources, &i, &mut buf) {
+                gx[ndarray::IxDyn(&buf)] += g;
+            }
+        }
+        vec![Ok(gx)]
+    }
+
+    fn grad(&self, ggx: &Tensor, _: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
+        // Padding is linear, so this is the transpose of the transpose.
+        let mode = match self.mode {
+            PadMode::Constant(_) => PadMode::Constant(0.),
+            mode => mode,
+        };
+        let ggy = Tensor::builder().set_input(ggx).build(Pad {
+            pads: self.pads.clone(),
+            mode,
+        });
+        vec![Some(ggy), None]
+    }
+}
+
+impl op::Op for TakeAlongAxis {
+    fn name(&self) -> &str {
+        "TakeAlongAxis"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        let xs = ctx.grab_inputs();
+        let (indices, x) = (xs[0], xs[1]);
+        let axis = ndarray::Axis(ndarray_ext::normalize_negative_axis(self.axis, x.ndim()));
+        let mut y = NdArray::zeros(indices.shape());
+        for ((i, x), mut y) in indices
+            .lanes(axis)
+            .into_iter()
+            .zip(x.lanes(axis))
+            .zip(y.lanes_mut(axis))
+        {
+            for (y, &i) in y.iter_mut().zip(i) {
+                *y = x[i as usize];
+            }
+        }
+        vec![Ok(y)]
+    }
+
+    fn grad(&self, gy: &Tensor, xs: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
+        let gx = Tensor::builder()
+            .set_inputs(vec![xs[0], gy, xs[1]])
+            .set_shape(xs[1].shape())
+            .build(PutAlongAxis { axis: self.axis });
+        vec![None, Some(gx)]
+    }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape())
+    }
+}
+
+impl op::Op for PutAlongAxis {
+    fn name(&self) -> &str {
+        "PutAlongAxis"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        let xs = ctx.grab_inputs();
+        let (indices, updates, x) = (xs[0], xs[1], xs[2]);
+        let axis = ndarray::Axis(ndarray_ext::normalize_negative_axis(self.axis, x.ndim()));
+        let mut y = NdArray::zeros(x.shape());
+        for ((i, u), mut y) in indices
+            .lanes(axis)
+            .into_iter()
+            .zip(updates.lanes(axis))
+            .zip(y.lanes_mut(axis))
+        {
+            for (&u, &i) in u.iter().zip(i) {
+                y[i as usize] += u;
+            }
+        }
+        vec![Ok(y)]
+    }
+
+    fn grad(&self, gy: &Tensor, xs: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
+        let gu = Tensor::builder()
+            .set_inputs(vec![xs[0], gy])
+            .set_shape(xs[0].shape())
+            .build(TakeAlongAxis { axis: self.axis });
+        vec![None, Some(gu), None]
+    }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[2].static_shape())
+    }
+}
+
+impl op::Op for MaskIndices {
+    fn name(&self) -> &str {
+        "MaskIndices"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        use ndarray::Dimension;
+        let xs = ctx.grab_inputs();
+        let (mask, x) = (xs[0], xs[1]);
+        assert!(
+            mask.ndim() > 0 && x.shape().starts_with(mask.shape()),
+            "ag::boolean_mask: Shape of mask {:?} must be a prefix of {:?}",
+            mask.shape(),
+            x.shape()
+        );
+        let mut coords = Vec::new();
+        let mut n = 0;
+        for (i, &m) in mask.indexed_iter() {
+            if m != 0. {
+                coords.extend(i.slice().iter().map(|&a| a as f32));
+                n += 1;
+            }
+        }
+        let shape = ndarray::IxDyn(&[n, mask.ndim()]);
+        vec![Ok(NdArray::from_shape_vec(shape, coords).unwrap())]
+    }
+
+    fn grad(&self, _: &Tensor, _: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
+        vec![None, None]
+    }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape().map(|s| vec![-1, s.len() as isize]))
+    }
+}
+
+impl op::Op for ConcatIndexedSlices {
+    fn name(&self) -> &str {
+        "ConcatIndexedSlices"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        let xs = ctx.grab_inputs();
+        let (indices0, values0) = (xs[0], xs[1]);
+        let row_shape = &values0.shape()[indices0.ndim()..];
+        let row_len = row_shape.iter().product::<usize>();
+        let mut indices = Vec::new();
+        let mut values = Vec::new();
+        for pair in xs.chunks(2) {
+            let (i, v) = (pair[0], pair[1]);
+            assert_eq!(
+                v.len(),
+                i.len() * row_len,
+                "ConcatIndexedSlices: Row shapes mismatch: {:?} vs {:?}",
+                values0.shape(),
+                v.shape()
+            );
+            indices.extend(i.iter());
+            values.extend(v.iter());
+        }
+        let num_rows = indices.len();
+        let values_shape = [num_rows]
+            .iter()
+            .chain(row_shape)
+            .cloned()
+            .collect::<Vec<_>>();
+        // unwrap is safe
+        vec![
+            Ok(NdArray::from_shape_vec(ndarray::IxDyn(&[num_rows]), indices).unwrap()),
+            Ok(NdArray::from_shape_vec(values_shape, values).unwrap()),
+        ]
+    }
+
+    fn grad(&self, _: &Tensor, inputs: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
+        vec![None; inputs.len()]
+    }
 }
 
 impl op::Op for AddN {
@@ -443,6 +1171,20 @@ impl op::Op for AddN {
             .map(|_| Some(gy.clone()))
             .collect::<Vec<Option<_>>>()
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        let mut ret = match xs[0].static_shape() {
+            Some(a) => a,
+            None => return Ok(None),
+        };
+        for x in xs[1..].iter() {
+            match x.static_shape() {
+                Some(a) => ret = ops::binary_ops::broadcast_static_shapes(ret, a)?,
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(ret))
+    }
 }
 
 impl op::Op for Clip {
@@ -465,6 +1207,10 @@ impl op::Op for Clip {
             });
         vec![Some(gx)]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape())
+    }
 }
 
 impl op::Op for ClipGrad {
@@ -532,6 +1278,46 @@ impl op::Op for Concat {
             .collect::<Vec<Option<Tensor>>>();
         gxs
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        if xs.is_empty() {
+            return Ok(None);
+        }
+        let mut shapes = Vec::with_capacity(xs.len());
+        for x in xs.iter() {
+            match x.static_shape() {
+                Some(a) => shapes.push(a),
+                None => return Ok(None),
+            }
+        }
+        let mut ret = shapes[0].clone();
+        let rank = ret.len();
+        let axis = ndarray_ext::normalize_negative_axis(self.axis, rank);
+        if axis >= rank {
+            return Err(format!("Axis {} is out of range for {:?}", self.axis, ret));
+        }
+        for shape in shapes[1..].iter() {
+            if shape.len() != rank {
+                return Err(format!(
+                    "Can't concat tensors whose ranks differ: {:?} vs {:?}",
+                    shapes[0], shape
+                ));
+            }
+            for (i, (a, &b)) in ret.iter_mut().zip(shape).enumerate() {
+                if i == axis {
+                    *a = if *a == -1 || b == -1 { -1 } else { *a + b };
+                } else if *a == -1 {
+                    *a = b;
+                } else if b != -1 && *a != b {
+                    return Err(format!(
+                        "Can't concat {:?} and {:?} along axis {}",
+                        shapes[0], shape, self.axis
+                    ));
+                }
+            }
+        }
+        Ok(Some(ret))
+    }
 }
 
 impl op::Op for ConcatGrad {
@@ -560,7 +1346,7 @@ impl op::Op for ConcatGrad {
             .map(move |_axis| {
                 if _axis == axis {
                     // partial region
-                    ndarray::Si(start_idx as isize, Some(region_len), 1)
+                    ndarray::Si(start_idx as isize, Some(start_idx as isize + region_len), 1)
                 } else {
                     // full slice
                     ndarray::Si(0, None, 1)
@@ -575,6 +1361,11 @@ impl op::Op for ConcatGrad {
     fn grad(&self, _: &Tensor, inputs: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
         (0..inputs.len()).map(|_| None).collect::<Vec<_>>()
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        // xs: [gy, x1, x2, ...]
+        Ok(xs[self.index + 1].static_shape())
+    }
 }
 
 impl op::Op for Tile {
@@ -607,6 +1398,29 @@ impl op::Op for Tile {
     fn grad(&self, gy: &Tensor, _: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
         vec![Some(ops::reduce_sum(gy, &[self.axis], true))]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        let mut shape = match xs[0].static_shape() {
+            Some(a) => a,
+            None => return Ok(None),
+        };
+        // same as `compute`
+        let axis = if self.axis >= 0 {
+            self.axis as usize
+        } else {
+            shape.len().wrapping_sub(1)
+        };
+        if axis >= shape.len() {
+            return Err(format!(
+                "Axis {} is out of range for {:?}",
+                self.axis, shape
+            ));
+        }
+        if shape[axis] != -1 {
+            shape[axis] *= self.num as isize;
+        }
+        Ok(Some(shape))
+    }
 }
 
 impl op::Op for Split {
@@ -642,6 +1456,22 @@ impl op::Op for Split {
             .build(op);
         vec![Some(gx)]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        let mut shape = match xs[0].static_shape() {
+            Some(a) => a,
+            None => return Ok(None),
+        };
+        let axis = ndarray_ext::normalize_negative_axis(self.axis, shape.len());
+        if axis >= shape.len() {
+            return Err(format!(
+                "Axis {} is out of range for {:?}",
+                self.axis, shape
+            ));
+        }
+        shape[axis] = self.sizes[self.index] as isize;
+        Ok(Some(shape))
+    }
 }
 
 impl op::Op for SplitGrad {
@@ -673,6 +1503,25 @@ impl op::Op for SplitGrad {
     fn grad(&self, _: &Tensor, _: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
         vec![None]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape())
+    }
+}
+
+// Merges two static shapes of tensors which must have the same shape.
+pub fn merge_static_shapes(shape0: Vec<isize>, shape1: Vec<isize>) -> Result<Vec<isize>, String> {
+    if shape0.len() != shape1.len() {
+        return Err(format!("Shapes mismatch: {:?} vs {:?}", shape0, shape1));
+    }
+    let mut ret = Vec::with_capacity(shape0.len());
+    for (&a, &b) in shape0.iter().zip(&shape1) {
+        if a != -1 && b != -1 && a != b {
+            return Err(format!("Shapes mismatch: {:?} vs {:?}", shape0, shape1));
+        }
+        ret.push(if a == -1 { b } else { a });
+    }
+    Ok(ret)
 }
 
 #[inline]
@@ -746,6 +1595,10 @@ impl op::Op for SliceGrad {
         // is this ok?
         vec![None, None]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape())
+    }
 }
 impl op::Op for Squeeze {
     fn name(&self) -> &str {
@@ -23,3 +23,25 @@ macro_rules! impl_op {
 impl_op!(Variable);
 impl_op!(Const);
 impl_op!(Placeholder);
+
+/// Source op of `ag::variable_half`: decodes the half precision storage into f32.
+///
+/// The storage stays in 16 bits; the decoded array lives only during an evaluation.
+#[cfg(feature = "half")]
+pub struct HalfVariable;
+
+#[cfg(feature = "half")]
+impl ::op::Op for HalfVariable {
+    fn name(&self) -> &str {
+        "HalfVariable"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        // unwrap is safe
+        vec![Ok(ctx.get_node().get_half_array().unwrap().to_f32())]
+    }
+
+    fn grad(&self, _: &Tensor, _: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
+        vec![]
+    }
+}
@@ -151,6 +151,10 @@ impl op::Op for AddOp {
         let (gy1, gy2) = preprocess_gy(inputs[0], inputs[1], gy);
         vec![Some(gy1), Some(gy2)]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        infer_bin_op_static_shape(xs)
+    }
 }
 
 impl op::Op for SubOp {
@@ -177,6 +181,10 @@ impl op::Op for SubOp {
         let (gy1, gy2) = preprocess_gy(inputs[0], inputs[1], gy);
         vec![Some(gy1), Some(ops::neg(&gy2))]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        infer_bin_op_static_shape(xs)
+    }
 }
 
 impl op::Op for MulOp {
@@ -195,6 +203,10 @@ impl op::Op for MulOp {
         let (gy1, gy2) = preprocess_gy(x0, x1, gy);
         vec![Some(gy1 * x1), Some(gy2 * x0)]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        infer_bin_op_static_shape(xs)
+    }
 }
 
 impl op::Op for DivOp {
@@ -230,6 +242,10 @@ impl op::Op for DivOp {
         let (gy1, gy2) = preprocess_gy(x0, x1, gy);
         vec![Some(gy1 / x1), Some(ops::neg(x0) * ops::pow(x1, -2.) * gy2)]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        infer_bin_op_static_shape(xs)
+    }
 }
 
 impl op::Op for InplaceAddOp {
@@ -249,6 +265,10 @@ impl op::Op for InplaceAddOp {
         let (gy1, gy2) = preprocess_gy(inputs[0], inputs[1], gy);
         vec![Some(gy1), Some(gy2)]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape())
+    }
 }
 
 impl op::Op for InplaceSubOp {
@@ -268,6 +288,10 @@ impl op::Op for InplaceSubOp {
         let (gy1, gy2) = preprocess_gy(inputs[0], inputs[1], gy);
         vec![Some(gy1), Some(ops::neg(&gy2))]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape())
+    }
 }
 
 impl op::Op for InplaceMulOp {
@@ -286,6 +310,10 @@ impl op::Op for InplaceMulOp {
     fn grad(&self, _: &Tensor, _: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
         vec![None, None]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape())
+    }
 }
 
 impl op::Op for InplaceDivOp {
@@ -304,6 +332,62 @@ impl op::Op for InplaceDivOp {
     fn grad(&self, _: &Tensor, _: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
         vec![None, None]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape())
+    }
+}
+
+// Infers the static shape of the result of a broadcasting binary op.
+pub fn infer_bin_op_static_shape(xs: &[&Tensor]) -> op::StaticShapeResult {
+    match (xs[0].static_shape(), xs[1].static_shape()) {
+        (Some(a), Some(b)) => broadcast_static_shapes(a, b).map(Some),
+        _ => Ok(None),
+    }
+}
+
+// Broadcasts two static shapes with each other. Unknown dims are `-1`.
+pub fn broadcast_static_shapes(
+    shape0: Vec<isize>,
+    shape1: Vec<isize>,
+) -> Result<Vec<isize>, String> {
+    // Same as `compute`s, [] and [0] are treated as scalars.
+    let is_scalar = |shape: &[isize]| shape.is_empty() || shape == &[0];
+    if is_scalar(&shape1) {
+        return Ok(shape0);
+    }
+    if is_scalar(&shape0) {
+        return Ok(shape1);
+    }
+    let rank = shape0.len().max(shape1.len());
+    let mut ret = Vec::with_capacity(rank);
+    for i in 0..rank {
+        // trailing axes are aligned
+        let a = if i + shape0.len() >= rank {
+            shape0[i + shape0.len() - rank]
+        } else {
+            1
+        };
+        let b = if i + shape1.len() >= rank {
+            shape1[i + shape1.len() - rank]
+        } else {
+            1
+        };
+        let dim = if a == b || b == 1 {
+            a
+        } else if a == 1 || a == -1 {
+            b
+        } else if b == -1 {
+            a
+        } else {
+            return Err(format!(
+                "Shapes {:?} and {:?} are not broadcastable",
+                shape0, shape1
+            ));
+        };
+        ret.push(dim);
+    }
+    Ok(ret)
 }
 
 // Reduce gy if broadcast occurred in the forward path.
@@ -14,6 +14,16 @@ pub struct Scalar {
     pub val: f32,
 }
 
+// Static shape of a tensor whose shape is given as the tensor `shape`.
+//
+// Only the rank is known at graph build time.
+pub fn static_shape_from_shape_tensor(shape: &Tensor) -> Option<Vec<isize>> {
+    match shape.static_shape() {
+        Some(ref a) if a.len() == 1 && a[0] >= 0 => Some(vec![-1; a[0] as usize]),
+        _ => None,
+    }
+}
+
 impl op::Op for Scalar {
     fn name(&self) -> &str {
         "Scalar"
@@ -26,6 +36,10 @@ impl op::Op for Scalar {
     fn grad(&self, _: &Tensor, _: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
         vec![None]
     }
+
+    fn static_shape(&self, _: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(Some(vec![]))
+    }
 }
 
 impl op::Op for Zeros {
@@ -55,6 +69,10 @@ impl op::Op for Zeros {
     fn grad(&self, _: &Tensor, _: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
         vec![None]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(static_shape_from_shape_tensor(xs[0]))
+    }
 }
 
 impl op::Op for Ones {
@@ -84,6 +102,10 @@ impl op::Op for Ones {
     fn grad(&self, _: &Tensor, _: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
         vec![None]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(static_shape_from_shape_tensor(xs[0]))
+    }
 }
 
 impl op::Op for Range {
@@ -112,6 +134,10 @@ impl op::Op for Range {
     fn grad(&self, _: &Tensor, _: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
         vec![None, None, None]
     }
+
+    fn static_shape(&self, _: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(Some(vec![-1]))
+    }
 }
 
 impl op::Op for ConvertToTensor {
@@ -126,4 +152,8 @@ impl op::Op for ConvertToTensor {
     fn grad(&self, _: &Tensor, _: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
         vec![]
     }
+
+    fn static_shape(&self, _: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(Some(self.arr.shape().iter().map(|&a| a as isize).collect()))
+    }
 }
@@ -0,0 +1,252 @@
+use super::*;
+use backend::{AvgPoolParams, PoolWindow};
+use tensor::Tensor;
+
+/// How `AvgPool2D` splits its input into windows.
+#[derive(Clone, Copy, Debug)]
+pub enum AvgPoolWindows {
+    /// Windows of `size` moved by `stride`, both `(h, w)`.
+    ///
+    /// Padded elements are counted in the divisors if `count_include_pad` is true.
+    Strided {
+        padding: Padding,
+        stride: (usize, usize),
+        size: (usize, usize),
+        count_include_pad: bool,
+    },
+    /// Windows that divide the input into an `(out_h, out_w)` output as evenly as possible.
+    Adaptive(usize, usize),
+}
+
+/// 2D average pooling.
+pub struct AvgPool2D {
+    pub windows: AvgPoolWindows,
+    pub format: DataFormat,
+}
+
+// Takes `x` as the second input to know the shape of gx.
+pub struct AvgPool2DGrad {
+    windows: AvgPoolWindows,
+    format: DataFormat,
+}
+
+impl AvgPoolWindows {
+    /// Windows along the rows and the columns of an `(xh, xw)` input.
+    fn get(&self, xh: usize, xw: usize) -> (Vec<PoolWindow>, Vec<PoolWindow>) {
+        match *self {
+            AvgPoolWindows::Strided {
+                padding,
+                stride,
+                size,
+                count_include_pad,
+            } => (
+                strided_windows(padding, 0, xh, size.0, stride.0, count_include_pad),
+                strided_windows(padding, 1, xw, size.1, stride.1, count_include_pad),
+            ),
+            AvgPoolWindows::Adaptive(out_h, out_w) => {
+                (adaptive_windows(xh, out_h), adaptive_windows(xw, out_w))
+            }
+        }
+    }
+
+    fn static_out_len(&self, axis: usize, x: isize) -> isize {
+        match *self {
+            AvgPoolWindows::Strided {
+                padding,
+                stride,
+                size,
+                ..
+            } => {
+                let window = Window2D {
+                    padding,
+                    stride,
+                    dilation: (1, 1),
+                };
+                let size = if axis == 0 { size.0 } else { size.1 };
+                window.static_out_len(axis, x, size as isize)
+            }
+            AvgPoolWindows::Adaptive(out_h, out_w) => {
+                if axis == 0 {
+                    out_h as isize
+                } else {
+                    out_w as isize
+                }
+            }
+        }
+    }
+}
+
+fn strided_windows(
+    padding: Padding,
+    axis: usize,
+    len: usize,
+    size: usize,
+    stride: usize,
+    count_include_pad: bool,
+) -> Vec<PoolWindow> {
+    assert!(size > 0, "ag::avg_pool2d: pool_size must be positive");
+    let (pad_begin, pad_end) = padding.pads(axis, len, size, stride);
+    (0..padding.out_len(axis, len, size, stride))
+        .map(|i| {
+            let begin = (i * stride) as isize - pad_begin as isize;
+            let end = (begin + size as isize).min((len + pad_end) as isize);
+            let padded_len = (end - begin) as usize;
+            let (begin, end) = (begin.max(0) as usize, end.min(len as isize) as usize);
+            assert!(
+                begin < end,
+                "ag::avg_pool2d: A window must not be entirely in the padding"
+            );
+            PoolWindow {
+                begin,
+                end,
+                divisor: if count_include_pad {
+                    padded_len
+                } else {
+                    end - begin
+                },
+            }
+        })
+        .collect()
+}
+
+fn adaptive_windows(len: usize, out: usize) -> Vec<PoolWindow> {
+    (0..out)
+        .map(|i| {
+            let begin = i * len / out;
+            let end = ((i + 1) * len + out - 1) / out;
+            PoolWindow {
+                begin,
+                end,
+                divisor: end - begin,
+            }
+        })
+        .collect()
+}
+
+impl ::op::Op for AvgPool2D {
+    fn name(&self) -> &str {
+        "AvgPool"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> ::op::ComputeResult {
+        let xs = ctx.grab_inputs();
+        let x: &NdArray = xs[0];
+        let x_shape = x.shape();
+        assert_eq!(
+            x_shape.len(),
+            4,
+            "ag::avg_pool2d: Input must be 4D (got {:?})",
+            x_shape
+        );
+        let x_shape = self.format.to_nchw(x_shape);
+        let (batch, c, xh, xw) = (x_shape[0], x_shape[1], x_shape[2], x_shape[3]);
+        let (rows, cols) = self.windows.get(xh, xw);
+        let (yh, yw) = (rows.len(), cols.len());
+        let mut y = vec![0.; batch * c * yh * yw];
+        let params = AvgPoolParams {
+            batch,
+            channels: c,
+            height: xh,
+            width: xw,
+            channels_last: self.format.is_channels_last(),
+            rows: &rows,
+            cols: &cols,
+        };
+        ctx.backend().avg_pool(&params, as_slice(x), &mut y);
+        let y_shape = self.format.from_nchw(&[batch, c, yh, yw]);
+        let y = NdArray::from_shape_vec(ndarray::IxDyn(&y_shape), y);
+        vec![Ok(y.unwrap())]
+    }
+
+    fn grad(&self, gy: &Tensor, xs: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
+        let gx = Tensor::builder()
+            .set_inputs(vec![gy, xs[0]])
+            .build(AvgPool2DGrad {
+                windows: self.windows,
+                format: self.format,
+            });
+        vec![Some(gx)]
+    }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> ::op::StaticShapeResult {
+        let x_shape = match xs[0].static_shape() {
+            Some(a) => a,
+            None => return Ok(None),
+        };
+        if x_shape.len() != 4 {
+            return Err(format!("Input must be 4D: {:?}", x_shape));
+        }
+        let x_shape = self.format.to_nchw(&x_shape);
+        Ok(Some(self.format.from_nchw(&[
+            x_shape[0],
+            x_shape[1],
+            self.windows.static_out_len(0, x_shape[2]),
+            self.windows.static_out_len(1, x_shape[3]),
+        ])))
+    }
+}
+
+impl ::op::Op for AvgPool2DGrad {
+    fn name(&self) -> &str {
+        "AvgPoolGrad"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> ::op::ComputeResult {
+        let xs = ctx.grab_inputs();
+        let gy = xs[0];
+        let x_shape = self.format.to_nchw(xs[1].shape());
+        let (batch, c, xh, xw) = (x_shape[0], x_shape[1], x_shape[2], x_shape[3]);
+        let (rows, cols) = self.windows.get(xh, xw);
+        // avg_pool_grad accumulates into gx
+        let mut gx = vec![0.; batch * c * xh * xw];
+        let params = AvgPoolParams {
+            batch,
+            channels: c,
+            height: xh,
+            width: xw,
+            channels_last: self.format.is_channels_last(),
+            rows: &rows,
+            cols: &cols,
+        };
+        ctx.backend().avg_pool_grad(&params, as_slice(gy), &mut gx);
+        let gx = NdArray::from_shape_vec(xs[1].shape(), gx);
+        vec![Ok(gx.unwrap())]
+    }
+
+    fn grad(&self, ggx: &Tensor, _: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
+        // Average pooling is linear, so this is the transpose of the transpose.
+        let ggy = Tensor::builder().set_input(ggx).build(AvgPool2D {
+            windows: self.windows,
+            format: self.format,
+        });
+        vec![Some(ggy), None]
+    }
+}
+
+#[test]
+fn test_pool_windows() {
+    let w = |begin, end, divisor| PoolWindow {
+        begin,
+        end,
+        divisor,
+    };
+    // Pads (1, 1)
+    let padding = Padding::Explicit(1, 1, 1, 1);
+    assert_eq!(
+        strided_windows(padding, 0, 4, 3, 2, true),
+        vec![w(0, 2, 3), w(1, 4, 3)]
+    );
+    assert_eq!(
+        strided_windows(padding, 0, 4, 3, 2, false),
+        vec![w(0, 2, 2), w(1, 4, 3)]
+    );
+    // Pads (0, 1)
+    assert_eq!(
+        strided_windows(Padding::Same, 1, 5, 2, 2, true),
+        vec![w(0, 2, 2), w(2, 4, 2), w(4, 5, 2)]
+    );
+    assert_eq!(
+        adaptive_windows(5, 3),
+        vec![w(0, 2, 2), w(1, 4, 3), w(3, 5, 2)]
+    );
+}
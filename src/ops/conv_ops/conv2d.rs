@@ -1,25 +1,38 @@
 use super::*;
-use std::slice;
 use NdArray;
 
+/// 2D convolution.
+///
+/// Input channels are split into `groups` groups, and each group is convolved
+/// with its own part of the filter of shape `(out_ch, in_ch / groups, kh, kw)`.
+/// `stride` and `dilation` are `(h, w)`.
+///
+/// In `NHWC`, the filter is `(kh, kw, in_ch / groups, out_ch)` instead.
 pub struct Conv2D {
-    pub pad: usize,
-    pub stride: usize,
-    pub dilation: usize,
+    pub padding: Padding,
+    pub stride: (usize, usize),
+    pub dilation: (usize, usize),
+    pub groups: Groups,
+    pub format: DataFormat,
 }
 
+// The number of groups of these is inferred from the shapes of their inputs.
 pub struct Conv2DFilterGrad {
-    pub pad: usize,
-    pub stride: usize,
-    pub dilation: usize,
+    pub padding: Padding,
+    pub stride: (usize, usize),
+    pub dilation: (usize, usize),
+    pub format: DataFormat,
 }
 
 pub struct Conv2DWithCols {
-    pub pad: usize,
-    pub stride: usize,
-    pub dilation: usize,
+    pub padding: Padding,
+    pub stride: (usize, usize),
+    pub dilation: (usize, usize),
+    pub format: DataFormat,
 }
 
+impl_window2d!(Conv2D);
+
 impl ::op::Op for Conv2D {
     fn name(&self) -> &str {
         "Conv2D"
@@ -31,6 +44,12 @@ impl ::op::Op for Conv2D {
         let x: &NdArray = xs[0];
         let w: &NdArray = xs[1];
 
+        if self.format.is_channels_last() {
+            let (y, cols) =
+                super::conv2d_nhwc::conv2d(ctx.backend(), x, w, &self.window(), self.groups);
+            return vec![Ok(y), Ok(cols)];
+        }
+
         // Extract size params
         let (batch_size, xch, xh, xw) = {
             let x_shape = x.shape();
@@ -42,7 +61,7 @@ impl ::op::Op for Conv2D {
             );
             (x_shape[0], x_shape[1], x_shape[2], x_shape[3])
         };
-        let (ych, kh, kw) = {
+        let (ych, kh, kw, groups) = {
             let k_shape = w.shape();
             assert_eq!(
                 k_shape.len(),
@@ -50,105 +69,56 @@ impl ::op::Op for Conv2D {
                 "ag::conv2d: filter must be 4D (got {:?})",
                 k_shape
             );
-            assert_eq!(
-                xch, k_shape[1],
-                "ag::conv2d: Number of input's channel ({:?}) must match second filter dim ({:?})",
-                xch, k_shape[1]
-            );
-            (k_shape[0], k_shape[2], k_shape[3])
+            let groups = self.groups.resolve(xch, k_shape[1], k_shape[0]);
+            (k_shape[0], k_shape[2], k_shape[3], groups)
+        };
+        let window = self.window();
+        let (yh, yw) = window.out_size((xh, xw), (kh, kw));
+        let params = Im2ColParams {
+            channels: xch,
+            height: xh,
+            width: xw,
+            kernel: (kh, kw),
+            pad: window.pads((xh, xw), (kh, kw)),
+            stride: self.stride,
+            dilation: self.dilation,
         };
-        let yh = get_yh!(self, xh, kh);
-        let yw = get_yw!(self, xw, kw);
 
         let num_elements_in_batch_x = xch * xh * xw;
         let num_elements_in_batch_y = ych * yh * yw;
         let num_elements_in_batch_c = xch * kw * kh * yh * yw;
 
-        // Parameters for sgemm
-        let m = ych;
+        // Parameters for sgemm (per group)
+        let m = ych / groups;
         let n = yh * yw;
-        let k = xch * kh * kw;
+        let k = xch / groups * kh * kw;
 
-        // Prepare pointers to buffers
-        let x = unsafe { slice::from_raw_parts(x.as_ptr(), batch_size * xch * xh * xw) };
-        let c = alloc_uninitialized_buf(batch_size * num_elements_in_batch_c);
-        let y = alloc_uninitialized_buf(batch_size * num_elements_in_batch_y);
-        let w: &f32 = unsafe { &*w.as_ptr() };
-
-        #[cfg(feature = "mkl")]
-        {
-            (0..batch_size).into_par_iter().for_each(|i| {
+        // Prepare buffers
+        let x = &as_slice(x)[..batch_size * num_elements_in_batch_x];
+        let mut c = vec![0.; batch_size * num_elements_in_batch_c];
+        let mut y = vec![0.; batch_size * num_elements_in_batch_y];
+
+        let backend = ctx.backend();
+        c.par_chunks_mut(num_elements_in_batch_c)
+            .zip(x.par_chunks(num_elements_in_batch_x))
+            .for_each(|(c, x)| {
                 // for each batch
-                let x_region_head = &x[i * num_elements_in_batch_x];
-                let c_region_head = &c[i * num_elements_in_batch_c];
-                im2col(
-                    x_region_head,
-                    xch,
-                    xh,
-                    xw,
-                    kh,
-                    kw,
-                    self.pad,
-                    self.pad,
-                    self.stride,
-                    self.stride,
-                    self.dilation,
-                    self.dilation,
-                    c_region_head,
-                );
+                backend.im2col(&params, x, c);
             });
-            cblas_sgemm_batch_wrapper(
-                false,
-                false,
+        backend.sgemm_batch(
+            &GemmParams {
+                trans_a: false,
+                trans_b: false,
                 m,
                 n,
                 k,
-                &[1.],
-                vec![w; batch_size],
-                get_region_heads(batch_size, c.as_slice()),
-                &[0.],
-                get_region_heads(batch_size, y.as_slice()),
-                1,
-                batch_size,
-            );
-        }
-        #[cfg(not(feature = "mkl"))]
-        {
-            // fallback: parallel im2col + sgemm using rayon
-            (0..batch_size).into_par_iter().for_each(|i| {
-                // for each batch
-                let x_region_head = &x[i * num_elements_in_batch_x];
-                let c_region_head = &c[i * num_elements_in_batch_c];
-                let y_region_head = &y[i * num_elements_in_batch_y];
-                im2col(
-                    x_region_head,
-                    xch,
-                    xh,
-                    xw,
-                    kh,
-                    kw,
-                    self.pad,
-                    self.pad,
-                    self.stride,
-                    self.stride,
-                    self.dilation,
-                    self.dilation,
-                    c_region_head,
-                );
-                sgemm(
-                    false,
-                    false,
-                    w,
-                    c_region_head,
-                    y_region_head,
-                    m,
-                    n,
-                    k,
-                    1.,
-                    0.,
-                );
-            });
-        }
+                alpha: 1.,
+                beta: 0.,
+            },
+            &get_group_regions(as_slice(w), batch_size, groups, 0, m * k),
+            &get_group_regions(&c, batch_size, groups, num_elements_in_batch_c, k * n),
+            &mut split_regions_mut(batch_size * groups, &mut y),
+        );
         // Move vectors into NdArrays
         let y = NdArray::from_shape_vec(ndarray::IxDyn(&[batch_size, ych, yh, yw]), y).unwrap();
 
@@ -162,11 +132,14 @@ impl ::op::Op for Conv2D {
         let x = xs[0];
         let w = xs[1];
 
-        let gx = Tensor::builder().set_inputs(vec![gy, w]).build(
+        // `x` tells the shape of gx to Conv2DTranspose.
+        let gx = Tensor::builder().set_inputs(vec![gy, w, x]).build(
             super::conv2d_transpose::Conv2DTranspose {
-                pad: self.pad,
+                padding: self.padding,
                 stride: self.stride,
                 dilation: self.dilation,
+                groups: 0,
+                format: self.format,
             },
         );
 
@@ -175,13 +148,24 @@ impl ::op::Op for Conv2D {
             .set_inputs(vec![cols, gy, w])
             .set_backprop_inputs(vec![x.clone(), gy.clone()])
             .build(Conv2DFilterGrad {
-                pad: self.pad,
+                padding: self.padding,
                 stride: self.stride,
                 dilation: self.dilation,
+                format: self.format,
             });
 
         vec![Some(gx), Some(gw)]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> ::op::StaticShapeResult {
+        let (window, format) = (self.window(), self.format);
+        let x = xs[0].static_shape().map(|s| format.to_nchw(&s));
+        let w = xs[1].static_shape().map(|s| format.filter_to_nchw(&s));
+        let y = conv_static_shape(x, w, 4, 1, 0, self.groups.static_count(), |axis, x, k| {
+            window.static_out_len(axis, x, k)
+        })?;
+        Ok(y.map(|s| format.from_nchw(&s)))
+    }
 }
 
 impl ::op::Op for Conv2DWithCols {
@@ -195,65 +179,44 @@ impl ::op::Op for Conv2DWithCols {
         let cols: &NdArray = xs[0];
         let w: &NdArray = xs[1];
 
+        if self.format.is_channels_last() {
+            let y = super::conv2d_nhwc::conv2d_with_cols(ctx.backend(), cols, w);
+            return vec![Ok(y)];
+        }
+
         // Extract size params
         let cols_shape = cols.shape();
         let k_shape = w.shape();
-        let (ych, xch, kh, kw) = { (k_shape[0], k_shape[1], k_shape[2], k_shape[3]) };
+        let (ych, kch, kh, kw) = { (k_shape[0], k_shape[1], k_shape[2], k_shape[3]) };
         let yh = cols_shape[4];
         let yw = cols_shape[5];
         let batch_size = cols_shape[0];
+        let groups = cols.len() / (batch_size * kch * kh * kw * yh * yw);
 
-        // Parameters for sgemm
+        // Parameters for sgemm (per group)
         let num_elements_in_batch_y = ych * yh * yw;
-        let m = ych;
+        let m = ych / groups;
         let n = yh * yw;
-        let k = xch * kh * kw;
+        let k = kch * kh * kw;
 
         // Prepare buffers
-        let c = unsafe { slice::from_raw_parts(cols.as_ptr(), cols.len()) };
-        let y = alloc_uninitialized_buf(batch_size * num_elements_in_batch_y);
-        let w: &f32 = unsafe { &*w.as_ptr() };
-
-        #[cfg(feature = "mkl")]
-        {
-            cblas_sgemm_batch_wrapper(
-                false,
-                false,
+        let c = as_slice(cols);
+        let mut y = vec![0.; batch_size * num_elements_in_batch_y];
+
+        ctx.backend().sgemm_batch(
+            &GemmParams {
+                trans_a: false,
+                trans_b: false,
                 m,
                 n,
                 k,
-                &[1.],
-                vec![w; batch_size],
-                get_region_heads(batch_size, c),
-                &[0.],
-                get_region_heads(batch_size, y.as_slice()),
-                1,
-                batch_size,
-            );
-        }
-        #[cfg(not(feature = "mkl"))]
-        {
-            let num_elements_in_batch_c =
-                { cols_shape[1] * cols_shape[2] * cols_shape[3] * cols_shape[4] * cols_shape[5] };
-            // fallback: parallel sgemm using rayon
-            (0..batch_size).into_par_iter().for_each(|i| {
-                // for each batch
-                let c_region_head = &c[i * num_elements_in_batch_c];
-                let y_region_head = &y[i * num_elements_in_batch_y];
-                sgemm(
-                    false,
-                    false,
-                    w,
-                    c_region_head,
-                    y_region_head,
-                    m,
-                    n,
-                    k,
-                    1.,
-                    0.,
-                );
-            });
-        }
+                alpha: 1.,
+                beta: 0.,
+            },
+            &get_group_regions(as_slice(w), batch_size, groups, 0, m * k),
+            &get_group_regions(c, batch_size, groups, c.len() / batch_size, k * n),
+            &mut split_regions_mut(batch_size * groups, &mut y),
+        );
         // Move vectors into NdArrays
         let y = NdArray::from_shape_vec(ndarray::IxDyn(&[batch_size, ych, yh, yw]), y).unwrap();
 
@@ -263,25 +226,26 @@ impl ::op::Op for Conv2DWithCols {
     fn grad(&self, gy: &Tensor, xs: &[&Tensor], y: &Tensor) -> Vec<Option<Tensor>> {
         let cols = xs[0];
         let w = xs[1];
+        let x = &y.inputs_on_backprop.as_ref().unwrap()[0];
 
-        let gx = Tensor::builder().set_inputs(vec![gy, w]).build(
+        let gx = Tensor::builder().set_inputs(vec![gy, w, x]).build(
             super::conv2d_transpose::Conv2DTranspose {
-                pad: self.pad,
+                padding: self.padding,
                 stride: self.stride,
                 dilation: self.dilation,
+                groups: 0,
+                format: self.format,
             },
         );
 
         let gw = Tensor::builder()
             .set_inputs(vec![cols, gy, w])
-            .set_backprop_inputs(vec![
-                y.inputs_on_backprop.as_ref().unwrap()[0].clone(),
-                gy.clone(),
-            ])
+            .set_backprop_inputs(vec![x.clone(), gy.clone()])
             .build(Conv2DFilterGrad {
-                pad: self.pad,
+                padding: self.padding,
                 stride: self.stride,
                 dilation: self.dilation,
+                format: self.format,
             });
 
         vec![Some(gx), Some(gw)]
@@ -298,6 +262,12 @@ impl ::op::Op for Conv2DFilterGrad {
         let cols = xs[0]; // must be columns
         let gy = xs[1];
         let k_shape = xs[2].shape();
+
+        if self.format.is_channels_last() {
+            let gw = super::conv2d_nhwc::filter_grad(ctx.backend(), cols, gy, k_shape);
+            return vec![Ok(gw)];
+        }
+
         let cols_shape = cols.shape();
         let gy_shape = gy.shape();
 
@@ -306,31 +276,34 @@ impl ::op::Op for Conv2DFilterGrad {
         let num_elements_in_batch_c =
             { cols_shape[1] * cols_shape[2] * cols_shape[3] * cols_shape[4] * cols_shape[5] };
 
-        let (xch, kh, kw) = (k_shape[1], k_shape[2], k_shape[3]);
+        let (kch, kh, kw) = (k_shape[1], k_shape[2], k_shape[3]);
         let (batch_size, ych, yh, yw) = (gy_shape[0], gy_shape[1], gy_shape[2], gy_shape[3]);
+        let groups = num_elements_in_batch_c / (kch * kh * kw * yh * yw);
 
-        let m = ych;
-        let n = kh * kw * xch;
+        // Parameters for sgemm (per group)
+        let m = ych / groups;
+        let n = kh * kw * kch;
         let k = yh * yw;
 
         // Prepare bufs
-        let cols = unsafe { slice::from_raw_parts(cols.as_ptr(), cols.len()) };
-        let gy = unsafe { slice::from_raw_parts(gy.as_ptr(), gy.len()) };
-        let gw = alloc_uninitialized_buf(ych * xch * kh * kw);
-        let gw_head = unsafe { &*gw.as_ptr() };
+        let cols = as_slice(cols);
+        let gy = as_slice(gy);
+        let mut gw = vec![0.; ych * kch * kh * kw];
 
         for i in 0..batch_size {
-            sgemm(
-                false,
-                true,
-                &gy[i * num_elements_in_batch_g],
-                &cols[i * num_elements_in_batch_c],
-                gw_head,
-                m,
-                n,
-                k,
-                1.,
-                (i != 0) as i32 as f32, // beta: if i==0 then 0 else 1
+            ctx.backend().sgemm_batch(
+                &GemmParams {
+                    trans_a: false,
+                    trans_b: true,
+                    m,
+                    n,
+                    k,
+                    alpha: 1.,
+                    beta: (i != 0) as i32 as f32, // beta: if i==0 then 0 else 1
+                },
+                &get_group_regions(&gy[i * num_elements_in_batch_g..], 1, groups, 0, m * k),
+                &get_group_regions(&cols[i * num_elements_in_batch_c..], 1, groups, 0, n * k),
+                &mut split_regions_mut(groups, &mut gw),
             );
         }
         vec![Ok(NdArray::from_shape_vec(k_shape, gw).unwrap())]
@@ -339,26 +312,27 @@ impl ::op::Op for Conv2DFilterGrad {
     fn grad(&self, ggw: &Tensor, xs: &[&Tensor], y: &Tensor) -> Vec<Option<Tensor>> {
         let cols = xs[0];
         let gy = xs[1]; // For example, gradient of output of Conv2D.
+        let x = &y.inputs_on_backprop.as_ref().unwrap()[0];
 
         // grad grad
-        let gx = Tensor::builder().set_inputs(vec![gy, ggw]).build(
+        let gx = Tensor::builder().set_inputs(vec![gy, ggw, x]).build(
             super::conv2d_transpose::Conv2DTranspose {
-                pad: self.pad,
+                padding: self.padding,
                 stride: self.stride,
                 dilation: self.dilation,
+                groups: 0,
+                format: self.format,
             },
         );
 
         let ggy = Tensor::builder()
             .set_inputs(vec![cols, ggw])
-            .set_backprop_inputs(vec![
-                y.inputs_on_backprop.as_ref().unwrap()[0].clone(),
-                ggw.clone(),
-            ])
+            .set_backprop_inputs(vec![x.clone(), ggw.clone()])
             .build(Conv2DWithCols {
-                pad: self.pad,
+                padding: self.padding,
                 stride: self.stride,
                 dilation: self.dilation,
+                format: self.format,
             });
 
         vec![Some(gx), Some(ggy), None]
@@ -368,58 +342,58 @@ impl ::op::Op for Conv2DFilterGrad {
 #[test]
 fn test_tensor_size_after_convolution() {
     let op = Conv2D {
-        pad: 0,
-        stride: 1,
-        dilation: 1,
+        padding: Padding::Valid,
+        stride: (1, 1),
+        dilation: (1, 1),
+        groups: Groups::Count(1),
+        format: DataFormat::NCHW,
     };
 
     let (xh, xw) = (3, 3);
     let (kh, kw) = (2, 2);
-    let yh = get_yh!(&op, xh, kh);
-    let yw = get_yw!(&op, xw, kw);
+    let (yh, yw) = op.window().out_size((xh, xw), (kh, kw));
     assert_eq!(yh, 2);
     assert_eq!(yw, 2);
 }
 
 #[test]
 fn test_parallel_im2col() {
+    use backend::{Backend, CpuBackend};
     let op = Conv2D {
-        pad: 0,
-        stride: 1,
-        dilation: 1,
+        padding: Padding::Valid,
+        stride: (1, 1),
+        dilation: (1, 1),
+        groups: Groups::Count(1),
+        format: DataFormat::NCHW,
     };
 
     let batch_size = 2;
     let xch = 2;
     let (xh, xw) = (3, 3);
     let (kh, kw) = (2, 2);
-    let yh = get_yh!(&op, xh, kh);
-    let yw = get_yw!(&op, xw, kw);
+    let (yh, yw) = op.window().out_size((xh, xw), (kh, kw));
     let num_elements_in_batch_x = xch * xh * xw;
     let num_elements_in_batch_c = xch * kw * kh * yh * yw;
     let x = (0..(batch_size * num_elements_in_batch_x))
         .map(|a| a as f32)
         .collect::<Vec<_>>();
-    let c = alloc_uninitialized_buf(batch_size * num_elements_in_batch_c);
+    let mut c = vec![0.; batch_size * num_elements_in_batch_c];
+    let params = Im2ColParams {
+        channels: xch,
+        height: xh,
+        width: xw,
+        kernel: (kh, kw),
+        pad: (0, 0, 0, 0),
+        stride: op.stride,
+        dilation: op.dilation,
+    };
     // Call im2col on 2 chunks in parallel.
-    (0..batch_size).into_par_iter().for_each(|i| {
-        // for each mini-batch
-        im2col(
-            &x[i * num_elements_in_batch_x],
-            xch,
-            xh,
-            xw,
-            kh,
-            kw,
-            op.pad,
-            op.pad,
-            op.stride,
-            op.stride,
-            op.dilation,
-            op.dilation,
-            &c[i * num_elements_in_batch_c],
-        );
-    });
+    c.par_chunks_mut(num_elements_in_batch_c)
+        .zip(x.par_chunks(num_elements_in_batch_x))
+        .for_each(|(c, x)| {
+            // for each mini-batch
+            CpuBackend.im2col(&params, x, c);
+        });
 
     assert_eq!(
         c,
@@ -435,41 +409,39 @@ fn test_parallel_im2col() {
 
 #[test]
 fn test_im2col() {
+    use backend::{Backend, CpuBackend};
     let op = Conv2D {
-        pad: 0,
-        stride: 1,
-        dilation: 1,
+        padding: Padding::Valid,
+        stride: (1, 1),
+        dilation: (1, 1),
+        groups: Groups::Count(1),
+        format: DataFormat::NCHW,
     };
 
     let xch = 2;
     let (xh, xw) = (3, 3);
     let (kh, kw) = (2, 2);
-    let yh = get_yh!(&op, xh, kh);
-    let yw = get_yw!(&op, xw, kw);
+    let (yh, yw) = op.window().out_size((xh, xw), (kh, kw));
 
     let x = ndarray::Array1::range(0., (xch * xw * xh) as f32, 1.)
         .into_shape((1, xch as usize, xw as usize, xh as usize))
         .unwrap();
 
-    let cols = alloc_uninitialized_buf(1 * xch * kw * kh * yh * yw);
-
-    unsafe {
-        im2col_cpu(
-            x.as_ptr(),
-            xch as i32,
-            xh as i32,
-            xw as i32,
-            kh as i32,
-            kw as i32,
-            op.pad as i32,
-            op.pad as i32,
-            op.stride as i32,
-            op.stride as i32,
-            op.dilation as i32,
-            op.dilation as i32,
-            cols.as_ptr(),
-        )
-    };
+    let mut cols = vec![0.; xch * kw * kh * yh * yw];
+
+    CpuBackend.im2col(
+        &Im2ColParams {
+            channels: xch,
+            height: xh,
+            width: xw,
+            kernel: (kh, kw),
+            pad: (0, 0, 0, 0),
+            stride: op.stride,
+            dilation: op.dilation,
+        },
+        x.as_slice().unwrap(),
+        &mut cols,
+    );
 
     assert_eq!(
         cols,
@@ -485,9 +457,11 @@ fn test_im2col() {
 fn test_conv2d() {
     use op::Op;
     let op = Conv2D {
-        pad: 0,
-        stride: 1,
-        dilation: 1,
+        padding: Padding::Valid,
+        stride: (1, 1),
+        dilation: (1, 1),
+        groups: Groups::Count(1),
+        format: DataFormat::NCHW,
     };
 
     let x = ndarray::Array1::range(0., 2. * 2. * 3. * 3., 1.)
@@ -496,10 +470,7 @@ fn test_conv2d() {
         .into_dyn();
 
     let w = ::ndarray_ext::ones(&[
-        /*out_ch=*/ 2,
-        /*in_ch=*/ 2,
-        /*row=*/ 2,
-        /*col=*/ 2,
+        /*out_ch=*/ 2, /*in_ch=*/ 2, /*row=*/ 2, /*col=*/ 2,
     ]);
 
     let y = op.compute(::runtime::OpComputeContext::new(
@@ -0,0 +1,285 @@
+//! Channels-last versions of the 2D convolution kernels.
+//!
+//! Images are (batch, h, w, channels) and filters are (kh, kw, in_ch / groups, out_ch)
+//! as in TensorFlow. Columns are (groups, batch, out_h, out_w, kh * kw * in_ch / groups),
+//! so that each group is convolved by a single matrix multiplication over the whole batch.
+use super::*;
+use backend::Backend;
+use std::borrow::Cow;
+
+/// Reorders a row-major `(rows, groups * n)` matrix into `(groups, rows, n)`.
+fn group_major(a: &[f32], rows: usize, groups: usize) -> Cow<'_, [f32]> {
+    if groups == 1 {
+        return Cow::Borrowed(a);
+    }
+    let n = a.len() / (rows * groups);
+    let mut ret = Vec::with_capacity(a.len());
+    for g in 0..groups {
+        for r in 0..rows {
+            let head = (r * groups + g) * n;
+            ret.extend_from_slice(&a[head..head + n]);
+        }
+    }
+    Cow::Owned(ret)
+}
+
+/// Inverse of `group_major`.
+fn group_minor(a: Vec<f32>, rows: usize, groups: usize) -> Vec<f32> {
+    if groups == 1 {
+        return a;
+    }
+    let n = a.len() / (rows * groups);
+    let mut ret = Vec::with_capacity(a.len());
+    for r in 0..rows {
+        for g in 0..groups {
+            let head = (g * rows + r) * n;
+            ret.extend_from_slice(&a[head..head + n]);
+        }
+    }
+    ret
+}
+
+fn im2col(
+    backend: &dyn Backend,
+    x: &NdArray,
+    groups: usize,
+    kernel: (usize, usize),
+    window: &Window2D,
+) -> Vec<f32> {
+    let x_shape = x.shape();
+    let (batch, xh, xw, xch) = (x_shape[0], x_shape[1], x_shape[2], x_shape[3]);
+    let (yh, yw) = window.out_size((xh, xw), kernel);
+    let params = Im2ColParams {
+        channels: xch,
+        height: xh,
+        width: xw,
+        kernel,
+        pad: window.pads((xh, xw), kernel),
+        stride: window.stride,
+        dilation: window.dilation,
+    };
+    let mut cols = vec![0.; batch * yh * yw * kernel.0 * kernel.1 * xch];
+    backend.im2col_nhwc(&params, batch, groups, as_slice(x), &mut cols);
+    cols
+}
+
+/// `y_g = cols_g w_g` for each group, where `cols` is (groups, rows, k) and `w` is
+/// (k, ych). Returns `y` of shape (rows, ych).
+fn matmul_groups(
+    backend: &dyn Backend,
+    cols: &[f32],
+    w: &[f32],
+    rows: usize,
+    k: usize,
+    ych: usize,
+    groups: usize,
+) -> Vec<f32> {
+    let n = ych / groups;
+    let w = group_major(w, k, groups);
+    let mut y = vec![0.; rows * ych];
+    backend.sgemm_batch(
+        &GemmParams {
+            trans_a: false,
+            trans_b: false,
+            m: rows,
+            n,
+            k,
+            alpha: 1.,
+            beta: 0.,
+        },
+        &split_regions(groups, cols),
+        &split_regions(groups, &w),
+        &mut split_regions_mut(groups, &mut y),
+    );
+    group_minor(y, rows, groups)
+}
+
+/// `gw_g = cols_g^T gy_g` for each group, where `cols` is (groups, rows, k) and `gy` is
+/// (rows, ych). Returns `gw` of `w_shape`.
+fn filter_grad_groups(
+    backend: &dyn Backend,
+    cols: &[f32],
+    gy: &[f32],
+    w_shape: &[usize],
+    groups: usize,
+) -> NdArray {
+    let (k, ych) = (w_shape[0] * w_shape[1] * w_shape[2], w_shape[3]);
+    let (rows, n) = (gy.len() / ych, ych / groups);
+    let gy = group_major(gy, rows, groups);
+    let mut gw = vec![0.; k * ych];
+    backend.sgemm_batch(
+        &GemmParams {
+            trans_a: true,
+            trans_b: false,
+            m: k,
+            n,
+            k: rows,
+            alpha: 1.,
+            beta: 0.,
+        },
+        &split_regions(groups, cols),
+        &split_regions(groups, &gy),
+        &mut split_regions_mut(groups, &mut gw),
+    );
+    NdArray::from_shape_vec(w_shape, group_minor(gw, k, groups)).unwrap()
+}
+
+/// Returns `y` and its columns.
+pub fn conv2d(
+    backend: &dyn Backend,
+    x: &NdArray,
+    w: &NdArray,
+    window: &Window2D,
+    groups: Groups,
+) -> (NdArray, NdArray) {
+    let x_shape = x.shape();
+    assert_eq!(
+        x_shape.len(),
+        4,
+        "ag::conv2d: Input must be 4D (got {:?})",
+        x_shape
+    );
+    let k_shape = w.shape();
+    assert_eq!(
+        k_shape.len(),
+        4,
+        "ag::conv2d: filter must be 4D (got {:?})",
+        k_shape
+    );
+    let (batch, xh, xw, xch) = (x_shape[0], x_shape[1], x_shape[2], x_shape[3]);
+    let (kh, kw, kch, ych) = (k_shape[0], k_shape[1], k_shape[2], k_shape[3]);
+    let groups = groups.resolve(xch, kch, ych);
+    let (yh, yw) = window.out_size((xh, xw), (kh, kw));
+    let k = kh * kw * kch;
+
+    let cols = im2col(backend, x, groups, (kh, kw), window);
+    let y = matmul_groups(backend, &cols, as_slice(w), batch * yh * yw, k, ych, groups);
+
+    let y = NdArray::from_shape_vec(ndarray::IxDyn(&[batch, yh, yw, ych]), y).unwrap();
+    let cols = NdArray::from_shape_vec(ndarray::IxDyn(&[groups, batch, yh, yw, k]), cols).unwrap();
+    (y, cols)
+}
+
+pub fn conv2d_with_cols(backend: &dyn Backend, cols: &NdArray, w: &NdArray) -> NdArray {
+    let c_shape = cols.shape();
+    let (groups, batch, yh, yw, k) = (c_shape[0], c_shape[1], c_shape[2], c_shape[3], c_shape[4]);
+    let ych = w.shape()[3];
+    let y = matmul_groups(
+        backend,
+        as_slice(cols),
+        as_slice(w),
+        batch * yh * yw,
+        k,
+        ych,
+        groups,
+    );
+    NdArray::from_shape_vec(ndarray::IxDyn(&[batch, yh, yw, ych]), y).unwrap()
+}
+
+pub fn filter_grad(
+    backend: &dyn Backend,
+    cols: &NdArray,
+    gy: &NdArray,
+    w_shape: &[usize],
+) -> NdArray {
+    let groups = cols.shape()[0];
+    filter_grad_groups(backend, as_slice(cols), as_slice(gy), w_shape, groups)
+}
+
+/// Transposed convolution of `gy`.
+///
+/// The number of groups and the spatial size of the output are taken from `x` if given,
+/// as in `Conv2DTranspose`.
+pub fn conv2d_transpose(
+    backend: &dyn Backend,
+    gy: &NdArray,
+    w: &NdArray,
+    x: Option<&NdArray>,
+    window: &Window2D,
+    groups: usize,
+) -> NdArray {
+    let gy_shape = gy.shape();
+    let f_shape = w.shape();
+    assert_eq!(
+        gy_shape.len(),
+        4,
+        "ag::conv2d: Input must be 4D (got {:?})",
+        gy_shape
+    );
+    assert_eq!(
+        f_shape.len(),
+        4,
+        "ag::conv2d: Filter must be 4D (got {:?})",
+        f_shape
+    );
+    let (batch, yh, yw, ych) = (gy_shape[0], gy_shape[1], gy_shape[2], gy_shape[3]);
+    let (kh, kw, kch) = (f_shape[0], f_shape[1], f_shape[2]);
+    assert_eq!(
+        ych, f_shape[3],
+        "ag::conv2d: Number of input channels ({:?}) must match last filter dim ({:?})",
+        ych, f_shape[3]
+    );
+    let groups = x.map_or(groups, |x| x.shape()[3] / kch);
+    assert_ne!(groups, 0, "ag::conv2d_transpose: groups must be positive");
+    assert_eq!(
+        ych % groups,
+        0,
+        "ag::conv2d: Number of input channels ({:?}) must be divisible by groups ({:?})",
+        ych,
+        groups
+    );
+    let xch = groups * kch;
+    let (xh, xw) = match x {
+        Some(x) if window.out_size((x.shape()[1], x.shape()[2]), (kh, kw)) == (yh, yw) => {
+            (x.shape()[1], x.shape()[2])
+        }
+        _ => window.in_size((yh, yw), (kh, kw)),
+    };
+    let params = Im2ColParams {
+        channels: xch,
+        height: xh,
+        width: xw,
+        kernel: (kh, kw),
+        pad: window.pads((xh, xw), (kh, kw)),
+        stride: window.stride,
+        dilation: window.dilation,
+    };
+
+    // cols_g = gy_g w_g^T for each group
+    let (rows, k, n) = (batch * yh * yw, kh * kw * kch, ych / groups);
+    let gy = group_major(as_slice(gy), rows, groups);
+    let w = group_major(as_slice(w), k, groups);
+    let mut cols = vec![0.; groups * rows * k];
+    backend.sgemm_batch(
+        &GemmParams {
+            trans_a: false,
+            trans_b: true,
+            m: rows,
+            n: k,
+            k: n,
+            alpha: 1.,
+            beta: 0.,
+        },
+        &split_regions(groups, &gy),
+        &split_regions(groups, &w),
+        &mut split_regions_mut(groups, &mut cols),
+    );
+    // Col2im buffer must be initialized with zeros
+    let mut gx = vec![0.; batch * xh * xw * xch];
+    backend.col2im_nhwc(&params, batch, groups, &cols, &mut gx);
+    NdArray::from_shape_vec(ndarray::IxDyn(&[batch, xh, xw, xch]), gx).unwrap()
+}
+
+/// Gradient of the filter of a transposed convolution whose input is `x` and
+/// output gradient is `gy`.
+pub fn transpose_filter_grad(
+    backend: &dyn Backend,
+    gy: &NdArray,
+    x: &NdArray,
+    w_shape: &[usize],
+    window: &Window2D,
+) -> NdArray {
+    let groups = gy.shape()[3] / w_shape[2];
+    let cols = im2col(backend, gy, groups, (w_shape[0], w_shape[1]), window);
+    filter_grad_groups(backend, &cols, as_slice(x), w_shape, groups)
+}
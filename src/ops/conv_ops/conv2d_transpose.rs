@@ -1,17 +1,30 @@
 use super::*;
 
+/// 2D transposed convolution.
+///
+/// Takes an optional third input that has as many channels as the output
+/// (e.g. the input of the `Conv2D` being differentiated).
+/// The number of groups is inferred from it if given, otherwise `groups` is used.
+/// Its spatial size is also used as the output's one if consistent with the first input,
+/// since several sizes can result in the same convolution output.
 pub struct Conv2DTranspose {
-    pub pad: usize,
-    pub stride: usize,
-    pub dilation: usize,
+    pub padding: Padding,
+    pub stride: (usize, usize),
+    pub dilation: (usize, usize),
+    pub groups: usize,
+    pub format: DataFormat,
 }
 
+// The number of groups of this is inferred from the shapes of its inputs.
 pub struct Conv2DTransposeFilterGrad {
-    pub pad: usize,
-    pub stride: usize,
-    pub dilation: usize,
+    pub padding: Padding,
+    pub stride: (usize, usize),
+    pub dilation: (usize, usize),
+    pub format: DataFormat,
 }
 
+impl_window2d!(Conv2DTranspose, Conv2DTransposeFilterGrad);
+
 impl ::op::Op for Conv2DTranspose {
     fn name(&self) -> &str {
         "Conv2DTranspose"
@@ -21,21 +34,23 @@ impl ::op::Op for Conv2DTranspose {
         let xs = ctx.grab_inputs();
 
         let gy: &NdArray = xs[0]; // (batch, ych, yh, yw)
-        let w: &NdArray = xs[1]; // (ych, xch, kh, kw)
+        let w: &NdArray = xs[1]; // (ych, xch / groups, kh, kw)
+
+        if self.format.is_channels_last() {
+            let gx = super::conv2d_nhwc::conv2d_transpose(
+                ctx.backend(),
+                gy,
+                w,
+                xs.get(2).cloned(),
+                &self.window(),
+                self.groups,
+            );
+            return vec![Ok(gx)];
+        }
+
         let gy_shape = gy.shape();
         let f_shape = w.shape();
 
-        let batch_size = gy_shape[0];
-        let ych = gy_shape[1];
-        let yh = gy_shape[2];
-        let yw = gy_shape[3];
-
-        let xch = f_shape[1];
-        let kh = f_shape[2];
-        let kw = f_shape[3];
-        let xh = get_xh!(self, yh, kh);
-        let xw = get_xw!(self, yw, kw);
-
         assert_eq!(
             gy_shape.len(),
             4,
@@ -48,102 +63,86 @@ impl ::op::Op for Conv2DTranspose {
             "ag::conv2d: Filter must be 4D (got {:?})",
             f_shape
         );
+
+        let batch_size = gy_shape[0];
+        let ych = gy_shape[1];
+        let yh = gy_shape[2];
+        let yw = gy_shape[3];
+
+        let kch = f_shape[1];
+        let kh = f_shape[2];
+        let kw = f_shape[3];
+        let window = self.window();
+        let (xh, xw) = match xs.get(2) {
+            Some(x) if window.out_size((x.shape()[2], x.shape()[3]), (kh, kw)) == (yh, yw) => {
+                (x.shape()[2], x.shape()[3])
+            }
+            _ => window.in_size((yh, yw), (kh, kw)),
+        };
+        let pad = window.pads((xh, xw), (kh, kw));
+        let groups = if let Some(x) = xs.get(2) {
+            x.shape()[1] / kch
+        } else {
+            self.groups
+        };
+        assert_ne!(groups, 0, "ag::conv2d_transpose: groups must be positive");
+        let xch = groups * kch;
+
         assert_eq!(
             ych, f_shape[0],
-            "ag::conv2d: Number of input channels ({:?}) must match second filter dim ({:?})",
+            "ag::conv2d: Number of input channels ({:?}) must match first filter dim ({:?})",
             ych, f_shape[0]
         );
+        assert_eq!(
+            ych % groups,
+            0,
+            "ag::conv2d: Number of input channels ({:?}) must be divisible by groups ({:?})",
+            ych,
+            groups
+        );
 
-        // sgemm params
-        let k = ych;
+        // sgemm params (per group)
+        let k = ych / groups;
         let n = yh * yw;
-        let m = kh * kw * xch;
+        let m = kh * kw * kch;
 
         let num_elements_in_batch_gx = xch * xh * xw;
         let num_elements_in_batch_col = xch * kh * kw * yh * yw;
 
-        let gy = unsafe { slice::from_raw_parts(gy.as_ptr(), gy.len()) };
-        let w: &f32 = unsafe { &*w.as_ptr() };
-        let col = alloc_uninitialized_buf(batch_size * num_elements_in_batch_col);
+        let mut col = vec![0.; batch_size * num_elements_in_batch_col];
         // Col2im buffer must be initialized with zeros
-        let gx = vec![0.; batch_size * num_elements_in_batch_gx];
+        let mut gx = vec![0.; batch_size * num_elements_in_batch_gx];
 
-        #[cfg(feature = "mkl")]
-        {
-            cblas_sgemm_batch_wrapper(
-                true,
-                false,
+        let backend = ctx.backend();
+        backend.sgemm_batch(
+            &GemmParams {
+                trans_a: true,
+                trans_b: false,
                 m,
                 n,
                 k,
-                &[1.],
-                vec![w; batch_size],
-                get_region_heads(batch_size, gy),
-                &[0.],
-                get_region_heads(batch_size, col.as_slice()),
-                1,
-                batch_size,
-            );
-
-            (0..batch_size).into_par_iter().for_each(|i| {
-                // for each mini-batch
-                let col_region_head = &col[i * num_elements_in_batch_col];
-                let gx_region_head = &gx[i * num_elements_in_batch_gx];
-                col2im(
-                    col_region_head,
-                    xch,
-                    xh,
-                    xw,
-                    kh,
-                    kw,
-                    self.pad,
-                    self.pad,
-                    self.stride,
-                    self.stride,
-                    self.dilation,
-                    self.dilation,
-                    gx_region_head,
-                );
-            });
-        }
-        #[cfg(not(feature = "mkl"))]
-        {
-            let num_elements_in_batch_gy = ych * yh * yw;
-            // fallback: parallel sgemm + col2im using rayon
-            (0..batch_size).into_par_iter().for_each(|i| {
+                alpha: 1.,
+                beta: 0.,
+            },
+            &get_group_regions(as_slice(w), batch_size, groups, 0, k * m),
+            &get_group_regions(as_slice(gy), batch_size, groups, ych * n, k * n),
+            &mut split_regions_mut(batch_size * groups, &mut col),
+        );
+        let params = Im2ColParams {
+            channels: xch,
+            height: xh,
+            width: xw,
+            kernel: (kh, kw),
+            pad,
+            stride: self.stride,
+            dilation: self.dilation,
+        };
+        gx.par_chunks_mut(num_elements_in_batch_gx)
+            .zip(col.par_chunks(num_elements_in_batch_col))
+            .for_each(|(gx, col)| {
                 // for each mini-batch
-                let gy_region_head = &gy[i * num_elements_in_batch_gy];
-                let col_region_head = &col[i * num_elements_in_batch_col];
-                let gx_region_head = &gx[i * num_elements_in_batch_gx];
-                sgemm(
-                    true,
-                    false,
-                    w,
-                    gy_region_head,
-                    col_region_head,
-                    m,
-                    n,
-                    k,
-                    1.,
-                    0.,
-                );
-                col2im(
-                    col_region_head,
-                    xch,
-                    xh,
-                    xw,
-                    kh,
-                    kw,
-                    self.pad,
-                    self.pad,
-                    self.stride,
-                    self.stride,
-                    self.dilation,
-                    self.dilation,
-                    gx_region_head,
-                );
+                backend.col2im(&params, col, gx);
             });
-        }
 
         let gx = NdArray::from_shape_vec(ndarray::IxDyn(&[batch_size, xch, xh, xw]), gx);
         vec![Ok(gx.unwrap())]
@@ -156,20 +155,47 @@ impl ::op::Op for Conv2DTranspose {
         let gx = Tensor::builder()
             .set_inputs(vec![gy, w])
             .build(super::conv2d::Conv2D {
-                pad: self.pad,
+                padding: self.padding,
                 stride: self.stride,
                 dilation: self.dilation,
+                groups: Groups::Inferred,
+                format: self.format,
             });
 
         let gw = Tensor::builder()
             .set_inputs(vec![gy, x, &::ops::stop_gradient(w)])
             .build(Conv2DTransposeFilterGrad {
-                pad: self.pad,
+                padding: self.padding,
                 stride: self.stride,
                 dilation: self.dilation,
+                format: self.format,
             });
 
-        vec![Some(gx), Some(gw)]
+        let mut ret = vec![Some(gx), Some(gw)];
+        if xs.len() == 3 {
+            ret.push(None);
+        }
+        ret
+    }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> ::op::StaticShapeResult {
+        let (window, format) = (self.window(), self.format);
+        let gy = xs[0].static_shape().map(|s| format.to_nchw(&s));
+        let w = xs[1].static_shape().map(|s| format.filter_to_nchw(&s));
+        let mut ret = conv_static_shape(gy, w, 4, 0, 1, self.groups as isize, |axis, y, k| {
+            window.static_in_len(axis, y, k)
+        })?;
+        if let (Some(ref mut ret), Some(x)) = (ret.as_mut(), xs.get(2)) {
+            let x = x.static_shape().map(|s| format.to_nchw(&s));
+            ret[1] = x.as_ref().map_or(-1, |x| x[1]);
+            // The spatial size may be taken from `x`, so it's certain only if both agree.
+            for i in 2..4 {
+                if x.as_ref().map_or(true, |x| x[i] != ret[i]) {
+                    ret[i] = -1;
+                }
+            }
+        }
+        Ok(ret.map(|s| format.from_nchw(&s)))
     }
 }
 
@@ -184,63 +210,71 @@ impl ::op::Op for Conv2DTransposeFilterGrad {
         let x = xs[1];
         let k_shape = xs[2].shape();
 
+        if self.format.is_channels_last() {
+            let gw = super::conv2d_nhwc::transpose_filter_grad(
+                ctx.backend(),
+                gy,
+                x,
+                k_shape,
+                &self.window(),
+            );
+            return vec![Ok(gw)];
+        }
+
         let x_shape = x.shape();
         let gy_shape = gy.shape();
 
         let batch_size = x_shape[0];
-        let (kh, kw) = (k_shape[2], k_shape[3]);
+        let (kch, kh, kw) = (k_shape[1], k_shape[2], k_shape[3]);
+        let groups = gy_shape[1] / kch;
+
+        let window = self.window();
+        let (yh, yw) = window.out_size((gy_shape[2], gy_shape[3]), (kh, kw));
+        let params = Im2ColParams {
+            channels: gy_shape[1],
+            height: gy_shape[2],
+            width: gy_shape[3],
+            kernel: (kh, kw),
+            pad: window.pads((gy_shape[2], gy_shape[3]), (kh, kw)),
+            stride: self.stride,
+            dilation: self.dilation,
+        };
 
         let num_elements_in_batch_g = { gy_shape[1] * gy_shape[2] * gy_shape[3] };
-        let num_elements_in_batch_c = {
-            get_yh!(self, gy_shape[2], kh) * get_yw!(self, gy_shape[3], kw) * kh * kw * gy_shape[1]
-        };
+        let num_elements_in_batch_c = { yh * yw * kh * kw * gy_shape[1] };
         let num_elements_in_batch_x = x_shape[1] * x_shape[2] * x_shape[3];
 
-        // sgemm params
-        let m = x_shape[1];
-        let n = kh * kw * gy_shape[1];
-        let k = get_yh!(self, gy_shape[2], kh) * get_yw!(self, gy_shape[3], kw);
-
-        let x = unsafe { slice::from_raw_parts(x.as_ptr(), x.len()) };
-        let gy = unsafe { slice::from_raw_parts(gy.as_ptr(), gy.len()) };
-        let cols = alloc_uninitialized_buf(batch_size * num_elements_in_batch_c);
-        let gw = alloc_uninitialized_buf(k_shape[0] * k_shape[1] * k_shape[2] * k_shape[3]);
-        let gw_head = unsafe { &*gw.as_ptr() };
-
-        (0..batch_size).into_par_iter().for_each(|i| {
-            let c_region_head = &cols[i * num_elements_in_batch_c];
-            let g_region_head = &gy[i * num_elements_in_batch_g];
-            im2col(
-                g_region_head,
-                gy_shape[1],
-                gy_shape[2],
-                gy_shape[3],
-                kh,
-                kw,
-                self.pad,
-                self.pad,
-                self.stride,
-                self.stride,
-                self.dilation,
-                self.dilation,
-                c_region_head,
-            );
-        });
+        // sgemm params (per group)
+        let m = x_shape[1] / groups;
+        let n = kh * kw * kch;
+        let k = yh * yw;
+
+        let x = as_slice(x);
+        let gy = as_slice(gy);
+        let mut cols = vec![0.; batch_size * num_elements_in_batch_c];
+        let mut gw = vec![0.; k_shape[0] * k_shape[1] * k_shape[2] * k_shape[3]];
+
+        let backend = ctx.backend();
+        cols.par_chunks_mut(num_elements_in_batch_c)
+            .zip(gy.par_chunks(num_elements_in_batch_g))
+            .for_each(|(cols, gy)| {
+                backend.im2col(&params, gy, cols);
+            });
 
         for i in 0..batch_size {
-            let x_region_head = &x[i * num_elements_in_batch_x];
-            let c_region_head = &cols[i * num_elements_in_batch_c];
-            sgemm(
-                false,
-                true,
-                x_region_head,
-                c_region_head,
-                gw_head,
-                m,
-                n,
-                k,
-                1.,
-                (i != 0) as i32 as f32,
+            backend.sgemm_batch(
+                &GemmParams {
+                    trans_a: false,
+                    trans_b: true,
+                    m,
+                    n,
+                    k,
+                    alpha: 1.,
+                    beta: (i != 0) as i32 as f32,
+                },
+                &get_group_regions(&x[i * num_elements_in_batch_x..], 1, groups, 0, m * k),
+                &get_group_regions(&cols[i * num_elements_in_batch_c..], 1, groups, 0, n * k),
+                &mut split_regions_mut(groups, &mut gw),
             );
         }
 
@@ -252,19 +286,23 @@ impl ::op::Op for Conv2DTransposeFilterGrad {
         let x = xs[1];
 
         let ggy = Tensor::builder()
-            .set_inputs(vec![x, gw])
+            .set_inputs(vec![x, gw, gy])
             .build(Conv2DTranspose {
-                pad: self.pad,
+                padding: self.padding,
                 stride: self.stride,
                 dilation: self.dilation,
+                groups: 0,
+                format: self.format,
             });
 
         let ggx = Tensor::builder()
             .set_inputs(vec![gy, gw])
             .build(super::conv2d::Conv2D {
-                pad: self.pad,
+                padding: self.padding,
                 stride: self.stride,
                 dilation: self.dilation,
+                groups: Groups::Inferred,
+                format: self.format,
             });
 
         vec![Some(ggy), Some(ggx), None]
@@ -274,56 +312,54 @@ impl ::op::Op for Conv2DTransposeFilterGrad {
 #[test]
 fn test_tensor_size_after_convolution_t() {
     let op = Conv2DTranspose {
-        pad: 0,
-        stride: 1,
-        dilation: 1,
+        padding: Padding::Valid,
+        stride: (1, 1),
+        dilation: (1, 1),
+        groups: 1,
+        format: DataFormat::NCHW,
     };
     let (yh, yw) = (2, 2);
     let (kh, kw) = (2, 2);
-    let xh = get_xh!(&op, yh, kh);
-    let xw = get_xw!(&op, yw, kw);
+    let (xh, xw) = op.window().in_size((yh, yw), (kh, kw));
     assert_eq!(xh, 3);
     assert_eq!(xw, 3);
 }
 
 #[test]
 fn test_parallel_col2im() {
+    use backend::{Backend, CpuBackend};
     let batch_size = 2;
     let op = Conv2DTranspose {
-        pad: 0,
-        stride: 1,
-        dilation: 1,
+        padding: Padding::Valid,
+        stride: (1, 1),
+        dilation: (1, 1),
+        groups: 1,
+        format: DataFormat::NCHW,
     };
     let xch = 3;
     let (yh, yw) = (2, 2);
     let (kh, kw) = (2, 2);
-    let xh = get_xh!(&op, yh, kh);
-    let xw = get_xw!(&op, yw, kw);
+    let (xh, xw) = op.window().in_size((yh, yw), (kh, kw));
 
     let num_elements_in_batch_col = xch * kh * kw * yh * yw;
     let num_elements_in_batch_im = xch * xh * xw;
     let cols = vec![2f32; 108 * batch_size];
-    let im = vec![0f32; batch_size * xch * xh * xw];
-
-    (0..batch_size).into_par_iter().for_each(|i| unsafe {
-        let cols_head = (&cols[i * num_elements_in_batch_col]) as *const f32;
-        let im_head = (&im[i * num_elements_in_batch_im]) as *const f32;
-        col2im_cpu(
-            cols_head,
-            xch as i32,
-            xh as i32,
-            xw as i32,
-            kh as i32,
-            kw as i32,
-            op.pad as i32,
-            op.pad as i32,
-            op.stride as i32,
-            op.stride as i32,
-            op.dilation as i32,
-            op.dilation as i32,
-            im_head,
-        );
-    });
+    let mut im = vec![0f32; batch_size * xch * xh * xw];
+    let params = Im2ColParams {
+        channels: xch,
+        height: xh,
+        width: xw,
+        kernel: (kh, kw),
+        pad: (0, 0, 0, 0),
+        stride: op.stride,
+        dilation: op.dilation,
+    };
+
+    im.par_chunks_mut(num_elements_in_batch_im)
+        .zip(cols.par_chunks(num_elements_in_batch_col))
+        .for_each(|(im, cols)| {
+            CpuBackend.col2im(&params, cols, im);
+        });
 
     assert_eq!(
         im,
@@ -340,9 +376,11 @@ fn test_parallel_col2im() {
 fn test_deconv() {
     use op::Op;
     let op = Conv2DTranspose {
-        pad: 0,
-        stride: 1,
-        dilation: 1,
+        padding: Padding::Valid,
+        stride: (1, 1),
+        dilation: (1, 1),
+        groups: 1,
+        format: DataFormat::NCHW,
     };
     let (kh, kw) = (2, 2);
     let (xch, ych) = (3, 2);
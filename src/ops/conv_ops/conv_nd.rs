@@ -0,0 +1,585 @@
+//! Convolutions with an arbitrary number of spatial axes (`ag::conv1d`, `ag::conv3d`).
+//!
+//! Same strategy as `conv2d`: inputs are unfolded with `Backend::im2col_nd`
+//! and multiplied by filters with gemm.
+use super::*;
+use backend::{Backend, Im2ColNdParams};
+use NdArray;
+
+/// Convolution over `ndim` spatial axes.
+pub struct ConvND {
+    pub ndim: usize,
+    pub pad: usize,
+    pub stride: usize,
+    pub dilation: usize,
+}
+
+pub struct ConvNDWithCols {
+    pub ndim: usize,
+    pub pad: usize,
+    pub stride: usize,
+    pub dilation: usize,
+}
+
+pub struct ConvNDFilterGrad {
+    pub ndim: usize,
+    pub pad: usize,
+    pub stride: usize,
+    pub dilation: usize,
+}
+
+/// Transposed convolution over `ndim` spatial axes.
+pub struct ConvNDTranspose {
+    pub ndim: usize,
+    pub pad: usize,
+    pub stride: usize,
+    pub dilation: usize,
+}
+
+pub struct ConvNDTransposeFilterGrad {
+    pub ndim: usize,
+    pub pad: usize,
+    pub stride: usize,
+    pub dilation: usize,
+}
+
+macro_rules! impl_params {
+    ($name:ident) => {
+        impl $name {
+            #[inline]
+            fn params(&self) -> Params {
+                Params {
+                    ndim: self.ndim,
+                    pad: self.pad,
+                    stride: self.stride,
+                    dilation: self.dilation,
+                }
+            }
+        }
+    };
+}
+
+impl_params!(ConvND);
+impl_params!(ConvNDWithCols);
+impl_params!(ConvNDFilterGrad);
+impl_params!(ConvNDTranspose);
+impl_params!(ConvNDTransposeFilterGrad);
+
+// Hyper parameters shared by the ops in this module.
+struct Params {
+    ndim: usize,
+    pad: usize,
+    stride: usize,
+    dilation: usize,
+}
+
+impl Params {
+    fn conv(&self) -> ConvND {
+        ConvND {
+            ndim: self.ndim,
+            pad: self.pad,
+            stride: self.stride,
+            dilation: self.dilation,
+        }
+    }
+
+    fn conv_with_cols(&self) -> ConvNDWithCols {
+        ConvNDWithCols {
+            ndim: self.ndim,
+            pad: self.pad,
+            stride: self.stride,
+            dilation: self.dilation,
+        }
+    }
+
+    fn filter_grad(&self) -> ConvNDFilterGrad {
+        ConvNDFilterGrad {
+            ndim: self.ndim,
+            pad: self.pad,
+            stride: self.stride,
+            dilation: self.dilation,
+        }
+    }
+
+    fn transpose(&self) -> ConvNDTranspose {
+        ConvNDTranspose {
+            ndim: self.ndim,
+            pad: self.pad,
+            stride: self.stride,
+            dilation: self.dilation,
+        }
+    }
+
+    fn transpose_filter_grad(&self) -> ConvNDTransposeFilterGrad {
+        ConvNDTransposeFilterGrad {
+            ndim: self.ndim,
+            pad: self.pad,
+            stride: self.stride,
+            dilation: self.dilation,
+        }
+    }
+
+    fn check_shapes(&self, x_shape: &[usize], w_shape: &[usize], ch_axis: usize) {
+        let rank = self.ndim + 2;
+        assert_eq!(
+            x_shape.len(),
+            rank,
+            "ag::conv{}d: Input must be {}D (got {:?})",
+            self.ndim,
+            rank,
+            x_shape
+        );
+        assert_eq!(
+            w_shape.len(),
+            rank,
+            "ag::conv{}d: Filter must be {}D (got {:?})",
+            self.ndim,
+            rank,
+            w_shape
+        );
+        assert_eq!(
+            x_shape[1], w_shape[ch_axis],
+            "ag::conv{}d: Number of input's channel ({:?}) must match filter dim {} ({:?})",
+            self.ndim, x_shape[1], ch_axis, w_shape[ch_axis]
+        );
+    }
+
+    // Spatial shape of the output of the convolution
+    fn out_shape(&self, x: &[usize], kernel: &[usize]) -> Vec<usize> {
+        x.iter()
+            .zip(kernel)
+            .map(|(&x, &k)| (x + 2 * self.pad - (self.dilation * (k - 1) + 1)) / self.stride + 1)
+            .collect()
+    }
+
+    // Spatial shape of the input of the convolution (inverse of `out_shape`)
+    fn in_shape(&self, y: &[usize], kernel: &[usize]) -> Vec<usize> {
+        y.iter()
+            .zip(kernel)
+            .map(|(&y, &k)| self.stride * (y - 1) - 2 * self.pad + (self.dilation * (k - 1) + 1))
+            .collect()
+    }
+
+    // Unfolds each image of `x` (batch, channel, shape...) into columns.
+    fn im2col(
+        &self,
+        backend: &dyn Backend,
+        x: &[f32],
+        batch_size: usize,
+        channels: usize,
+        shape: &[usize],
+        kernel: &[usize],
+    ) -> Vec<f32> {
+        let (pad, stride, dilation) = self.per_axis();
+        let params = Im2ColNdParams {
+            channels,
+            shape,
+            kernel,
+            pad: &pad,
+            stride: &stride,
+            dilation: &dilation,
+        };
+        let num_elements_in_batch_x = x.len() / batch_size;
+        let num_elements_in_batch_c = channels
+            * kernel.iter().product::<usize>()
+            * params.out_shape().iter().product::<usize>();
+        let mut cols = vec![0.; batch_size * num_elements_in_batch_c];
+        cols.par_chunks_mut(num_elements_in_batch_c)
+            .zip(x.par_chunks(num_elements_in_batch_x))
+            .for_each(|(cols, x)| {
+                backend.im2col_nd(&params, x, cols);
+            });
+        cols
+    }
+
+    fn per_axis(&self) -> (Vec<usize>, Vec<usize>, Vec<usize>) {
+        (
+            vec![self.pad; self.ndim],
+            vec![self.stride; self.ndim],
+            vec![self.dilation; self.ndim],
+        )
+    }
+}
+
+// Computes `sum_i a[i] * b[i]^T` where `a[i]` is (m, k) and `b[i]` is (n, k).
+fn accumulate_filter_grad(
+    backend: &dyn Backend,
+    a: &[f32],
+    b: &[f32],
+    batch_size: usize,
+    m: usize,
+    n: usize,
+    k: usize,
+) -> Vec<f32> {
+    let mut gw = vec![0.; m * n];
+    for i in 0..batch_size {
+        backend.sgemm(
+            &GemmParams {
+                trans_a: false,
+                trans_b: true,
+                m,
+                n,
+                k,
+                alpha: 1.,
+                beta: (i != 0) as i32 as f32, // beta: if i==0 then 0 else 1
+            },
+            &a[i * m * k..(i + 1) * m * k],
+            &b[i * n * k..(i + 1) * n * k],
+            &mut gw,
+        );
+    }
+    gw
+}
+
+impl ::op::Op for ConvND {
+    fn name(&self) -> &str {
+        "ConvND"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> ::op::ComputeResult {
+        let xs = ctx.grab_inputs();
+        let x: &NdArray = xs[0];
+        let w: &NdArray = xs[1];
+        let params = self.params();
+        params.check_shapes(x.shape(), w.shape(), 1);
+
+        let (batch_size, xch) = (x.shape()[0], x.shape()[1]);
+        let ych = w.shape()[0];
+        let kernel = &w.shape()[2..];
+        let x_spatial = &x.shape()[2..];
+        let y_spatial = params.out_shape(x_spatial, kernel);
+
+        // Parameters for sgemm
+        let m = ych;
+        let n = y_spatial.iter().product::<usize>();
+        let k = xch * kernel.iter().product::<usize>();
+
+        let backend = ctx.backend();
+        let c = params.im2col(backend, as_slice(x), batch_size, xch, x_spatial, kernel);
+        let mut y = vec![0.; batch_size * m * n];
+        backend.sgemm_batch(
+            &GemmParams {
+                trans_a: false,
+                trans_b: false,
+                m,
+                n,
+                k,
+                alpha: 1.,
+                beta: 0.,
+            },
+            &vec![as_slice(w); batch_size],
+            &split_regions(batch_size, &c),
+            &mut split_regions_mut(batch_size, &mut y),
+        );
+
+        let mut y_shape = vec![batch_size, ych];
+        y_shape.extend(&y_spatial);
+        let mut cols_shape = vec![batch_size, xch];
+        cols_shape.extend(kernel);
+        cols_shape.extend(&y_spatial);
+
+        let y = NdArray::from_shape_vec(ndarray::IxDyn(&y_shape), y).unwrap();
+        let cols = NdArray::from_shape_vec(ndarray::IxDyn(&cols_shape), c).unwrap();
+        vec![Ok(y), Ok(cols)]
+    }
+
+    fn grad(&self, gy: &Tensor, xs: &[&Tensor], y: &Tensor) -> Vec<Option<Tensor>> {
+        let x = xs[0];
+        let w = xs[1];
+        let params = self.params();
+
+        let gx = Tensor::builder()
+            .set_inputs(vec![gy, w])
+            .build(params.transpose());
+
+        let cols = &::ops::nth_tensor(y, 1);
+        let gw = Tensor::builder()
+            .set_inputs(vec![cols, gy, w])
+            .set_backprop_inputs(vec![x.clone(), gy.clone()])
+            .build(params.filter_grad());
+
+        vec![Some(gx), Some(gw)]
+    }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> ::op::StaticShapeResult {
+        let (pad, stride, dilation) = (
+            self.pad as isize,
+            self.stride as isize,
+            self.dilation as isize,
+        );
+        let (x, w) = (xs[0].static_shape(), xs[1].static_shape());
+        conv_static_shape(x, w, self.ndim + 2, 1, 0, 1, |_, x, k| {
+            (x + 2 * pad - (dilation * (k - 1) + 1)) / stride + 1
+        })
+    }
+}
+
+impl ::op::Op for ConvNDWithCols {
+    fn name(&self) -> &str {
+        "ConvNDWithCols"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> ::op::ComputeResult {
+        let xs = ctx.grab_inputs();
+        let cols: &NdArray = xs[0]; // (batch, xch, kernel..., y...)
+        let w: &NdArray = xs[1];
+
+        let batch_size = cols.shape()[0];
+        let ych = w.shape()[0];
+        let y_spatial = &cols.shape()[self.ndim + 2..];
+
+        // Parameters for sgemm
+        let m = ych;
+        let n = y_spatial.iter().product::<usize>();
+        let k = w.len() / ych;
+
+        let mut y = vec![0.; batch_size * m * n];
+        ctx.backend().sgemm_batch(
+            &GemmParams {
+                trans_a: false,
+                trans_b: false,
+                m,
+                n,
+                k,
+                alpha: 1.,
+                beta: 0.,
+            },
+            &vec![as_slice(w); batch_size],
+            &split_regions(batch_size, as_slice(cols)),
+            &mut split_regions_mut(batch_size, &mut y),
+        );
+
+        let mut y_shape = vec![batch_size, ych];
+        y_shape.extend(y_spatial);
+        let y = NdArray::from_shape_vec(ndarray::IxDyn(&y_shape), y).unwrap();
+        vec![Ok(y)]
+    }
+
+    fn grad(&self, gy: &Tensor, xs: &[&Tensor], y: &Tensor) -> Vec<Option<Tensor>> {
+        let cols = xs[0];
+        let w = xs[1];
+        let params = self.params();
+
+        let gx = Tensor::builder()
+            .set_inputs(vec![gy, w])
+            .build(params.transpose());
+
+        let gw = Tensor::builder()
+            .set_inputs(vec![cols, gy, w])
+            .set_backprop_inputs(vec![
+                y.inputs_on_backprop.as_ref().unwrap()[0].clone(),
+                gy.clone(),
+            ])
+            .build(params.filter_grad());
+
+        vec![Some(gx), Some(gw)]
+    }
+}
+
+impl ::op::Op for ConvNDFilterGrad {
+    fn name(&self) -> &str {
+        "ConvNDFilterGrad"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> ::op::ComputeResult {
+        let xs = ctx.grab_inputs();
+        let cols = xs[0]; // must be columns
+        let gy = xs[1];
+        let k_shape = xs[2].shape();
+
+        let (batch_size, ych) = (gy.shape()[0], gy.shape()[1]);
+        let m = ych;
+        let n = xs[2].len() / ych;
+        let k = gy.len() / (batch_size * ych);
+
+        let gw = accumulate_filter_grad(
+            ctx.backend(),
+            as_slice(gy),
+            as_slice(cols),
+            batch_size,
+            m,
+            n,
+            k,
+        );
+        vec![Ok(NdArray::from_shape_vec(k_shape, gw).unwrap())]
+    }
+
+    fn grad(&self, ggw: &Tensor, xs: &[&Tensor], y: &Tensor) -> Vec<Option<Tensor>> {
+        let cols = xs[0];
+        let gy = xs[1]; // For example, gradient of output of ConvND.
+        let params = self.params();
+
+        // grad grad
+        let gx = Tensor::builder()
+            .set_inputs(vec![gy, ggw])
+            .build(params.transpose());
+
+        let ggy = Tensor::builder()
+            .set_inputs(vec![cols, ggw])
+            .set_backprop_inputs(vec![
+                y.inputs_on_backprop.as_ref().unwrap()[0].clone(),
+                ggw.clone(),
+            ])
+            .build(params.conv_with_cols());
+
+        vec![Some(gx), Some(ggy), None]
+    }
+}
+
+impl ::op::Op for ConvNDTranspose {
+    fn name(&self) -> &str {
+        "ConvNDTranspose"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> ::op::ComputeResult {
+        let xs = ctx.grab_inputs();
+        let gy: &NdArray = xs[0]; // (batch, ych, y...)
+        let w: &NdArray = xs[1]; // (ych, xch, kernel...)
+        let params = self.params();
+        params.check_shapes(gy.shape(), w.shape(), 0);
+
+        let (batch_size, ych) = (gy.shape()[0], gy.shape()[1]);
+        let xch = w.shape()[1];
+        let kernel = &w.shape()[2..];
+        let y_spatial = &gy.shape()[2..];
+        let x_spatial = params.in_shape(y_spatial, kernel);
+
+        // sgemm params
+        let k = ych;
+        let n = y_spatial.iter().product::<usize>();
+        let m = xch * kernel.iter().product::<usize>();
+
+        let num_elements_in_batch_gx = xch * x_spatial.iter().product::<usize>();
+        let num_elements_in_batch_col = m * n;
+
+        let mut col = vec![0.; batch_size * num_elements_in_batch_col];
+        // Col2im buffer must be initialized with zeros
+        let mut gx = vec![0.; batch_size * num_elements_in_batch_gx];
+
+        let backend = ctx.backend();
+        backend.sgemm_batch(
+            &GemmParams {
+                trans_a: true,
+                trans_b: false,
+                m,
+                n,
+                k,
+                alpha: 1.,
+                beta: 0.,
+            },
+            &vec![as_slice(w); batch_size],
+            &split_regions(batch_size, as_slice(gy)),
+            &mut split_regions_mut(batch_size, &mut col),
+        );
+        let (pad, stride, dilation) = params.per_axis();
+        let params = Im2ColNdParams {
+            channels: xch,
+            shape: &x_spatial,
+            kernel,
+            pad: &pad,
+            stride: &stride,
+            dilation: &dilation,
+        };
+        gx.par_chunks_mut(num_elements_in_batch_gx)
+            .zip(col.par_chunks(num_elements_in_batch_col))
+            .for_each(|(gx, col)| {
+                backend.col2im_nd(&params, col, gx);
+            });
+
+        let mut gx_shape = vec![batch_size, xch];
+        gx_shape.extend(&x_spatial);
+        let gx = NdArray::from_shape_vec(ndarray::IxDyn(&gx_shape), gx);
+        vec![Ok(gx.unwrap())]
+    }
+
+    fn grad(&self, gy: &Tensor, xs: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
+        let x = xs[0];
+        let w = xs[1];
+        let params = self.params();
+
+        let gx = Tensor::builder()
+            .set_inputs(vec![gy, w])
+            .build(params.conv());
+
+        let gw = Tensor::builder()
+            .set_inputs(vec![gy, x, &::ops::stop_gradient(w)])
+            .build(params.transpose_filter_grad());
+
+        vec![Some(gx), Some(gw)]
+    }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> ::op::StaticShapeResult {
+        let (pad, stride, dilation) = (
+            self.pad as isize,
+            self.stride as isize,
+            self.dilation as isize,
+        );
+        let (gy, w) = (xs[0].static_shape(), xs[1].static_shape());
+        conv_static_shape(gy, w, self.ndim + 2, 0, 1, 1, |_, y, k| {
+            stride * (y - 1) - 2 * pad + (dilation * (k - 1) + 1)
+        })
+    }
+}
+
+impl ::op::Op for ConvNDTransposeFilterGrad {
+    fn name(&self) -> &str {
+        "ConvNDTransposeFilterGrad"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> ::op::ComputeResult {
+        let xs = ctx.grab_inputs();
+        let gy = xs[0];
+        let x = xs[1];
+        let k_shape = xs[2].shape();
+        let params = self.params();
+
+        let (batch_size, xch) = (x.shape()[0], x.shape()[1]);
+        let kernel = &k_shape[2..];
+        let cols = params.im2col(
+            ctx.backend(),
+            as_slice(gy),
+            batch_size,
+            gy.shape()[1],
+            &gy.shape()[2..],
+            kernel,
+        );
+
+        // sgemm params
+        let m = xch;
+        let n = xs[2].len() / xch;
+        let k = x.len() / (batch_size * xch);
+
+        let gw = accumulate_filter_grad(ctx.backend(), as_slice(x), &cols, batch_size, m, n, k);
+        vec![Ok(NdArray::from_shape_vec(k_shape, gw).unwrap())]
+    }
+
+    fn grad(&self, gw: &Tensor, xs: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
+        let gy = xs[0];
+        let x = xs[1];
+        let params = self.params();
+
+        let ggy = Tensor::builder()
+            .set_inputs(vec![x, gw])
+            .build(params.transpose());
+
+        let ggx = Tensor::builder()
+            .set_inputs(vec![gy, gw])
+            .build(params.conv());
+
+        vec![Some(ggy), Some(ggx), None]
+    }
+}
+
+#[test]
+fn test_conv_nd_shapes() {
+    let params = ConvND {
+        ndim: 3,
+        pad: 1,
+        stride: 2,
+        dilation: 1,
+    }
+    .params();
+    let y = params.out_shape(&[5, 6, 7], &[3, 2, 3]);
+    assert_eq!(y, vec![3, 4, 4]);
+    assert_eq!(params.in_shape(&y, &[3, 2, 3]), vec![5, 6, 7]);
+}
@@ -1,26 +1,79 @@
-use rayon::iter::*;
-
 use super::*;
+use backend::MaxPoolParams;
 use tensor::Tensor;
 
+/// Pool size of `MaxPool2D`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PoolSize {
+    /// Windows of `(h, w)`; both must be positive.
+    Window(usize, usize),
+    /// A window over the whole `(h, w)` plane.
+    Global,
+}
+
+impl PoolSize {
+    /// Pool size for an `(xh, xw)` input.
+    #[inline]
+    fn get(self, (xh, xw): (usize, usize)) -> (usize, usize) {
+        match self {
+            PoolSize::Window(h, w) => {
+                assert!(h > 0 && w > 0, "ag::max_pool2d: pool_size must be positive");
+                (h, w)
+            }
+            PoolSize::Global => (xh, xw),
+        }
+    }
+}
+
+/// 2D max pooling.
+///
+/// `stride` is `(h, w)`.
+/// Padded elements are never selected.
 pub struct MaxPool2D {
-    pub pad: usize,
-    pub stride: usize,
-    pub size: usize,
+    pub padding: Padding,
+    pub stride: (usize, usize),
+    pub size: PoolSize,
+    pub format: DataFormat,
 }
 
+// Takes `x` as the third input to know the shape of gx.
 pub struct MaxPool2DGrad {
-    pad: usize,
-    stride: usize,
-    size: usize,
+    padding: Padding,
+    stride: (usize, usize),
+    size: PoolSize,
+    format: DataFormat,
 }
 
 pub struct MaxPool2DGradGrad {
-    pad: usize,
-    stride: usize,
-    size: usize,
+    padding: Padding,
+    stride: (usize, usize),
+    size: PoolSize,
+    format: DataFormat,
+}
+
+macro_rules! impl_pool_window {
+    ($($op:ident),*) => {$(
+        impl $op {
+            #[inline]
+            fn window(&self) -> Window2D {
+                Window2D {
+                    padding: self.padding,
+                    stride: self.stride,
+                    dilation: (1, 1),
+                }
+            }
+
+            /// Pool size for an `(xh, xw)` input.
+            #[inline]
+            fn pool_size(&self, x: (usize, usize)) -> (usize, usize) {
+                self.size.get(x)
+            }
+        }
+    )*};
 }
 
+impl_pool_window!(MaxPool2D, MaxPool2DGradGrad);
+
 impl ::op::Op for MaxPool2D {
     fn name(&self) -> &str {
         "MaxPool"
@@ -29,49 +82,78 @@ impl ::op::Op for MaxPool2D {
     fn compute(&self, ctx: ::runtime::OpComputeContext) -> ::op::ComputeResult {
         let xs = ctx.grab_inputs();
         let x: &NdArray = xs[0];
-        let x_shape = x.shape();
+        let x_shape = self.format.to_nchw(x.shape());
         let batch = x_shape[0];
         let c = x_shape[1];
         let xh = x_shape[2];
         let xw = x_shape[3];
 
-        let yh = (xh + 2 * self.pad - self.size) / self.stride + 1;
-        let yw = (xw + 2 * self.pad - self.size) / self.stride + 1;
+        let window = self.window();
+        let size = self.pool_size((xh, xw));
+        let (yh, yw) = window.out_size((xh, xw), size);
+        let (pad_top, _, pad_left, _) = window.pads((xh, xw), size);
         let all_len_y = batch * c * yh * yw;
-        let output = alloc_uninitialized_buf(all_len_y);
-        let indices = alloc_uninitialized_buf(all_len_y);
-        (0..batch).into_par_iter().for_each(|b| {
-            max_pool_unbatched(
-                unsafe { &*x.as_ptr() },
-                self.pad,
-                xh,
-                xw,
-                yh,
-                yw,
-                c,
-                b,
-                self.size,
-                self.stride,
-                unsafe { &*output.as_ptr() },
-                unsafe { &*indices.as_ptr() },
-            );
-        });
-        let output = NdArray::from_shape_vec(ndarray::IxDyn(&[batch, c, yh, yw]), output);
-        let indices = NdArray::from_shape_vec(ndarray::IxDyn(&[batch, c, yh, yw]), indices);
+        let mut output = vec![0.; all_len_y];
+        let mut indices = vec![0.; all_len_y];
+        let params = MaxPoolParams {
+            batch,
+            channels: c,
+            height: xh,
+            width: xw,
+            channels_last: self.format.is_channels_last(),
+            size,
+            stride: self.stride,
+            pad: (pad_top, pad_left),
+            out_size: (yh, yw),
+        };
+        ctx.backend()
+            .max_pool(&params, as_slice(x), &mut output, &mut indices);
+        let y_shape = self.format.from_nchw(&[batch, c, yh, yw]);
+        let output = NdArray::from_shape_vec(ndarray::IxDyn(&y_shape), output);
+        let indices = NdArray::from_shape_vec(ndarray::IxDyn(&y_shape), indices);
         vec![Ok(output.unwrap()), Ok(indices.unwrap())]
     }
 
-    fn grad(&self, gy: &Tensor, _: &[&Tensor], y: &Tensor) -> Vec<Option<Tensor>> {
+    fn grad(&self, gy: &Tensor, xs: &[&Tensor], y: &Tensor) -> Vec<Option<Tensor>> {
         let indices = ::ops::nth_tensor(y, 1);
         let gx = Tensor::builder()
-            .set_inputs(vec![&gy, &indices])
+            .set_inputs(vec![&gy, &indices, xs[0]])
             .build(MaxPool2DGrad {
-                pad: self.pad,
+                padding: self.padding,
                 stride: self.stride,
                 size: self.size,
+                format: self.format,
             });
         vec![Some(gx)]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> ::op::StaticShapeResult {
+        let x_shape = match xs[0].static_shape() {
+            Some(a) => a,
+            None => return Ok(None),
+        };
+        if x_shape.len() != 4 {
+            return Err(format!("Input must be 4D: {:?}", x_shape));
+        }
+        let x_shape = self.format.to_nchw(&x_shape);
+        let window = self.window();
+        let (yh, yw) = match self.size {
+            PoolSize::Window(h, w) => {
+                let (h, w) = self.size.get((h, w));
+                (
+                    window.static_out_len(0, x_shape[2], h as isize),
+                    window.static_out_len(1, x_shape[3], w as isize),
+                )
+            }
+            PoolSize::Global => (
+                window.static_out_len(0, x_shape[2], x_shape[2]),
+                window.static_out_len(1, x_shape[3], x_shape[3]),
+            ),
+        };
+        Ok(Some(
+            self.format.from_nchw(&[x_shape[0], x_shape[1], yh, yw]),
+        ))
+    }
 }
 
 #[test]
@@ -79,9 +161,10 @@ fn test_max_pool2d() {
     use op::Op;
 
     let op = MaxPool2D {
-        pad: 0,
-        stride: 1,
-        size: 2,
+        padding: Padding::Valid,
+        stride: (1, 1),
+        size: PoolSize::Window(2, 2),
+        format: DataFormat::NCHW,
     };
     let x = vec![0., 1., 2., 5., 4., 3., 6., 7., 8.];
     let y = op.compute(::runtime::OpComputeContext::new(
@@ -107,25 +190,11 @@ impl ::op::Op for MaxPool2DGrad {
         let xs = ctx.grab_inputs();
         let gy = xs[0];
         let argmax = xs[1];
-        let gy_shape = gy.shape();
-        let batch = gy_shape[0];
-        let c = gy_shape[1];
-        let yh = gy_shape[2];
-        let yw = gy_shape[3];
-
-        let xh = self.stride * (yh - 1) - 2 * self.pad + self.size;
-        let xw = self.stride * (yw - 1) - 2 * self.pad + self.size;
-        let gx = vec![0.; batch * c * xh * xw];
-        max_pool_grad(
-            unsafe { &*gy.as_ptr() },
-            yh,
-            yw,
-            c,
-            batch,
-            unsafe { &*gx.as_ptr() },
-            unsafe { &*argmax.as_ptr() },
-        );
-        let gx = NdArray::from_shape_vec(ndarray::IxDyn(&[batch, c, xh, xw]), gx);
+        let x_shape = xs[2].shape();
+        let mut gx = vec![0.; xs[2].len()];
+        ctx.backend()
+            .max_pool_grad(as_slice(gy), as_slice(argmax), &mut gx);
+        let gx = NdArray::from_shape_vec(x_shape, gx);
         vec![Ok(gx.unwrap())]
     }
 
@@ -134,11 +203,12 @@ impl ::op::Op for MaxPool2DGrad {
         let ggy = Tensor::builder()
             .set_inputs(vec![ggx, argmax])
             .build(MaxPool2DGradGrad {
-                pad: self.pad,
+                padding: self.padding,
                 stride: self.stride,
                 size: self.size,
+                format: self.format,
             });
-        vec![Some(ggy), None]
+        vec![Some(ggy), None, None]
     }
 }
 
@@ -150,25 +220,18 @@ impl ::op::Op for MaxPool2DGradGrad {
     fn compute(&self, ctx: ::runtime::OpComputeContext) -> ::op::ComputeResult {
         let xs = ctx.grab_inputs();
         let ggx = xs[0];
-        let x_shape = ggx.shape();
+        let x_shape = self.format.to_nchw(ggx.shape());
         let batch = x_shape[0];
         let c = x_shape[1];
         let xh = x_shape[2];
         let xw = x_shape[3];
-        let yh = (xh + 2 * self.pad - self.size) / self.stride + 1;
-        let yw = (xw + 2 * self.pad - self.size) / self.stride + 1;
+        let (yh, yw) = self.window().out_size((xh, xw), self.pool_size((xh, xw)));
         let argmax = xs[1];
-        let ggy = alloc_uninitialized_buf(batch * c * yh * yw);
-        max_pool_grad_grad(
-            unsafe { &*ggx.as_ptr() },
-            yh,
-            yw,
-            c,
-            batch,
-            unsafe { &*ggy.as_ptr() },
-            unsafe { &*argmax.as_ptr() },
-        );
-        let ggy = NdArray::from_shape_vec(ndarray::IxDyn(&[batch, c, yh, yw]), ggy).unwrap();
+        let mut ggy = vec![0.; batch * c * yh * yw];
+        ctx.backend()
+            .max_pool_grad_grad(as_slice(ggx), as_slice(argmax), &mut ggy);
+        let y_shape = self.format.from_nchw(&[batch, c, yh, yw]);
+        let ggy = NdArray::from_shape_vec(ndarray::IxDyn(&y_shape), ggy).unwrap();
         vec![Ok(ggy)]
     }
 
@@ -1,555 +1,365 @@
-#[cfg(feature = "blas")]
-extern crate cblas_sys;
-extern crate intel_mkl_src;
-extern crate libc;
-#[cfg(not(feature = "blas"))]
-extern crate matrixmultiply;
 extern crate ndarray;
-#[cfg(feature = "blas")]
-extern crate openblas_src;
 extern crate rayon;
-use self::libc::{c_float, c_int};
 #[allow(unused_imports)]
-use self::rayon::iter::*;
+use self::rayon::prelude::*;
+use backend::{split_regions, split_regions_mut, GemmParams, Im2ColParams};
 use ndarray_ext::NdArray;
 use std::f32;
-use std::mem;
 use std::slice;
 use tensor::Tensor;
 
-macro_rules! get_xw {
-    ($op:expr, $yw:expr, $kw:expr) => {
-        $op.stride * ($yw - 1) - 2 * $op.pad + ($op.dilation * ($kw - 1) + 1)
-    };
-}
-
-macro_rules! get_xh {
-    ($op:expr, $yh:expr, $kh:expr) => {
-        $op.stride * ($yh - 1) - 2 * $op.pad + ($op.dilation * ($kh - 1) + 1)
-    };
+/// Padding of the spatial axes of 2D convolutions and poolings.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Padding {
+    /// No padding.
+    Valid,
+    /// Pads so that the output size is `ceil(input / stride)`, as TensorFlow's `"SAME"`.
+    ///
+    /// If the total padding of an axis is odd, the extra one goes to its end (bottom/right).
+    Same,
+    /// `(top, bottom, left, right)` zeros.
+    Explicit(usize, usize, usize, usize),
 }
 
-macro_rules! get_yw {
-    ($op:expr, $xw:expr, $kw:expr) => {
-        ($xw + 2 * $op.pad - ($op.dilation * ($kw - 1) + 1)) / $op.stride + 1
-    };
-}
-
-macro_rules! get_yh {
-    ($op:expr, $xh:expr, $kh:expr) => {
-        ($xh + 2 * $op.pad - ($op.dilation * ($kh - 1) + 1)) / $op.stride + 1
-    };
-}
-
-pub mod conv2d;
-pub mod conv2d_transpose;
-pub mod max_pool2d;
+impl Padding {
+    /// `(begin, end)` pads of `axis` (0: height, 1: width) of length `size`.
+    ///
+    /// `kernel` is the dilated kernel size.
+    fn pads(&self, axis: usize, size: usize, kernel: usize, stride: usize) -> (usize, usize) {
+        match *self {
+            Padding::Valid => (0, 0),
+            Padding::Same => {
+                let out = (size + stride - 1) / stride;
+                let total = ((out - 1) * stride + kernel).saturating_sub(size);
+                (total / 2, total - total / 2)
+            }
+            Padding::Explicit(top, bottom, left, right) => {
+                if axis == 0 {
+                    (top, bottom)
+                } else {
+                    (left, right)
+                }
+            }
+        }
+    }
 
-type MklInt = i64;
+    /// Output length of `axis` of length `size`.
+    fn out_len(&self, axis: usize, size: usize, kernel: usize, stride: usize) -> usize {
+        let (begin, end) = self.pads(axis, size, kernel, stride);
+        (size + begin + end - kernel) / stride + 1
+    }
 
-#[repr(C)]
-#[derive(Clone, Copy, Debug)]
-enum CblasTranspose {
-    CblasNoTrans = 111,
-    CblasTrans = 112,
-    // CblasConjTrans = 113,
+    /// Input length of `axis` inferred from its output length.
+    ///
+    /// Several input lengths can give the same output; this returns the one a transposed
+    /// convolution produces by convention (the largest one for `Same`).
+    fn in_len(&self, axis: usize, out: usize, kernel: usize, stride: usize) -> usize {
+        match *self {
+            Padding::Same => out * stride,
+            _ => {
+                let (begin, end) = self.pads(axis, 0, kernel, stride);
+                stride * (out - 1) + kernel - begin - end
+            }
+        }
+    }
 }
 
-type CblasLayout = usize;
-
-extern "C" {
-    // Batched sgemm from intel MKL
-    fn cblas_sgemm_batch(
-        layout: CblasLayout,
-        transa_array: *const CblasTranspose, // batch of CblasTranspose
-        transb_array: *const CblasTranspose, // batch of CblasTranspose
-        m_array: *const MklInt,              // batch of m
-        n_array: *const MklInt,              // batch of n
-        k_array: *const MklInt,              // batch of k
-        alpha_array: *const libc::c_float,   // batch of alpha
-        a_array: *const *const libc::c_float, // a
-        lda_array: *const MklInt,            // batch of lda
-        b_array: *const *const libc::c_float, // b
-        ldb_array: *const MklInt,            // batch of ldb
-        beta_array: *const libc::c_float,    // batch of beta
-        c_array: *mut *mut libc::c_float,    // c
-        ldc_array: *const MklInt,            // batch of odc
-        group_count: MklInt,                 // batch size
-        group_size: *const MklInt,
-    ); // num of matrices in each batch
+/// Number of groups of `Conv2D`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Groups {
+    /// Input channels are split into this many groups.
+    Count(usize),
+    /// One group per input channel, as in `ag::depthwise_conv2d`.
+    Depthwise,
+    /// Inferred from the shapes of the input and filter, as in the gradients.
+    Inferred,
 }
 
-#[inline]
-fn cblas_sgemm_batch_wrapper(
-    trans_a: bool,
-    trans_b: bool,
-    m: usize,
-    n: usize,
-    k: usize,
-    alpha: &[f32],
-    a_array: Vec<&f32>,
-    b_array: Vec<&f32>,
-    beta: &[f32],
-    c_array: Vec<&f32>,
-    group_count: usize,
-    size_per_group: usize,
-) {
-    let size_per_group = size_per_group as usize;
-    let lda = if trans_a { m } else { k } as MklInt;
-    let ldb = if trans_b { k } else { n } as MklInt;
-    let ldc = n as MklInt;
-    let trans_a = if trans_a {
-        CblasTranspose::CblasTrans
-    } else {
-        CblasTranspose::CblasNoTrans
-    };
-    let trans_b = if trans_b {
-        CblasTranspose::CblasTrans
-    } else {
-        CblasTranspose::CblasNoTrans
-    };
-    unsafe {
-        const CBLAS_ROW_MAGER: usize = 101;
-        cblas_sgemm_batch(
-            CBLAS_ROW_MAGER,
-            vec![trans_a; group_count].as_slice().as_ptr(),
-            vec![trans_b; group_count].as_slice().as_ptr(),
-            vec![m as MklInt; group_count].as_slice().as_ptr(),
-            vec![n as MklInt; group_count].as_slice().as_ptr(),
-            vec![k as MklInt; group_count].as_slice().as_ptr(),
-            alpha.as_ptr(),
-            mem::transmute(a_array.as_slice().as_ptr()), // safe
-            vec![lda as MklInt; group_count].as_slice().as_ptr(),
-            mem::transmute(b_array.as_slice().as_ptr()), // safe
-            vec![ldb as MklInt; group_count].as_slice().as_ptr(),
-            beta.as_ptr(),
-            mem::transmute(c_array.as_slice().as_ptr()), // ???
-            vec![ldc as MklInt; group_count].as_slice().as_ptr(),
-            group_count as MklInt,
-            vec![size_per_group as MklInt; group_count]
-                .as_slice()
-                .as_ptr(),
+impl Groups {
+    /// Number of groups for `xch` input channels, `kch` filter channels per group
+    /// and `ych` output channels, which are checked to be consistent.
+    fn resolve(self, xch: usize, kch: usize, ych: usize) -> usize {
+        let groups = match self {
+            Groups::Count(groups) => groups,
+            Groups::Depthwise => {
+                assert_eq!(
+                    kch, 1,
+                    "ag::depthwise_conv2d: Filter must have one channel per group (got {:?})",
+                    kch
+                );
+                assert!(
+                    xch != 0 && ych % xch == 0,
+                    "ag::depthwise_conv2d: Number of output channels ({:?}) must be a multiple of input channels ({:?})",
+                    ych,
+                    xch
+                );
+                return xch;
+            }
+            Groups::Inferred => xch / kch,
+        };
+        assert_ne!(groups, 0, "ag::conv2d: groups must be positive");
+        assert_eq!(
+            xch,
+            groups * kch,
+            "ag::conv2d: Number of input's channel ({:?}) must be groups ({:?}) * filter's channels per group ({:?})",
+            xch,
+            groups,
+            kch
+        );
+        assert_eq!(
+            ych % groups,
+            0,
+            "ag::conv2d: Number of output channels ({:?}) must be divisible by groups ({:?})",
+            ych,
+            groups
         );
+        groups
     }
-}
 
-#[test]
-fn test_sgemm_batch_trans_a() {
-    let batch = 2;
-    let w = vec![0., 1., 2., 3., 4., 5.]; // (2, 3)
-    let x = vec![0., 1., 2., 3., 4., 5., 6., 7.]; // (2, 2, 2)
-    let z = alloc_uninitialized_buf(12); // (2, 2, 2)
-    let m = 3; // row of op(a)
-    let n = 2; // col of op(b)
-    let k = 2; // col of op(a)
-    cblas_sgemm_batch_wrapper(
-        true,
-        false,
-        m,
-        n,
-        k,
-        &[1.],              // alpha
-        vec![&w[0], &w[0]], // a
-        get_region_heads(batch, &x),
-        &[0.], // beta
-        get_region_heads(batch, &z),
-        1,
-        batch,
-    );
-    assert_eq!(
-        z,
-        vec![6., 9., 8., 13., 10., 17., 18., 21., 28., 33., 38., 45.]
-    );
+    /// `groups` of `conv_static_shape`.
+    fn static_count(self) -> isize {
+        match self {
+            Groups::Count(groups) => groups as isize,
+            _ => 0,
+        }
+    }
 }
 
-#[test]
-fn test_sgemm_batch() {
-    let batch = 2;
-    let x = vec![0., 1., 2., 3.]; // (2, 2)
-    let y = vec![0., 1., 2., 3., 4., 5., 6., 7.]; // (2, 2, 2)
-    let z = alloc_uninitialized_buf(8); // (2, 2, 2)
-
-    cblas_sgemm_batch_wrapper(
-        false,
-        false,
-        2,                  // m
-        2,                  // n
-        2,                  // k
-        &[1.],              // alpha
-        vec![&x[0], &x[0]], // a
-        get_region_heads(batch, &y),
-        &[0.], // beta
-        get_region_heads(batch, &z),
-        1,
-        batch,
-    );
-    assert_eq!(z, vec![2., 3., 6., 11., 6., 7., 26., 31.]);
+/// Memory layout of the images of 2D convolutions and poolings.
+///
+/// Filters of convolutions are `(out_ch, in_ch / groups, kh, kw)` in `NCHW`, and
+/// `(kh, kw, in_ch / groups, out_ch)` in `NHWC` as in TensorFlow.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DataFormat {
+    /// `(batch, channel, height, width)`
+    NCHW,
+    /// `(batch, height, width, channel)`
+    NHWC,
 }
 
-#[inline]
-fn get_region_heads<'a>(size: usize, slice: &'a [f32]) -> Vec<&'a f32> {
-    let size_per_batch = slice.len() / size;
-    let mut ret = Vec::with_capacity(size_per_batch);
-    for i in 0..size {
-        ret.push(&slice[i * size_per_batch]);
+impl DataFormat {
+    #[inline]
+    fn is_channels_last(&self) -> bool {
+        *self == DataFormat::NHWC
     }
-    ret
-}
-
-#[link(name = "conv")]
-#[no_mangle]
-extern "C" {
-    fn im2col_cpu(
-        data_im: *const c_float,
-        channels: c_int,
-        height: c_int,
-        width: c_int,
-        kernel_h: c_int,
-        kernel_w: c_int,
-        pad_h: c_int,
-        pad_w: c_int,
-        stride_h: c_int,
-        stride_w: c_int,
-        dilation_h: c_int,
-        dilation_w: c_int,
-        data_col: *const c_float,
-    );
 
-    fn col2im_cpu(
-        data_col: *const c_float,
-        channels: c_int,
-        height: c_int,
-        width: c_int,
-        kernel_h: c_int,
-        kernel_w: c_int,
-        pad_h: c_int,
-        pad_w: c_int,
-        stride_h: c_int,
-        stride_w: c_int,
-        dilation_h: c_int,
-        dilation_w: c_int,
-        data_im: *const c_float,
-    );
-
-    fn max_pool_cpu_unbatched(
-        input: *const c_float,
-        pad: c_int,
-        h: c_int,
-        w: c_int,
-        out_h: c_int,
-        out_w: c_int,
-        c: c_int,
-        b: c_int,
-        size: c_int,
-        stride: c_int,
-        output: *const c_float,
-        argmax: *const c_float,
-        float_min: c_float,
-    );
+    /// Permutes an image shape in this format into NCHW order.
+    ///
+    /// Shapes that are not 4D are returned as they are.
+    fn to_nchw<T: Copy>(&self, s: &[T]) -> Vec<T> {
+        match *self {
+            DataFormat::NHWC if s.len() == 4 => vec![s[0], s[3], s[1], s[2]],
+            _ => s.to_vec(),
+        }
+    }
 
-    #[allow(dead_code)]
-    fn max_pool_cpu(
-        input: *const c_float,
-        pad: c_int,
-        h: c_int,
-        w: c_int,
-        out_h: c_int,
-        out_w: c_int,
-        c: c_int,
-        batch: c_int,
-        size: c_int,
-        stride: c_int,
-        output: *const c_float,
-        argmax: *const c_float,
-        float_min: c_float,
-    );
+    /// Inverse of `to_nchw`.
+    fn from_nchw<T: Copy>(&self, s: &[T]) -> Vec<T> {
+        match *self {
+            DataFormat::NHWC if s.len() == 4 => vec![s[0], s[2], s[3], s[1]],
+            _ => s.to_vec(),
+        }
+    }
 
-    fn max_pool_grad_cpu(
-        input: *const c_float,
-        h: c_int,
-        w: c_int,
-        c: c_int,
-        batch: c_int,
-        gx: *const c_float,
-        argmax: *const c_float,
-    );
+    /// Permutes a filter shape in this format into `(out_ch, in_ch / groups, kh, kw)` order.
+    fn filter_to_nchw<T: Copy>(&self, s: &[T]) -> Vec<T> {
+        match *self {
+            DataFormat::NHWC if s.len() == 4 => vec![s[3], s[2], s[0], s[1]],
+            _ => s.to_vec(),
+        }
+    }
+}
 
-    fn max_pool_grad_grad_cpu(
-        ggx: *const c_float,
-        h: c_int,
-        w: c_int,
-        c: c_int,
-        batch: c_int,
-        ggy: *const c_float,
-        argmax: *const c_float,
-    );
+/// Spatial geometry of a 2D window op: padding, stride and dilation along (h, w).
+#[derive(Clone, Copy)]
+struct Window2D {
+    padding: Padding,
+    stride: (usize, usize),
+    dilation: (usize, usize),
 }
 
-#[inline(always)]
-fn max_pool_unbatched(
-    input: &c_float,
-    pad: usize,
-    h: usize,
-    w: usize,
-    out_h: usize,
-    out_w: usize,
-    c: usize,
-    b: usize,
-    size: usize,
-    stride: usize,
-    output: &c_float,
-    argmax: &c_float,
-) {
-    unsafe {
-        max_pool_cpu_unbatched(
-            input as *const _,
-            pad as c_int,
-            h as c_int,
-            w as c_int,
-            out_h as c_int,
-            out_w as c_int,
-            c as c_int,
-            b as c_int,
-            size as c_int,
-            stride as c_int,
-            output as *const _,
-            argmax as *const _,
-            f32::MIN,
+impl Window2D {
+    #[inline]
+    fn kernel(&self, (kh, kw): (usize, usize)) -> (usize, usize) {
+        (
+            self.dilation.0 * (kh - 1) + 1,
+            self.dilation.1 * (kw - 1) + 1,
         )
     }
-}
 
-#[inline]
-#[allow(dead_code)]
-fn max_pool(
-    input: &c_float,
-    pad: usize,
-    h: usize,
-    w: usize,
-    out_h: usize,
-    out_w: usize,
-    c: usize,
-    batch: usize,
-    size: usize,
-    stride: usize,
-    output: &c_float,
-    argmax: &c_float,
-) {
-    unsafe {
-        max_pool_cpu(
-            input as *const _,
-            pad as c_int,
-            h as c_int,
-            w as c_int,
-            out_h as c_int,
-            out_w as c_int,
-            c as c_int,
-            batch as c_int,
-            size as c_int,
-            stride as c_int,
-            output as *const _,
-            argmax as *const _,
-            f32::MIN,
-        )
+    /// `(top, bottom, left, right)` pads for an input of size `x`.
+    fn pads(&self, x: (usize, usize), kernel: (usize, usize)) -> (usize, usize, usize, usize) {
+        let (kh, kw) = self.kernel(kernel);
+        let (top, bottom) = self.padding.pads(0, x.0, kh, self.stride.0);
+        let (left, right) = self.padding.pads(1, x.1, kw, self.stride.1);
+        (top, bottom, left, right)
     }
-}
 
-#[inline]
-fn max_pool_grad(
-    gy: &c_float,
-    h: usize,
-    w: usize,
-    c: usize,
-    batch: usize,
-    gx: &c_float,
-    argmax: &c_float,
-) {
-    unsafe {
-        max_pool_grad_cpu(
-            gy as *const _,
-            h as c_int,
-            w as c_int,
-            c as c_int,
-            batch as c_int,
-            gx as *const c_float,
-            argmax as *const c_float,
+    /// Output size for an input of size `x`.
+    fn out_size(&self, x: (usize, usize), kernel: (usize, usize)) -> (usize, usize) {
+        let (kh, kw) = self.kernel(kernel);
+        (
+            self.padding.out_len(0, x.0, kh, self.stride.0),
+            self.padding.out_len(1, x.1, kw, self.stride.1),
         )
     }
-}
 
-#[inline]
-fn max_pool_grad_grad(
-    ggx: &c_float,
-    h: usize,
-    w: usize,
-    c: usize,
-    batch: usize,
-    ggy: &c_float,
-    argmax: &c_float,
-) {
-    unsafe {
-        max_pool_grad_grad_cpu(
-            ggx as *const _,
-            h as c_int,
-            w as c_int,
-            c as c_int,
-            batch as c_int,
-            ggy as *const c_float,
-            argmax as *const c_float,
+    /// Input size inferred from the output size `y`.
+    fn in_size(&self, y: (usize, usize), kernel: (usize, usize)) -> (usize, usize) {
+        let (kh, kw) = self.kernel(kernel);
+        (
+            self.padding.in_len(0, y.0, kh, self.stride.0),
+            self.padding.in_len(1, y.1, kw, self.stride.1),
         )
     }
-}
 
-#[inline]
-fn im2col(
-    data_im: &c_float,
-    channels: usize,
-    height: usize,
-    width: usize,
-    kernel_h: usize,
-    kernel_w: usize,
-    pad_h: usize,
-    pad_w: usize,
-    stride_h: usize,
-    stride_w: usize,
-    dilation_h: usize,
-    dilation_w: usize,
-    data_col: &c_float,
-) {
-    unsafe {
-        im2col_cpu(
-            data_im as *const c_float,
-            channels as i32,
-            height as i32,
-            width as i32,
-            kernel_h as i32,
-            kernel_w as i32,
-            pad_h as i32,
-            pad_w as i32,
-            stride_h as i32,
-            stride_w as i32,
-            dilation_h as i32,
-            dilation_w as i32,
-            data_col,
-        )
+    /// Static output length of `axis` (-1 if unknown).
+    fn static_out_len(&self, axis: usize, x: isize, k: isize) -> isize {
+        let (stride, dilation) = self.axis(axis);
+        if x == -1 || k == -1 {
+            return -1;
+        }
+        let k = dilation * (k as usize - 1) + 1;
+        self.padding.out_len(axis, x as usize, k, stride) as isize
     }
-}
 
-#[inline]
-fn col2im(
-    data_col: &f32,
-    channels: usize,
-    height: usize,
-    width: usize,
-    kernel_h: usize,
-    kernel_w: usize,
-    pad_h: usize,
-    pad_w: usize,
-    stride_h: usize,
-    stride_w: usize,
-    dilation_h: usize,
-    dilation_w: usize,
-    data_im: &f32,
-) {
-    unsafe {
-        col2im_cpu(
-            data_col as *const c_float,
-            channels as c_int,
-            height as c_int,
-            width as c_int,
-            kernel_h as c_int,
-            kernel_w as c_int,
-            pad_h as c_int,
-            pad_w as c_int,
-            stride_h as c_int,
-            stride_w as c_int,
-            dilation_h as c_int,
-            dilation_w as c_int,
-            data_im as *const c_float,
-        )
+    /// Static input length of `axis` (-1 if unknown).
+    fn static_in_len(&self, axis: usize, y: isize, k: isize) -> isize {
+        let (stride, dilation) = self.axis(axis);
+        if y == -1 || k == -1 {
+            return -1;
+        }
+        let k = dilation * (k as usize - 1) + 1;
+        self.padding.in_len(axis, y as usize, k, stride) as isize
     }
-}
 
-#[inline]
-fn alloc_uninitialized_buf(size: usize) -> Vec<f32> {
-    let mut buf = Vec::with_capacity(size);
-    unsafe {
-        buf.set_len(size);
+    #[inline]
+    fn axis(&self, axis: usize) -> (usize, usize) {
+        if axis == 0 {
+            (self.stride.0, self.dilation.0)
+        } else {
+            (self.stride.1, self.dilation.1)
+        }
     }
-    buf
 }
 
-#[inline]
-fn sgemm(
-    trans_a: bool,
-    trans_b: bool,
-    a: &f32,
-    b: &f32,
-    c: &f32,
-    m: usize,
-    n: usize,
-    k: usize,
-    alpha: f32,
-    beta: f32,
-) {
-    let rsa = if trans_a { 1 } else { k };
-    let csa = if trans_a { m } else { 1 };
-    let rsb = if trans_b { 1 } else { n };
-    let csb = if trans_b { k } else { 1 };
-    let rsc = n;
-    let csc = 1;
-    unsafe {
-        let c: *mut f32 = mem::transmute(c);
-        matrixmultiply::sgemm(
-            m,
-            k,
-            n,
-            alpha,
-            a as *const f32,
-            rsa as isize,
-            csa as isize,
-            b as *const f32,
-            rsb as isize,
-            csb as isize,
-            beta,
-            c as *mut f32,
-            rsc as isize,
-            csc as isize,
-        )
+/// Static shape of a conv-like output: `(batch, channels, spatial dim fn)`.
+///
+/// `f` takes the index of a spatial axis and its input and kernel sizes.
+///
+/// Axis 1 of the filter has the number of channels per group.
+/// `groups == 0` means that the number of groups is unknown.
+fn conv_static_shape<F>(
+    x_shape: Option<Vec<isize>>,
+    w_shape: Option<Vec<isize>>,
+    rank: usize,
+    in_ch_axis: usize,
+    out_ch_axis: usize,
+    groups: isize,
+    f: F,
+) -> ::op::StaticShapeResult
+where
+    F: Fn(usize, isize, isize) -> isize,
+{
+    let (x_shape, w_shape) = match (x_shape, w_shape) {
+        (Some(a), Some(b)) => (a, b),
+        _ => return Ok(None),
+    };
+    if x_shape.len() != rank || w_shape.len() != rank {
+        return Err(format!(
+            "Input and filter must be {}D: {:?} vs {:?}",
+            rank, x_shape, w_shape
+        ));
     }
+    let channels = |axis: usize| {
+        let ch = w_shape[axis];
+        if axis != 1 {
+            ch
+        } else if ch == -1 || groups == 0 {
+            -1
+        } else {
+            ch * groups
+        }
+    };
+    let (xch, wch) = (x_shape[1], channels(in_ch_axis));
+    if xch != -1 && wch != -1 && xch != wch {
+        return Err(format!(
+            "Number of input channels mismatch: {:?} vs {:?}",
+            x_shape, w_shape
+        ));
+    }
+    let spatial = |i: usize| {
+        let (x, k) = (x_shape[i], w_shape[i]);
+        if x == -1 || k == -1 {
+            -1
+        } else {
+            f(i - 2, x, k)
+        }
+    };
+    let mut ret = vec![x_shape[0], channels(out_ch_axis)];
+    for i in 2..rank {
+        ret.push(spatial(i));
+    }
+    Ok(Some(ret))
 }
 
-#[test]
-fn test_gemm_trans_a() {
-    let a = [1., 2., 3., 4., 5., 6.];
-    let b = [1., 2., 3., 4.];
-    let c = [0.; 6];
-    let m = 3; // row of op(a)
-    let n = 2; // col of op(b)
-    let k = 2; // col of op(a)
-    sgemm(true, false, &a[0], &b[0], &c[0], m, n, k, 1., 0.);
-    assert_eq!(&c, &[13.0, 18.0, 17.0, 24.0, 21.0, 30.0]);
+macro_rules! impl_window2d {
+    ($($op:ident),*) => {$(
+        impl $op {
+            #[inline]
+            fn window(&self) -> Window2D {
+                Window2D {
+                    padding: self.padding,
+                    stride: self.stride,
+                    dilation: self.dilation,
+                }
+            }
+        }
+    )*};
 }
 
-#[test]
-fn test_gemm_trans_b() {
-    let a = [1., 2., 3., 4.];
-    let b = [1., 2., 3., 4., 5., 6.];
-    let c = [0.; 6];
-    let m = 2; // row of op(a)
-    let n = 3; // col of op(b)
-    let k = 2; // col of op(a)
-    sgemm(false, true, &a[0], &b[0], &c[0], m, n, k, 1., 0.);
-    assert_eq!(&c, &[5., 11., 17., 11., 25., 39.]);
+pub mod avg_pool2d;
+pub mod conv2d;
+mod conv2d_nhwc;
+pub mod conv2d_transpose;
+pub mod conv_nd;
+pub mod max_pool2d;
+pub mod resize;
+
+/// Regions of `len` elements for each pair of (batch, group) in `buf`.
+///
+/// Regions of a batch are `batch_stride` apart, and ones of a group are `len` apart.
+#[inline]
+fn get_group_regions(
+    buf: &[f32],
+    batch_size: usize,
+    groups: usize,
+    batch_stride: usize,
+    len: usize,
+) -> Vec<&[f32]> {
+    let mut ret = Vec::with_capacity(batch_size * groups);
+    for i in 0..batch_size {
+        for g in 0..groups {
+            let head = i * batch_stride + g * len;
+            ret.push(&buf[head..head + len]);
+        }
+    }
+    ret
+}
+
+#[inline]
+fn as_slice(a: &NdArray) -> &[f32] {
+    unsafe { slice::from_raw_parts(a.as_ptr(), a.len()) }
 }
 
 #[test]
 fn test_conv_filter_grad() {
     use op::Op;
     let op = conv2d::Conv2DFilterGrad {
-        pad: 0,
-        stride: 1,
-        dilation: 1,
+        padding: Padding::Valid,
+        stride: (1, 1),
+        dilation: (1, 1),
+        format: DataFormat::NCHW,
     };
 
     let (kh, kw) = (2, 2);
@@ -569,55 +379,3 @@ fn test_conv_filter_grad() {
     assert_eq!(w.shape(), ret[0].as_ref().unwrap().shape()); // (2, 3, 2, 2)
     assert_eq!(ret[0].clone().unwrap().into_raw_vec(), vec![8.; 24]);
 }
-
-#[test]
-fn test_sequential_sgemm() {
-    let x = [0., 1., 2., 3.];
-    let y = [0., 1., 2., 3.];
-    let z = [0.; 8];
-
-    for i in 0..2 {
-        sgemm(false, false, &x[0], &y[0], &z[i * 4], 2, 2, 2, 1., 0.)
-    }
-    assert_eq!([2.0, 3.0, 6.0, 11.0, 2.0, 3.0, 6.0, 11.0], z);
-}
-
-#[test]
-fn test_sgemm_acc() {
-    let x = [0., 1., 2., 3.];
-    let y = [0., 1., 2., 3.];
-    let z = [0.; 4];
-
-    let num_iter = 3.;
-
-    for _ in 0..num_iter as usize {
-        sgemm(false, false, &x[0], &y[0], &z[0], 2, 2, 2, 1., 1.)
-    }
-    assert_eq!(
-        [2. * num_iter, 3. * num_iter, 6. * num_iter, 11. * num_iter],
-        z
-    );
-}
-
-#[test]
-fn test_max_pool_cpu() {
-    let x = vec![0., 1., 2., 5., 4., 3., 6., 7., 8.];
-    let output = alloc_uninitialized_buf(4);
-    let argmax = alloc_uninitialized_buf(4);
-    max_pool(
-        &x[0],
-        0, // pad
-        3,
-        3, // h, w
-        2,
-        2, // out_h, out_w
-        1, // c
-        1, // batch
-        2, // size
-        1, // stride
-        &output[0],
-        &argmax[0],
-    );
-    assert_eq!(output, vec![5., 4., 7., 8.]);
-    assert_eq!(argmax, vec![3., 4., 7., 8.]);
-}
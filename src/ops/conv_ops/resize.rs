@@ -0,0 +1,290 @@
+use super::*;
+use tensor::Tensor;
+
+/// Interpolation of `Resize2D`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResizeMethod {
+    /// Takes the pixel at `floor(dst * src_len / dst_len)`.
+    Nearest,
+    /// Bilinear interpolation.
+    ///
+    /// If `align_corners` is true, the corner pixels of the input and output are aligned,
+    /// otherwise pixels are treated as squares whose centers are sampled (PyTorch's default).
+    Bilinear { align_corners: bool },
+}
+
+/// Resizes `(h, w)` of NCHW images to `size`.
+pub struct Resize2D {
+    pub size: (usize, usize),
+    pub method: ResizeMethod,
+}
+
+// Takes `x` as the second input to know the shape of gx.
+pub struct Resize2DGrad {
+    size: (usize, usize),
+    method: ResizeMethod,
+}
+
+/// Rearranges `(batch, c * r * r, h, w)` into `(batch, c, h * r, w * r)`, or the inverse.
+pub struct PixelShuffle {
+    pub factor: usize,
+    pub inverse: bool,
+}
+
+/// Source pixels of an output pixel along an axis: `(begin, end, weight of end)`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Tap(usize, usize, f32);
+
+impl ResizeMethod {
+    /// Taps of each output pixel of an axis resized from `len` to `out`.
+    fn taps(&self, len: usize, out: usize) -> Vec<Tap> {
+        (0..out)
+            .map(|i| match *self {
+                ResizeMethod::Nearest => {
+                    let src = (i * len / out).min(len - 1);
+                    Tap(src, src, 0.)
+                }
+                ResizeMethod::Bilinear { align_corners } => {
+                    let src = if align_corners {
+                        if out > 1 {
+                            i as f32 * (len - 1) as f32 / (out - 1) as f32
+                        } else {
+                            0.
+                        }
+                    } else {
+                        ((i as f32 + 0.5) * len as f32 / out as f32 - 0.5).max(0.)
+                    };
+                    let begin = (src.floor() as usize).min(len - 1);
+                    if begin + 1 < len {
+                        Tap(begin, begin + 1, src - begin as f32)
+                    } else {
+                        Tap(begin, begin, 0.)
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+fn get_nchw(shape: &[usize], name: &str) -> (usize, usize, usize, usize) {
+    assert_eq!(
+        shape.len(),
+        4,
+        "ag::{}: Input must be 4D (got {:?})",
+        name,
+        shape
+    );
+    (shape[0], shape[1], shape[2], shape[3])
+}
+
+impl ::op::Op for Resize2D {
+    fn name(&self) -> &str {
+        "Resize2D"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> ::op::ComputeResult {
+        let xs = ctx.grab_inputs();
+        let x = xs[0];
+        let (batch, c, xh, xw) = get_nchw(x.shape(), "resize");
+        let (yh, yw) = self.size;
+        let (rows, cols) = (self.method.taps(xh, yh), self.method.taps(xw, yw));
+
+        let x = as_slice(x);
+        let mut y = vec![0.; batch * c * yh * yw];
+        y.par_chunks_mut(yh * yw)
+            .zip(x.par_chunks(xh * xw))
+            .for_each(|(y, x)| {
+                // for each plane
+                for (i, &Tap(r0, r1, wr)) in rows.iter().enumerate() {
+                    let (x0, x1) = (&x[r0 * xw..], &x[r1 * xw..]);
+                    for (j, &Tap(c0, c1, wc)) in cols.iter().enumerate() {
+                        let top = x0[c0] + (x0[c1] - x0[c0]) * wc;
+                        let bottom = x1[c0] + (x1[c1] - x1[c0]) * wc;
+                        y[i * yw + j] = top + (bottom - top) * wr;
+                    }
+                }
+            });
+        let y = NdArray::from_shape_vec(ndarray::IxDyn(&[batch, c, yh, yw]), y);
+        vec![Ok(y.unwrap())]
+    }
+
+    fn grad(&self, gy: &Tensor, xs: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
+        let gx = Tensor::builder()
+            .set_inputs(vec![gy, xs[0]])
+            .build(Resize2DGrad {
+                size: self.size,
+                method: self.method,
+            });
+        vec![Some(gx)]
+    }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> ::op::StaticShapeResult {
+        let x_shape = match xs[0].static_shape() {
+            Some(a) => a,
+            None => return Ok(None),
+        };
+        if x_shape.len() != 4 {
+            return Err(format!("Input must be 4D: {:?}", x_shape));
+        }
+        Ok(Some(vec![
+            x_shape[0],
+            x_shape[1],
+            self.size.0 as isize,
+            self.size.1 as isize,
+        ]))
+    }
+}
+
+impl ::op::Op for Resize2DGrad {
+    fn name(&self) -> &str {
+        "Resize2DGrad"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> ::op::ComputeResult {
+        let xs = ctx.grab_inputs();
+        let gy = xs[0];
+        let (batch, c, xh, xw) = get_nchw(xs[1].shape(), "resize");
+        let (yh, yw) = self.size;
+        let (rows, cols) = (self.method.taps(xh, yh), self.method.taps(xw, yw));
+
+        let gy = as_slice(gy);
+        let mut gx = vec![0.; batch * c * xh * xw];
+        gx.par_chunks_mut(xh * xw)
+            .zip(gy.par_chunks(yh * yw))
+            .for_each(|(gx, gy)| {
+                // for each plane
+                for (i, &Tap(r0, r1, wr)) in rows.iter().enumerate() {
+                    for (j, &Tap(c0, c1, wc)) in cols.iter().enumerate() {
+                        let g = gy[i * yw + j];
+                        let (top, bottom) = (g * (1. - wr), g * wr);
+                        gx[r0 * xw + c0] += top * (1. - wc);
+                        gx[r0 * xw + c1] += top * wc;
+                        gx[r1 * xw + c0] += bottom * (1. - wc);
+                        gx[r1 * xw + c1] += bottom * wc;
+                    }
+                }
+            });
+        let gx = NdArray::from_shape_vec(ndarray::IxDyn(&[batch, c, xh, xw]), gx);
+        vec![Ok(gx.unwrap())]
+    }
+
+    fn grad(&self, ggx: &Tensor, _: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
+        // Resizing is linear, so this is the transpose of the transpose.
+        let ggy = Tensor::builder().set_input(ggx).build(Resize2D {
+            size: self.size,
+            method: self.method,
+        });
+        vec![Some(ggy), None]
+    }
+}
+
+impl ::op::Op for PixelShuffle {
+    fn name(&self) -> &str {
+        "PixelShuffle"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> ::op::ComputeResult {
+        let xs = ctx.grab_inputs();
+        let x = xs[0];
+        let r = self.factor;
+        let (batch, c, h, w) = get_nchw(x.shape(), "pixel_shuffle");
+        let y = if self.inverse {
+            assert!(
+                h % r == 0 && w % r == 0,
+                "ag::pixel_shuffle: Spatial size ({:?}, {:?}) must be divisible by factor ({:?})",
+                h,
+                w,
+                r
+            );
+            let shape = ndarray::IxDyn(&[batch, c * r * r, h / r, w / r]);
+            NdArray::from_shape_fn(shape, |i| {
+                let (ch, di, dj) = (i[1] / (r * r), i[1] / r % r, i[1] % r);
+                x[ndarray::IxDyn(&[i[0], ch, i[2] * r + di, i[3] * r + dj])]
+            })
+        } else {
+            assert_eq!(
+                c % (r * r),
+                0,
+                "ag::pixel_shuffle: Number of channels ({:?}) must be divisible by factor^2 ({:?})",
+                c,
+                r * r
+            );
+            let shape = ndarray::IxDyn(&[batch, c / (r * r), h * r, w * r]);
+            NdArray::from_shape_fn(shape, |i| {
+                let ch = (i[1] * r + i[2] % r) * r + i[3] % r;
+                x[ndarray::IxDyn(&[i[0], ch, i[2] / r, i[3] / r])]
+            })
+        };
+        vec![Ok(y)]
+    }
+
+    fn grad(&self, gy: &Tensor, _: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
+        let gx = Tensor::builder().set_input(gy).build(PixelShuffle {
+            factor: self.factor,
+            inverse: !self.inverse,
+        });
+        vec![Some(gx)]
+    }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> ::op::StaticShapeResult {
+        let x_shape = match xs[0].static_shape() {
+            Some(a) => a,
+            None => return Ok(None),
+        };
+        if x_shape.len() != 4 {
+            return Err(format!("Input must be 4D: {:?}", x_shape));
+        }
+        let r = self.factor as isize;
+        let mul = |a: isize, b: isize| if a == -1 { -1 } else { a * b };
+        let div = |a: isize, b: isize| if a == -1 { -1 } else { a / b };
+        Ok(Some(if self.inverse {
+            vec![
+                x_shape[0],
+                mul(x_shape[1], r * r),
+                div(x_shape[2], r),
+                div(x_shape[3], r),
+            ]
+        } else {
+            vec![
+                x_shape[0],
+                div(x_shape[1], r * r),
+                mul(x_shape[2], r),
+                mul(x_shape[3], r),
+            ]
+        }))
+    }
+}
+
+#[test]
+fn test_resize_taps() {
+    let bilinear = |align_corners| ResizeMethod::Bilinear { align_corners };
+    assert_eq!(
+        ResizeMethod::Nearest.taps(3, 5),
+        vec![
+            Tap(0, 0, 0.),
+            Tap(0, 0, 0.),
+            Tap(1, 1, 0.),
+            Tap(1, 1, 0.),
+            Tap(2, 2, 0.)
+        ]
+    );
+    assert_eq!(
+        bilinear(true).taps(3, 5),
+        vec![
+            Tap(0, 1, 0.),
+            Tap(0, 1, 0.5),
+            Tap(1, 2, 0.),
+            Tap(1, 2, 0.5),
+            Tap(2, 2, 0.)
+        ]
+    );
+    assert_eq!(
+        bilinear(false).taps(2, 4),
+        vec![
+            Tap(0, 1, 0.),
+            Tap(0, 1, 0.25),
+            Tap(0, 1, 0.75),
+            Tap(1, 1, 0.)
+        ]
+    );
+}
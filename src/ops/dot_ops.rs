@@ -1,8 +1,8 @@
+use backend::{split_regions, split_regions_mut, GemmParams};
 use ndarray;
-use ndarray_ext;
 use ndarray_ext::NdArray;
 use op;
-use rayon::iter::*;
+use std::borrow::Cow;
 use tensor::Tensor;
 
 // `Tensordot` is implemented in `ops/mod.rs`.
@@ -30,20 +30,35 @@ impl op::Op for MatMul {
         let x1_shape = x1.shape();
         assert_eq!(x0_shape.len(), 2, "First input to matmul should be Matrix");
         assert_eq!(x1_shape.len(), 2, "Second input to matmul should be Matrix");
-        let x0_view = x0.view();
-        let x1_view = x1.view();
-        // unwrap is always safe
-        let mut a = x0_view.into_shape((x0_shape[0], x0_shape[1])).unwrap();
-        let mut b = x1_view.into_shape((x1_shape[0], x1_shape[1])).unwrap();
-        if self.transpose_a {
-            // almost zero cost
-            a.swap_axes(0, 1);
-        }
-        if self.transpose_b {
-            // almost zero cost
-            b.swap_axes(0, 1);
+        let (m, k) = transposed_if(x0_shape[0], x0_shape[1], self.transpose_a);
+        let (k_, n) = transposed_if(x1_shape[0], x1_shape[1], self.transpose_b);
+        assert_eq!(
+            k, k_,
+            "Inner dims of matmul mismatch: {:?} vs {:?}",
+            x0_shape, x1_shape
+        );
+        let a = as_row_major(x0);
+        let b = as_row_major(x1);
+        let mut c = vec![0.; m * n];
+        if m * n > 0 {
+            ctx.backend().sgemm(
+                &GemmParams {
+                    trans_a: self.transpose_a,
+                    trans_b: self.transpose_b,
+                    m,
+                    n,
+                    k,
+                    alpha: 1.,
+                    beta: 0.,
+                },
+                &a,
+                &b,
+                &mut c,
+            );
         }
-        vec![Ok(a.dot(&b).into_dyn())]
+        // unwrap is always safe
+        let c = NdArray::from_shape_vec(ndarray::IxDyn(&[m, n]), c).unwrap();
+        vec![Ok(c)]
     }
 
     fn grad(&self, gy: &Tensor, inputs: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
@@ -63,6 +78,28 @@ impl op::Op for MatMul {
 
         vec![Some(opa), Some(opb)]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        let (shape0, shape1) = match (xs[0].static_shape(), xs[1].static_shape()) {
+            (Some(a), Some(b)) => (a, b),
+            _ => return Ok(None),
+        };
+        if shape0.len() != 2 || shape1.len() != 2 {
+            return Err(format!(
+                "Inputs should be matrices: {:?} vs {:?}",
+                shape0, shape1
+            ));
+        }
+        let (row0, col0) = transposed_if(shape0[0], shape0[1], self.transpose_a);
+        let (row1, col1) = transposed_if(shape1[0], shape1[1], self.transpose_b);
+        if col0 != -1 && row1 != -1 && col0 != row1 {
+            return Err(format!(
+                "Inner dims mismatch: {:?} vs {:?} (transpose_a={}, transpose_b={})",
+                shape0, shape1, self.transpose_a, self.transpose_b
+            ));
+        }
+        Ok(Some(vec![row0, col1]))
+    }
 }
 
 impl op::Op for BatchMatMul {
@@ -70,7 +107,6 @@ impl op::Op for BatchMatMul {
         "BatchMatMul"
     }
 
-    // TODO: Remove unnecessary mem copy
     fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
         let xs = ctx.grab_inputs();
         let x0: &NdArray = xs[0];
@@ -84,72 +120,43 @@ impl op::Op for BatchMatMul {
             panic!("Input shapes mismatch: {:?} vs {:?}", shape0, shape1);
         }
 
-        let row0 = shape0[rank0 - 2];
-        let row1 = shape1[rank0 - 2];
-
-        let col0 = shape0[rank0 - 1];
-        let col1 = shape1[rank0 - 1];
-
-        // squashes dims (remains last two dims)
-        // unwrap is always safe
-        let x0_flattened = {
-            let mut a = x0.view()
-                .into_shape((x0.len() / row0 / col0, row0, col0))
-                .unwrap();
-            if self.transpose_a {
-                a.swap_axes(1, 2);
-            }
-            a
-        };
-
-        let x1_flattened = {
-            let mut b = x1.view()
-                .into_shape((x1.len() / row1 / col1, row1, col1))
-                .unwrap();
-            if self.transpose_b {
-                b.swap_axes(1, 2);
-            }
-            b
-        };
-
-        // parallel mm
-        let dot = (0..x0_flattened.shape()[0] as isize)
-            .into_par_iter()
-            .map(|i| {
-                let x0_mat = x0_flattened
-                    .slice(s![i..i + 1, .., ..])
-                    .remove_axis(ndarray::Axis(0))
-                    .to_owned();
-                let x1_mat = x1_flattened
-                    .slice(s![i..i + 1, .., ..])
-                    .remove_axis(ndarray::Axis(0))
-                    .to_owned();
-                x0_mat.dot(&x1_mat).into_dyn()
-            })
-            .collect::<Vec<_>>();
-
-        // owned to ref
-        let mut dot_view = Vec::with_capacity(dot.len());
-        for i in 0..dot.len() {
-            dot_view.push(ndarray_ext::expand_dims_view(dot[i].view(), 0));
+        let (m, k) = transposed_if(shape0[rank0 - 2], shape0[rank0 - 1], self.transpose_a);
+        let (k_, n) = transposed_if(shape1[rank0 - 2], shape1[rank0 - 1], self.transpose_b);
+        assert_eq!(
+            k, k_,
+            "Inner dims of batch_matmul mismatch: {:?} vs {:?}",
+            shape0, shape1
+        );
+        let batch_size = shape0[..rank0 - 2].iter().product::<usize>();
+
+        let dst_shape = shape0[..rank0 - 2]
+            .iter()
+            .chain(&[m, n])
+            .cloned()
+            .collect::<Vec<usize>>();
+        let mut c = vec![0.; batch_size * m * n];
+        if c.is_empty() {
+            return vec![Ok(NdArray::from_shape_vec(dst_shape, c).unwrap())];
         }
 
-        // stack dot result
-        let stacked = ndarray::stack(ndarray::Axis(0), dot_view.as_slice()).unwrap();
-
-        let dst_shape = {
-            let stacked_shape = stacked.shape();
-            shape0[..rank0 - 2]
-                .into_iter()
-                .chain(&[stacked_shape[1], stacked_shape[2]])
-                .cloned()
-                .collect::<Vec<usize>>()
-        };
-
-        // reshape to dst shape with safe unwrapping
-        vec![Ok(stacked
-            .into_shape(ndarray::IxDyn(dst_shape.as_slice()))
-            .unwrap())]
+        let a = as_row_major(x0);
+        let b = as_row_major(x1);
+        ctx.backend().sgemm_batch(
+            &GemmParams {
+                trans_a: self.transpose_a,
+                trans_b: self.transpose_b,
+                m,
+                n,
+                k,
+                alpha: 1.,
+                beta: 0.,
+            },
+            &split_regions(batch_size, &a),
+            &split_regions(batch_size, &b),
+            &mut split_regions_mut(batch_size, &mut c),
+        );
+        // unwrap is safe
+        vec![Ok(NdArray::from_shape_vec(dst_shape, c).unwrap())]
     }
 
     fn grad(&self, gy: &Tensor, inputs: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
@@ -169,4 +176,54 @@ impl op::Op for BatchMatMul {
 
         vec![Some(opa), Some(opb)]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        let (shape0, shape1) = match (xs[0].static_shape(), xs[1].static_shape()) {
+            (Some(a), Some(b)) => (a, b),
+            _ => return Ok(None),
+        };
+        let rank = shape0.len();
+        if rank < 2 || rank != shape1.len() {
+            return Err(format!(
+                "Input shapes mismatch: {:?} vs {:?}",
+                shape0, shape1
+            ));
+        }
+        let mut ret = Vec::with_capacity(rank);
+        for (&a, &b) in shape0[..rank - 2].iter().zip(&shape1[..rank - 2]) {
+            if a != -1 && b != -1 && a != b {
+                return Err(format!("Batch dims mismatch: {:?} vs {:?}", shape0, shape1));
+            }
+            ret.push(if a == -1 { b } else { a });
+        }
+        let (row0, col0) = transposed_if(shape0[rank - 2], shape0[rank - 1], self.transpose_a);
+        let (row1, col1) = transposed_if(shape1[rank - 2], shape1[rank - 1], self.transpose_b);
+        if col0 != -1 && row1 != -1 && col0 != row1 {
+            return Err(format!(
+                "Inner dims mismatch: {:?} vs {:?} (transpose_a={}, transpose_b={})",
+                shape0, shape1, self.transpose_a, self.transpose_b
+            ));
+        }
+        ret.push(row0);
+        ret.push(col1);
+        Ok(Some(ret))
+    }
+}
+
+#[inline]
+fn transposed_if<T>(row: T, col: T, transpose: bool) -> (T, T) {
+    if transpose {
+        (col, row)
+    } else {
+        (row, col)
+    }
+}
+
+// Returns `x`'s elements in row-major order, copying only if needed.
+#[inline]
+fn as_row_major(x: &NdArray) -> Cow<'_, [f32]> {
+    match x.as_slice() {
+        Some(a) => Cow::Borrowed(a),
+        None => Cow::Owned(x.iter().cloned().collect()),
+    }
 }
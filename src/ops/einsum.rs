@@ -0,0 +1,290 @@
+//! Lowering of `ag::einsum` to `reduce_sum`, `transpose` and `batch_matmul`.
+use ndarray;
+use ndarray_ext::NdArray;
+use ops;
+use std::collections::HashMap;
+use tensor::Tensor;
+
+/// Operand of the contraction with a label for each axis.
+struct Term {
+    x: Tensor,
+    labels: Vec<char>,
+}
+
+/// Parses `spec` into the labels of each operand and of the output.
+///
+/// Without `->`, the output has the labels appearing only once, in alphabetical order.
+fn parse(spec: &str, num_operands: usize) -> (Vec<Vec<char>>, Vec<char>) {
+    let spec = spec.chars().filter(|c| !c.is_whitespace()).collect::<String>();
+    let (lhs, rhs) = match spec.find("->") {
+        Some(i) => (&spec[..i], Some(&spec[i + 2..])),
+        None => (spec.as_str(), None),
+    };
+    let check_labels = |s: &str| -> Vec<char> {
+        let labels = s.chars().collect::<Vec<_>>();
+        for (i, &c) in labels.iter().enumerate() {
+            assert!(
+                c.is_ascii_alphabetic(),
+                "ag::einsum: Invalid label {:?} in {:?}",
+                c,
+                spec
+            );
+            assert!(
+                !labels[..i].contains(&c),
+                "ag::einsum: Repeated label {:?} in a term of {:?} is not supported",
+                c,
+                spec
+            );
+        }
+        labels
+    };
+    let inputs = lhs.split(',').map(&check_labels).collect::<Vec<_>>();
+    assert_eq!(
+        inputs.len(),
+        num_operands,
+        "ag::einsum: {:?} needs {} operands, but got {}",
+        spec,
+        inputs.len(),
+        num_operands
+    );
+    let output = match rhs {
+        Some(rhs) => {
+            let output = check_labels(rhs);
+            for c in output.iter() {
+                assert!(
+                    inputs.iter().any(|l| l.contains(c)),
+                    "ag::einsum: Output label {:?} of {:?} is not in the inputs",
+                    c,
+                    spec
+                );
+            }
+            output
+        }
+        None => {
+            let mut output = inputs
+                .iter()
+                .flat_map(|l| l.iter().cloned())
+                .filter(|c| inputs.iter().filter(|l| l.contains(c)).count() == 1)
+                .collect::<Vec<_>>();
+            output.sort();
+            output
+        }
+    };
+    (inputs, output)
+}
+
+#[inline]
+fn as_tensor(a: &[usize]) -> Tensor {
+    let arr = a.iter().map(|&a| a as f32).collect::<Vec<_>>();
+    // unwrap is safe
+    ops::convert_to_tensor(NdArray::from_shape_vec(ndarray::IxDyn(&[a.len()]), arr).unwrap())
+}
+
+impl Term {
+    fn position(&self, c: char) -> usize {
+        self.labels.iter().position(|&a| a == c).unwrap()
+    }
+
+    /// Sums out the axes whose labels are not kept.
+    fn sum_out<F: Fn(char) -> bool>(self, keep: F) -> Term {
+        let axes = (0..self.labels.len())
+            .filter(|&i| !keep(self.labels[i]))
+            .collect::<Vec<_>>();
+        if axes.is_empty() {
+            return self;
+        }
+        let labels = self.labels.iter().cloned().filter(|&c| keep(c)).collect();
+        Term {
+            x: ops::reduce_sum(&self.x, &as_tensor(&axes), false),
+            labels,
+        }
+    }
+
+    /// Transposes the axes into the order of `labels`.
+    fn permute(self, labels: &[char]) -> Term {
+        if self.labels.as_slice() == labels {
+            return self;
+        }
+        let perm = labels.iter().map(|&c| self.position(c)).collect::<Vec<_>>();
+        Term {
+            x: ops::transpose(&self.x, &as_tensor(&perm)),
+            labels: labels.to_vec(),
+        }
+    }
+
+    /// Symbolic sizes of the axes of `labels`.
+    fn dims(&self, labels: &[char]) -> Tensor {
+        let indices = labels.iter().map(|&c| self.position(c)).collect::<Vec<_>>();
+        ops::gather(self.x.shape(), &as_tensor(&indices), 0)
+    }
+
+    /// Symbolic product of the sizes of the axes of `labels` as a 1-element vector.
+    fn dims_prod(&self, labels: &[char]) -> Tensor {
+        if labels.is_empty() {
+            ops::ones(&[1])
+        } else {
+            ops::reduce_prod(self.dims(labels), &[0], true)
+        }
+    }
+}
+
+/// Contracts `a` and `b` with a `batch_matmul`, keeping the labels `keep` says.
+fn contract<F: Fn(char) -> bool>(a: Term, b: Term, keep: F) -> Term {
+    let a = {
+        let b_labels = &b.labels;
+        a.sum_out(|c| keep(c) || b_labels.contains(&c))
+    };
+    let b = {
+        let a_labels = &a.labels;
+        b.sum_out(|c| keep(c) || a_labels.contains(&c))
+    };
+
+    let shared = |c: &char| b.labels.contains(c);
+    let batch = a
+        .labels
+        .iter()
+        .cloned()
+        .filter(|c| shared(c) && keep(*c))
+        .collect::<Vec<_>>();
+    let summed = a
+        .labels
+        .iter()
+        .cloned()
+        .filter(|c| shared(c) && !keep(*c))
+        .collect::<Vec<_>>();
+    let a_free = a
+        .labels
+        .iter()
+        .cloned()
+        .filter(|c| !shared(c))
+        .collect::<Vec<_>>();
+    let b_free = b
+        .labels
+        .iter()
+        .cloned()
+        .filter(|c| !a.labels.contains(c))
+        .collect::<Vec<_>>();
+
+    // a: (batch, a_free, summed), b: (batch, summed, b_free)
+    let a = a.permute(&[&batch[..], &a_free, &summed].concat());
+    let b = b.permute(&[&batch[..], &summed, &b_free].concat());
+    let batch_len = a.dims_prod(&batch);
+    let summed_len = a.dims_prod(&summed);
+    let a3 = ops::reshape(
+        &a.x,
+        &ops::concat(&[&batch_len, &a.dims_prod(&a_free), &summed_len], 0),
+    );
+    let b3 = ops::reshape(
+        &b.x,
+        &ops::concat(&[&batch_len, &summed_len, &b.dims_prod(&b_free)], 0),
+    );
+    let c = ops::batch_matmul(&a3, &b3);
+
+    let mut shapes = Vec::with_capacity(3);
+    if !batch.is_empty() {
+        shapes.push(a.dims(&batch));
+    }
+    if !a_free.is_empty() {
+        shapes.push(a.dims(&a_free));
+    }
+    if !b_free.is_empty() {
+        shapes.push(b.dims(&b_free));
+    }
+    let shape = if shapes.is_empty() {
+        as_tensor(&[])
+    } else {
+        ops::concat(&shapes.iter().collect::<Vec<_>>(), 0)
+    };
+    Term {
+        x: ops::reshape(&c, &shape),
+        labels: [&batch[..], &a_free, &b_free].concat(),
+    }
+}
+
+/// Static size of each label; unknown sizes are taken as 1 for planning.
+fn label_sizes(terms: &[Term], spec: &str) -> HashMap<char, usize> {
+    let mut sizes = HashMap::new();
+    for t in terms {
+        let shape = match t.x.static_shape() {
+            Some(a) => a,
+            None => continue,
+        };
+        assert_eq!(
+            shape.len(),
+            t.labels.len(),
+            "ag::einsum: Term {:?} of {:?} doesn't match the operand of shape {:?}",
+            t.labels.iter().collect::<String>(),
+            spec,
+            shape
+        );
+        for (&c, &dim) in t.labels.iter().zip(&shape) {
+            if dim == -1 {
+                continue;
+            }
+            let prev = *sizes.entry(c).or_insert(dim as usize);
+            assert_eq!(
+                prev, dim as usize,
+                "ag::einsum: Sizes of label {:?} mismatch in {:?}",
+                c, spec
+            );
+        }
+    }
+    sizes
+}
+
+pub fn einsum(spec: &str, operands: &[&Tensor]) -> Tensor {
+    let (inputs, output) = parse(spec, operands.len());
+    let mut terms = operands
+        .iter()
+        .zip(inputs)
+        .map(|(&x, labels)| Term {
+            x: x.clone(),
+            labels,
+        })
+        .collect::<Vec<_>>();
+    let sizes = label_sizes(&terms, spec);
+    let size_of = |labels: &[char]| -> usize {
+        labels
+            .iter()
+            .map(|c| sizes.get(c).cloned().unwrap_or(1))
+            .product()
+    };
+
+    // Greedily contracts the pair with the smallest result.
+    while terms.len() > 1 {
+        let mut best: Option<(usize, usize, usize)> = None;
+        for i in 0..terms.len() {
+            for j in i + 1..terms.len() {
+                let kept = terms[i]
+                    .labels
+                    .iter()
+                    .chain(terms[j].labels.iter().filter(|c| !terms[i].labels.contains(c)))
+                    .cloned()
+                    .filter(|c| {
+                        output.contains(c) || terms
+                            .iter()
+                            .enumerate()
+                            .any(|(k, t)| k != i && k != j && t.labels.contains(c))
+                    })
+                    .collect::<Vec<_>>();
+                let cost = size_of(&kept);
+                if best.map(|(_, _, c)| cost < c).unwrap_or(true) {
+                    best = Some((i, j, cost));
+                }
+            }
+        }
+        let (i, j, _) = best.unwrap();
+        let b = terms.remove(j);
+        let a = terms.remove(i);
+        let ret = {
+            let (output, rest) = (&output, &terms);
+            contract(a, b, |c| {
+                output.contains(&c) || rest.iter().any(|t| t.labels.contains(&c))
+            })
+        };
+        terms.push(ret);
+    }
+
+    let ret = terms.pop().unwrap();
+    ret.sum_out(|c| output.contains(&c)).permute(&output).x
+}
@@ -0,0 +1,437 @@
+use ndarray_ext::NdArray;
+use op;
+use std::f64::consts::PI;
+use tensor::Tensor;
+
+// Complex values are represented by a trailing axis of size 2 (real and imaginary
+// parts). Transforms run along the last axis of real arrays, and along the axis just
+// before the complex one otherwise.
+
+/// Discrete Fourier transform of complex inputs.
+///
+/// `inverse` flips the sign of the exponent, `normalized` divides the result by `n`.
+/// `ag::ifft` is the inverse normalized one.
+pub struct Fft {
+    pub inverse: bool,
+    pub normalized: bool,
+}
+
+/// Non-negative frequency terms of the transform of real inputs: `(.., n)` to
+/// `(.., n / 2 + 1, 2)`.
+pub struct Rfft;
+
+/// Transpose of `Rfft`, taking the length `n` from the 2nd input.
+pub struct RfftGrad;
+
+/// Inverse of `Rfft` for signals of length `n`, assuming Hermitian symmetry:
+/// `(.., n / 2 + 1, 2)` to `(.., n)`.
+pub struct Irfft {
+    pub n: usize,
+}
+
+/// Transpose of `Irfft`.
+pub struct IrfftGrad {
+    pub n: usize,
+}
+
+/// Overlapping frames of the last axis multiplied by `window`:
+/// `(.., t)` to `(.., 1 + (t - window.len()) / step, window.len())`.
+pub struct Frame {
+    pub window: Vec<f32>,
+    pub step: usize,
+}
+
+/// Transpose of `Frame`: sums the windowed frames back into the positions they came
+/// from, taking the signal length from the 2nd input.
+pub struct OverlapAdd {
+    pub window: Vec<f32>,
+    pub step: usize,
+}
+
+type Complex = (f64, f64);
+
+/// Smallest prime factor of `n` (`n >= 2`).
+fn smallest_factor(n: usize) -> usize {
+    (2..)
+        .take_while(|p| p * p <= n)
+        .find(|p| n % p == 0)
+        .unwrap_or(n)
+}
+
+/// Unnormalized DFT of `x` with `exp(sign * 2 pi i k j / n)`.
+///
+/// Recursive mixed-radix Cooley-Tukey; prime lengths fall back to the naive sum.
+fn dft(x: &[Complex], sign: f64) -> Vec<Complex> {
+    let n = x.len();
+    if n <= 1 {
+        return x.to_vec();
+    }
+    let p = smallest_factor(n);
+    let m = n / p;
+    let subs = (0..p)
+        .map(|r| {
+            let sub = x.iter().skip(r).step_by(p).cloned().collect::<Vec<_>>();
+            dft(&sub, sign)
+        })
+        .collect::<Vec<_>>();
+    (0..n)
+        .map(|k| {
+            subs.iter().enumerate().fold((0., 0.), |(re, im), (r, sub)| {
+                let theta = sign * 2. * PI * ((r * k) % n) as f64 / n as f64;
+                let (c, s) = (theta.cos(), theta.sin());
+                let (a, b) = sub[k % m];
+                (re + a * c - b * s, im + a * s + b * c)
+            })
+        })
+        .collect()
+}
+
+/// Checks that `x` has a trailing complex axis and returns the transform length.
+fn complex_len(op: &str, x: &NdArray) -> usize {
+    let shape = x.shape();
+    assert!(
+        x.ndim() >= 2 && shape[x.ndim() - 1] == 2,
+        "{}: Input must have a trailing axis of size 2, got {:?}",
+        op,
+        shape
+    );
+    shape[x.ndim() - 2]
+}
+
+/// Applies `f` to each signal of length `n` in `x` in logical order.
+fn map_signals<F>(x: &NdArray, n: usize, mut f: F) -> Vec<f32>
+where
+    F: FnMut(&[f64], &mut Vec<f32>),
+{
+    let a = x.iter().map(|&a| a as f64).collect::<Vec<_>>();
+    let mut y = Vec::with_capacity(a.len());
+    if n > 0 {
+        for signal in a.chunks(n) {
+            f(signal, &mut y);
+        }
+    }
+    y
+}
+
+/// `x` with its last `k` axes replaced by `dims`.
+fn replace_last(x: &NdArray, k: usize, dims: &[usize]) -> Vec<usize> {
+    [&x.shape()[..x.ndim() - k], dims].concat()
+}
+
+/// Hermitian weights of `Irfft`: the bins mirrored into the negative frequencies
+/// count twice.
+fn irfft_weight(k: usize, n: usize) -> f64 {
+    if k == 0 || 2 * k == n {
+        1. / n as f64
+    } else {
+        2. / n as f64
+    }
+}
+
+/// `(Re Σ_k g_k exp(2 pi i k j / n))_j` for `j < n`.
+fn rfft_transpose(g: &[Complex], n: usize) -> Vec<f64> {
+    let mut full = vec![(0., 0.); n];
+    full[..g.len()].copy_from_slice(g);
+    dft(&full, 1.).into_iter().map(|(re, _)| re).collect()
+}
+
+fn to_complex(a: &[f64]) -> Vec<Complex> {
+    a.chunks(2).map(|c| (c[0], c[1])).collect()
+}
+
+fn push_complex(y: &mut Vec<f32>, a: &[Complex]) {
+    for &(re, im) in a {
+        y.push(re as f32);
+        y.push(im as f32);
+    }
+}
+
+fn output(shape: Vec<usize>, y: Vec<f32>) -> op::ComputeResult {
+    // unwrap is safe
+    vec![Ok(NdArray::from_shape_vec(shape, y).unwrap())]
+}
+
+impl op::Op for Fft {
+    fn name(&self) -> &str {
+        "Fft"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        let x = ctx.grab_inputs()[0];
+        let n = complex_len("ag::fft", x);
+        let sign = if self.inverse { 1. } else { -1. };
+        let scale = if self.normalized { 1. / n as f64 } else { 1. };
+        let y = map_signals(x, 2 * n, |signal, y| {
+            let ret = dft(&to_complex(signal), sign)
+                .into_iter()
+                .map(|(re, im)| (re * scale, im * scale))
+                .collect::<Vec<_>>();
+            push_complex(y, &ret);
+        });
+        output(x.shape().to_vec(), y)
+    }
+
+    fn grad(&self, gy: &Tensor, _: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
+        // The adjoint of the DFT matrix is the DFT with the opposite sign.
+        let gx = Tensor::builder().set_input(gy).build(Fft {
+            inverse: !self.inverse,
+            normalized: self.normalized,
+        });
+        vec![Some(gx)]
+    }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape())
+    }
+}
+
+impl op::Op for Rfft {
+    fn name(&self) -> &str {
+        "Rfft"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        let x = ctx.grab_inputs()[0];
+        assert!(x.ndim() >= 1, "ag::rfft: Input must have at least 1 axis");
+        let n = x.shape()[x.ndim() - 1];
+        let m = n / 2 + 1;
+        let y = map_signals(x, n, |signal, y| {
+            let signal = signal.iter().map(|&a| (a, 0.)).collect::<Vec<_>>();
+            push_complex(y, &dft(&signal, -1.)[..m]);
+        });
+        output(replace_last(x, 1, &[m, 2]), y)
+    }
+
+    fn grad(&self, gy: &Tensor, xs: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
+        let gx = Tensor::builder()
+            .set_inputs(vec![gy, xs[0]])
+            .build(RfftGrad);
+        vec![Some(gx)]
+    }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape().and_then(|mut s| {
+            let n = s.pop()?;
+            s.push(if n == -1 { -1 } else { n / 2 + 1 });
+            s.push(2);
+            Some(s)
+        }))
+    }
+}
+
+impl op::Op for RfftGrad {
+    fn name(&self) -> &str {
+        "RfftGrad"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        let xs = ctx.grab_inputs();
+        let (gy, x) = (xs[0], xs[1]);
+        let n = x.shape()[x.ndim() - 1];
+        let m = complex_len("RfftGrad", gy);
+        let gx = map_signals(gy, 2 * m, |g, gx| {
+            gx.extend(rfft_transpose(&to_complex(g), n).into_iter().map(|a| a as f32));
+        });
+        output(x.shape().to_vec(), gx)
+    }
+
+    fn grad(&self, gy: &Tensor, _: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
+        let ggy = Tensor::builder().set_input(gy).build(Rfft);
+        vec![Some(ggy), None]
+    }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[1].static_shape())
+    }
+}
+
+impl op::Op for Irfft {
+    fn name(&self) -> &str {
+        "Irfft"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        let x = ctx.grab_inputs()[0];
+        let m = complex_len("ag::irfft", x);
+        let n = self.n;
+        assert_eq!(
+            m,
+            n / 2 + 1,
+            "ag::irfft: Signals of length {} need {} frequency terms, got {}",
+            n,
+            n / 2 + 1,
+            m
+        );
+        let y = map_signals(x, 2 * m, |signal, y| {
+            let weighted = to_complex(signal)
+                .into_iter()
+                .enumerate()
+                .map(|(k, (re, im))| {
+                    let w = irfft_weight(k, n);
+                    (re * w, im * w)
+                })
+                .collect::<Vec<_>>();
+            y.extend(rfft_transpose(&weighted, n).into_iter().map(|a| a as f32));
+        });
+        output(replace_last(x, 2, &[n]), y)
+    }
+
+    fn grad(&self, gy: &Tensor, _: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
+        let gx = Tensor::builder()
+            .set_input(gy)
+            .build(IrfftGrad { n: self.n });
+        vec![Some(gx)]
+    }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape().and_then(|mut s| {
+            s.pop()?;
+            s.pop()?;
+            s.push(self.n as isize);
+            Some(s)
+        }))
+    }
+}
+
+impl op::Op for IrfftGrad {
+    fn name(&self) -> &str {
+        "IrfftGrad"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        let gy = ctx.grab_inputs()[0];
+        let n = self.n;
+        let m = n / 2 + 1;
+        let gx = map_signals(gy, n, |g, gx| {
+            let g = g.iter().map(|&a| (a, 0.)).collect::<Vec<_>>();
+            let ret = dft(&g, -1.)[..m]
+                .iter()
+                .enumerate()
+                .map(|(k, &(re, im))| {
+                    let w = irfft_weight(k, n);
+                    (re * w, im * w)
+                })
+                .collect::<Vec<_>>();
+            push_complex(gx, &ret);
+        });
+        output(replace_last(gy, 1, &[m, 2]), gx)
+    }
+
+    fn grad(&self, gy: &Tensor, _: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
+        let ggy = Tensor::builder().set_input(gy).build(Irfft { n: self.n });
+        vec![Some(ggy)]
+    }
+}
+
+/// Number of frames of `Frame` for a signal of length `t`.
+fn num_frames(t: usize, length: usize, step: usize) -> usize {
+    assert!(
+        t >= length,
+        "ag::stft: Signal length {} is shorter than the frame length {}",
+        t,
+        length
+    );
+    1 + (t - length) / step
+}
+
+impl op::Op for Frame {
+    fn name(&self) -> &str {
+        "Frame"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        let x = ctx.grab_inputs()[0];
+        assert!(x.ndim() >= 1, "ag::stft: Input must have at least 1 axis");
+        let t = x.shape()[x.ndim() - 1];
+        let length = self.window.len();
+        let frames = num_frames(t, length, self.step);
+        let y = map_signals(x, t, |signal, y| {
+            for f in 0..frames {
+                let frame = &signal[f * self.step..f * self.step + length];
+                y.extend(frame.iter().zip(&self.window).map(|(&a, &w)| a as f32 * w));
+            }
+        });
+        output(replace_last(x, 1, &[frames, length]), y)
+    }
+
+    fn grad(&self, gy: &Tensor, xs: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
+        let gx = Tensor::builder()
+            .set_inputs(vec![gy, xs[0]])
+            .build(OverlapAdd {
+                window: self.window.clone(),
+                step: self.step,
+            });
+        vec![Some(gx)]
+    }
+}
+
+impl op::Op for OverlapAdd {
+    fn name(&self) -> &str {
+        "OverlapAdd"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        let xs = ctx.grab_inputs();
+        let (gy, x) = (xs[0], xs[1]);
+        let t = x.shape()[x.ndim() - 1];
+        let length = self.window.len();
+        let frames = num_frames(t, length, self.step);
+        let gx = map_signals(gy, frames * length, |g, gx| {
+            let mut signal = vec![0.; t];
+            for (f, frame) in g.chunks(length).enumerate() {
+                let target = &mut signal[f * self.step..f * self.step + length];
+                for ((s, &a), &w) in target.iter_mut().zip(frame).zip(&self.window) {
+                    *s += a as f32 * w;
+                }
+            }
+            gx.extend(signal);
+        });
+        output(x.shape().to_vec(), gx)
+    }
+
+    fn grad(&self, gy: &Tensor, _: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
+        let ggy = Tensor::builder().set_input(gy).build(Frame {
+            window: self.window.clone(),
+            step: self.step,
+        });
+        vec![Some(ggy), None]
+    }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[1].static_shape())
+    }
+}
+
+/// Periodic Hann window of `length`.
+pub fn hann_window(length: usize) -> Vec<f32> {
+    (0..length)
+        .map(|i| (0.5 - 0.5 * (2. * PI * i as f64 / length as f64).cos()) as f32)
+        .collect()
+}
+
+#[test]
+fn test_dft() {
+    // mixed radices and a prime length
+    for &n in [1, 2, 6, 7, 12].iter() {
+        let x = (0..n)
+            .map(|j| ((j as f64).sin(), (j as f64 * 0.5).cos()))
+            .collect::<Vec<_>>();
+        let y = dft(&x, -1.);
+        for k in 0..n {
+            let naive = (0..n).fold((0., 0.), |(re, im), j| {
+                let theta = -2. * PI * (k * j) as f64 / n as f64;
+                let (a, b) = x[j];
+                (
+                    re + a * theta.cos() - b * theta.sin(),
+                    im + a * theta.sin() + b * theta.cos(),
+                )
+            });
+            assert!((y[k].0 - naive.0).abs() < 1e-9 && (y[k].1 - naive.1).abs() < 1e-9);
+        }
+        let back = dft(&y, 1.);
+        for j in 0..n {
+            assert!((back[j].0 / n as f64 - x[j].0).abs() < 1e-9);
+            assert!((back[j].1 / n as f64 - x[j].1).abs() < 1e-9);
+        }
+    }
+}
@@ -17,6 +17,12 @@ impl ::op::Op for AdamOp {
 
     fn compute(&self, mut ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
         let StaticParams { alpha, eps, b1, b2 } = self.static_params;
+        // Half precision variable updated from the master weights `xs[0]`
+        #[cfg(feature = "half")]
+        let half_var = ctx
+            .grab_inputs()
+            .get(5)
+            .map(|_| ctx.grab_input_node(5).clone());
         let xs = unsafe { ctx.grab_assignable_inputs() };
 
         // Make new m
@@ -47,6 +53,15 @@ impl ::op::Op for AdamOp {
         // Update t and param
         xs[4][ndarray::IxDyn(&[])] += 1.;
         xs[0].scaled_add(-alpha, &m_hat);
+        #[cfg(feature = "half")]
+        {
+            if let Some(var) = half_var {
+                // unwrap is safe
+                unsafe { var.get_half_array_mut() }
+                    .unwrap()
+                    .assign_f32(xs[0]);
+            }
+        }
         vec![Err(::op::ComputeException::NoOutput)]
     }
 
@@ -55,6 +70,77 @@ impl ::op::Op for AdamOp {
     }
 }
 
+// Lazy version of `AdamOp` for sparse gradients.
+//
+// Only the rows that the sparse gradient `(xs[1], xs[2])` points to are updated,
+// including their `m` and `v`.
+struct SparseAdamOp {
+    static_params: StaticParams,
+}
+
+impl ::op::Op for SparseAdamOp {
+    fn name(&self) -> &str {
+        "SparseAdam"
+    }
+
+    fn compute(&self, mut ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        let StaticParams { alpha, eps, b1, b2 } = self.static_params;
+        // Half precision variable updated from the master weights `xs[0]`
+        #[cfg(feature = "half")]
+        let half_var = ctx
+            .grab_inputs()
+            .get(6)
+            .map(|_| ctx.grab_input_node(6).clone());
+        let xs = unsafe { ctx.grab_assignable_inputs() };
+
+        let t = xs[5][ndarray::IxDyn(&[])];
+        let m_scale = 1. / (1. - b1.powf(t));
+        let v_scale = 1. / (1. - b2.powf(t));
+        let rows = super::aggregate_sparse_rows(xs[0].shape()[0], xs[1], xs[2]);
+        for (i, g) in rows {
+            let new_m = xs[3]
+                .subview_mut(ndarray::Axis(0), i)
+                .iter_mut()
+                .zip(&g)
+                .map(|(m, &g)| {
+                    *m = b1 * *m + (1. - b1) * g;
+                    *m
+                })
+                .collect::<Vec<f32>>();
+            let new_v = xs[4]
+                .subview_mut(ndarray::Axis(0), i)
+                .iter_mut()
+                .zip(&g)
+                .map(|(v, &g)| {
+                    *v = b2 * *v + (1. - b2) * g * g;
+                    *v
+                })
+                .collect::<Vec<f32>>();
+            let mut row = xs[0].subview_mut(ndarray::Axis(0), i);
+            for ((a, m), v) in row.iter_mut().zip(new_m).zip(new_v) {
+                *a -= alpha * (m * m_scale) / ((v * v_scale).sqrt() + eps);
+            }
+        }
+
+        // Update t
+        xs[5][ndarray::IxDyn(&[])] += 1.;
+        #[cfg(feature = "half")]
+        {
+            if let Some(var) = half_var {
+                // unwrap is safe
+                unsafe { var.get_half_array_mut() }
+                    .unwrap()
+                    .assign_f32(xs[0]);
+            }
+        }
+        vec![Err(::op::ComputeException::NoOutput)]
+    }
+
+    fn grad(&self, _: &Tensor, xs: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
+        vec![None; xs.len()]
+    }
+}
+
 pub struct StatefulVariable<'a> {
     pub var: &'a Tensor,
     pub state: StatefulParams,
@@ -63,6 +149,12 @@ pub struct StatefulVariable<'a> {
 /// Adam optimizer
 ///
 /// The implementation is based on http://arxiv.org/abs/1412.6980v8
+///
+/// Variables made with `ag::variable_half` are optimized on master f32 weights,
+/// which are rounded into the half precision storage after each update.
+///
+/// Gradients having sparse representations (e.g. gradients of `ag::gather`) are applied
+/// lazily: only the gathered rows of the variable and their moments are updated.
 pub struct Adam {
     pub alpha: f32,
     pub eps: f32,
@@ -89,13 +181,14 @@ impl Adam {
             .into_iter()
             .map(|var| {
                 // let var = var.as_ref();
-                if let Some(var_arr) = var.get_persistent_array() {
+                if let Some((shape, master)) = shape_and_master(var) {
                     match var2state.entry(super::StateKey(var)) {
                         Entry::Vacant(ent) => {
                             let inserted = ent.insert(StatefulParams {
-                                m: ::ops::variable(NdArray::zeros(var_arr.shape())),
-                                v: ::ops::variable(NdArray::zeros(var_arr.shape())),
+                                m: ::ops::variable(NdArray::zeros(shape.as_slice())),
+                                v: ::ops::variable(NdArray::zeros(shape.as_slice())),
                                 t: ::ops::variable(::ndarray_ext::from_scalar(1.)),
+                                master,
                             });
                             StatefulVariable {
                                 var,
@@ -128,17 +221,32 @@ impl Adam {
                     ref m,
                     ref v,
                     ref t,
+                    ref master,
                 } = param.state;
-                Tensor::builder()
-                    .set_inputs(vec![param.var, grad.as_ref(), m, v, t])
-                    .build(AdamOp {
-                        static_params: StaticParams {
-                            alpha: self.alpha,
-                            eps: self.eps,
-                            b1: self.b1,
-                            b2: self.b2,
-                        },
-                    })
+                let static_params = StaticParams {
+                    alpha: self.alpha,
+                    eps: self.eps,
+                    b1: self.b1,
+                    b2: self.b2,
+                };
+                let var = master.as_ref().unwrap_or(param.var);
+                let half_var = master.as_ref().map(|_| param.var);
+                match grad.as_ref().get_indexed_slices() {
+                    Some(slices) => {
+                        let mut inputs = vec![var, &slices.indices, &slices.values, m, v, t];
+                        inputs.extend(half_var);
+                        Tensor::builder()
+                            .set_inputs(inputs)
+                            .build(SparseAdamOp { static_params })
+                    }
+                    None => {
+                        let mut inputs = vec![var, grad.as_ref(), m, v, t];
+                        inputs.extend(half_var);
+                        Tensor::builder()
+                            .set_inputs(inputs)
+                            .build(AdamOp { static_params })
+                    }
+                }
             })
             .collect()
     }
@@ -157,4 +265,20 @@ pub struct StatefulParams {
     pub m: Tensor,
     pub v: Tensor,
     pub t: Tensor, // shape: []
+    /// Master f32 weights of a half precision variable
+    pub master: Option<Tensor>,
+}
+
+// Returns the shape of `var`, and its master weights if `var` is a half precision variable.
+fn shape_and_master(var: &Tensor) -> Option<(Vec<usize>, Option<Tensor>)> {
+    if let Some(arr) = var.get_persistent_array() {
+        return Some((arr.shape().to_vec(), None));
+    }
+    #[cfg(feature = "half")]
+    {
+        if let Some(arr) = var.get_half_array() {
+            return Some((arr.shape().to_vec(), Some(::ops::variable(arr.to_f32()))));
+        }
+    }
+    None
 }
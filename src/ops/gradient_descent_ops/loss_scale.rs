@@ -0,0 +1,157 @@
+use ndarray;
+use op;
+use tensor::Tensor;
+
+struct UnscaleGrads;
+
+struct LossScaleUpdate {
+    growth_factor: f32,
+    backoff_factor: f32,
+    growth_interval: usize,
+}
+
+impl ::op::Op for UnscaleGrads {
+    fn name(&self) -> &str {
+        "UnscaleGrads"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        let xs = ctx.grab_inputs();
+        let scale = xs[0][ndarray::IxDyn(&[])];
+        let grads = &xs[1..];
+        let finite = grads.iter().all(|g| g.iter().all(|a| a.is_finite()));
+        let mut ys: op::ComputeResult = grads
+            .iter()
+            .map(|&g| {
+                if finite {
+                    Ok(g.mapv(move |a| a / scale))
+                } else {
+                    // Ops consuming a missing output are not computed,
+                    // so every update of this step is skipped.
+                    Err(::op::ComputeException::NoOutput)
+                }
+            })
+            .collect();
+        // The last output tells `LossScaleUpdate` whether the step was skipped.
+        ys.push(Ok(::ndarray_ext::from_scalar(if finite { 1. } else { 0. })));
+        ys
+    }
+
+    fn grad(&self, _: &Tensor, xs: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
+        vec![None; xs.len()]
+    }
+}
+
+impl ::op::Op for LossScaleUpdate {
+    fn name(&self) -> &str {
+        "LossScaleUpdate"
+    }
+
+    fn compute(&self, mut ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        let xs = unsafe { ctx.grab_assignable_inputs() };
+        let finite = xs[2][ndarray::IxDyn(&[])] != 0.;
+        let (scale, good_steps) = xs.split_at_mut(1);
+        let scale = &mut scale[0][ndarray::IxDyn(&[])];
+        let good_steps = &mut good_steps[0][ndarray::IxDyn(&[])];
+        if finite {
+            *good_steps += 1.;
+            if *good_steps >= self.growth_interval as f32 {
+                *scale *= self.growth_factor;
+                *good_steps = 0.;
+            }
+        } else {
+            *scale *= self.backoff_factor;
+            *good_steps = 0.;
+        }
+        vec![Err(::op::ComputeException::NoOutput)]
+    }
+
+    fn grad(&self, _: &Tensor, xs: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
+        vec![None; xs.len()]
+    }
+}
+
+/// Dynamic loss scaling for mixed precision training.
+///
+/// The loss is multiplied by `scale` before differentiation so that small gradients
+/// don't underflow in half precision, and the gradients are divided by it afterwards.
+/// `scale` is reduced by `backoff_factor` when the gradients overflow, and grown by
+/// `growth_factor` after `growth_interval` consecutive finite steps.
+///
+/// ```
+/// extern crate ndarray;
+/// extern crate autograd as ag;
+///
+/// use ag::gradient_descent_ops::{DynamicLossScale, SGD};
+///
+/// let ref w = ag::variable(ndarray::arr1(&[1., 2.]));
+/// let ref loss = ag::reduce_sum(&(w * w), &[0], false);
+///
+/// let loss_scale = DynamicLossScale::new(1024.);
+/// let ref grads = ag::grad(&[&loss_scale.scale_loss(loss)], &[w]);
+/// let (ref grads, ref finite) = loss_scale.unscale(grads);
+///
+/// let mut sgd = SGD { lr: 0.5 };
+/// let mut updates = sgd.compute_updates(&[w], grads);
+/// updates.push(loss_scale.update(finite));
+/// ag::eval(&updates, &[]);
+///
+/// assert_eq!(w.eval(&[]), Some(ndarray::arr1(&[0., 0.]).into_dyn()));
+/// ```
+pub struct DynamicLossScale {
+    /// Current scale (shape: [])
+    pub scale: Tensor,
+    /// Number of consecutive steps with finite gradients (shape: [])
+    pub good_steps: Tensor,
+    pub growth_factor: f32,
+    pub backoff_factor: f32,
+    pub growth_interval: usize,
+}
+
+impl DynamicLossScale {
+    pub fn new(init_scale: f32) -> DynamicLossScale {
+        DynamicLossScale {
+            scale: ::ops::variable(::ndarray_ext::from_scalar(init_scale)),
+            good_steps: ::ops::variable(::ndarray_ext::from_scalar(0.)),
+            growth_factor: 2.,
+            backoff_factor: 0.5,
+            growth_interval: 2000,
+        }
+    }
+
+    /// Multiplies `loss` by the current scale.
+    pub fn scale_loss<A: AsRef<Tensor>>(&self, loss: A) -> Tensor {
+        loss.as_ref() * ::ops::stop_gradient(&self.scale)
+    }
+
+    /// Divides gradients of the scaled loss by the current scale.
+    ///
+    /// Returns the unscaled gradients, and a flag (shape: []) which is 1 if they are
+    /// finite and 0 otherwise, to be passed to `update`.
+    ///
+    /// If any of `grads` is non-finite, none of the unscaled gradients has a value,
+    /// so the optimizers' updates depending on them are skipped for the whole step.
+    pub fn unscale<T: AsRef<Tensor>>(&self, grads: &[T]) -> (Vec<Tensor>, Tensor) {
+        let mut inputs = vec![&self.scale];
+        inputs.extend(grads.iter().map(|g| g.as_ref()));
+        let y = Tensor::builder()
+            .set_inputs(inputs)
+            .set_differentiable(false)
+            .build(UnscaleGrads);
+        let unscaled = (0..grads.len()).map(|i| ::ops::nth_tensor(&y, i)).collect();
+        (unscaled, ::ops::nth_tensor(&y, grads.len()))
+    }
+
+    /// Returns an op which updates `scale` according to `finite`, the flag returned by `unscale`.
+    ///
+    /// Evaluate this together with the optimizer's updates.
+    pub fn update(&self, finite: &Tensor) -> Tensor {
+        Tensor::builder()
+            .set_inputs(vec![&self.scale, &self.good_steps, finite])
+            .build(LossScaleUpdate {
+                growth_factor: self.growth_factor,
+                backoff_factor: self.backoff_factor,
+                growth_interval: self.growth_interval,
+            })
+    }
+}
@@ -1,15 +1,44 @@
 extern crate ndarray;
 
 pub mod adam;
+pub mod loss_scale;
 #[allow(dead_code)]
 pub mod sgd;
 
 pub use self::adam::Adam;
+pub use self::loss_scale::DynamicLossScale;
 pub use self::sgd::SGD;
 
+use ndarray_ext::NdArray;
 use std::cmp::{Eq, Ordering, PartialEq};
+use std::collections::BTreeMap;
 use tensor::Tensor;
 
+// Sums up rows of a sparse gradient (see `IndexedSlices`) for each row index of the
+// variable. Negative indices are counted from the end as in `ag::gather_common`.
+fn aggregate_sparse_rows(
+    num_rows: usize,
+    indices: &NdArray,
+    values: &NdArray,
+) -> BTreeMap<usize, Vec<f32>> {
+    let row_len = if indices.len() == 0 {
+        0
+    } else {
+        values.len() / indices.len()
+    };
+    let values = values.iter().cloned().collect::<Vec<f32>>();
+    let mut ret = BTreeMap::<usize, Vec<f32>>::new();
+    for (&i, g) in indices.iter().zip(values.chunks(row_len.max(1))) {
+        let i = if i < 0. { num_rows as f32 + i } else { i } as usize;
+        assert!(i < num_rows, "Sparse gradient index {} is out of range", i);
+        let row = ret.entry(i).or_insert_with(|| vec![0.; row_len]);
+        for (a, &b) in row.iter_mut().zip(g) {
+            *a += b;
+        }
+    }
+    ret
+}
+
 /// Key to access a state tensor.
 /// Stateful optimizers use this.
 pub struct StateKey<'a>(pub &'a Tensor);
@@ -1,3 +1,4 @@
+use ndarray;
 use ndarray_ext::NdArray;
 use op;
 use tensor::Tensor;
@@ -12,12 +13,20 @@ impl ::op::Op for SGDOp {
     }
 
     fn compute(&self, mut ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        #[cfg(feature = "half")]
+        let var = ctx.grab_input_node(0).clone();
         let xs = unsafe { ctx.grab_assignable_inputs() };
         let updates = {
             let grad: &NdArray = xs[1];
             grad * self.lr
         };
         xs[0].zip_mut_with(&updates, |a, &b| *a -= b);
+        #[cfg(feature = "half")]
+        {
+            if let Some(arr) = unsafe { var.get_half_array_mut() } {
+                arr.assign_f32(xs[0]);
+            }
+        }
         vec![Err(::op::ComputeException::NoOutput)]
     }
 
@@ -26,7 +35,47 @@ impl ::op::Op for SGDOp {
     }
 }
 
+// Updates only the rows of `xs[0]` that the sparse gradient `(xs[1], xs[2])` points to.
+struct SparseSGDOp {
+    pub lr: f32,
+}
+
+impl ::op::Op for SparseSGDOp {
+    fn name(&self) -> &str {
+        "SparseSGD"
+    }
+
+    fn compute(&self, mut ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        #[cfg(feature = "half")]
+        let var = ctx.grab_input_node(0).clone();
+        let xs = unsafe { ctx.grab_assignable_inputs() };
+        let rows = super::aggregate_sparse_rows(xs[0].shape()[0], xs[1], xs[2]);
+        for (i, g) in rows {
+            let mut row = xs[0].subview_mut(ndarray::Axis(0), i);
+            for (a, &g) in row.iter_mut().zip(&g) {
+                *a -= self.lr * g;
+            }
+        }
+        #[cfg(feature = "half")]
+        {
+            if let Some(arr) = unsafe { var.get_half_array_mut() } {
+                arr.assign_f32(xs[0]);
+            }
+        }
+        vec![Err(::op::ComputeException::NoOutput)]
+    }
+
+    fn grad(&self, _: &Tensor, _: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
+        vec![None, None, None]
+    }
+}
+
 /// Vanilla SGD optimizer
+///
+/// Half precision variables are updated in place without master weights.
+///
+/// Gradients having sparse representations (e.g. gradients of `ag::gather`)
+/// update only the gathered rows.
 pub struct SGD {
     pub lr: f32,
 }
@@ -40,10 +89,13 @@ impl<'a> SGD {
         params
             .into_iter()
             .zip(grads)
-            .map(|(param, grad)| {
-                Tensor::builder()
+            .map(|(param, grad)| match grad.as_ref().get_indexed_slices() {
+                Some(slices) => Tensor::builder()
+                    .set_inputs(vec![param, &slices.indices, &slices.values])
+                    .build(SparseSGDOp { lr: self.lr }),
+                None => Tensor::builder()
                     .set_inputs(vec![param, grad.as_ref()])
-                    .build(SGDOp { lr: self.lr })
+                    .build(SGDOp { lr: self.lr }),
             })
             .collect()
     }
@@ -15,4 +15,8 @@ impl op::Op for StopGradient {
     fn grad(&self, _: &Tensor, _: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
         vec![None]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape())
+    }
 }
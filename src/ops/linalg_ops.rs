@@ -0,0 +1,1259 @@
+use ndarray;
+use ndarray_ext::NdArray;
+use op;
+use ops;
+use ops::dot_ops::BatchMatMul;
+use tensor::Tensor;
+
+// All ops here treat the last two axes as matrices and the others as the batch.
+// Kernels work on row-major `f64` copies of each matrix.
+
+pub struct MatrixInverse;
+
+/// Determinant of each square matrix.
+pub struct Determinant;
+
+/// `log(|det(x)|)` of each square matrix.
+pub struct LogDet;
+
+/// Solves `a x = b` (or `a^T x = b` if `adjoint`) for `x`, where `a` (1st input) is
+/// `(.., n, n)` and `b` (2nd input) is `(.., n, k)` with the same batch shape.
+pub struct Solve {
+    pub adjoint: bool,
+}
+
+/// `Solve` using only the lower (or upper) triangle of `a`.
+pub struct TriangularSolve {
+    pub lower: bool,
+    pub adjoint: bool,
+}
+
+/// Lower triangular `l` such that `x = l l^T` for each symmetric positive definite `x`.
+pub struct Cholesky;
+
+/// Swaps the last two axes.
+pub struct MatrixTranspose;
+
+/// Zeros the upper (or lower if `!lower`) triangle and scales the diagonal by `diag`.
+pub struct TriangularPart {
+    pub lower: bool,
+    pub diag: f32,
+}
+
+/// Reduced QR decomposition of each matrix with at least as many rows as columns.
+///
+/// Outputs `q` and `r`; the diagonal of `r` is non-negative.
+pub struct Qr;
+
+/// Reduced singular value decomposition of each matrix.
+///
+/// Outputs `u`, `s` (descending) and `v` such that `x = u diag(s) v^T`.
+pub struct Svd;
+
+/// Eigendecomposition of each symmetric matrix, reading only its lower triangle.
+///
+/// Outputs the eigenvalues (ascending) and the eigenvectors as columns.
+pub struct Eigh;
+
+/// `index`-th output of `Qr`, `Svd` or `Eigh`.
+///
+/// The gradients of the `num_outputs` outputs reach the decomposition packed by
+/// `PackGrads`, since a multi-output op receives a single gradient tensor.
+pub struct DecompositionOutput {
+    pub index: usize,
+    pub num_outputs: usize,
+}
+
+/// Flattens the gradient (1st input) of the `index`-th output into the concatenation
+/// of all outputs (the other inputs), filling the others with zeros.
+pub struct PackGrads {
+    pub index: usize,
+}
+
+/// Inverse of `PackGrads`: extracts the gradient of the `index`-th output.
+pub struct UnpackGrad {
+    pub index: usize,
+}
+
+/// Diagonal matrices from the vectors in the last axis.
+pub struct MatrixDiag;
+
+/// Diagonals of the square matrices in the last two axes.
+pub struct MatrixDiagPart;
+
+/// `f[i][j] = 1 / (e[j] - e[i])` for each vector `e` in the last axis;
+/// zero on the diagonal and where `e[i] == e[j]`.
+pub struct ReciprocalGaps;
+
+/// Batch size and `(rows, cols)` of the matrices in `shape`.
+fn matrix_dims(op: &str, shape: &[usize]) -> (usize, usize, usize) {
+    assert!(
+        shape.len() >= 2,
+        "{}: Input must have at least 2 axes, got {:?}",
+        op,
+        shape
+    );
+    let rank = shape.len();
+    let batch = shape[..rank - 2].iter().product();
+    (batch, shape[rank - 2], shape[rank - 1])
+}
+
+/// Batch size and `n` of the square matrices in `shape`.
+fn square_dims(op: &str, shape: &[usize]) -> (usize, usize) {
+    let (batch, n, m) = matrix_dims(op, shape);
+    assert_eq!(n, m, "{}: Matrices must be square, got {:?}", op, shape);
+    (batch, n)
+}
+
+fn to_f64(x: &NdArray) -> Vec<f64> {
+    x.iter().map(|&a| a as f64).collect()
+}
+
+fn from_f64(shape: &[usize], a: Vec<f64>) -> NdArray {
+    let a = a.into_iter().map(|a| a as f32).collect();
+    // unwrap is safe
+    NdArray::from_shape_vec(shape, a).unwrap()
+}
+
+/// LU decomposition with partial pivoting of the `n * n` matrix `a`, in place.
+///
+/// Returns the pivot rows and the sign of the permutation,
+/// or `None` if `a` is singular. NaN in `a` propagates to the factors.
+fn lu(a: &mut [f64], n: usize) -> Option<(Vec<usize>, f64)> {
+    let mut piv = (0..n).collect::<Vec<_>>();
+    let mut sign = 1.;
+    for k in 0..n {
+        // NaN is taken as the largest, so that it spreads instead of being skipped.
+        let p = (k..n)
+            .max_by(|&i, &j| ops::cmp_nan_last(&a[i * n + k].abs(), &a[j * n + k].abs()))
+            .unwrap();
+        if a[p * n + k] == 0. {
+            return None;
+        }
+        if p != k {
+            for j in 0..n {
+                a.swap(k * n + j, p * n + j);
+            }
+            piv.swap(k, p);
+            sign = -sign;
+        }
+        let pivot = a[k * n + k];
+        for i in k + 1..n {
+            let f = a[i * n + k] / pivot;
+            a[i * n + k] = f;
+            for j in k + 1..n {
+                a[i * n + j] -= f * a[k * n + j];
+            }
+        }
+    }
+    Some((piv, sign))
+}
+
+/// Solves `l x = b` (or `l^T x = b` if `adjoint`) for the `n * k` matrix `b`, in place,
+/// where `l` is the lower (or upper) triangle of the `n * n` matrix `a`.
+/// `unit` treats the diagonal as ones.
+fn triangular_solve(
+    a: &[f64],
+    b: &mut [f64],
+    n: usize,
+    k: usize,
+    lower: bool,
+    adjoint: bool,
+    unit: bool,
+) {
+    // Element (i, j) of the triangle, seen transposed if `adjoint`.
+    let at = |i: usize, j: usize| if adjoint { a[j * n + i] } else { a[i * n + j] };
+    // Solving with the lower triangle forward, or with the transposed lower triangle
+    // (which is upper) backward, and vice versa.
+    let forward = lower != adjoint;
+    for step in 0..n {
+        let i = if forward { step } else { n - 1 - step };
+        let (lo, hi) = if forward { (0, i) } else { (i + 1, n) };
+        for c in 0..k {
+            let mut s = b[i * k + c];
+            for j in lo..hi {
+                s -= at(i, j) * b[j * k + c];
+            }
+            b[i * k + c] = if unit { s } else { s / at(i, i) };
+        }
+    }
+}
+
+/// Solves `a x = b` for the `n * k` matrix `b`, in place, given `lu(a)`.
+fn lu_solve(lu: &[f64], piv: &[usize], b: &mut [f64], n: usize, k: usize) {
+    let permuted = piv
+        .iter()
+        .flat_map(|&p| b[p * k..(p + 1) * k].to_vec())
+        .collect::<Vec<_>>();
+    b.copy_from_slice(&permuted);
+    triangular_solve(lu, b, n, k, true, false, true);
+    triangular_solve(lu, b, n, k, false, false, false);
+}
+
+/// Cholesky decomposition of the `n * n` matrix `a`, or `None` if not positive definite.
+fn cholesky(a: &[f64], n: usize) -> Option<Vec<f64>> {
+    let mut l = vec![0.; n * n];
+    for j in 0..n {
+        let mut d = a[j * n + j];
+        for k in 0..j {
+            d -= l[j * n + k] * l[j * n + k];
+        }
+        if d <= 0. || d.is_nan() {
+            return None;
+        }
+        let d = d.sqrt();
+        l[j * n + j] = d;
+        for i in j + 1..n {
+            let mut s = a[i * n + j];
+            for k in 0..j {
+                s -= l[i * n + k] * l[j * n + k];
+            }
+            l[i * n + j] = s / d;
+        }
+    }
+    Some(l)
+}
+
+fn identity(n: usize) -> Vec<f64> {
+    let mut a = vec![0.; n * n];
+    for i in 0..n {
+        a[i * n + i] = 1.;
+    }
+    a
+}
+
+/// Upper bound of Jacobi sweeps; they usually converge in less than 10.
+const MAX_SWEEPS: usize = 100;
+
+/// Applies the reflection `I - 2 v v^T` to the rows `k..` of the `? * cols` matrix `a`.
+fn reflect(a: &mut [f64], v: &[f64], k: usize, cols: usize) {
+    for j in 0..cols {
+        let d = v
+            .iter()
+            .enumerate()
+            .map(|(i, &v)| v * a[(k + i) * cols + j])
+            .sum::<f64>();
+        for (i, &v) in v.iter().enumerate() {
+            a[(k + i) * cols + j] -= 2. * d * v;
+        }
+    }
+}
+
+/// Reduced QR decomposition of the `m * n` matrix `a` (`m >= n`) by Householder
+/// reflections.
+///
+/// Returns `q` (`m * n`) and `r` (`n * n`) with the non-negative diagonal.
+fn qr(a: &[f64], m: usize, n: usize) -> (Vec<f64>, Vec<f64>) {
+    let mut r = a.to_vec();
+    let mut vs = Vec::with_capacity(n);
+    for k in 0..n {
+        let mut v = (k..m).map(|i| r[i * n + k]).collect::<Vec<_>>();
+        let norm = v.iter().map(|a| a * a).sum::<f64>().sqrt();
+        v[0] += if v[0] > 0. { norm } else { -norm };
+        let v_norm = v.iter().map(|a| a * a).sum::<f64>().sqrt();
+        if v_norm > 0. {
+            for a in v.iter_mut() {
+                *a /= v_norm;
+            }
+            reflect(&mut r, &v, k, n);
+        }
+        vs.push(v);
+    }
+    let mut q = vec![0.; m * n];
+    for i in 0..n {
+        q[i * n + i] = 1.;
+    }
+    for (k, v) in vs.iter().enumerate().rev() {
+        reflect(&mut q, v, k, n);
+    }
+
+    r.truncate(n * n);
+    for i in 0..n {
+        for j in 0..i {
+            r[i * n + j] = 0.;
+        }
+        if r[i * n + i] < 0. {
+            for j in i..n {
+                r[i * n + j] = -r[i * n + j];
+            }
+            for k in 0..m {
+                q[k * n + i] = -q[k * n + i];
+            }
+        }
+    }
+    (q, r)
+}
+
+/// Rotates the columns `p` and `q` of the `? * cols` matrix `a` by `(c, s)`.
+fn rotate_columns(a: &mut [f64], cols: usize, p: usize, q: usize, c: f64, s: f64) {
+    for row in a.chunks_mut(cols) {
+        let (x, y) = (row[p], row[q]);
+        row[p] = c * x - s * y;
+        row[q] = s * x + c * y;
+    }
+}
+
+/// `(cos, sin)` of the Jacobi rotation annihilating `a_pq` of `[[a_pp, a_pq], [a_pq, a_qq]]`.
+fn jacobi_rotation(a_pp: f64, a_qq: f64, a_pq: f64) -> (f64, f64) {
+    let theta = (a_qq - a_pp) / (2. * a_pq);
+    let t = theta.signum() / (theta.abs() + (theta * theta + 1.).sqrt());
+    let c = 1. / (t * t + 1.).sqrt();
+    (c, t * c)
+}
+
+/// Reorders the columns of the `? * cols` matrix `a` by `perm`.
+fn permute_columns(a: &[f64], cols: usize, perm: &[usize]) -> Vec<f64> {
+    a.chunks(cols)
+        .flat_map(|row| perm.iter().map(move |&j| row[j]))
+        .collect()
+}
+
+/// Eigendecomposition of the symmetric `n * n` matrix `a` by cyclic Jacobi rotations,
+/// reading only its lower triangle.
+///
+/// Returns the eigenvalues in ascending order and the eigenvectors as columns.
+fn eigh(a: &[f64], n: usize) -> (Vec<f64>, Vec<f64>) {
+    let mut a = (0..n * n)
+        .map(|k| {
+            let (i, j) = (k / n, k % n);
+            if i >= j {
+                a[k]
+            } else {
+                a[j * n + i]
+            }
+        })
+        .collect::<Vec<_>>();
+    let mut v = identity(n);
+    let norm = a.iter().map(|a| a * a).sum::<f64>();
+    for _ in 0..MAX_SWEEPS {
+        let off = (0..n * n)
+            .filter(|k| k / n != k % n)
+            .map(|k| a[k] * a[k])
+            .sum::<f64>();
+        if off <= norm * f64::EPSILON * f64::EPSILON {
+            break;
+        }
+        for p in 0..n {
+            for q in p + 1..n {
+                if a[p * n + q] == 0. {
+                    continue;
+                }
+                let (c, s) = jacobi_rotation(a[p * n + p], a[q * n + q], a[p * n + q]);
+                // a <- j^T a j
+                rotate_columns(&mut a, n, p, q, c, s);
+                for k in 0..n {
+                    let (x, y) = (a[p * n + k], a[q * n + k]);
+                    a[p * n + k] = c * x - s * y;
+                    a[q * n + k] = s * x + c * y;
+                }
+                a[p * n + q] = 0.;
+                a[q * n + p] = 0.;
+                rotate_columns(&mut v, n, p, q, c, s);
+            }
+        }
+    }
+    let mut perm = (0..n).collect::<Vec<_>>();
+    // NaN comes last, as in `ag::sort`.
+    perm.sort_by(|&i, &j| ops::cmp_nan_last(&a[i * n + i], &a[j * n + j]));
+    let e = perm.iter().map(|&i| a[i * n + i]).collect();
+    (e, permute_columns(&v, n, &perm))
+}
+
+/// Reduced SVD of the `m * n` matrix `a` (`m >= n`) by one-sided Jacobi rotations.
+///
+/// Returns `u` (`m * n`), the singular values in descending order and `v` (`n * n`).
+fn svd_tall(a: &[f64], m: usize, n: usize) -> (Vec<f64>, Vec<f64>, Vec<f64>) {
+    let mut u = a.to_vec();
+    let mut v = identity(n);
+    let column_dot = |u: &[f64], p: usize, q: usize| {
+        (0..m).map(|i| u[i * n + p] * u[i * n + q]).sum::<f64>()
+    };
+    for _ in 0..MAX_SWEEPS {
+        let mut rotated = false;
+        for p in 0..n {
+            for q in p + 1..n {
+                let alpha = column_dot(&u, p, p);
+                let beta = column_dot(&u, q, q);
+                let gamma = column_dot(&u, p, q);
+                if gamma == 0. || gamma.abs() <= f64::EPSILON * (alpha * beta).sqrt() {
+                    continue;
+                }
+                rotated = true;
+                let (c, s) = jacobi_rotation(alpha, beta, gamma);
+                rotate_columns(&mut u, n, p, q, c, s);
+                rotate_columns(&mut v, n, p, q, c, s);
+            }
+        }
+        if !rotated {
+            break;
+        }
+    }
+
+    let norms = (0..n).map(|j| column_dot(&u, j, j).sqrt()).collect::<Vec<_>>();
+    let mut perm = (0..n).collect::<Vec<_>>();
+    // NaN comes first, as in descending `ag::sort`.
+    perm.sort_by(|&i, &j| ops::cmp_nan_last(&norms[j], &norms[i]));
+    let s = perm.iter().map(|&j| norms[j]).collect::<Vec<_>>();
+    let mut u = permute_columns(&u, n, &perm);
+    let v = permute_columns(&v, n, &perm);
+
+    // Normalizes the columns, completing those of zero singular values to an
+    // orthonormal basis.
+    let tol = s.first().cloned().unwrap_or(0.) * m as f64 * f64::EPSILON;
+    for j in 0..n {
+        if s[j] > tol {
+            for i in 0..m {
+                u[i * n + j] /= s[j];
+            }
+            continue;
+        }
+        for e in 0..m {
+            let mut w = (0..m).map(|i| if i == e { 1. } else { 0. }).collect::<Vec<_>>();
+            for k in 0..j {
+                let d = (0..m).map(|i| u[i * n + k] * w[i]).sum::<f64>();
+                for i in 0..m {
+                    w[i] -= d * u[i * n + k];
+                }
+            }
+            let w_norm = w.iter().map(|a| a * a).sum::<f64>().sqrt();
+            if w_norm > 0.5 {
+                for i in 0..m {
+                    u[i * n + j] = w[i] / w_norm;
+                }
+                break;
+            }
+        }
+    }
+    (u, s, v)
+}
+
+/// Reduced SVD of the `m * n` matrix `a`.
+///
+/// Returns `u` (`m * k`), the singular values and `v` (`n * k`) where `k = min(m, n)`.
+fn svd(a: &[f64], m: usize, n: usize) -> (Vec<f64>, Vec<f64>, Vec<f64>) {
+    if m >= n {
+        svd_tall(a, m, n)
+    } else {
+        let a_t = (0..m * n).map(|k| a[(k % m) * n + k / m]).collect::<Vec<_>>();
+        let (v, s, u) = svd_tall(&a_t, n, m);
+        (u, s, v)
+    }
+}
+
+/// Static shape of a square-matrix input, checking the last two axes.
+fn square_static_shape(xs: &[&Tensor]) -> op::StaticShapeResult {
+    let shape = match xs[0].static_shape() {
+        Some(a) => a,
+        None => return Ok(None),
+    };
+    let rank = shape.len();
+    if rank < 2 {
+        return Err(format!("Input must have at least 2 axes, got {:?}", shape));
+    }
+    let (n, m) = (shape[rank - 2], shape[rank - 1]);
+    if n != -1 && m != -1 && n != m {
+        return Err(format!("Matrices must be square, got {:?}", shape));
+    }
+    Ok(Some(shape))
+}
+
+/// Static shape of `Solve`, i.e. that of `b`.
+fn solve_static_shape(xs: &[&Tensor]) -> op::StaticShapeResult {
+    let (a, b) = match (square_static_shape(xs)?, xs[1].static_shape()) {
+        (Some(a), Some(b)) => (a, b),
+        (_, b) => return Ok(b),
+    };
+    let rank = a.len();
+    // All but the last axis must match.
+    if rank != b.len()
+        || a[..rank - 1]
+            .iter()
+            .zip(&b[..rank - 1])
+            .any(|(&p, &q)| p != -1 && q != -1 && p != q)
+    {
+        return Err(format!("Input shapes mismatch: {:?} vs {:?}", a, b));
+    }
+    Ok(Some(b))
+}
+
+/// Checks that `a` and `b` of `Solve` are compatible, and returns `(batch, n, k)`.
+fn solve_dims(op: &str, a: &NdArray, b: &NdArray) -> (usize, usize, usize) {
+    let (batch, n) = square_dims(op, a.shape());
+    let (_, rows, k) = matrix_dims(op, b.shape());
+    let rank = a.ndim();
+    assert!(
+        b.ndim() == rank && a.shape()[..rank - 2] == b.shape()[..rank - 2] && rows == n,
+        "{}: Input shapes mismatch: {:?} vs {:?}",
+        op,
+        a.shape(),
+        b.shape()
+    );
+    (batch, n, k)
+}
+
+/// `x` with two trailing axes of size 1.
+fn expand_last2(x: &Tensor) -> Tensor {
+    ops::reshape(x, &ops::concat(&[&x.shape(), &ops::ones(&[2])], 0))
+}
+
+/// `a b` with optionally transposed operands.
+fn matmul(a: &Tensor, b: &Tensor, transpose_a: bool, transpose_b: bool) -> Tensor {
+    Tensor::builder().set_inputs(vec![a, b]).build(BatchMatMul {
+        transpose_a,
+        transpose_b,
+    })
+}
+
+fn matrix_transpose(x: &Tensor) -> Tensor {
+    Tensor::builder().set_input(x).build(MatrixTranspose)
+}
+
+impl op::Op for MatrixInverse {
+    fn name(&self) -> &str {
+        "MatrixInverse"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        let x = ctx.grab_inputs()[0];
+        let (batch, n) = square_dims("ag::matrix_inverse", x.shape());
+        let mut a = to_f64(x);
+        let mut y = Vec::with_capacity(a.len());
+        for i in 0..batch {
+            let a = &mut a[i * n * n..(i + 1) * n * n];
+            let (piv, _) = lu(a, n).expect("ag::matrix_inverse: Matrix is singular");
+            let mut inv = identity(n);
+            lu_solve(a, &piv, &mut inv, n, n);
+            y.extend(inv);
+        }
+        vec![Ok(from_f64(x.shape(), y))]
+    }
+
+    fn grad(&self, gy: &Tensor, _: &[&Tensor], y: &Tensor) -> Vec<Option<Tensor>> {
+        // -y^T gy y^T
+        let gx = matmul(&matmul(y, gy, true, false), y, false, true);
+        vec![Some(ops::neg(&gx))]
+    }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        square_static_shape(xs)
+    }
+}
+
+/// Computes `f(sign, log|det|)` of each matrix of `x`.
+fn map_det<F: Fn(f64, f64) -> f64>(op: &str, x: &NdArray, f: F) -> NdArray {
+    let (batch, n) = square_dims(op, x.shape());
+    let mut a = to_f64(x);
+    let y = (0..batch)
+        .map(|i| {
+            let a = &mut a[i * n * n..(i + 1) * n * n];
+            match lu(a, n) {
+                Some((_, sign)) => {
+                    let (sign, log) = (0..n).fold((sign, 0.), |(s, l), i| {
+                        let d: f64 = a[i * n + i];
+                        (s * d.signum(), l + d.abs().ln())
+                    });
+                    f(sign, log)
+                }
+                None => f(0., f64::NEG_INFINITY),
+            }
+        })
+        .collect::<Vec<_>>();
+    from_f64(&x.shape()[..x.ndim() - 2], y)
+}
+
+impl op::Op for Determinant {
+    fn name(&self) -> &str {
+        "Determinant"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        let x = ctx.grab_inputs()[0];
+        let y = map_det("ag::determinant", x, |sign, log| sign * log.exp());
+        vec![Ok(y)]
+    }
+
+    fn grad(&self, gy: &Tensor, xs: &[&Tensor], y: &Tensor) -> Vec<Option<Tensor>> {
+        // gy det(x) x^-T
+        let inv = Tensor::builder().set_input(xs[0]).build(MatrixInverse);
+        let gx = expand_last2(&(gy * y)) * matrix_transpose(&inv);
+        vec![Some(gx)]
+    }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(square_static_shape(xs)?.map(|s| s[..s.len() - 2].to_vec()))
+    }
+}
+
+impl op::Op for LogDet {
+    fn name(&self) -> &str {
+        "LogDet"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        let x = ctx.grab_inputs()[0];
+        vec![Ok(map_det("ag::log_det", x, |_, log| log))]
+    }
+
+    fn grad(&self, gy: &Tensor, xs: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
+        // gy x^-T
+        let inv = Tensor::builder().set_input(xs[0]).build(MatrixInverse);
+        let gx = expand_last2(gy) * matrix_transpose(&inv);
+        vec![Some(gx)]
+    }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(square_static_shape(xs)?.map(|s| s[..s.len() - 2].to_vec()))
+    }
+}
+
+/// Gradients of `a` and `b` of `Solve` and `TriangularSolve`, given the gradient of `b`.
+fn solve_grads(gb: Tensor, x: &Tensor, adjoint: bool) -> (Tensor, Tensor) {
+    // -gb x^T, or its transpose if `adjoint`
+    let ga = if adjoint {
+        matmul(x, &gb, false, true)
+    } else {
+        matmul(&gb, x, false, true)
+    };
+    (ops::neg(&ga), gb)
+}
+
+impl op::Op for Solve {
+    fn name(&self) -> &str {
+        "Solve"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        let xs = ctx.grab_inputs();
+        let (batch, n, k) = solve_dims("ag::solve", xs[0], xs[1]);
+        let mut a = to_f64(xs[0]);
+        let mut b = to_f64(xs[1]);
+        for i in 0..batch {
+            let a = &mut a[i * n * n..(i + 1) * n * n];
+            if self.adjoint {
+                for r in 0..n {
+                    for c in r + 1..n {
+                        a.swap(r * n + c, c * n + r);
+                    }
+                }
+            }
+            let (piv, _) = lu(a, n).expect("ag::solve: Matrix is singular");
+            lu_solve(a, &piv, &mut b[i * n * k..(i + 1) * n * k], n, k);
+        }
+        vec![Ok(from_f64(xs[1].shape(), b))]
+    }
+
+    fn grad(&self, gy: &Tensor, xs: &[&Tensor], y: &Tensor) -> Vec<Option<Tensor>> {
+        let gb = Tensor::builder()
+            .set_inputs(vec![xs[0], gy])
+            .set_shape(gy.shape())
+            .build(Solve {
+                adjoint: !self.adjoint,
+            });
+        let (ga, gb) = solve_grads(gb, y, self.adjoint);
+        vec![Some(ga), Some(gb)]
+    }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        solve_static_shape(xs)
+    }
+}
+
+impl op::Op for TriangularSolve {
+    fn name(&self) -> &str {
+        "TriangularSolve"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        let xs = ctx.grab_inputs();
+        let (batch, n, k) = solve_dims("ag::triangular_solve", xs[0], xs[1]);
+        let a = to_f64(xs[0]);
+        let mut b = to_f64(xs[1]);
+        for i in 0..batch {
+            triangular_solve(
+                &a[i * n * n..(i + 1) * n * n],
+                &mut b[i * n * k..(i + 1) * n * k],
+                n,
+                k,
+                self.lower,
+                self.adjoint,
+                false,
+            );
+        }
+        vec![Ok(from_f64(xs[1].shape(), b))]
+    }
+
+    fn grad(&self, gy: &Tensor, xs: &[&Tensor], y: &Tensor) -> Vec<Option<Tensor>> {
+        let gb = Tensor::builder()
+            .set_inputs(vec![xs[0], gy])
+            .set_shape(gy.shape())
+            .build(TriangularSolve {
+                lower: self.lower,
+                adjoint: !self.adjoint,
+            });
+        let (ga, gb) = solve_grads(gb, y, self.adjoint);
+        // The other triangle of `a` is not used.
+        let ga = Tensor::builder().set_input(&ga).build(TriangularPart {
+            lower: self.lower,
+            diag: 1.,
+        });
+        vec![Some(ga), Some(gb)]
+    }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        solve_static_shape(xs)
+    }
+}
+
+impl op::Op for Cholesky {
+    fn name(&self) -> &str {
+        "Cholesky"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        let x = ctx.grab_inputs()[0];
+        let (batch, n) = square_dims("ag::cholesky", x.shape());
+        let a = to_f64(x);
+        let mut y = Vec::with_capacity(a.len());
+        for i in 0..batch {
+            let l = cholesky(&a[i * n * n..(i + 1) * n * n], n)
+                .expect("ag::cholesky: Matrix is not positive definite");
+            y.extend(l);
+        }
+        vec![Ok(from_f64(x.shape(), y))]
+    }
+
+    // cf. https://arxiv.org/abs/1602.07527
+    fn grad(&self, gy: &Tensor, _: &[&Tensor], y: &Tensor) -> Vec<Option<Tensor>> {
+        // gx = sym(l^-T phi(l^T gy) l^-1), where `phi` takes the lower triangle
+        // with the diagonal halved.
+        let solve_lt = |b: &Tensor| {
+            Tensor::builder()
+                .set_inputs(vec![y, b])
+                .build(TriangularSolve {
+                    lower: true,
+                    adjoint: true,
+                })
+        };
+        let phi = Tensor::builder()
+            .set_input(&matmul(y, gy, true, false))
+            .build(TriangularPart {
+                lower: true,
+                diag: 0.5,
+            });
+        let sym = &phi + &matrix_transpose(&phi);
+        let gx = solve_lt(&matrix_transpose(&solve_lt(&sym))) * 0.5;
+        vec![Some(gx)]
+    }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        square_static_shape(xs)
+    }
+}
+
+impl op::Op for MatrixTranspose {
+    fn name(&self) -> &str {
+        "MatrixTranspose"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        let x = ctx.grab_inputs()[0];
+        let rank = x.ndim();
+        assert!(rank >= 2, "MatrixTranspose: Input must have at least 2 axes");
+        let mut y = x.view();
+        y.swap_axes(rank - 2, rank - 1);
+        vec![Ok(y.to_owned())]
+    }
+
+    fn grad(&self, gy: &Tensor, _: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
+        vec![Some(matrix_transpose(gy))]
+    }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape().map(|mut s| {
+            let rank = s.len();
+            s.swap(rank - 2, rank - 1);
+            s
+        }))
+    }
+}
+
+impl op::Op for TriangularPart {
+    fn name(&self) -> &str {
+        "TriangularPart"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        let x = ctx.grab_inputs()[0];
+        let rank = x.ndim();
+        assert!(rank >= 2, "TriangularPart: Input must have at least 2 axes");
+        let rows = x.shape()[rank - 2];
+        let mut y = x.to_owned();
+        for (i, mut lane) in y.lanes_mut(ndarray::Axis(rank - 1)).into_iter().enumerate() {
+            let row = i % rows;
+            for (col, a) in lane.iter_mut().enumerate() {
+                if col == row {
+                    *a *= self.diag;
+                } else if (col > row) == self.lower {
+                    *a = 0.;
+                }
+            }
+        }
+        vec![Ok(y)]
+    }
+
+    fn grad(&self, gy: &Tensor, _: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
+        let gx = Tensor::builder().set_input(gy).build(TriangularPart {
+            lower: self.lower,
+            diag: self.diag,
+        });
+        vec![Some(gx)]
+    }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape())
+    }
+}
+
+/// `x` with its matrix axes (the last two) replaced by `dims`.
+fn with_matrix_dims(x: &NdArray, dims: &[usize]) -> Vec<usize> {
+    [&x.shape()[..x.ndim() - 2], dims].concat()
+}
+
+/// `index`-th outputs of the decomposition `y` having `num_outputs` outputs.
+pub fn decomposition_outputs(y: &Tensor, num_outputs: usize) -> Vec<Tensor> {
+    (0..num_outputs)
+        .map(|index| {
+            Tensor::builder()
+                .set_input(y)
+                .set_input_indices(vec![index])
+                .build(DecompositionOutput { index, num_outputs })
+        })
+        .collect()
+}
+
+/// The outputs of the decomposition `y` and their gradients unpacked from `gy`.
+fn unpack_grads(gy: &Tensor, y: &Tensor, num_outputs: usize) -> (Vec<Tensor>, Vec<Tensor>) {
+    let outputs = (0..num_outputs)
+        .map(|i| ops::nth_tensor(y, i))
+        .collect::<Vec<_>>();
+    let grads = (0..num_outputs)
+        .map(|index| {
+            let mut inputs = vec![gy];
+            inputs.extend(outputs.iter());
+            Tensor::builder()
+                .set_inputs(inputs)
+                .build(UnpackGrad { index })
+        })
+        .collect();
+    (outputs, grads)
+}
+
+fn matrix_diag(x: &Tensor) -> Tensor {
+    Tensor::builder().set_input(x).build(MatrixDiag)
+}
+
+impl op::Op for Qr {
+    fn name(&self) -> &str {
+        "Qr"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        let x = ctx.grab_inputs()[0];
+        let (batch, m, n) = matrix_dims("ag::qr", x.shape());
+        assert!(
+            m >= n,
+            "ag::qr: Matrices must have at least as many rows as columns, got {:?}",
+            x.shape()
+        );
+        let a = to_f64(x);
+        let mut q = Vec::with_capacity(batch * m * n);
+        let mut r = Vec::with_capacity(batch * n * n);
+        for i in 0..batch {
+            let (q_, r_) = qr(&a[i * m * n..(i + 1) * m * n], m, n);
+            q.extend(q_);
+            r.extend(r_);
+        }
+        vec![
+            Ok(from_f64(&with_matrix_dims(x, &[m, n]), q)),
+            Ok(from_f64(&with_matrix_dims(x, &[n, n]), r)),
+        ]
+    }
+
+    // cf. https://arxiv.org/abs/1710.08717
+    fn grad(&self, gy: &Tensor, _: &[&Tensor], y: &Tensor) -> Vec<Option<Tensor>> {
+        let (outputs, grads) = unpack_grads(gy, y, 2);
+        let (q, r, gq, gr) = (&outputs[0], &outputs[1], &grads[0], &grads[1]);
+        // b r^-T
+        let solve_rt = |b: &Tensor| {
+            let x = Tensor::builder()
+                .set_inputs(vec![r, &matrix_transpose(b)])
+                .build(TriangularSolve {
+                    lower: false,
+                    adjoint: false,
+                });
+            matrix_transpose(&x)
+        };
+        let qdq = matmul(q, gq, true, false);
+        let rdr = matmul(r, gr, false, true);
+        let tril = Tensor::builder()
+            .set_input(&(&qdq - &matrix_transpose(&qdq) + &rdr - &matrix_transpose(&rdr)))
+            .build(TriangularPart {
+                lower: true,
+                diag: 1.,
+            });
+        let gx = matmul(q, &(gr + &solve_rt(&tril)), false, false)
+            + solve_rt(&(gq - &matmul(q, &qdq, false, false)));
+        vec![Some(gx)]
+    }
+}
+
+impl op::Op for Svd {
+    fn name(&self) -> &str {
+        "Svd"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        let x = ctx.grab_inputs()[0];
+        let (batch, m, n) = matrix_dims("ag::svd", x.shape());
+        let k = m.min(n);
+        let a = to_f64(x);
+        let mut u = Vec::with_capacity(batch * m * k);
+        let mut s = Vec::with_capacity(batch * k);
+        let mut v = Vec::with_capacity(batch * n * k);
+        for i in 0..batch {
+            let (u_, s_, v_) = svd(&a[i * m * n..(i + 1) * m * n], m, n);
+            u.extend(u_);
+            s.extend(s_);
+            v.extend(v_);
+        }
+        vec![
+            Ok(from_f64(&with_matrix_dims(x, &[m, k]), u)),
+            Ok(from_f64(&with_matrix_dims(x, &[k]), s)),
+            Ok(from_f64(&with_matrix_dims(x, &[n, k]), v)),
+        ]
+    }
+
+    // cf. https://j-towns.github.io/papers/svd-derivative.pdf
+    fn grad(&self, gy: &Tensor, _: &[&Tensor], y: &Tensor) -> Vec<Option<Tensor>> {
+        let (outputs, grads) = unpack_grads(gy, y, 3);
+        let (u, s, v) = (&outputs[0], &outputs[1], &outputs[2]);
+        let (gu, gs, gv) = (&grads[0], &grads[1], &grads[2]);
+        let f = Tensor::builder()
+            .set_input(&ops::square(s))
+            .build(ReciprocalGaps);
+        let s_mat = matrix_diag(s);
+        let s_inv = matrix_diag(&ops::reciprocal(s));
+        let utgu = matmul(u, gu, true, false);
+        let vtgv = matmul(v, gv, true, false);
+
+        let inner = matmul(&(&f * &(&utgu - &matrix_transpose(&utgu))), &s_mat, false, false)
+            + matrix_diag(gs)
+            + matmul(&s_mat, &(&f * &(&vtgv - &matrix_transpose(&vtgv))), false, false);
+        let gx = matmul(&matmul(u, &inner, false, false), v, false, true)
+            // (I - u u^T) gu s^-1 v^T
+            + matmul(
+                &matmul(&(gu - &matmul(u, &utgu, false, false)), &s_inv, false, false),
+                v,
+                false,
+                true,
+            )
+            // u s^-1 gv^T (I - v v^T)
+            + matmul(
+                &matmul(u, &s_inv, false, false),
+                &(gv - &matmul(v, &vtgv, false, false)),
+                false,
+                true,
+            );
+        vec![Some(gx)]
+    }
+}
+
+impl op::Op for Eigh {
+    fn name(&self) -> &str {
+        "Eigh"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        let x = ctx.grab_inputs()[0];
+        let (batch, n) = square_dims("ag::eigh", x.shape());
+        let a = to_f64(x);
+        let mut e = Vec::with_capacity(batch * n);
+        let mut v = Vec::with_capacity(batch * n * n);
+        for i in 0..batch {
+            let (e_, v_) = eigh(&a[i * n * n..(i + 1) * n * n], n);
+            e.extend(e_);
+            v.extend(v_);
+        }
+        vec![
+            Ok(from_f64(&with_matrix_dims(x, &[n]), e)),
+            Ok(from_f64(x.shape(), v)),
+        ]
+    }
+
+    fn grad(&self, gy: &Tensor, _: &[&Tensor], y: &Tensor) -> Vec<Option<Tensor>> {
+        let (outputs, grads) = unpack_grads(gy, y, 2);
+        let (e, v, ge, gv) = (&outputs[0], &outputs[1], &grads[0], &grads[1]);
+        // v (diag(ge) + f * (v^T gv)) v^T
+        let f = Tensor::builder().set_input(e).build(ReciprocalGaps);
+        let inner = matrix_diag(ge) + f * matmul(v, gv, true, false);
+        let g = matmul(&matmul(v, &inner, false, false), v, false, true);
+        // Only the lower triangle has been read.
+        let gx = Tensor::builder()
+            .set_input(&(&g + &matrix_transpose(&g)))
+            .build(TriangularPart {
+                lower: true,
+                diag: 0.5,
+            });
+        vec![Some(gx)]
+    }
+}
+
+impl op::Op for DecompositionOutput {
+    fn name(&self) -> &str {
+        "DecompositionOutput"
+    }
+
+    fn compute(&self, _: ::runtime::OpComputeContext) -> op::ComputeResult {
+        vec![Err(::op::ComputeException::Delegate { to: 0 })]
+    }
+
+    fn grad(&self, gy: &Tensor, xs: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
+        let outputs = (0..self.num_outputs)
+            .map(|i| ops::nth_tensor(xs[0], i))
+            .collect::<Vec<_>>();
+        let mut inputs = vec![gy];
+        inputs.extend(outputs.iter());
+        let gx = Tensor::builder()
+            .set_inputs(inputs)
+            .build(PackGrads { index: self.index });
+        vec![Some(gx)]
+    }
+}
+
+/// Offset and length of the `index`-th array of `ys` in their concatenation.
+fn packed_range(ys: &[&NdArray], index: usize) -> (usize, usize) {
+    let offset = ys[..index].iter().map(|y| y.len()).sum();
+    (offset, ys[index].len())
+}
+
+impl op::Op for PackGrads {
+    fn name(&self) -> &str {
+        "PackGrads"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        let xs = ctx.grab_inputs();
+        let (offset, len) = packed_range(&xs[1..], self.index);
+        let total = xs[1..].iter().map(|y| y.len()).sum();
+        let mut packed = vec![0.; total];
+        for (p, &g) in packed[offset..offset + len].iter_mut().zip(xs[0].iter()) {
+            *p = g;
+        }
+        // unwrap is safe
+        vec![Ok(NdArray::from_shape_vec(ndarray::IxDyn(&[total]), packed).unwrap())]
+    }
+
+    fn grad(&self, gy: &Tensor, xs: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
+        let mut inputs = vec![gy];
+        inputs.extend(xs[1..].iter().cloned());
+        let gx = Tensor::builder()
+            .set_inputs(inputs)
+            .build(UnpackGrad { index: self.index });
+        let mut ret = vec![Some(gx)];
+        ret.extend(xs[1..].iter().map(|_| None));
+        ret
+    }
+}
+
+impl op::Op for UnpackGrad {
+    fn name(&self) -> &str {
+        "UnpackGrad"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        let xs = ctx.grab_inputs();
+        let (offset, len) = packed_range(&xs[1..], self.index);
+        let g = xs[0].iter().skip(offset).take(len).cloned().collect();
+        // unwrap is safe
+        vec![Ok(NdArray::from_shape_vec(xs[1 + self.index].shape(), g).unwrap())]
+    }
+
+    fn grad(&self, gy: &Tensor, xs: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
+        let mut inputs = vec![gy];
+        inputs.extend(xs[1..].iter().cloned());
+        let gx = Tensor::builder()
+            .set_inputs(inputs)
+            .build(PackGrads { index: self.index });
+        let mut ret = vec![Some(gx)];
+        ret.extend(xs[1..].iter().map(|_| None));
+        ret
+    }
+}
+
+/// Static shape of `x` with the size of the last axis appended.
+fn diag_static_shape(x: &Tensor) -> op::StaticShapeResult {
+    Ok(x.static_shape().and_then(|mut s| {
+        let k = *s.last()?;
+        s.push(k);
+        Some(s)
+    }))
+}
+
+impl op::Op for MatrixDiag {
+    fn name(&self) -> &str {
+        "MatrixDiag"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        let x = ctx.grab_inputs()[0];
+        assert!(x.ndim() >= 1, "MatrixDiag: Input must have at least 1 axis");
+        let k = x.shape()[x.ndim() - 1];
+        let mut y = vec![0.; x.len() * k];
+        for (i, &a) in x.iter().enumerate() {
+            y[i * k + i % k] = a;
+        }
+        let shape = [x.shape(), &[k]].concat();
+        // unwrap is safe
+        vec![Ok(NdArray::from_shape_vec(shape, y).unwrap())]
+    }
+
+    fn grad(&self, gy: &Tensor, _: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
+        vec![Some(Tensor::builder().set_input(gy).build(MatrixDiagPart))]
+    }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        diag_static_shape(xs[0])
+    }
+}
+
+impl op::Op for MatrixDiagPart {
+    fn name(&self) -> &str {
+        "MatrixDiagPart"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        let x = ctx.grab_inputs()[0];
+        let (_, k) = square_dims("MatrixDiagPart", x.shape());
+        let y = x
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| (i / k) % k == i % k)
+            .map(|(_, &a)| a)
+            .collect();
+        // unwrap is safe
+        vec![Ok(NdArray::from_shape_vec(&x.shape()[..x.ndim() - 1], y).unwrap())]
+    }
+
+    fn grad(&self, gy: &Tensor, _: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
+        vec![Some(matrix_diag(gy))]
+    }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape().map(|mut s| {
+            s.pop();
+            s
+        }))
+    }
+}
+
+impl op::Op for ReciprocalGaps {
+    fn name(&self) -> &str {
+        "ReciprocalGaps"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        let x = ctx.grab_inputs()[0];
+        assert!(x.ndim() >= 1, "ReciprocalGaps: Input must have at least 1 axis");
+        let k = x.shape()[x.ndim() - 1];
+        let e = x.iter().cloned().collect::<Vec<f32>>();
+        let mut y = Vec::with_capacity(e.len() * k);
+        for e in e.chunks(k) {
+            for i in 0..k {
+                for j in 0..k {
+                    let d = e[j] - e[i];
+                    y.push(if i != j && d != 0. { 1. / d } else { 0. });
+                }
+            }
+        }
+        let shape = [x.shape(), &[k]].concat();
+        // unwrap is safe
+        vec![Ok(NdArray::from_shape_vec(shape, y).unwrap())]
+    }
+
+    fn grad(&self, gy: &Tensor, _: &[&Tensor], y: &Tensor) -> Vec<Option<Tensor>> {
+        // d(f[i][j]) = f[i][j]^2 (de[i] - de[j])
+        let g = gy * ops::square(y);
+        let gx = ops::reduce_sum(&g, &[-1], false) - ops::reduce_sum(&g, &[-2], false);
+        vec![Some(gx)]
+    }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        diag_static_shape(xs[0])
+    }
+}
+
+#[test]
+fn test_lu_solve_and_cholesky() {
+    let a = [4., 2., 0., 2., 5., 3., 0., 3., 6.];
+    let mut lu_a = a.to_vec();
+    let (piv, _) = lu(&mut lu_a, 3).unwrap();
+    let mut inv = identity(3);
+    lu_solve(&lu_a, &piv, &mut inv, 3, 3);
+    for i in 0..3 {
+        for j in 0..3 {
+            let p = (0..3).map(|k| a[i * 3 + k] * inv[k * 3 + j]).sum::<f64>();
+            assert!((p - if i == j { 1. } else { 0. }).abs() < 1e-12);
+        }
+    }
+
+    let l = cholesky(&a, 3).unwrap();
+    for i in 0..3 {
+        for j in 0..3 {
+            let p = (0..3).map(|k| l[i * 3 + k] * l[j * 3 + k]).sum::<f64>();
+            assert!((p - a[i * 3 + j]).abs() < 1e-12);
+        }
+    }
+    assert!(cholesky(&[1., 2., 2., 1.], 2).is_none());
+
+    // NaN is taken as the pivot and spreads to the factors.
+    let mut nan_a = [f64::NAN, 1., 2., 3.];
+    assert_eq!(lu(&mut nan_a, 2).unwrap().0, [0, 1]);
+    assert!(nan_a[3].is_nan());
+}
+
+#[test]
+fn test_decomposition_kernels() {
+    let a = [4., 1., -2., 1., 2., 0., -2., 0., 3., 1., 1., 1.];
+    let (q, r) = qr(&a, 4, 3);
+    for i in 0..4 {
+        for j in 0..3 {
+            let p = (0..3).map(|k| q[i * 3 + k] * r[k * 3 + j]).sum::<f64>();
+            assert!((p - a[i * 3 + j]).abs() < 1e-12);
+        }
+    }
+    assert!((0..3).all(|i| r[i * 3 + i] >= 0.));
+
+    let (u, s, v) = svd(&a, 4, 3);
+    assert!(s[0] >= s[1] && s[1] >= s[2]);
+    for i in 0..4 {
+        for j in 0..3 {
+            let p = (0..3).map(|k| u[i * 3 + k] * s[k] * v[j * 3 + k]).sum::<f64>();
+            assert!((p - a[i * 3 + j]).abs() < 1e-12);
+        }
+    }
+
+    let sym = &a[..9];
+    let (e, v) = eigh(sym, 3);
+    assert!(e[0] <= e[1] && e[1] <= e[2]);
+    for i in 0..3 {
+        for j in 0..3 {
+            let p = (0..3).map(|k| v[i * 3 + k] * e[k] * v[j * 3 + k]).sum::<f64>();
+            assert!((p - sym[i * 3 + j]).abs() < 1e-12);
+        }
+    }
+
+    // NaN spreads to the results instead of panicking.
+    let nan_a = [f64::NAN, 1., 1., 2.];
+    assert!(eigh(&nan_a, 2).0.iter().any(|e| e.is_nan()));
+    assert!(svd(&nan_a, 2, 2).1[0].is_nan());
+}
@@ -41,6 +41,8 @@ pub struct LogSumExp {
 pub struct Transpose {
     pub zip: bool,
 }
+/// `cond != 0 ? a : b` elementwise, where the inputs `cond`, `a` and `b` are broadcast.
+pub struct Select;
 
 #[inline(always)]
 fn equal(a: f32, b: f32) -> f32 {
@@ -169,6 +171,10 @@ macro_rules! impl_cmp_op {
             fn grad(&self, gy: &Tensor, xs: &[&Tensor], y: &Tensor) -> Vec<Option<Tensor>> {
                 $grad_fn(gy, xs, y)
             }
+
+            fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+                ops::binary_ops::infer_bin_op_static_shape(xs)
+            }
         }
     };
 }
@@ -197,6 +203,84 @@ fn min_max_grad(gy: &Tensor, xs: &[&Tensor], y: &Tensor) -> Vec<Option<Tensor>>
     ]
 }
 
+/// Broadcast shape of `shapes`, which must have the same rank unless they are scalars.
+fn broadcast_shapes(shapes: &[&[usize]], name: &str) -> Vec<usize> {
+    let mut ret: Vec<usize> = Vec::new();
+    for &shape in shapes.iter().filter(|s| !s.is_empty()) {
+        if ret.is_empty() {
+            ret = shape.to_vec();
+            continue;
+        }
+        assert!(
+            shape.len() == ret.len()
+                && shape
+                    .iter()
+                    .zip(&ret)
+                    .all(|(&a, &b)| a == b || a == 1 || b == 1),
+            "ag::{}: Shapes {:?} don't broadcast",
+            name,
+            shapes
+        );
+        for (r, &a) in ret.iter_mut().zip(shape) {
+            *r = (*r).max(a);
+        }
+    }
+    ret
+}
+
+impl op::Op for Select {
+    fn name(&self) -> &str {
+        "Select"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        let xs = ctx.grab_inputs();
+        let shape = broadcast_shapes(&[xs[0].shape(), xs[1].shape(), xs[2].shape()], "select");
+        let (cond, a, b) = (
+            xs[0].broadcast(shape.as_slice()).unwrap(),
+            xs[1].broadcast(shape.as_slice()).unwrap(),
+            xs[2].broadcast(shape.as_slice()).unwrap(),
+        );
+        // Not arithmetic, so that Inf or NaN of the other side doesn't leak.
+        let mut y = NdArray::zeros(shape);
+        Zip::from(&mut y)
+            .and(&cond)
+            .and(&a)
+            .and(&b)
+            .apply(|y, &c, &a, &b| *y = if c != 0. { a } else { b });
+        vec![Ok(y)]
+    }
+
+    fn grad(&self, gy: &Tensor, xs: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
+        let cond = xs[0];
+        let ref zero = ops::scalar(0.);
+        let reduce = |g: Tensor, x: &Tensor| {
+            let shape = x.shape();
+            Tensor::builder()
+                .set_inputs(vec![&g, &shape])
+                .set_shape(shape)
+                .build(ops::binary_ops::PreprocessBinOpGrad)
+        };
+        let ga = ops::select(cond, gy, zero);
+        let gb = ops::select(cond, zero, gy);
+        vec![None, Some(reduce(ga, xs[1])), Some(reduce(gb, xs[2]))]
+    }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        match (
+            xs[0].static_shape(),
+            xs[1].static_shape(),
+            xs[2].static_shape(),
+        ) {
+            (Some(c), Some(a), Some(b)) => {
+                let ab = ops::binary_ops::broadcast_static_shapes(a, b)?;
+                ops::binary_ops::broadcast_static_shapes(c, ab).map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
 impl op::Op for Abs {
     fn name(&self) -> &str {
         "Abs"
@@ -204,12 +288,16 @@ impl op::Op for Abs {
 
     fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
         let xs = ctx.grab_inputs();
-        vec![Ok(xs[0].map(|x| x.abs()))]
+        vec![Ok(ctx.backend().map(xs[0], &|x| x.abs()))]
     }
 
     fn grad(&self, gy: &Tensor, inputs: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
         vec![Some(gy * ops::sign(inputs[0]))]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape())
+    }
 }
 
 impl op::Op for NegOp {
@@ -219,12 +307,16 @@ impl op::Op for NegOp {
 
     fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
         let xs = ctx.grab_inputs();
-        vec![Ok(xs[0].map(|x| x.neg()))]
+        vec![Ok(ctx.backend().map(xs[0], &|x| x.neg()))]
     }
 
     fn grad(&self, gy: &Tensor, _: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
         vec![Some(ops::neg(gy))]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape())
+    }
 }
 
 impl op::Op for Square {
@@ -234,12 +326,16 @@ impl op::Op for Square {
 
     fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
         let xs = ctx.grab_inputs();
-        vec![Ok(xs[0].map(|x| x * x))]
+        vec![Ok(ctx.backend().map(xs[0], &|x| x * x))]
     }
 
     fn grad(&self, gy: &Tensor, inputs: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
         vec![Some(2 * inputs[0] * gy)]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape())
+    }
 }
 
 impl op::Op for Reciprocal {
@@ -249,12 +345,16 @@ impl op::Op for Reciprocal {
 
     fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
         let xs = ctx.grab_inputs();
-        vec![Ok(xs[0].map(|x| x.recip()))]
+        vec![Ok(ctx.backend().map(xs[0], &|x| x.recip()))]
     }
 
     fn grad(&self, gy: &Tensor, _: &[&Tensor], output: &Tensor) -> Vec<Option<Tensor>> {
         vec![Some(ops::neg(&ops::square(output)) * gy)]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape())
+    }
 }
 
 impl op::Op for Sign {
@@ -264,7 +364,7 @@ impl op::Op for Sign {
 
     fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
         let xs = ctx.grab_inputs();
-        vec![Ok(xs[0].mapv(|x| {
+        vec![Ok(ctx.backend().map(xs[0], &|x| {
             if x == 0. {
                 0.
             } else {
@@ -276,6 +376,10 @@ impl op::Op for Sign {
     fn grad(&self, _: &Tensor, _: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
         vec![None]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape())
+    }
 }
 
 impl op::Op for Floor {
@@ -285,12 +389,16 @@ impl op::Op for Floor {
 
     fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
         let xs = ctx.grab_inputs();
-        vec![Ok(xs[0].map(|x| x.floor()))]
+        vec![Ok(ctx.backend().map(xs[0], &|x| x.floor()))]
     }
 
     fn grad(&self, _: &Tensor, _: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
         vec![None]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape())
+    }
 }
 
 impl op::Op for Ceil {
@@ -300,12 +408,16 @@ impl op::Op for Ceil {
 
     fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
         let xs = ctx.grab_inputs();
-        vec![Ok(xs[0].map(|x| x.ceil()))]
+        vec![Ok(ctx.backend().map(xs[0], &|x| x.ceil()))]
     }
 
     fn grad(&self, _: &Tensor, _: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
         vec![None]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape())
+    }
 }
 
 impl op::Op for Transpose {
@@ -350,6 +462,11 @@ impl op::Op for Transpose {
             .build(Transpose { zip: !self.zip });
         vec![Some(gx), None]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        // permutation is unknown until runtime
+        Ok(xs[0].static_shape().map(|s| vec![-1; s.len()]))
+    }
 }
 
 fn do_transpose(mut x: ::ndarray_ext::NdArrayView, mut src_dst: Vec<(usize, usize)>) -> NdArray {
@@ -453,6 +570,26 @@ impl op::Op for LogSumExp {
         let gx = ops::softmax(inputs[0], self.axis) * gy;
         vec![Some(gx)]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        let mut shape = match xs[0].static_shape() {
+            Some(a) => a,
+            None => return Ok(None),
+        };
+        let axis = ::ndarray_ext::normalize_negative_axis(self.axis, shape.len());
+        if axis >= shape.len() {
+            return Err(format!(
+                "Axis {} is out of range for {:?}",
+                self.axis, shape
+            ));
+        }
+        if self.keep_dims {
+            shape[axis] = 1;
+        } else {
+            shape.remove(axis);
+        }
+        Ok(Some(shape))
+    }
 }
 
 impl op::Op for Pow {
@@ -463,7 +600,7 @@ impl op::Op for Pow {
     fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
         let x0 = ctx.grab_inputs()[0];
         let a = self.a;
-        vec![Ok(x0.map(move |x| x.powf(a)))]
+        vec![Ok(ctx.backend().map(x0, &move |x| x.powf(a)))]
     }
 
     fn grad(&self, gy: &Tensor, inputs: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
@@ -471,6 +608,10 @@ impl op::Op for Pow {
         let gx = gy * self.a * ops::pow(x, self.a - 1.);
         vec![Some(gx)]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape())
+    }
 }
 
 impl op::Op for Sqrt {
@@ -480,7 +621,7 @@ impl op::Op for Sqrt {
 
     fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
         let x0 = ctx.grab_inputs()[0];
-        vec![Ok(x0.map(|a| a.sqrt()))]
+        vec![Ok(ctx.backend().map(x0, &|a| a.sqrt()))]
     }
 
     fn grad(&self, gy: &Tensor, inputs: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
@@ -488,6 +629,10 @@ impl op::Op for Sqrt {
         let ret = 0.5 * ops::pow(x, -0.5);
         vec![Some(gy * ret)]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape())
+    }
 }
 
 impl op::Op for Log {
@@ -497,12 +642,16 @@ impl op::Op for Log {
 
     fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
         let x = ctx.grab_inputs()[0];
-        vec![Ok(x.map(move |a| a.log(self.a)))]
+        vec![Ok(ctx.backend().map(x, &move |a| a.log(self.a)))]
     }
 
     fn grad(&self, gy: &Tensor, inputs: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
         vec![Some(gy / inputs[0])]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape())
+    }
 }
 
 impl op::Op for Exp {
@@ -512,12 +661,16 @@ impl op::Op for Exp {
 
     fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
         let x = ctx.grab_inputs()[0];
-        vec![Ok(x.map(|a| a.exp()))]
+        vec![Ok(ctx.backend().map(x, &|a| a.exp()))]
     }
 
     fn grad(&self, gy: &Tensor, _: &[&Tensor], output: &Tensor) -> Vec<Option<Tensor>> {
         vec![Some(output * gy)]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape())
+    }
 }
 
 impl op::Op for Atanh {
@@ -527,7 +680,7 @@ impl op::Op for Atanh {
 
     fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
         let x = ctx.grab_inputs()[0];
-        vec![Ok(x.map(|a| a.atanh()))]
+        vec![Ok(ctx.backend().map(x, &|a| a.atanh()))]
     }
 
     fn grad(&self, gy: &Tensor, inputs: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
@@ -535,6 +688,10 @@ impl op::Op for Atanh {
         let y = ops::reciprocal(&(1 - ops::square(x)));
         vec![Some(y * gy)]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape())
+    }
 }
 
 impl op::Op for Acosh {
@@ -544,7 +701,7 @@ impl op::Op for Acosh {
 
     fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
         let x = ctx.grab_inputs()[0];
-        vec![Ok(x.map(|a| a.acosh()))]
+        vec![Ok(ctx.backend().map(x, &|a| a.acosh()))]
     }
 
     fn grad(&self, gy: &Tensor, inputs: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
@@ -552,6 +709,10 @@ impl op::Op for Acosh {
         let y = -1 / ops::sqrt(&(ops::square(x) - 1));
         vec![Some(y * gy)]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape())
+    }
 }
 
 impl op::Op for Asinh {
@@ -561,7 +722,7 @@ impl op::Op for Asinh {
 
     fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
         let x = ctx.grab_inputs()[0];
-        vec![Ok(x.map(|a| a.asinh()))]
+        vec![Ok(ctx.backend().map(x, &|a| a.asinh()))]
     }
 
     fn grad(&self, gy: &Tensor, inputs: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
@@ -569,6 +730,10 @@ impl op::Op for Asinh {
         let y = 1 / ops::sqrt(&(x * x + 1));
         vec![Some(y * gy)]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape())
+    }
 }
 
 impl op::Op for Tanh {
@@ -578,12 +743,16 @@ impl op::Op for Tanh {
 
     fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
         let x = ctx.grab_inputs()[0];
-        vec![Ok(x.map(|a| a.tanh()))]
+        vec![Ok(ctx.backend().map(x, &|a| a.tanh()))]
     }
 
     fn grad(&self, gy: &Tensor, _: &[&Tensor], y: &Tensor) -> Vec<Option<Tensor>> {
         vec![Some(gy * (1 - ops::square(y)))]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape())
+    }
 }
 
 impl op::Op for Cosh {
@@ -593,12 +762,16 @@ impl op::Op for Cosh {
 
     fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
         let x = ctx.grab_inputs()[0];
-        vec![Ok(x.map(|a| a.cosh()))]
+        vec![Ok(ctx.backend().map(x, &|a| a.cosh()))]
     }
 
     fn grad(&self, gy: &Tensor, inputs: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
         vec![Some(ops::sinh(inputs[0]) * gy)]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape())
+    }
 }
 
 impl op::Op for Sinh {
@@ -608,12 +781,16 @@ impl op::Op for Sinh {
 
     fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
         let x = ctx.grab_inputs()[0];
-        vec![Ok(x.map(|a| a.sinh()))]
+        vec![Ok(ctx.backend().map(x, &|a| a.sinh()))]
     }
 
     fn grad(&self, gy: &Tensor, inputs: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
         vec![Some(ops::cosh(inputs[0]) * gy)]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape())
+    }
 }
 
 impl op::Op for Atan {
@@ -623,7 +800,7 @@ impl op::Op for Atan {
 
     fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
         let x = ctx.grab_inputs()[0];
-        vec![Ok(x.map(|a| a.atan()))]
+        vec![Ok(ctx.backend().map(x, &|a| a.atan()))]
     }
 
     fn grad(&self, gy: &Tensor, inputs: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
@@ -631,6 +808,10 @@ impl op::Op for Atan {
         let y = ops::reciprocal(&(1 + ops::square(x)));
         vec![Some(y * gy)]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape())
+    }
 }
 
 impl op::Op for Acos {
@@ -640,7 +821,7 @@ impl op::Op for Acos {
 
     fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
         let x = ctx.grab_inputs()[0];
-        vec![Ok(x.map(|a| a.acos()))]
+        vec![Ok(ctx.backend().map(x, &|a| a.acos()))]
     }
 
     fn grad(&self, gy: &Tensor, inputs: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
@@ -648,6 +829,10 @@ impl op::Op for Acos {
         let y = -1 / ops::sqrt(&(1 - ops::square(x)));
         vec![Some(y * gy)]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape())
+    }
 }
 
 impl op::Op for Asin {
@@ -657,7 +842,7 @@ impl op::Op for Asin {
 
     fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
         let x = ctx.grab_inputs()[0];
-        vec![Ok(x.map(|a| a.asin()))]
+        vec![Ok(ctx.backend().map(x, &|a| a.asin()))]
     }
 
     fn grad(&self, gy: &Tensor, inputs: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
@@ -665,6 +850,10 @@ impl op::Op for Asin {
         let y = 1 / ops::sqrt(&(1 - x * x));
         vec![Some(y * gy)]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape())
+    }
 }
 
 impl op::Op for Sin {
@@ -674,12 +863,16 @@ impl op::Op for Sin {
 
     fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
         let x = ctx.grab_inputs()[0];
-        vec![Ok(x.map(|a| a.sin()))]
+        vec![Ok(ctx.backend().map(x, &|a| a.sin()))]
     }
 
     fn grad(&self, gy: &Tensor, inputs: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
         vec![Some(ops::cos(inputs[0]) * gy)]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape())
+    }
 }
 
 impl op::Op for Cos {
@@ -689,12 +882,16 @@ impl op::Op for Cos {
 
     fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
         let x = ctx.grab_inputs()[0];
-        vec![Ok(x.map(|a| a.cos()))]
+        vec![Ok(ctx.backend().map(x, &|a| a.cos()))]
     }
 
     fn grad(&self, gy: &Tensor, inputs: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
         vec![Some(ops::neg(&(ops::sin(inputs[0]) * gy)))]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape())
+    }
 }
 
 impl op::Op for Tan {
@@ -704,11 +901,15 @@ impl op::Op for Tan {
 
     fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
         let x = ctx.grab_inputs()[0];
-        vec![Ok(x.map(|a| a.tan()))]
+        vec![Ok(ctx.backend().map(x, &|a| a.tan()))]
     }
 
     fn grad(&self, gy: &Tensor, inputs: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
         let ref cos = ops::cos(inputs[0]);
         vec![Some(gy / (ops::square(cos)))]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape())
+    }
 }
@@ -11,13 +11,20 @@ mod binary_ops;
 mod const_gen_ops;
 mod conv_ops;
 mod dot_ops;
+mod einsum;
+mod fft_ops;
 pub mod gradient_descent_ops;
 mod gradient_ops;
+mod linalg_ops;
 mod math_ops;
+mod norm_ops;
 mod random_ops;
 mod reduction_ops;
 mod xent_ops;
 
+pub use self::array_ops::PadMode;
+pub use self::conv_ops::{DataFormat, Padding};
+
 impl Tensor {
     /// Looks up a symbolic element from this tensor.
     ///
@@ -157,6 +164,54 @@ pub fn jacobians(y: &Tensor, xs: &[&Tensor], objective_len: usize) -> Vec<Tensor
         .collect::<Vec<_>>()
 }
 
+/// Collects the variables that `ys` depend on.
+///
+/// Only tensors made from `ag::variable` are collected; constants, placeholders and
+/// anything behind `stop_gradient` are excluded.
+/// The variables are returned in the order they are found by depth-first search from `ys`,
+/// and the result can be passed to `grad` and `gradient_descent_ops` as is.
+///
+/// ```
+/// extern crate autograd as ag;
+///
+/// let ref x = ag::placeholder(&[-1, 2]);
+/// let ref w = ag::variable(ag::ndarray_ext::glorot_uniform(&[2, 3]));
+/// let ref b = ag::variable(ag::ndarray_ext::zeros(&[1, 3]));
+/// let ref frozen = ag::variable(ag::ndarray_ext::zeros(&[1, 3]));
+/// let ref c = ag::constant(ag::ndarray_ext::ones(&[1, 3]));
+/// let ref z = ag::matmul(x, w) + b + ag::stop_gradient(frozen) + c;
+/// let ref loss = ag::reduce_mean(z, &[0, 1], false);
+///
+/// let ref params = ag::trainable_variables(&[loss]);
+/// assert_eq!(params, &[w, b]);
+///
+/// let ref grads = ag::grad(&[loss], params);
+/// let ref adam = ag::gradient_descent_ops::Adam::default();
+/// let ref stateful_params = ag::gradient_descent_ops::Adam::vars_with_states(params);
+/// let ref update_ops = adam.compute_updates(stateful_params, grads);
+/// ```
+pub fn trainable_variables<'a>(ys: &[&'a Tensor]) -> Vec<&'a Tensor> {
+    let mut visited = ::std::collections::HashSet::new();
+    let mut ret = Vec::new();
+    let mut dfs_stack: Vec<&Tensor> = ys.iter().rev().map(|&y| y).collect();
+    while let Some(node) = dfs_stack.pop() {
+        // `stop_gradient` and the like are not differentiable
+        if !node.is_differentiable || !visited.insert(&*node.0 as *const ::tensor::TensorCore) {
+            continue;
+        }
+        if node.is_variable() {
+            ret.push(node);
+        }
+        let inputs = if let Some(ref a) = node.inputs_on_backprop {
+            a
+        } else {
+            &node.inputs
+        };
+        dfs_stack.extend(inputs.iter().rev());
+    }
+    ret
+}
+
 /// (Experimental) Computes hessian vector product
 ///
 /// `ys` must be scalars.
@@ -210,6 +265,36 @@ pub fn variable<T: ndarray::Dimension>(arr: ndarray::Array<f32, T>) -> Tensor {
         .build(basic_source_ops::Variable)
 }
 
+/// Creates a shared variable tensor stored in half precision.
+///
+/// `arr` is rounded into `ty` (`F16` or `BF16`) and only the 16-bit storage is kept
+/// between evaluations. Each evaluation decodes it into a temporary f32 array,
+/// so downstream ops such as `matmul` or `conv2d` compute and accumulate in f32.
+///
+/// Gradient descent methods in `autograd::gradient_descent_ops` can update this;
+/// `Adam` keeps master f32 weights for it.
+///
+/// ```
+/// extern crate ndarray;
+/// extern crate autograd as ag;
+///
+/// use ag::ndarray_ext::HalfType;
+///
+/// let ref x = ag::variable_half(ndarray::arr1(&[1., 1.0001]), HalfType::F16);
+/// assert_eq!(x.eval(&[]), Some(ndarray::arr1(&[1., 1.]).into_dyn()));
+/// ```
+#[cfg(feature = "half")]
+pub fn variable_half<T: ndarray::Dimension>(
+    arr: ndarray::Array<f32, T>,
+    ty: ::ndarray_ext::HalfType,
+) -> Tensor {
+    let arr = arr.into_dyn();
+    Tensor::builder()
+        .set_shape(convert_to_tensor(::ndarray_ext::shape_of(&arr)))
+        .set_half_array(::ndarray_ext::HalfArray::from_f32(&arr, ty))
+        .build(basic_source_ops::HalfVariable)
+}
+
 /// Creates a placeholder tensor.
 ///
 /// ```
@@ -224,7 +309,9 @@ pub fn variable<T: ndarray::Dimension>(arr: ndarray::Array<f32, T>) -> Tensor {
 /// ```
 #[inline]
 pub fn placeholder(shape_: &[isize]) -> Tensor {
-    let b = Tensor::builder().set_is_placeholder(true);
+    let b = Tensor::builder()
+        .set_is_placeholder(true)
+        .set_static_shape(shape_.to_vec());
     let rank = shape_.len();
     let b = if rank == 0 || -1 != shape_[0] {
         b.set_shape(convert_to_tensor(
@@ -437,6 +524,13 @@ pub fn identity<A: AsRef<Tensor>>(x: A) -> Tensor {
         .build(activation_ops::Identity)
 }
 
+/// Total order of floats that puts NaN after every other value.
+#[inline]
+fn cmp_nan_last<T: ndarray::NdFloat>(a: &T, b: &T) -> ::std::cmp::Ordering {
+    a.partial_cmp(b)
+        .unwrap_or_else(|| a.is_nan().cmp(&b.is_nan()))
+}
+
 #[inline]
 fn infer_bin_op_shape<T: AsRef<Tensor>, A: AsRef<Tensor>>(shape_a: T, shape_b: A) -> Tensor {
     Tensor::builder()
@@ -700,6 +794,8 @@ pub fn minimum<A: AsRef<Tensor>, B: AsRef<Tensor>>(a: A, b: B) -> Tensor {
 /// Adds all input tensors, element-wise.
 ///
 /// All the input tensors must have same shapes.
+/// If all of them have sparse representations (see `Tensor::get_indexed_slices`),
+/// so does the result.
 ///
 /// ```
 /// extern crate ndarray;
@@ -719,10 +815,28 @@ pub fn add_n(xs: &[&Tensor]) -> Tensor {
     if len == 1 {
         xs[0].clone()
     } else {
-        Tensor::builder()
+        let mut builder = Tensor::builder()
             .set_inputs(xs.to_vec())
-            .set_shape(xs[0].shape())
-            .build(array_ops::AddN)
+            .set_shape(xs[0].shape());
+        let slices = xs
+            .iter()
+            .map(|x| x.get_indexed_slices())
+            .collect::<Option<Vec<_>>>();
+        if let Some(slices) = slices {
+            // Sparse representation of the sum is the concatenation of them.
+            let pairs = slices
+                .iter()
+                .flat_map(|s| vec![&s.indices, &s.values])
+                .collect::<Vec<_>>();
+            let concat = Tensor::builder()
+                .set_inputs(pairs)
+                .build(array_ops::ConcatIndexedSlices);
+            builder = builder.set_indexed_slices(::tensor::IndexedSlices {
+                indices: nth_tensor(&concat, 0),
+                values: nth_tensor(&concat, 1),
+            });
+        }
+        builder.build(array_ops::AddN)
     }
 }
 
@@ -790,6 +904,110 @@ pub fn argmax<A: AsRef<Tensor>>(x: A, axis: isize, keep_dim: bool) -> Tensor {
     Tensor::builder().set_input(x.as_ref()).build(op)
 }
 
+/// Takes argmin along specified axis.
+///
+/// `axis` can be negative.
+///
+/// ```
+/// extern crate ndarray;
+/// extern crate autograd as ag;
+///
+/// let ref x = ag::constant(ndarray::arr2(&[[3., 4.], [6., 5.]]));
+/// let ref y = ag::argmin(x, 1, false);
+///
+/// assert_eq!(y.eval(&[]), Some(ndarray::arr1(&[0., 1.]).into_dyn()));
+/// ```
+pub fn argmin<A: AsRef<Tensor>>(x: A, axis: isize, keep_dim: bool) -> Tensor {
+    let op = reduction_ops::ArgMin { axis, keep_dim };
+    Tensor::builder().set_input(x.as_ref()).build(op)
+}
+
+/// Returns the `k` largest elements along `axis` and their indices, largest first.
+///
+/// Ties are broken by the lower index, and NaN counts as the largest.
+/// The gradient of the values flows back to the positions they were taken from;
+/// the indices have no gradient.
+/// `axis` can be negative.
+///
+/// ```
+/// extern crate ndarray;
+/// extern crate autograd as ag;
+///
+/// let ref x = ag::constant(ndarray::arr2(&[[1., 4., 2., 3.], [8., 5., 6., 7.]]));
+/// let (values, indices) = ag::top_k(x, 2, 1);
+///
+/// assert_eq!(values.eval(&[]), Some(ndarray::arr2(&[[4., 3.], [8., 7.]]).into_dyn()));
+/// assert_eq!(indices.eval(&[]), Some(ndarray::arr2(&[[1., 3.], [0., 3.]]).into_dyn()));
+/// ```
+pub fn top_k<A: AsRef<Tensor>>(x: A, k: usize, axis: isize) -> (Tensor, Tensor) {
+    let op = reduction_ops::Sort {
+        axis,
+        k: Some(k),
+        descending: true,
+    };
+    let y = Tensor::builder().set_input(x.as_ref()).build(op);
+    (nth_tensor(&y, 0), sort_indices(&y))
+}
+
+// Indices output of `reduction_ops::Sort`, which stops gradients.
+fn sort_indices(y: &Tensor) -> Tensor {
+    Tensor::builder()
+        .set_input(y)
+        .set_input_indices(vec![1])
+        .set_differentiable(false)
+        .build(activation_ops::Identity)
+}
+
+/// Sorts `x` along `axis`.
+///
+/// The sort is stable, and NaN comes last (first if `descending`).
+/// The gradient flows back to the original positions.
+/// `axis` can be negative.
+///
+/// ```
+/// extern crate ndarray;
+/// extern crate autograd as ag;
+///
+/// let ref x = ag::constant(ndarray::arr1(&[3., 1., 2.]));
+///
+/// assert_eq!(ag::sort(x, 0, false).eval(&[]), Some(ndarray::arr1(&[1., 2., 3.]).into_dyn()));
+/// assert_eq!(ag::sort(x, 0, true).eval(&[]), Some(ndarray::arr1(&[3., 2., 1.]).into_dyn()));
+/// ```
+pub fn sort<A: AsRef<Tensor>>(x: A, axis: isize, descending: bool) -> Tensor {
+    let op = reduction_ops::Sort {
+        axis,
+        k: None,
+        descending,
+    };
+    let x = x.as_ref();
+    Tensor::builder()
+        .set_input(x)
+        .set_shape(x.shape())
+        .build(op)
+}
+
+/// Returns the indices that sort `x` along `axis`.
+///
+/// Same as the order of `ag::sort`; not differentiable.
+///
+/// ```
+/// extern crate ndarray;
+/// extern crate autograd as ag;
+///
+/// let ref x = ag::constant(ndarray::arr1(&[3., 1., 2.]));
+///
+/// assert_eq!(ag::argsort(x, 0, false).eval(&[]), Some(ndarray::arr1(&[1., 2., 0.]).into_dyn()));
+/// ```
+pub fn argsort<A: AsRef<Tensor>>(x: A, axis: isize, descending: bool) -> Tensor {
+    let op = reduction_ops::Sort {
+        axis,
+        k: None,
+        descending,
+    };
+    let y = Tensor::builder().set_input(x.as_ref()).build(op);
+    sort_indices(&y)
+}
+
 /// Expands specified dims.
 ///
 /// Each axis can be negative.
@@ -980,6 +1198,59 @@ pub fn reduce_prod<T: ArrayLike, A: AsRef<Tensor>>(x: A, axes: &T, keep_dims: bo
         .build(op)
 }
 
+/// Cumulative sum along `axis`.
+///
+/// `exclusive` leaves out the current element (so the first output is 0) and
+/// `reverse` accumulates from the end of the axis. `axis` can be negative.
+///
+/// ```
+/// extern crate ndarray;
+/// extern crate autograd as ag;
+///
+/// let ref x = ag::constant(ndarray::arr1(&[1., 2., 3.]));
+///
+/// assert_eq!(ag::cumsum(x, 0, false, false).eval(&[]), Some(ndarray::arr1(&[1., 3., 6.]).into_dyn()));
+/// assert_eq!(ag::cumsum(x, 0, true, false).eval(&[]), Some(ndarray::arr1(&[0., 1., 3.]).into_dyn()));
+/// assert_eq!(ag::cumsum(x, 0, false, true).eval(&[]), Some(ndarray::arr1(&[6., 5., 3.]).into_dyn()));
+/// ```
+pub fn cumsum<A: AsRef<Tensor>>(x: A, axis: isize, exclusive: bool, reverse: bool) -> Tensor {
+    let x = x.as_ref();
+    Tensor::builder()
+        .set_input(x)
+        .set_shape(x.shape())
+        .build(reduction_ops::Cumsum {
+            axis,
+            exclusive,
+            reverse,
+        })
+}
+
+/// Cumulative product along `axis`.
+///
+/// `exclusive` and `reverse` are the same as `ag::cumsum`.
+/// The gradient doesn't divide by `x`, so it is correct even if `x` contains zeros.
+///
+/// ```
+/// extern crate ndarray;
+/// extern crate autograd as ag;
+///
+/// let ref x = ag::constant(ndarray::arr1(&[2., 3., 4.]));
+///
+/// assert_eq!(ag::cumprod(x, 0, false, false).eval(&[]), Some(ndarray::arr1(&[2., 6., 24.]).into_dyn()));
+/// assert_eq!(ag::cumprod(x, 0, true, true).eval(&[]), Some(ndarray::arr1(&[12., 4., 1.]).into_dyn()));
+/// ```
+pub fn cumprod<A: AsRef<Tensor>>(x: A, axis: isize, exclusive: bool, reverse: bool) -> Tensor {
+    let x = x.as_ref();
+    Tensor::builder()
+        .set_input(x)
+        .set_shape(x.shape())
+        .build(reduction_ops::Cumprod {
+            axis,
+            exclusive,
+            reverse,
+        })
+}
+
 /// Reshapes input tensor.
 ///
 /// Only one element in `shape` can be `-1`.
@@ -1165,6 +1436,45 @@ pub fn greater<A: AsRef<Tensor>, B: AsRef<Tensor>>(a: A, b: B) -> Tensor {
         .build(math_ops::Greater)
 }
 
+/// Selects elements of `a` where `cond` is nonzero and of `b` elsewhere.
+///
+/// `cond`, `a` and `b` are broadcast to the same shape. Unlike arithmetic on 0/1 masks,
+/// Inf or NaN of the unselected side doesn't leak into the result; gradients flow to
+/// `a` and `b` only where they are selected.
+///
+/// The zero gradients of the unselected side are still propagated through the ops that
+/// computed it, and become NaN where their derivatives are Inf or NaN
+/// (e.g. `sqrt` of non-positive values).
+/// To get finite gradients, also select safe inputs for such ops, as in the example below;
+/// `select(c, sqrt(x), -1)` alone has NaN gradients wherever `x <= 0`.
+///
+/// # Panics
+/// When broadcast is impossible
+///
+/// ```
+/// extern crate ndarray;
+/// extern crate autograd as ag;
+///
+/// let ref x = ag::variable(ndarray::arr1(&[-1., 0., 4.]));
+/// let ref c = ag::greater(x, ag::scalar(0.));
+/// let ref safe_x = ag::select(c, x, ag::scalar(1.));
+/// let ref y = ag::select(c, ag::sqrt(safe_x), ag::scalar(-1.));
+/// let ref g = ag::grad(&[y], &[x])[0];
+///
+/// assert_eq!(y.eval(&[]), Some(ndarray::arr1(&[-1., -1., 2.]).into_dyn()));
+/// assert_eq!(g.eval(&[]), Some(ndarray::arr1(&[0., 0., 0.25]).into_dyn()));
+/// ```
+pub fn select<A, B, C>(cond: A, a: B, b: C) -> Tensor
+where
+    A: AsRef<Tensor>,
+    B: AsRef<Tensor>,
+    C: AsRef<Tensor>,
+{
+    Tensor::builder()
+        .set_inputs(vec![cond.as_ref(), a.as_ref(), b.as_ref()])
+        .build(math_ops::Select)
+}
+
 /// Returns a binary tensor.
 ///
 /// # Panics
@@ -1454,6 +1764,40 @@ where
     reshape(mm, &final_shape)
 }
 
+/// Einstein summation over `operands`.
+///
+/// `spec` labels each axis of the operands with a letter, e.g. `"bij,bjk->bik"`.
+/// Labels missing from the output are summed. Without `->`, the output has the labels
+/// that appear only once, in alphabetical order. A label repeated within a term
+/// (e.g. `"ii->i"`) is not supported.
+///
+/// Operands are contracted pairwise, each pair lowered to `transpose`, `reshape` and
+/// `batch_matmul`. The order is chosen greedily to keep intermediate results small,
+/// using the static shapes of the operands (unknown sizes are taken as 1).
+///
+/// ```
+/// extern crate ndarray;
+/// extern crate autograd as ag;
+///
+/// let ref a = ag::constant(ndarray::arr2(&[[1., 2.], [3., 4.]]));
+/// let ref b = ag::constant(ndarray::arr2(&[[5., 6.], [7., 8.]]));
+///
+/// let ref c = ag::einsum("ij,jk->ik", &[a, b]);
+/// assert_eq!(c.eval(&[]), ag::matmul(a, b).eval(&[]));
+///
+/// // Implicit output: trace of `a b^T`.
+/// let ref d = ag::einsum("ij,ij", &[a, b]);
+/// assert_eq!(d.eval(&[]), Some(ndarray::arr0(70.).into_dyn()));
+///
+/// let ref x = ag::zeros(&[4, 2, 3]);
+/// let ref y = ag::zeros(&[4, 3, 5]);
+/// let ref z = ag::zeros(&[5]);
+/// assert_eq!(ag::einsum("bij,bjk,k->bi", &[x, y, z]).eval(&[]).unwrap().shape(), &[4, 2]);
+/// ```
+pub fn einsum(spec: &str, operands: &[&Tensor]) -> Tensor {
+    einsum::einsum(spec, operands)
+}
+
 /// Batched matrix multiplication.
 ///
 /// The rank of `a` and `b` must be equals.
@@ -1479,568 +1823,1779 @@ pub fn batch_matmul<A: AsRef<Tensor>, B: AsRef<Tensor>>(a: A, b: B) -> Tensor {
         .build(op)
 }
 
-/// Takes diff between two tensors.
+/// Inverts each matrix in the last two axes of `x`.
 ///
-/// Returns the sorted, unique values in `a` that are not in `b`.
+/// Panics if a matrix is singular.
 ///
 /// ```
 /// extern crate ndarray;
 /// extern crate autograd as ag;
 ///
-/// let ref a = ag::constant(ndarray::arr1(&[4., 1., 5., 2., 3., 6.]));
-/// let ref b = ag::constant(ndarray::arr2(&[[2., 3.], [1., 4.]]));
-/// let ref c = ag::setdiff1d(a, b);
+/// let ref x = ag::constant(ndarray::arr2(&[[2., 0.], [0., 4.]]));
+/// let ref y = ag::matrix_inverse(x);
 ///
-/// assert_eq!(
-///     c.eval(&[]),
-///     Some(ndarray::arr1(&[5., 6.]).into_dyn())
-/// )
+/// assert_eq!(y.eval(&[]), Some(ndarray::arr2(&[[0.5, 0.], [0., 0.25]]).into_dyn()));
 /// ```
-///
-pub fn setdiff1d<A: AsRef<Tensor>, B: AsRef<Tensor>>(a: A, b: B) -> Tensor {
-    let op = array_ops::SetDiff1D;
+pub fn matrix_inverse<A: AsRef<Tensor>>(x: A) -> Tensor {
+    let x = x.as_ref();
     Tensor::builder()
-        .set_inputs(vec![a.as_ref(), b.as_ref()])
-        .build(op)
+        .set_input(x)
+        .set_shape(x.shape())
+        .build(linalg_ops::MatrixInverse)
 }
 
-/// Permutes dimensions.
+/// Determinant of each matrix in the last two axes of `x`.
 ///
-/// It's like TensorFlow or NumPy's.
+/// The result has the shape of `x` without the last two axes.
 ///
 /// ```
+/// extern crate ndarray;
 /// extern crate autograd as ag;
 ///
-/// let ref a = ag::zeros(&[1, 2, 3, 4, 5]);
-/// let ref b = ag::transpose(a, &[4, 2, 3, 0, 1]);
+/// let ref x = ag::constant(ndarray::arr2(&[[1., 2.], [3., 4.]]));
+/// let ref y = ag::determinant(x);
 ///
-/// assert_eq!(b.eval(&[]).unwrap().shape(), &[5, 3, 4, 1, 2]);
+/// assert!((y.eval(&[]).unwrap()[ndarray::IxDyn(&[])] + 2.).abs() < 1e-6);
 /// ```
-pub fn transpose<T: ArrayLike, A: AsRef<Tensor>>(x: A, perm: &T) -> Tensor {
-    let op = math_ops::Transpose { zip: true };
+pub fn determinant<A: AsRef<Tensor>>(x: A) -> Tensor {
     Tensor::builder()
-        .set_inputs(vec![x.as_ref(), &perm.as_tensor()])
-        .build(op)
+        .set_input(x.as_ref())
+        .build(linalg_ops::Determinant)
 }
 
-/// Splits input tensors into parts.
+/// Logarithm of the absolute determinant of each matrix in the last two axes of `x`.
 ///
-/// Splits `x` into `sizes.len()` parts along `axis`.
-///
-/// The size of dimension of each part is `sizes[i]` on `axis`, but
-/// `x.shape[i]` on other axis (similar to TensorFlow's `split`).
+/// More stable than `ag::log(ag::determinant(x))` for large matrices,
+/// e.g. for Gaussian log-likelihoods. The result is `-inf` for singular matrices.
 ///
 /// ```
+/// extern crate ndarray;
 /// extern crate autograd as ag;
 ///
-/// let ref a = ag::zeros(&[3, 7, 5]);
-/// let ref b = ag::split(a, &[2, 3, 2], 1);
-///
-/// let evaluated = ag::eval(&[&b[0], &b[1], &b[2]], &[]);
-/// let e0 = &evaluated[0];
-/// let e1 = &evaluated[1];
-/// let e2 = &evaluated[2];
+/// let ref x = ag::constant(ndarray::arr3(&[[[2., 0.], [0., 3.]], [[0., -1.], [1., 0.]]]));
+/// let ref y = ag::log_det(x);
 ///
-/// assert_eq!(e0.as_ref().unwrap().shape(), &[3, 2, 5]);
-/// assert_eq!(e1.as_ref().unwrap().shape(), &[3, 3, 5]);
-/// assert_eq!(e2.as_ref().unwrap().shape(), &[3, 2, 5]);
+/// assert!(y.eval(&[]).unwrap().all_close(&ndarray::arr1(&[6f32.ln(), 0.]), 1e-6));
 /// ```
-pub fn split<A: AsRef<Tensor>>(x: A, sizes: &[usize], axis: isize) -> Vec<Tensor> {
-    (0..sizes.len())
-        .map(|i| {
-            let op = array_ops::Split {
-                sizes: sizes.to_vec(),
-                index: i,
-                axis,
-            };
-            Tensor::builder().set_input(x.as_ref()).build(op)
-        })
-        .collect::<Vec<_>>()
+pub fn log_det<A: AsRef<Tensor>>(x: A) -> Tensor {
+    Tensor::builder()
+        .set_input(x.as_ref())
+        .build(linalg_ops::LogDet)
 }
 
-/// Slices the input tensor.
+/// Solves `a x = b` for `x`.
 ///
-/// # Arguments
-/// * `x` - Tensor with arbitrary shape.
-/// * `starts` - Start indices for each dimensions
-/// * `ends` - End indices for each dimensions.
-/// `-1` representing the last index is acceptable for each dimension.
+/// `a` is `(.., n, n)` and `b` is `(.., n, k)` with the same leading (batch) axes.
+/// Panics if a matrix of `a` is singular.
 ///
 /// ```
+/// extern crate ndarray;
 /// extern crate autograd as ag;
 ///
-/// let ref a = ag::zeros(&[4, 4]);
-/// let ref b = ag::slice(a, &[0, 0], &[-1, 2]); // numpy equivalent is a[:, 0:2]
+/// let ref a = ag::constant(ndarray::arr2(&[[2., 1.], [1., 3.]]));
+/// let ref b = ag::constant(ndarray::arr2(&[[3.], [5.]]));
+/// let ref x = ag::solve(a, b);
 ///
-/// assert_eq!(b.eval(&[]).unwrap().shape(), &[4, 2]);
+/// assert!(x.eval(&[]).unwrap().all_close(&ndarray::arr2(&[[0.8], [1.4]]), 1e-6));
 /// ```
-pub fn slice<A: AsRef<Tensor>>(x: A, starts: &[isize], ends: &[isize]) -> Tensor {
-    // TODO: Make starts and ends ArrayLike
-    assert_eq!(starts.len(), ends.len());
-    let starts_ends = starts.iter().zip(ends.iter());
-
-    let indices = starts_ends
-        .map(|(s, e)| ndarray::Si(*s, if *e == -1 { None } else { Some(*e) }, 1))
-        .collect::<Vec<ndarray::Si>>();
-
-    let op = array_ops::Slice {
-        indices: indices.into_boxed_slice(),
-    };
-    Tensor::builder().set_input(x.as_ref()).build(op)
+pub fn solve<A: AsRef<Tensor>, B: AsRef<Tensor>>(a: A, b: B) -> Tensor {
+    let b = b.as_ref();
+    Tensor::builder()
+        .set_inputs(vec![a.as_ref(), b])
+        .set_shape(b.shape())
+        .build(linalg_ops::Solve { adjoint: false })
 }
 
-/// Concatenates input tensors along specified axis.
+/// Solves `a x = b` for `x`, where `a` is triangular.
 ///
-/// `axis` can be negative.
+/// Only the lower triangle of `a` is used if `lower`, otherwise the upper one.
+/// If `adjoint`, solves `a^T x = b` instead. Shapes are the same as `ag::solve`.
 ///
 /// ```
+/// extern crate ndarray;
 /// extern crate autograd as ag;
 ///
-/// let ref a = ag::zeros(&[3, 2]);
-/// let ref b = ag::zeros(&[3, 2]);
-/// let ref c = ag::zeros(&[3, 2]);
-/// let ref d = ag::concat(&[a, b, c], 0);
+/// let ref a = ag::constant(ndarray::arr2(&[[2., 9.], [1., 4.]]));
+/// let ref b = ag::constant(ndarray::arr2(&[[2.], [9.]]));
+/// let ref x = ag::triangular_solve(a, b, true, false);
 ///
-/// assert_eq!(d.eval(&[]).unwrap().shape(), &[9, 2]);
+/// assert_eq!(x.eval(&[]), Some(ndarray::arr2(&[[1.], [2.]]).into_dyn()));
 /// ```
-pub fn concat(tensors: &[&Tensor], axis: isize) -> Tensor {
-    let op = array_ops::Concat { axis };
-    Tensor::builder().set_inputs(tensors.to_vec()).build(op)
+pub fn triangular_solve<A, B>(a: A, b: B, lower: bool, adjoint: bool) -> Tensor
+where
+    A: AsRef<Tensor>,
+    B: AsRef<Tensor>,
+{
+    let b = b.as_ref();
+    Tensor::builder()
+        .set_inputs(vec![a.as_ref(), b])
+        .set_shape(b.shape())
+        .build(linalg_ops::TriangularSolve { lower, adjoint })
 }
 
-/// Gathers subviews from the input tensor.
-///
-/// Same spec as https://www.tensorflow.org/api_docs/python/tf/gather.
-/// For example, this can be used for embedding vectors lookup etc.
+/// Cholesky decomposition of each matrix in the last two axes of `x`.
 ///
-/// Unlike `ag::gather`, `indices` can contain negative elements.
-///
-/// # Returns
-/// Tensor with shape `param.shape[..axis] + indices.shape + param.shape[axis+1..]`
+/// Returns the lower triangular `l` with `x = l l^T`.
+/// `x` must be symmetric positive definite; only its lower triangle is read.
+/// The gradient is symmetrized.
 ///
 /// ```
 /// extern crate ndarray;
 /// extern crate autograd as ag;
 ///
-/// let ref param = ag::constant(ag::ndarray_ext::zeros(&[5, 4, 8, 2]));
-/// let ref indices = ag::constant(ndarray::arr2(&[[5., -1., 3.], [2., 1., -2.]]));
-/// let ref y = ag::gather_common(param, indices, 2);
+/// let ref x = ag::constant(ndarray::arr2(&[[4., 2.], [2., 10.]]));
+/// let ref l = ag::cholesky(x);
 ///
-/// assert_eq!(y.eval(&[]).unwrap().shape(), &[5, 4, 2, 3, 2])
+/// assert_eq!(l.eval(&[]), Some(ndarray::arr2(&[[2., 0.], [1., 3.]]).into_dyn()));
 /// ```
-pub fn gather_common<T: ArrayLike, A: AsRef<Tensor>>(param: A, indices: &T, axis: isize) -> Tensor {
-    let op = array_ops::Gather {
-        axis,
-        should_normalize_negative_indices: true,
-    };
+pub fn cholesky<A: AsRef<Tensor>>(x: A) -> Tensor {
+    let x = x.as_ref();
     Tensor::builder()
-        .set_inputs(vec![&indices.as_tensor(), param.as_ref()])
-        .build(op)
+        .set_input(x)
+        .set_shape(x.shape())
+        .build(linalg_ops::Cholesky)
 }
 
-/// Gathers subviews from the input tensor.
-///
-/// Same spec as https://www.tensorflow.org/api_docs/python/tf/gather.
-/// For example, this can be used for embedding vectors lookup etc.
+/// Reduced QR decomposition of each matrix in the last two axes of `x`.
 ///
-/// # Returns
-/// Tensor with shape `param.shape[..axis] + indices.shape + param.shape[axis+1..]`
+/// For `x` of shape `(.., m, n)` with `m >= n`, returns `q` of shape `(.., m, n)` with
+/// orthonormal columns and upper triangular `r` of shape `(.., n, n)` such that
+/// `x = q r`. The diagonal of `r` is non-negative. The gradient requires `r` to be
+/// invertible.
 ///
 /// ```
 /// extern crate ndarray;
 /// extern crate autograd as ag;
 ///
-/// let ref param = ag::constant(ag::ndarray_ext::zeros(&[5, 4, 8, 2]));
-/// let ref indices = ag::constant(ndarray::arr2(&[[5., 4., 3.], [2., 1., 0.]]));
-/// let ref y = ag::gather(param, indices, 2);
+/// let ref x = ag::constant(ndarray::arr2(&[[3., 1.], [4., 2.]]));
+/// let (q, r) = ag::qr(x);
+/// let ref qr = ag::matmul(&q, &r);
 ///
-/// assert_eq!(y.eval(&[]).unwrap().shape(), &[5, 4, 2, 3, 2])
+/// let (qr, x) = (qr.eval(&[]).unwrap(), x.eval(&[]).unwrap());
+/// assert!(qr.all_close(&x, 1e-5));
+/// assert!(r.eval(&[]).unwrap().all_close(&ndarray::arr2(&[[5., 2.2], [0., 0.4]]), 1e-5));
 /// ```
-pub fn gather<T: ArrayLike, A: AsRef<Tensor>>(param: A, indices: &T, axis: isize) -> Tensor {
-    let op = array_ops::Gather {
-        axis,
-        should_normalize_negative_indices: false,
-    };
-    Tensor::builder()
-        .set_inputs(vec![&indices.as_tensor(), param.as_ref()])
-        .build(op)
+pub fn qr<A: AsRef<Tensor>>(x: A) -> (Tensor, Tensor) {
+    let y = Tensor::builder()
+        .set_input(x.as_ref())
+        .build(linalg_ops::Qr);
+    let mut outputs = linalg_ops::decomposition_outputs(&y, 2);
+    let r = outputs.pop().unwrap();
+    (outputs.pop().unwrap(), r)
 }
 
-/// Normalizes the input tensor with its mean and variance along specified axis.
+/// Reduced singular value decomposition of each matrix in the last two axes of `x`.
+///
+/// For `x` of shape `(.., m, n)` and `k = min(m, n)`, returns `u` of shape `(.., m, k)`,
+/// the singular values `s` of shape `(.., k)` in descending order and `v` of shape
+/// `(.., n, k)` such that `x = u diag(s) v^T`.
+/// The gradient requires distinct and nonzero singular values.
 ///
 /// ```
 /// extern crate ndarray;
 /// extern crate autograd as ag;
 ///
-/// let ref x = ag::standard_normal(&[3, 4]);
-/// let ref y1 = ag::normalize(x, &[0]);
-/// let ref y2 = ag::normalize(x, &[0]);
+/// let ref x = ag::constant(ndarray::arr2(&[[0., 2.], [3., 0.], [0., 0.]]));
+/// let (u, s, v) = ag::svd(x);
 ///
-/// let evaluated = ag::eval(&[y1, y2], &[]);
-/// let e0 = &evaluated[0];
-/// let e1 = &evaluated[1];
-/// assert_eq!(e0.as_ref().unwrap().shape(), &[3, 4]);
-/// assert_eq!(e1.as_ref().unwrap().shape(), &[3, 4]);
+/// assert_eq!(u.eval(&[]).unwrap().shape(), &[3, 2]);
+/// assert!(s.eval(&[]).unwrap().all_close(&ndarray::arr1(&[3., 2.]), 1e-5));
+/// assert_eq!(v.eval(&[]).unwrap().shape(), &[2, 2]);
 /// ```
-pub fn normalize<T: ArrayLike, A: AsRef<Tensor>>(x: A, axes: &T) -> Tensor {
-    let x = x.as_ref();
-    let axes = axes.as_tensor();
-    let ref mean = reduce_mean(x, &axes, true);
-    let ref centered = x - mean;
-    let ref variance = reduce_mean(square(centered), &axes, true);
-    (x - mean) / sqrt(&(variance + 1e-5))
+pub fn svd<A: AsRef<Tensor>>(x: A) -> (Tensor, Tensor, Tensor) {
+    let y = Tensor::builder()
+        .set_input(x.as_ref())
+        .build(linalg_ops::Svd);
+    let mut outputs = linalg_ops::decomposition_outputs(&y, 3);
+    let v = outputs.pop().unwrap();
+    let s = outputs.pop().unwrap();
+    (outputs.pop().unwrap(), s, v)
 }
 
-/// Applies batch normalization.
+/// Eigendecomposition of each symmetric matrix in the last two axes of `x`.
 ///
-/// `scale` and `shift` should be shared variables.
-/// Since normalization is performed along 1st axis of `x`,
-/// both of them should have shape `(1, x.shape[1])`
+/// Returns the eigenvalues of shape `(.., n)` in ascending order and the eigenvectors
+/// as the columns of `(.., n, n)`. Only the lower triangle of `x` is read.
+/// The gradient requires distinct eigenvalues and is symmetrized.
 ///
 /// ```
 /// extern crate ndarray;
 /// extern crate autograd as ag;
 ///
-/// let ref x = ag::standard_normal(&[3, 4]);
-/// let ref scale = ag::variable(ag::ndarray_ext::ones(&[1, 4]));
-/// let ref shift = ag::variable(ag::ndarray_ext::zeros(&[1, 4]));
-/// let ref norm = ag::batch_norm(x, scale, shift);
+/// let ref x = ag::constant(ndarray::arr2(&[[2., 1.], [1., 2.]]));
+/// let (e, v) = ag::eigh(x);
 ///
-/// assert_eq!(norm.eval(&[]).unwrap().shape(), &[3, 4]);
+/// assert!(e.eval(&[]).unwrap().all_close(&ndarray::arr1(&[1., 3.]), 1e-5));
+/// assert_eq!(v.eval(&[]).unwrap().shape(), &[2, 2]);
 /// ```
-pub fn batch_norm<A, B, C>(x: A, scale: B, shift: C) -> Tensor
-where
-    A: AsRef<Tensor>,
-    B: AsRef<Tensor>,
-    C: AsRef<Tensor>,
-{
-    normalize(x, &[0]) * scale.as_ref() + shift.as_ref()
+pub fn eigh<A: AsRef<Tensor>>(x: A) -> (Tensor, Tensor) {
+    let y = Tensor::builder()
+        .set_input(x.as_ref())
+        .build(linalg_ops::Eigh);
+    let mut outputs = linalg_ops::decomposition_outputs(&y, 2);
+    let v = outputs.pop().unwrap();
+    (outputs.pop().unwrap(), v)
 }
 
-/// Generates a zero-ranked tensor from a scalar value.
-pub fn scalar(val: f32) -> Tensor {
-    let op = const_gen_ops::Scalar { val };
+/// Discrete Fourier transform along the signal axis of complex `x`.
+///
+/// Complex values are represented by the trailing axis of size 2 holding the real
+/// and imaginary parts, so `x` of shape `(.., n, 2)` is transformed along the axis of
+/// size `n`. Any length works; lengths with large prime factors are slower.
+///
+/// ```
+/// extern crate ndarray;
+/// extern crate autograd as ag;
+///
+/// let ref x = ag::constant(ndarray::arr2(&[[1., 0.], [2., 0.], [3., 0.], [4., 0.]]));
+/// let ref y = ag::fft(x);
+///
+/// let expected = ndarray::arr2(&[[10., 0.], [-2., 2.], [-2., 0.], [-2., -2.]]);
+/// assert!(y.eval(&[]).unwrap().all_close(&expected, 1e-5));
+/// ```
+pub fn fft<A: AsRef<Tensor>>(x: A) -> Tensor {
+    let x = x.as_ref();
     Tensor::builder()
-        .set_shape(convert_to_tensor(::ndarray_ext::scalar_shape()))
-        .build(op)
-}
-
-/// Outputs values sampled from the normal distribution.
-pub fn random_normal<T: ArrayLike>(shape: &T, mean: f64, stddev: f64) -> Tensor {
-    random_normal_rng(Default::default(), shape, mean, stddev)
+        .set_input(x)
+        .set_shape(x.shape())
+        .build(fft_ops::Fft {
+            inverse: false,
+            normalized: false,
+        })
 }
 
-/// Outputs values sampled from the normal distribution.
-pub fn random_normal_rng<T: ArrayLike, R: Rng + 'static>(
-    arr_rng: ArrRng<R>,
-    shape: &T,
-    mean: f64,
-    stddev: f64,
-) -> Tensor {
-    let shape = shape.as_tensor();
+/// Inverse of `ag::fft`, scaled by `1 / n`.
+///
+/// ```
+/// extern crate ndarray;
+/// extern crate autograd as ag;
+///
+/// let ref x = ag::constant(ndarray::arr2(&[[1., 2.], [3., 4.], [5., 6.]]));
+/// let ref y = ag::ifft(ag::fft(x));
+///
+/// assert!(y.eval(&[]).unwrap().all_close(&x.eval(&[]).unwrap(), 1e-5));
+/// ```
+pub fn ifft<A: AsRef<Tensor>>(x: A) -> Tensor {
+    let x = x.as_ref();
     Tensor::builder()
-        .set_input(&shape)
-        .set_shape(shape)
-        .build(random_ops::RandomNormal::new(arr_rng, mean, stddev))
+        .set_input(x)
+        .set_shape(x.shape())
+        .build(fft_ops::Fft {
+            inverse: true,
+            normalized: true,
+        })
 }
 
-/// Outputs values sampled from the uniform distribution.
-pub fn random_uniform<T: ArrayLike>(shape: &T, min: f64, max: f64) -> Tensor {
-    random_uniform_rng(Default::default(), shape, min, max)
+/// Discrete Fourier transform of real signals in the last axis of `x`.
+///
+/// Returns the non-negative frequency terms only: `(.., n)` becomes
+/// `(.., n / 2 + 1, 2)` with the complex representation of `ag::fft`.
+///
+/// ```
+/// extern crate ndarray;
+/// extern crate autograd as ag;
+///
+/// let ref x = ag::constant(ndarray::arr1(&[1., 2., 3., 4.]));
+/// let ref y = ag::rfft(x);
+///
+/// let expected = ndarray::arr2(&[[10., 0.], [-2., 2.], [-2., 0.]]);
+/// assert!(y.eval(&[]).unwrap().all_close(&expected, 1e-5));
+/// ```
+pub fn rfft<A: AsRef<Tensor>>(x: A) -> Tensor {
+    Tensor::builder()
+        .set_input(x.as_ref())
+        .build(fft_ops::Rfft)
 }
 
-/// Outputs values sampled from the uniform distribution.
-pub fn random_uniform_rng<T: ArrayLike, R: Rng + 'static>(
-    arr_rng: ArrRng<R>,
-    shape: &T,
-    min: f64,
-    max: f64,
-) -> Tensor {
-    let shape = shape.as_tensor();
+/// Inverse of `ag::rfft` for real signals of length `n`.
+///
+/// `x` of shape `(.., n / 2 + 1, 2)` becomes `(.., n)`. The negative frequency terms
+/// are taken as the conjugates of the positive ones, so the imaginary parts of the
+/// zero (and the Nyquist, for even `n`) frequency terms are ignored.
+///
+/// ```
+/// extern crate ndarray;
+/// extern crate autograd as ag;
+///
+/// let ref x = ag::constant(ndarray::arr2(&[[1., 2., 3.], [4., 5., 6.]]));
+/// let ref y = ag::irfft(ag::rfft(x), 3);
+///
+/// assert!(y.eval(&[]).unwrap().all_close(&x.eval(&[]).unwrap(), 1e-5));
+/// ```
+pub fn irfft<A: AsRef<Tensor>>(x: A, n: usize) -> Tensor {
     Tensor::builder()
-        .set_input(&shape)
-        .set_shape(shape)
-        .build(random_ops::RandomUniform::new(arr_rng, min, max))
+        .set_input(x.as_ref())
+        .build(fft_ops::Irfft { n })
 }
 
-/// Outputs values sampled from the standard normal distribution.
-pub fn standard_normal<T: ArrayLike>(shape: &T) -> Tensor {
-    standard_normal_rng(Default::default(), shape)
+/// Short-time Fourier transform of real signals in the last axis of `x`.
+///
+/// Splits the signals into frames of `frame_length` every `frame_step` samples
+/// (without padding), applies the periodic Hann window and takes `ag::rfft` of each.
+/// `(.., t)` becomes `(.., 1 + (t - frame_length) / frame_step, frame_length / 2 + 1, 2)`.
+///
+/// ```
+/// extern crate ndarray;
+/// extern crate autograd as ag;
+///
+/// let ref x = ag::zeros(&[2, 100]);
+/// let ref y = ag::stft(x, 16, 8);
+///
+/// assert_eq!(y.eval(&[]).unwrap().shape(), &[2, 11, 9, 2]);
+/// ```
+pub fn stft<A: AsRef<Tensor>>(x: A, frame_length: usize, frame_step: usize) -> Tensor {
+    assert!(
+        frame_length > 0 && frame_step > 0,
+        "ag::stft: frame_length and frame_step must be positive"
+    );
+    let frames = Tensor::builder()
+        .set_input(x.as_ref())
+        .build(fft_ops::Frame {
+            window: fft_ops::hann_window(frame_length),
+            step: frame_step,
+        });
+    rfft(frames)
 }
 
-/// Outputs values sampled from the standard normal distribution.
-pub fn standard_normal_rng<T: ArrayLike, R: Rng + 'static>(
-    arr_rng: ArrRng<R>,
-    shape: &T,
-) -> Tensor {
-    let shape = shape.as_tensor();
+/// Takes diff between two tensors.
+///
+/// Returns the sorted, unique values in `a` that are not in `b`.
+///
+/// ```
+/// extern crate ndarray;
+/// extern crate autograd as ag;
+///
+/// let ref a = ag::constant(ndarray::arr1(&[4., 1., 5., 2., 3., 6.]));
+/// let ref b = ag::constant(ndarray::arr2(&[[2., 3.], [1., 4.]]));
+/// let ref c = ag::setdiff1d(a, b);
+///
+/// assert_eq!(
+///     c.eval(&[]),
+///     Some(ndarray::arr1(&[5., 6.]).into_dyn())
+/// )
+/// ```
+///
+pub fn setdiff1d<A: AsRef<Tensor>, B: AsRef<Tensor>>(a: A, b: B) -> Tensor {
+    let op = array_ops::SetDiff1D;
     Tensor::builder()
-        .set_input(&shape)
-        .set_shape(shape)
-        .build(random_ops::StandardNormal::new(arr_rng))
-}
-
-/// Outputs values sampled from the standard uniform distribution.
-pub fn standard_uniform<T: ArrayLike>(shape: &T) -> Tensor {
-    standard_uniform_rng(Default::default(), shape)
+        .set_inputs(vec![a.as_ref(), b.as_ref()])
+        .build(op)
 }
 
-/// Outputs values sampled from the standard uniform distribution.
-pub fn standard_uniform_rng<T: ArrayLike, R: Rng + 'static>(
-    arr_rng: ArrRng<R>,
-    shape: &T,
-) -> Tensor {
-    let shape = shape.as_tensor();
+/// Permutes dimensions.
+///
+/// It's like TensorFlow or NumPy's.
+///
+/// ```
+/// extern crate autograd as ag;
+///
+/// let ref a = ag::zeros(&[1, 2, 3, 4, 5]);
+/// let ref b = ag::transpose(a, &[4, 2, 3, 0, 1]);
+///
+/// assert_eq!(b.eval(&[]).unwrap().shape(), &[5, 3, 4, 1, 2]);
+/// ```
+pub fn transpose<T: ArrayLike, A: AsRef<Tensor>>(x: A, perm: &T) -> Tensor {
+    let op = math_ops::Transpose { zip: true };
     Tensor::builder()
-        .set_input(&shape)
-        .set_shape(shape)
-        .build(random_ops::StandardUniform::new(arr_rng))
+        .set_inputs(vec![x.as_ref(), &perm.as_tensor()])
+        .build(op)
 }
 
-/// Outputs values sampled from the bernoulli distribution.
-pub fn bernoulli<T: ArrayLike>(shape: &T, p: f64) -> Tensor {
-    bernoulli_rng(Default::default(), shape, p)
+/// Splits input tensors into parts.
+///
+/// Splits `x` into `sizes.len()` parts along `axis`.
+///
+/// The size of dimension of each part is `sizes[i]` on `axis`, but
+/// `x.shape[i]` on other axis (similar to TensorFlow's `split`).
+///
+/// ```
+/// extern crate autograd as ag;
+///
+/// let ref a = ag::zeros(&[3, 7, 5]);
+/// let ref b = ag::split(a, &[2, 3, 2], 1);
+///
+/// let evaluated = ag::eval(&[&b[0], &b[1], &b[2]], &[]);
+/// let e0 = &evaluated[0];
+/// let e1 = &evaluated[1];
+/// let e2 = &evaluated[2];
+///
+/// assert_eq!(e0.as_ref().unwrap().shape(), &[3, 2, 5]);
+/// assert_eq!(e1.as_ref().unwrap().shape(), &[3, 3, 5]);
+/// assert_eq!(e2.as_ref().unwrap().shape(), &[3, 2, 5]);
+/// ```
+pub fn split<A: AsRef<Tensor>>(x: A, sizes: &[usize], axis: isize) -> Vec<Tensor> {
+    (0..sizes.len())
+        .map(|i| {
+            let op = array_ops::Split {
+                sizes: sizes.to_vec(),
+                index: i,
+                axis,
+            };
+            Tensor::builder().set_input(x.as_ref()).build(op)
+        })
+        .collect::<Vec<_>>()
 }
 
-/// Outputs values sampled from the bernoulli distribution.
-pub fn bernoulli_rng<T: ArrayLike, R: Rng + 'static>(
-    arr_rng: ArrRng<R>,
-    shape: &T,
-    p: f64,
-) -> Tensor {
-    let shape = shape.as_tensor();
-    Tensor::builder()
-        .set_input(&shape)
-        .set_shape(shape)
-        .build(random_ops::Bernoulli::new(arr_rng, p))
+/// Slices the input tensor.
+///
+/// # Arguments
+/// * `x` - Tensor with arbitrary shape.
+/// * `starts` - Start indices for each dimensions
+/// * `ends` - End indices for each dimensions.
+/// `-1` representing the last index is acceptable for each dimension.
+///
+/// ```
+/// extern crate autograd as ag;
+///
+/// let ref a = ag::zeros(&[4, 4]);
+/// let ref b = ag::slice(a, &[0, 0], &[-1, 2]); // numpy equivalent is a[:, 0:2]
+///
+/// assert_eq!(b.eval(&[]).unwrap().shape(), &[4, 2]);
+/// ```
+pub fn slice<A: AsRef<Tensor>>(x: A, starts: &[isize], ends: &[isize]) -> Tensor {
+    // TODO: Make starts and ends ArrayLike
+    assert_eq!(starts.len(), ends.len());
+    let starts_ends = starts.iter().zip(ends.iter());
+
+    let indices = starts_ends
+        .map(|(s, e)| ndarray::Si(*s, if *e == -1 { None } else { Some(*e) }, 1))
+        .collect::<Vec<ndarray::Si>>();
+
+    let op = array_ops::Slice {
+        indices: indices.into_boxed_slice(),
+    };
+    Tensor::builder().set_input(x.as_ref()).build(op)
 }
 
-/// Outputs values sampled from the exponential distribution.
-pub fn random_exp<T: ArrayLike>(shape: &T, lambda: f64) -> Tensor {
-    random_exp_rng(Default::default(), shape, lambda)
+/// Concatenates input tensors along specified axis.
+///
+/// `axis` can be negative.
+///
+/// ```
+/// extern crate autograd as ag;
+///
+/// let ref a = ag::zeros(&[3, 2]);
+/// let ref b = ag::zeros(&[3, 2]);
+/// let ref c = ag::zeros(&[3, 2]);
+/// let ref d = ag::concat(&[a, b, c], 0);
+///
+/// assert_eq!(d.eval(&[]).unwrap().shape(), &[9, 2]);
+/// ```
+pub fn concat(tensors: &[&Tensor], axis: isize) -> Tensor {
+    let op = array_ops::Concat { axis };
+    Tensor::builder().set_inputs(tensors.to_vec()).build(op)
 }
 
-/// Outputs values sampled from the exponential distribution.
-pub fn random_exp_rng<T: ArrayLike, R: Rng + 'static>(
-    arr_rng: ArrRng<R>,
-    shape: &T,
-    lambda: f64,
-) -> Tensor {
-    let shape = shape.as_tensor();
+/// Pads each axis of `x` with `pads[axis] = (before, after)` elements.
+///
+/// `mode` is one of `PadMode::Constant(value)`, `PadMode::Reflect` and `PadMode::Replicate`.
+/// The gradient of `x` is the slice of the output gradient for the constant mode;
+/// for the other modes the gradients of the padded elements are accumulated back to
+/// the elements they were copied from.
+///
+/// ```
+/// extern crate ndarray;
+/// extern crate autograd as ag;
+///
+/// let ref x = ag::constant(ndarray::arr2(&[[1., 2., 3.]]));
+///
+/// let y = ag::pad(x, &[(0, 0), (2, 1)], ag::PadMode::Constant(-1.));
+/// assert_eq!(y.eval(&[]), Some(ndarray::arr2(&[[-1., -1., 1., 2., 3., -1.]]).into_dyn()));
+///
+/// let y = ag::pad(x, &[(0, 0), (2, 1)], ag::PadMode::Reflect);
+/// assert_eq!(y.eval(&[]), Some(ndarray::arr2(&[[3., 2., 1., 2., 3., 2.]]).into_dyn()));
+///
+/// let y = ag::pad(x, &[(1, 0), (2, 1)], ag::PadMode::Replicate);
+/// assert_eq!(
+///     y.eval(&[]),
+///     Some(ndarray::arr2(&[[1., 1., 1., 2., 3., 3.], [1., 1., 1., 2., 3., 3.]]).into_dyn())
+/// );
+/// ```
+pub fn pad<A: AsRef<Tensor>>(x: A, pads: &[(usize, usize)], mode: PadMode) -> Tensor {
     Tensor::builder()
-        .set_input(&shape)
-        .set_shape(shape)
-        .build(random_ops::Exponential::new(arr_rng, lambda))
+        .set_input(x.as_ref())
+        .build(array_ops::Pad {
+            pads: pads.to_vec(),
+            mode,
+        })
 }
 
-/// Outputs values sampled from the gamma distribution.
-pub fn random_gamma<T: ArrayLike>(shape: &T, shape_param: f64, scale: f64) -> Tensor {
-    random_gamma_rng(Default::default(), shape, shape_param, scale)
+/// Gathers subviews from the input tensor.
+///
+/// Same spec as https://www.tensorflow.org/api_docs/python/tf/gather.
+/// For example, this can be used for embedding vectors lookup etc.
+///
+/// Unlike `ag::gather`, `indices` can contain negative elements.
+///
+/// # Returns
+/// Tensor with shape `param.shape[..axis] + indices.shape + param.shape[axis+1..]`
+///
+/// ```
+/// extern crate ndarray;
+/// extern crate autograd as ag;
+///
+/// let ref param = ag::constant(ag::ndarray_ext::zeros(&[5, 4, 8, 2]));
+/// let ref indices = ag::constant(ndarray::arr2(&[[5., -1., 3.], [2., 1., -2.]]));
+/// let ref y = ag::gather_common(param, indices, 2);
+///
+/// assert_eq!(y.eval(&[]).unwrap().shape(), &[5, 4, 2, 3, 2])
+/// ```
+pub fn gather_common<T: ArrayLike, A: AsRef<Tensor>>(param: A, indices: &T, axis: isize) -> Tensor {
+    let op = array_ops::Gather {
+        axis,
+        should_normalize_negative_indices: true,
+    };
+    Tensor::builder()
+        .set_inputs(vec![&indices.as_tensor(), param.as_ref()])
+        .build(op)
 }
 
-/// Outputs values sampled from the gamma distribution.
-pub fn random_gamma_rng<T: ArrayLike, R: Rng + 'static>(
-    arr_rng: ArrRng<R>,
-    shape: &T,
-    shape_param: f64,
-    scale: f64,
-) -> Tensor {
-    let shape = shape.as_tensor();
+/// Gathers subviews from the input tensor.
+///
+/// Same spec as https://www.tensorflow.org/api_docs/python/tf/gather.
+/// For example, this can be used for embedding vectors lookup etc.
+///
+/// # Returns
+/// Tensor with shape `param.shape[..axis] + indices.shape + param.shape[axis+1..]`
+///
+/// ```
+/// extern crate ndarray;
+/// extern crate autograd as ag;
+///
+/// let ref param = ag::constant(ag::ndarray_ext::zeros(&[5, 4, 8, 2]));
+/// let ref indices = ag::constant(ndarray::arr2(&[[5., 4., 3.], [2., 1., 0.]]));
+/// let ref y = ag::gather(param, indices, 2);
+///
+/// assert_eq!(y.eval(&[]).unwrap().shape(), &[5, 4, 2, 3, 2])
+/// ```
+pub fn gather<T: ArrayLike, A: AsRef<Tensor>>(param: A, indices: &T, axis: isize) -> Tensor {
+    let op = array_ops::Gather {
+        axis,
+        should_normalize_negative_indices: false,
+    };
     Tensor::builder()
-        .set_input(&shape)
-        .set_shape(shape)
-        .build(random_ops::Gamma::new(arr_rng, shape_param, scale))
+        .set_inputs(vec![&indices.as_tensor(), param.as_ref()])
+        .build(op)
 }
 
-/// Outputs values sampled from the log-normal distribution.
-pub fn log_normal<T: ArrayLike>(shape: &T, mean: f64, stddev: f64) -> Tensor {
-    log_normal_rng(Default::default(), shape, mean, stddev)
+/// Adds `updates` to the subviews of `x` picked by `indices` along `axis`.
+///
+/// This is the inverse of `ag::gather`: `updates` should have shape
+/// `x.shape[..axis] + indices.shape + x.shape[axis+1..]`, and updates for duplicate
+/// indices are summed up.
+///
+/// ```
+/// extern crate ndarray;
+/// extern crate autograd as ag;
+///
+/// let ref x = ag::zeros(&[4, 2]);
+/// let ref updates = ag::constant(ndarray::arr2(&[[1., 2.], [3., 4.], [5., 6.]]));
+/// let ref y = ag::scatter_add(x, &[3., 0., 3.], updates, 0);
+///
+/// assert_eq!(
+///     y.eval(&[]),
+///     Some(ndarray::arr2(&[[3., 4.], [0., 0.], [0., 0.], [6., 8.]]).into_dyn())
+/// );
+/// ```
+pub fn scatter_add<T, A, B>(x: A, indices: &T, updates: B, axis: isize) -> Tensor
+where
+    T: ArrayLike,
+    A: AsRef<Tensor>,
+    B: AsRef<Tensor>,
+{
+    let op = array_ops::Scatter {
+        axis,
+        accumulate: true,
+    };
+    Tensor::builder()
+        .set_inputs(vec![x.as_ref(), &indices.as_tensor(), updates.as_ref()])
+        .build(op)
 }
 
-/// Outputs values sampled from the log-normal distribution.
-pub fn log_normal_rng<T: ArrayLike, R: Rng + 'static>(
-    arr_rng: ArrRng<R>,
-    shape: &T,
-    mean: f64,
-    stddev: f64,
-) -> Tensor {
-    let shape = shape.as_tensor();
+/// Replaces the subviews of `x` picked by `indices` along `axis` with `updates`.
+///
+/// Same as `ag::scatter_add` except that `updates` overwrite `x`;
+/// for duplicate indices, the last update is written.
+///
+/// ```
+/// extern crate ndarray;
+/// extern crate autograd as ag;
+///
+/// let ref x = ag::ones(&[2, 3]);
+/// let ref updates = ag::constant(ndarray::arr2(&[[5., 6.], [7., 8.]]));
+/// let ref y = ag::scatter(x, &[2., 0.], updates, 1);
+///
+/// assert_eq!(
+///     y.eval(&[]),
+///     Some(ndarray::arr2(&[[6., 1., 5.], [8., 1., 7.]]).into_dyn())
+/// );
+/// ```
+pub fn scatter<T, A, B>(x: A, indices: &T, updates: B, axis: isize) -> Tensor
+where
+    T: ArrayLike,
+    A: AsRef<Tensor>,
+    B: AsRef<Tensor>,
+{
+    let op = array_ops::Scatter {
+        axis,
+        accumulate: false,
+    };
     Tensor::builder()
-        .set_input(&shape)
-        .set_shape(shape)
-        .build(random_ops::LogNormal::new(arr_rng, mean, stddev))
+        .set_inputs(vec![x.as_ref(), &indices.as_tensor(), updates.as_ref()])
+        .build(op)
 }
 
-/// Converts an `ndarray::Array` to a `ag::Tensor`.
+/// Gathers slices from `params` by multi-dimensional coordinates.
+///
+/// Same spec as https://www.tensorflow.org/api_docs/python/tf/gather_nd:
+/// each vector in the last axis of `indices` is a coordinate of the first
+/// `k = indices.shape[-1]` axes of `params`.
+///
+/// # Returns
+/// Tensor with shape `indices.shape[..-1] + params.shape[k..]`
 ///
 /// ```
 /// extern crate ndarray;
 /// extern crate autograd as ag;
 ///
-/// let arr = ndarray::arr1(&[2., 3.]);
-/// let tensor = ag::convert_to_tensor(arr.clone());
-/// assert_eq!(tensor.eval(&[]), Some(arr.into_dyn()));
+/// let ref params = ag::constant(ndarray::arr2(&[[0., 1.], [2., 3.]]));
+/// let ref indices = ag::constant(ndarray::arr2(&[[1., 0.], [0., 1.]]));
+/// let ref y = ag::gather_nd(params, indices);
+///
+/// assert_eq!(y.eval(&[]), Some(ndarray::arr1(&[2., 1.]).into_dyn()));
 /// ```
-pub fn convert_to_tensor<T>(arr: ndarray::Array<f32, T>) -> Tensor
+pub fn gather_nd<T: ArrayLike, A: AsRef<Tensor>>(params: A, indices: &T) -> Tensor {
+    Tensor::builder()
+        .set_inputs(vec![params.as_ref(), &indices.as_tensor()])
+        .build(array_ops::GatherNd)
+}
+
+/// Scatters `updates` into zeros of `shape` by multi-dimensional coordinates.
+///
+/// Same spec as https://www.tensorflow.org/api_docs/python/tf/scatter_nd; this is the
+/// inverse of `ag::gather_nd`. Updates for duplicate coordinates are summed up.
+///
+/// ```
+/// extern crate ndarray;
+/// extern crate autograd as ag;
+///
+/// let ref indices = ag::constant(ndarray::arr2(&[[1., 0.], [0., 1.], [1., 0.]]));
+/// let ref updates = ag::constant(ndarray::arr1(&[1., 2., 3.]));
+/// let ref y = ag::scatter_nd(indices, updates, &[2, 2]);
+///
+/// assert_eq!(
+///     y.eval(&[]),
+///     Some(ndarray::arr2(&[[0., 2.], [4., 0.]]).into_dyn())
+/// );
+/// ```
+pub fn scatter_nd<T, U, A>(indices: &T, updates: A, shape: &U) -> Tensor
 where
-    T: ndarray::Dimension,
+    T: ArrayLike,
+    U: ArrayLike,
+    A: AsRef<Tensor>,
 {
-    let arr = arr.into_dyn();
-    let shape = {
-        let op = const_gen_ops::ConvertToTensor {
-            arr: ::ndarray_ext::shape_of(&arr),
-        };
-        Tensor::builder().build(op)
-    };
+    let shape = shape.as_tensor();
     Tensor::builder()
+        .set_inputs(vec![&indices.as_tensor(), updates.as_ref(), &shape])
         .set_shape(shape)
-        .build(const_gen_ops::ConvertToTensor { arr })
+        .build(array_ops::ScatterNd)
+}
+
+/// Selects elements of `x` where `mask` is nonzero.
+///
+/// `mask.shape` must be a prefix of `x.shape`, and the result has shape
+/// `[number of nonzero elements in mask] + x.shape[mask.ndim..]`.
+/// Gradients are scattered back to the selected positions of `x`.
+///
+/// ```
+/// extern crate ndarray;
+/// extern crate autograd as ag;
+///
+/// let ref x = ag::constant(ndarray::arr2(&[[1., 2.], [3., 4.], [5., 6.]]));
+/// let ref mask = ag::constant(ndarray::arr1(&[1., 0., 1.]));
+///
+/// assert_eq!(
+///     ag::boolean_mask(x, mask).eval(&[]),
+///     Some(ndarray::arr2(&[[1., 2.], [5., 6.]]).into_dyn())
+/// );
+/// ```
+pub fn boolean_mask<A: AsRef<Tensor>, B: AsRef<Tensor>>(x: A, mask: B) -> Tensor {
+    let x = x.as_ref();
+    let ref indices = Tensor::builder()
+        .set_inputs(vec![mask.as_ref(), x])
+        .build(array_ops::MaskIndices);
+    gather_nd(x, indices)
+}
+
+/// Normalizes the input tensor with its mean and variance along specified axis.
+///
+/// ```
+/// extern crate ndarray;
+/// extern crate autograd as ag;
+///
+/// let ref x = ag::standard_normal(&[3, 4]);
+/// let ref y1 = ag::normalize(x, &[0]);
+/// let ref y2 = ag::normalize(x, &[0]);
+///
+/// let evaluated = ag::eval(&[y1, y2], &[]);
+/// let e0 = &evaluated[0];
+/// let e1 = &evaluated[1];
+/// assert_eq!(e0.as_ref().unwrap().shape(), &[3, 4]);
+/// assert_eq!(e1.as_ref().unwrap().shape(), &[3, 4]);
+/// ```
+pub fn normalize<T: ArrayLike, A: AsRef<Tensor>>(x: A, axes: &T) -> Tensor {
+    let x = x.as_ref();
+    let axes = axes.as_tensor();
+    let ref mean = reduce_mean(x, &axes, true);
+    let ref centered = x - mean;
+    let ref variance = reduce_mean(square(centered), &axes, true);
+    (x - mean) / sqrt(&(variance + 1e-5))
+}
+
+/// Applies batch normalization.
+///
+/// This always uses the statistics of the batch;
+/// see `batch_norm_with_stats` for one that keeps running statistics for inference.
+///
+/// `scale` and `shift` should be shared variables.
+/// Since normalization is performed along 1st axis of `x`,
+/// both of them should have shape `(1, x.shape[1])`
+///
+/// ```
+/// extern crate ndarray;
+/// extern crate autograd as ag;
+///
+/// let ref x = ag::standard_normal(&[3, 4]);
+/// let ref scale = ag::variable(ag::ndarray_ext::ones(&[1, 4]));
+/// let ref shift = ag::variable(ag::ndarray_ext::zeros(&[1, 4]));
+/// let ref norm = ag::batch_norm(x, scale, shift);
+///
+/// assert_eq!(norm.eval(&[]).unwrap().shape(), &[3, 4]);
+/// ```
+pub fn batch_norm<A, B, C>(x: A, scale: B, shift: C) -> Tensor
+where
+    A: AsRef<Tensor>,
+    B: AsRef<Tensor>,
+    C: AsRef<Tensor>,
+{
+    normalize(x, &[0]) * scale.as_ref() + shift.as_ref()
+}
+
+/// Applies batch normalization with running statistics.
+///
+/// Normalizes `x` over all the axes but `axis`, the channel axis (e.g. `1` for
+/// `(batch, features)` and NCHW images, `-1` for NHWC), then scales and shifts it.
+/// `scale`, `shift`, `running_mean` and `running_var` should be shared variables that have
+/// `x.shape[axis]` elements each; they are typically initialized with ones, zeros, zeros
+/// and ones respectively.
+///
+/// In training mode (the default), `x` is normalized with the mean and variance of the batch,
+/// and the running statistics are updated in place every time this is evaluated:
+/// `running = momentum * running + (1 - momentum) * batch_statistic`, where the variance is
+/// unbiased.
+/// In inference mode (`ag::set_training(false)` or `Eval::set_training(false)`), `x` is
+/// normalized with the running statistics instead, so each sample is normalized
+/// independently of the others.
+///
+/// ```
+/// extern crate ndarray;
+/// extern crate autograd as ag;
+///
+/// let ref x = ag::standard_normal(&[8, 3, 4, 4]);
+/// let ref scale = ag::variable(ag::ndarray_ext::ones(&[3]));
+/// let ref shift = ag::variable(ag::ndarray_ext::zeros(&[3]));
+/// let ref mean = ag::variable(ag::ndarray_ext::zeros(&[3]));
+/// let ref var = ag::variable(ag::ndarray_ext::ones(&[3]));
+/// let ref y = ag::batch_norm_with_stats(x, scale, shift, mean, var, 1, 0.9, 1e-5);
+///
+/// // Training updates the running statistics.
+/// assert_eq!(y.eval(&[]).unwrap().shape(), &[8, 3, 4, 4]);
+/// assert_ne!(mean.eval(&[]).unwrap(), ag::ndarray_ext::zeros(&[3]));
+///
+/// // Inference uses them.
+/// let ref z = ag::Eval::new().set_training(false).push(y).run(&[])[0];
+/// assert_eq!(z.as_ref().unwrap().shape(), &[8, 3, 4, 4]);
+/// ```
+pub fn batch_norm_with_stats<A, B, C, D, E>(
+    x: A,
+    scale: B,
+    shift: C,
+    running_mean: D,
+    running_var: E,
+    axis: isize,
+    momentum: f32,
+    eps: f32,
+) -> Tensor
+where
+    A: AsRef<Tensor>,
+    B: AsRef<Tensor>,
+    C: AsRef<Tensor>,
+    D: AsRef<Tensor>,
+    E: AsRef<Tensor>,
+{
+    Tensor::builder()
+        .set_inputs(vec![
+            x.as_ref(),
+            scale.as_ref(),
+            shift.as_ref(),
+            running_mean.as_ref(),
+            running_var.as_ref(),
+        ])
+        .build(norm_ops::BatchNorm {
+            axis,
+            momentum,
+            eps,
+        })
+}
+
+/// Applies layer normalization.
+///
+/// Normalizes each sample of `x` over the axes from `axis` to the last (e.g. `-1` for the
+/// features of `(batch, seq, features)`), then scales and shifts it elementwise.
+/// `scale` and `shift` should be shared variables that have as many elements as
+/// `x.shape[axis..]`. Unlike `batch_norm`, this does not depend on the other samples.
+///
+/// ```
+/// extern crate ndarray;
+/// extern crate autograd as ag;
+///
+/// let ref x = ag::constant(ndarray::arr2(&[[1., 2., 3.], [0., 0., 6.]]));
+/// let ref scale = ag::variable(ag::ndarray_ext::ones(&[3]));
+/// let ref shift = ag::variable(ag::ndarray_ext::zeros(&[3]));
+/// let ref y = ag::layer_norm(x, scale, shift, -1, 0.);
+///
+/// let y = y.eval(&[]).unwrap();
+/// let (a, b) = (1.5f32.sqrt(), 0.5f32.sqrt());
+/// let expected = ndarray::arr2(&[[-a, 0., a], [-b, -b, 2. * b]]);
+/// assert!(y.all_close(&expected.into_dyn(), 1e-5));
+/// ```
+pub fn layer_norm<A, B, C>(x: A, scale: B, shift: C, axis: isize, eps: f32) -> Tensor
+where
+    A: AsRef<Tensor>,
+    B: AsRef<Tensor>,
+    C: AsRef<Tensor>,
+{
+    Tensor::builder()
+        .set_inputs(vec![x.as_ref(), scale.as_ref(), shift.as_ref()])
+        .build(norm_ops::LayerNorm { axis, eps })
+}
+
+/// Applies group normalization.
+///
+/// Splits the channels of `x`, which is `(batch, channels, ...)` such as NCHW images,
+/// into `groups` and normalizes each group of each sample over its channels and the
+/// remaining axes, then scales and shifts each channel.
+/// `scale` and `shift` should be shared variables that have `channels` elements.
+///
+/// ```
+/// extern crate ndarray;
+/// extern crate autograd as ag;
+///
+/// let ref x = ag::standard_normal(&[2, 6, 4, 4]);
+/// let ref scale = ag::variable(ag::ndarray_ext::ones(&[6]));
+/// let ref shift = ag::variable(ag::ndarray_ext::zeros(&[6]));
+/// let ref y = ag::group_norm(x, scale, shift, 3, 1e-5);
+///
+/// assert_eq!(y.eval(&[]).unwrap().shape(), &[2, 6, 4, 4]);
+/// ```
+pub fn group_norm<A, B, C>(x: A, scale: B, shift: C, groups: usize, eps: f32) -> Tensor
+where
+    A: AsRef<Tensor>,
+    B: AsRef<Tensor>,
+    C: AsRef<Tensor>,
+{
+    assert_ne!(groups, 0, "ag::group_norm: groups must be positive");
+    Tensor::builder()
+        .set_inputs(vec![x.as_ref(), scale.as_ref(), shift.as_ref()])
+        .build(norm_ops::GroupNorm { groups, eps })
+}
+
+/// Applies instance normalization.
+///
+/// Normalizes each channel of each sample of `x`, which is `(batch, channels, ...)`,
+/// over the remaining axes, then scales and shifts each channel.
+/// This is `group_norm` with one group per channel.
+/// `scale` and `shift` should be shared variables that have `channels` elements.
+///
+/// ```
+/// extern crate ndarray;
+/// extern crate autograd as ag;
+///
+/// let ref x = ag::standard_normal(&[2, 3, 4, 4]);
+/// let ref scale = ag::variable(ag::ndarray_ext::ones(&[3]));
+/// let ref shift = ag::variable(ag::ndarray_ext::zeros(&[3]));
+/// let ref y = ag::instance_norm(x, scale, shift, 1e-5);
+///
+/// assert_eq!(y.eval(&[]).unwrap().shape(), &[2, 3, 4, 4]);
+/// ```
+pub fn instance_norm<A, B, C>(x: A, scale: B, shift: C, eps: f32) -> Tensor
+where
+    A: AsRef<Tensor>,
+    B: AsRef<Tensor>,
+    C: AsRef<Tensor>,
+{
+    Tensor::builder()
+        .set_inputs(vec![x.as_ref(), scale.as_ref(), shift.as_ref()])
+        .build(norm_ops::InstanceNorm { eps })
+}
+
+/// Generates a zero-ranked tensor from a scalar value.
+pub fn scalar(val: f32) -> Tensor {
+    let op = const_gen_ops::Scalar { val };
+    Tensor::builder()
+        .set_shape(convert_to_tensor(::ndarray_ext::scalar_shape()))
+        .build(op)
+}
+
+/// Outputs values sampled from the normal distribution.
+pub fn random_normal<T: ArrayLike>(shape: &T, mean: f64, stddev: f64) -> Tensor {
+    random_normal_rng(Default::default(), shape, mean, stddev)
+}
+
+/// Outputs values sampled from the normal distribution.
+pub fn random_normal_rng<T: ArrayLike, R: Rng + 'static>(
+    arr_rng: ArrRng<R>,
+    shape: &T,
+    mean: f64,
+    stddev: f64,
+) -> Tensor {
+    let shape = shape.as_tensor();
+    Tensor::builder()
+        .set_input(&shape)
+        .set_shape(shape)
+        .build(random_ops::RandomNormal::new(arr_rng, mean, stddev))
+}
+
+/// Outputs values sampled from the uniform distribution.
+pub fn random_uniform<T: ArrayLike>(shape: &T, min: f64, max: f64) -> Tensor {
+    random_uniform_rng(Default::default(), shape, min, max)
+}
+
+/// Outputs values sampled from the uniform distribution.
+pub fn random_uniform_rng<T: ArrayLike, R: Rng + 'static>(
+    arr_rng: ArrRng<R>,
+    shape: &T,
+    min: f64,
+    max: f64,
+) -> Tensor {
+    let shape = shape.as_tensor();
+    Tensor::builder()
+        .set_input(&shape)
+        .set_shape(shape)
+        .build(random_ops::RandomUniform::new(arr_rng, min, max))
+}
+
+/// Outputs values sampled from the standard normal distribution.
+pub fn standard_normal<T: ArrayLike>(shape: &T) -> Tensor {
+    standard_normal_rng(Default::default(), shape)
+}
+
+/// Outputs values sampled from the standard normal distribution.
+pub fn standard_normal_rng<T: ArrayLike, R: Rng + 'static>(
+    arr_rng: ArrRng<R>,
+    shape: &T,
+) -> Tensor {
+    let shape = shape.as_tensor();
+    Tensor::builder()
+        .set_input(&shape)
+        .set_shape(shape)
+        .build(random_ops::StandardNormal::new(arr_rng))
+}
+
+/// Outputs values sampled from the standard uniform distribution.
+pub fn standard_uniform<T: ArrayLike>(shape: &T) -> Tensor {
+    standard_uniform_rng(Default::default(), shape)
+}
+
+/// Outputs values sampled from the standard uniform distribution.
+pub fn standard_uniform_rng<T: ArrayLike, R: Rng + 'static>(
+    arr_rng: ArrRng<R>,
+    shape: &T,
+) -> Tensor {
+    let shape = shape.as_tensor();
+    Tensor::builder()
+        .set_input(&shape)
+        .set_shape(shape)
+        .build(random_ops::StandardUniform::new(arr_rng))
+}
+
+/// Outputs values sampled from the bernoulli distribution.
+pub fn bernoulli<T: ArrayLike>(shape: &T, p: f64) -> Tensor {
+    bernoulli_rng(Default::default(), shape, p)
+}
+
+/// Outputs values sampled from the bernoulli distribution.
+pub fn bernoulli_rng<T: ArrayLike, R: Rng + 'static>(
+    arr_rng: ArrRng<R>,
+    shape: &T,
+    p: f64,
+) -> Tensor {
+    let shape = shape.as_tensor();
+    Tensor::builder()
+        .set_input(&shape)
+        .set_shape(shape)
+        .build(random_ops::Bernoulli::new(arr_rng, p))
+}
+
+/// Applies dropout with inverted scaling.
+///
+/// In training mode, each element of `x` is zeroed with probability `rate` and the others are
+/// scaled by `1 / (1 - rate)`, so that the expected value is unchanged and nothing has to be
+/// done at test time. Gradients flow through the kept elements with the same scaling.
+/// In inference mode (`ag::set_training(false)` or `Eval::set_training(false)`), this
+/// returns `x` as is.
+///
+/// ```
+/// extern crate ndarray;
+/// extern crate autograd as ag;
+///
+/// let ref x = ag::ones(&[2, 5]);
+/// let ref y = ag::dropout(x, 0.5);
+///
+/// let train = y.eval(&[]).unwrap();
+/// assert!(train.iter().all(|&a| a == 0. || a == 2.));
+///
+/// let ref test = ag::Eval::new().set_training(false).push(y).run(&[])[0];
+/// assert_eq!(test.as_ref().unwrap(), &ag::ndarray_ext::ones(&[2, 5]));
+/// ```
+pub fn dropout<A: AsRef<Tensor>>(x: A, rate: f64) -> Tensor {
+    dropout_rng(Default::default(), x, rate)
+}
+
+/// Applies dropout with inverted scaling using `arr_rng` (see `dropout`).
+pub fn dropout_rng<A: AsRef<Tensor>, R: Rng + 'static>(
+    arr_rng: ArrRng<R>,
+    x: A,
+    rate: f64,
+) -> Tensor {
+    assert!(
+        0. <= rate && rate < 1.,
+        "ag::dropout: rate must be in [0, 1) (got {:?})",
+        rate
+    );
+    Tensor::builder()
+        .set_input(x.as_ref())
+        .build(random_ops::Dropout::new(arr_rng, rate))
+}
+
+/// Outputs values sampled from the exponential distribution.
+pub fn random_exp<T: ArrayLike>(shape: &T, lambda: f64) -> Tensor {
+    random_exp_rng(Default::default(), shape, lambda)
+}
+
+/// Outputs values sampled from the exponential distribution.
+pub fn random_exp_rng<T: ArrayLike, R: Rng + 'static>(
+    arr_rng: ArrRng<R>,
+    shape: &T,
+    lambda: f64,
+) -> Tensor {
+    let shape = shape.as_tensor();
+    Tensor::builder()
+        .set_input(&shape)
+        .set_shape(shape)
+        .build(random_ops::Exponential::new(arr_rng, lambda))
+}
+
+/// Outputs values sampled from the gamma distribution.
+pub fn random_gamma<T: ArrayLike>(shape: &T, shape_param: f64, scale: f64) -> Tensor {
+    random_gamma_rng(Default::default(), shape, shape_param, scale)
+}
+
+/// Outputs values sampled from the gamma distribution.
+pub fn random_gamma_rng<T: ArrayLike, R: Rng + 'static>(
+    arr_rng: ArrRng<R>,
+    shape: &T,
+    shape_param: f64,
+    scale: f64,
+) -> Tensor {
+    let shape = shape.as_tensor();
+    Tensor::builder()
+        .set_input(&shape)
+        .set_shape(shape)
+        .build(random_ops::Gamma::new(arr_rng, shape_param, scale))
+}
+
+/// Outputs values sampled from the log-normal distribution.
+pub fn log_normal<T: ArrayLike>(shape: &T, mean: f64, stddev: f64) -> Tensor {
+    log_normal_rng(Default::default(), shape, mean, stddev)
+}
+
+/// Outputs values sampled from the log-normal distribution.
+pub fn log_normal_rng<T: ArrayLike, R: Rng + 'static>(
+    arr_rng: ArrRng<R>,
+    shape: &T,
+    mean: f64,
+    stddev: f64,
+) -> Tensor {
+    let shape = shape.as_tensor();
+    Tensor::builder()
+        .set_input(&shape)
+        .set_shape(shape)
+        .build(random_ops::LogNormal::new(arr_rng, mean, stddev))
+}
+
+/// Converts an `ndarray::Array` to a `ag::Tensor`.
+///
+/// ```
+/// extern crate ndarray;
+/// extern crate autograd as ag;
+///
+/// let arr = ndarray::arr1(&[2., 3.]);
+/// let tensor = ag::convert_to_tensor(arr.clone());
+/// assert_eq!(tensor.eval(&[]), Some(arr.into_dyn()));
+/// ```
+pub fn convert_to_tensor<T>(arr: ndarray::Array<f32, T>) -> Tensor
+where
+    T: ndarray::Dimension,
+{
+    let arr = arr.into_dyn();
+    let shape = {
+        let op = const_gen_ops::ConvertToTensor {
+            arr: ::ndarray_ext::shape_of(&arr),
+        };
+        Tensor::builder().build(op)
+    };
+    Tensor::builder()
+        .set_shape(shape)
+        .build(const_gen_ops::ConvertToTensor { arr })
+}
+
+/// Returns zeros with given shape.
+///
+/// ```
+/// extern crate ndarray;
+/// extern crate autograd as ag;
+///
+/// let a = ag::zeros(&[4, 2]);
+/// assert_eq!(a.eval(&[]), Some(ndarray::Array2::<f32>::zeros((4, 2)).into_dyn()));
+/// ```
+pub fn zeros<T: ArrayLike>(shape: &T) -> Tensor {
+    Tensor::builder()
+        .set_input(&shape.as_tensor())
+        .build(const_gen_ops::Zeros)
+}
+
+/// Returns ones with given shape.
+///
+/// ```
+/// extern crate ndarray;
+/// extern crate autograd as ag;
+///
+/// let a = ag::ones(&[4, 2]);
+/// assert_eq!(a.eval(&[]), Some(ndarray::Array2::<f32>::from_elem((4, 2), 1.).into_dyn()));
+/// ```
+pub fn ones<T: ArrayLike>(shape: &T) -> Tensor {
+    Tensor::builder()
+        .set_input(&shape.as_tensor())
+        .build(const_gen_ops::Ones)
+}
+
+/// Returns a range.
+///
+/// ```
+/// extern crate ndarray;
+/// extern crate autograd as ag;
+///
+/// let ref start = ag::scalar(0.);
+/// let ref end = ag::scalar(5.);
+/// let ref step = ag::scalar(1.);
+/// let ref z = ag::range(start, end, step);
+///
+/// assert_eq!(z.eval(&[]), Some(ndarray::Array1::range(0., 5., 1.).into_dyn()));
+/// ```
+pub fn range<T: ArrayLike>(start: &T, end: &T, step: &T) -> Tensor {
+    Tensor::builder()
+        .set_inputs(vec![
+            &start.as_tensor(),
+            &end.as_tensor(),
+            &step.as_tensor(),
+        ])
+        .build(const_gen_ops::Range)
+}
+
+/// 2D convolution.
+///
+/// * `x`: Tensor with shape `(batch, channel, h, w)`
+/// * `w`: Tensor with shape `(out_channel, channel, filter_h, filter_w)`
+///
+/// Returns a tensor with shape `(batch, out_channel, out_h, out_w)`
+///
+/// where
+///
+///   * `out_h` = `(h + 2 * pad - filter_h) / stride + 1`
+///   * `out_w` = `(w + 2 * pad - filter_w) / stride + 1`
+///
+pub fn conv2d<A, B>(x: A, w: B, pad: usize, stride: usize) -> Tensor
+where
+    A: AsRef<Tensor>,
+    B: AsRef<Tensor>,
+{
+    Tensor::builder()
+        .set_inputs(vec![x.as_ref(), w.as_ref()])
+        .build(conv_ops::conv2d::Conv2D {
+            padding: Padding::Explicit(pad, pad, pad, pad),
+            stride: (stride, stride),
+            dilation: (1, 1),
+            groups: conv_ops::Groups::Count(1),
+            format: DataFormat::NCHW,
+        })
+}
+
+/// 2D convolution with dilation.
+///
+/// * `x`: Tensor with shape `(batch, channel, h, w)`
+/// * `w`: Tensor with shape `(out_channel, in_channel, filter_h, filter_w)`
+///
+/// Returns a tensor with shape `(batch, out_channel, out_h, out_w)`
+///
+/// where
+///
+///   * `out_h` = `(h + 2 * pad - (dilate * (filter - 1) + 1)) / stride + 1`
+///   * `out_w` = `(w + 2 * pad - (dilate * (filter - 1) + 1)) / stride + 1`
+///
+pub fn dilated_conv2d<A, B>(x: A, w: B, pad: usize, stride: usize, dilate: usize) -> Tensor
+where
+    A: AsRef<Tensor>,
+    B: AsRef<Tensor>,
+{
+    Tensor::builder()
+        .set_inputs(vec![x.as_ref(), w.as_ref()])
+        .build(conv_ops::conv2d::Conv2D {
+            padding: Padding::Explicit(pad, pad, pad, pad),
+            stride: (stride, stride),
+            dilation: (dilate, dilate),
+            groups: conv_ops::Groups::Count(1),
+            format: DataFormat::NCHW,
+        })
+}
+
+/// 2D transposed convolution.
+///
+/// * `x`: Tensor with shape `(batch, in_channel, h, w)`
+/// * `w`: Tensor with shape `(in_channel, out_channel, filter_h, filter_w)`
+///
+/// Returns a tensor with shape `(batch, out_channel, out_h, out_w)`
+///
+/// where
+///
+///   * `out_h` = `stride * (h - 1) - pad + filter_h`
+///   * `out_w` = `stride * (w - 1) - pad + filter_w`
+///
+pub fn conv2d_transpose<A, B>(x: A, w: B, pad: usize, stride: usize) -> Tensor
+where
+    A: AsRef<Tensor>,
+    B: AsRef<Tensor>,
+{
+    Tensor::builder()
+        .set_inputs(vec![x.as_ref(), w.as_ref()])
+        .build(conv_ops::conv2d_transpose::Conv2DTranspose {
+            padding: Padding::Explicit(pad, pad, pad, pad),
+            stride: (stride, stride),
+            dilation: (1, 1),
+            groups: 1,
+            format: DataFormat::NCHW,
+        })
+}
+
+/// 2D transposed convolution with dilation.
+///
+/// * `x`: Tensor with shape `(batch, in_channel, h, w)`
+/// * `w`: Tensor with shape `(in_channel, out_channel, filter_h, filter_w)`
+///
+/// Returns a tensor with shape `(batch, out_channel, out_h, out_w)`
+///
+/// where
+///
+///   * `out_h` = `stride * (h - 1) - pad + (dilate * (filter_h - 1) + 1)`
+///   * `out_w` = `stride * (w - 1) - pad + (dilate * (filter_w - 1) + 1)`
+///
+pub fn dilated_conv2d_transpose<A, B>(
+    x: A,
+    w: B,
+    pad: usize,
+    stride: usize,
+    dilate: usize,
+) -> Tensor
+where
+    A: AsRef<Tensor>,
+    B: AsRef<Tensor>,
+{
+    Tensor::builder()
+        .set_inputs(vec![x.as_ref(), w.as_ref()])
+        .build(conv_ops::conv2d_transpose::Conv2DTranspose {
+            padding: Padding::Explicit(pad, pad, pad, pad),
+            stride: (stride, stride),
+            dilation: (dilate, dilate),
+            groups: 1,
+            format: DataFormat::NCHW,
+        })
+}
+
+/// 2D grouped convolution.
+///
+/// Input channels and output channels are split into `groups` groups respectively,
+/// and each group of the output is computed from the corresponding group of the input.
+///
+/// * `x`: Tensor with shape `(batch, channel, h, w)`
+/// * `w`: Tensor with shape `(out_channel, channel / groups, filter_h, filter_w)`
+///
+/// Returns a tensor with shape `(batch, out_channel, out_h, out_w)`
+///
+/// where
+///
+///   * `out_h` = `(h + 2 * pad - filter_h) / stride + 1`
+///   * `out_w` = `(w + 2 * pad - filter_w) / stride + 1`
+///
+/// ```
+/// extern crate autograd as ag;
+///
+/// let ref x = ag::ones(&[2, 4, 5, 5]);
+/// let ref w = ag::ones(&[6, 2, 3, 3]);
+/// let ref y = ag::grouped_conv2d(x, w, 1, 1, 2);
+///
+/// assert_eq!(y.eval(&[]).unwrap().shape(), &[2, 6, 5, 5]);
+/// ```
+pub fn grouped_conv2d<A, B>(x: A, w: B, pad: usize, stride: usize, groups: usize) -> Tensor
+where
+    A: AsRef<Tensor>,
+    B: AsRef<Tensor>,
+{
+    assert_ne!(groups, 0, "ag::grouped_conv2d: groups must be positive");
+    Tensor::builder()
+        .set_inputs(vec![x.as_ref(), w.as_ref()])
+        .build(conv_ops::conv2d::Conv2D {
+            padding: Padding::Explicit(pad, pad, pad, pad),
+            stride: (stride, stride),
+            dilation: (1, 1),
+            groups: conv_ops::Groups::Count(groups),
+            format: DataFormat::NCHW,
+        })
+}
+
+/// 2D depthwise convolution.
+///
+/// Each input channel is convolved with its own `multiplier` filters,
+/// i.e. grouped convolution where `groups` equals the number of input channels.
+/// Depthwise separable convolution is this followed by a 1x1 `conv2d`.
+///
+/// * `x`: Tensor with shape `(batch, channel, h, w)`
+/// * `w`: Tensor with shape `(channel * multiplier, 1, filter_h, filter_w)`
+///
+/// Returns a tensor with shape `(batch, channel * multiplier, out_h, out_w)`
+///
+/// where
+///
+///   * `out_h` = `(h + 2 * pad - filter_h) / stride + 1`
+///   * `out_w` = `(w + 2 * pad - filter_w) / stride + 1`
+///
+/// ```
+/// extern crate autograd as ag;
+///
+/// let ref x = ag::ones(&[2, 3, 5, 5]);
+/// let ref depthwise = ag::ones(&[6, 1, 3, 3]);
+/// let ref pointwise = ag::ones(&[8, 6, 1, 1]);
+/// let ref y = ag::conv2d(&ag::depthwise_conv2d(x, depthwise, 1, 1), pointwise, 0, 1);
+///
+/// assert_eq!(y.eval(&[]).unwrap().shape(), &[2, 8, 5, 5]);
+/// ```
+pub fn depthwise_conv2d<A, B>(x: A, w: B, pad: usize, stride: usize) -> Tensor
+where
+    A: AsRef<Tensor>,
+    B: AsRef<Tensor>,
+{
+    Tensor::builder()
+        .set_inputs(vec![x.as_ref(), w.as_ref()])
+        .build(conv_ops::conv2d::Conv2D {
+            padding: Padding::Explicit(pad, pad, pad, pad),
+            stride: (stride, stride),
+            dilation: (1, 1),
+            groups: conv_ops::Groups::Depthwise,
+            format: DataFormat::NCHW,
+        })
+}
+
+/// 2D grouped transposed convolution.
+///
+/// * `x`: Tensor with shape `(batch, in_channel, h, w)`
+/// * `w`: Tensor with shape `(in_channel, out_channel / groups, filter_h, filter_w)`
+///
+/// Returns a tensor with shape `(batch, out_channel, out_h, out_w)`
+///
+/// where
+///
+///   * `out_h` = `stride * (h - 1) - 2 * pad + filter_h`
+///   * `out_w` = `stride * (w - 1) - 2 * pad + filter_w`
+///
+/// ```
+/// extern crate autograd as ag;
+///
+/// let ref x = ag::ones(&[2, 4, 5, 5]);
+/// let ref w = ag::ones(&[4, 3, 3, 3]);
+/// let ref y = ag::grouped_conv2d_transpose(x, w, 1, 1, 2);
+///
+/// assert_eq!(y.eval(&[]).unwrap().shape(), &[2, 6, 5, 5]);
+/// ```
+pub fn grouped_conv2d_transpose<A, B>(
+    x: A,
+    w: B,
+    pad: usize,
+    stride: usize,
+    groups: usize,
+) -> Tensor
+where
+    A: AsRef<Tensor>,
+    B: AsRef<Tensor>,
+{
+    assert_ne!(
+        groups, 0,
+        "ag::grouped_conv2d_transpose: groups must be positive"
+    );
+    Tensor::builder()
+        .set_inputs(vec![x.as_ref(), w.as_ref()])
+        .build(conv_ops::conv2d_transpose::Conv2DTranspose {
+            padding: Padding::Explicit(pad, pad, pad, pad),
+            stride: (stride, stride),
+            dilation: (1, 1),
+            groups,
+            format: DataFormat::NCHW,
+        })
+}
+
+/// 2D convolution with per-axis parameters.
+///
+/// * `x`: Tensor with shape `(batch, channel, h, w)`
+/// * `w`: Tensor with shape `(out_channel, channel / groups, filter_h, filter_w)`
+/// * `padding`: `Padding::Valid`, `Padding::Same` or `Padding::Explicit(top, bottom, left, right)`
+/// * `stride`: `(stride_h, stride_w)`
+/// * `dilation`: `(dilation_h, dilation_w)`
+/// * `data_format`: `DataFormat::NCHW` or `DataFormat::NHWC`
+///
+/// Returns a tensor with shape `(batch, out_channel, out_h, out_w)`
+///
+/// where
+///
+///   * `out_h` = `(h + top + bottom - (dilation_h * (filter_h - 1) + 1)) / stride_h + 1`
+///   * `out_w` = `(w + left + right - (dilation_w * (filter_w - 1) + 1)) / stride_w + 1`
+///
+/// With `Padding::Same`, `out_h` = `ceil(h / stride_h)` and `out_w` = `ceil(w / stride_w)`,
+/// as in TensorFlow.
+///
+/// In `DataFormat::NHWC`, `x` is `(batch, h, w, channel)`,
+/// `w` is `(filter_h, filter_w, channel / groups, out_channel)` and
+/// the result is `(batch, out_h, out_w, out_channel)` as in TensorFlow.
+///
+/// ```
+/// extern crate autograd as ag;
+/// use ag::{DataFormat, Padding};
+///
+/// let ref x = ag::ones(&[2, 3, 7, 8]);
+/// let ref w = ag::ones(&[4, 3, 3, 2]);
+/// let ref y = ag::conv2d_with_padding(x, w, Padding::Same, (2, 3), (1, 1), 1, DataFormat::NCHW);
+///
+/// assert_eq!(y.eval(&[]).unwrap().shape(), &[2, 4, 4, 3]);
+///
+/// let ref x = ag::ones(&[2, 7, 8, 3]);
+/// let ref w = ag::ones(&[3, 2, 3, 4]);
+/// let ref y = ag::conv2d_with_padding(x, w, Padding::Same, (2, 3), (1, 1), 1, DataFormat::NHWC);
+///
+/// assert_eq!(y.eval(&[]).unwrap().shape(), &[2, 4, 3, 4]);
+/// ```
+pub fn conv2d_with_padding<A, B>(
+    x: A,
+    w: B,
+    padding: Padding,
+    stride: (usize, usize),
+    dilation: (usize, usize),
+    groups: usize,
+    data_format: DataFormat,
+) -> Tensor
+where
+    A: AsRef<Tensor>,
+    B: AsRef<Tensor>,
+{
+    assert_ne!(
+        groups, 0,
+        "ag::conv2d_with_padding: groups must be positive"
+    );
+    Tensor::builder()
+        .set_inputs(vec![x.as_ref(), w.as_ref()])
+        .build(conv_ops::conv2d::Conv2D {
+            padding,
+            stride,
+            dilation,
+            groups: conv_ops::Groups::Count(groups),
+            format: data_format,
+        })
+}
+
+/// 2D transposed convolution with per-axis parameters.
+///
+/// The inverse of `conv2d_with_padding` in terms of shapes.
+///
+/// * `x`: Tensor with shape `(batch, in_channel, h, w)`
+/// * `w`: Tensor with shape `(in_channel, out_channel / groups, filter_h, filter_w)`
+/// * `padding`: `Padding::Valid`, `Padding::Same` or `Padding::Explicit(top, bottom, left, right)`
+/// * `stride`: `(stride_h, stride_w)`
+/// * `dilation`: `(dilation_h, dilation_w)`
+/// * `data_format`: `DataFormat::NCHW` or `DataFormat::NHWC`
+///
+/// Returns a tensor with shape `(batch, out_channel, out_h, out_w)`
+///
+/// where
+///
+///   * `out_h` = `stride_h * (h - 1) - top - bottom + (dilation_h * (filter_h - 1) + 1)`
+///   * `out_w` = `stride_w * (w - 1) - left - right + (dilation_w * (filter_w - 1) + 1)`
+///
+/// With `Padding::Same`, `out_h` = `h * stride_h` and `out_w` = `w * stride_w`.
+///
+/// In `DataFormat::NHWC`, `x` is `(batch, h, w, in_channel)`,
+/// `w` is `(filter_h, filter_w, out_channel / groups, in_channel)` and
+/// the result is `(batch, out_h, out_w, out_channel)`.
+pub fn conv2d_transpose_with_padding<A, B>(
+    x: A,
+    w: B,
+    padding: Padding,
+    stride: (usize, usize),
+    dilation: (usize, usize),
+    groups: usize,
+    data_format: DataFormat,
+) -> Tensor
+where
+    A: AsRef<Tensor>,
+    B: AsRef<Tensor>,
+{
+    assert_ne!(
+        groups, 0,
+        "ag::conv2d_transpose_with_padding: groups must be positive"
+    );
+    Tensor::builder()
+        .set_inputs(vec![x.as_ref(), w.as_ref()])
+        .build(conv_ops::conv2d_transpose::Conv2DTranspose {
+            padding,
+            stride,
+            dilation,
+            groups,
+            format: data_format,
+        })
+}
+
+/// 1D convolution.
+///
+/// * `x`: Tensor with shape `(batch, channel, len)`
+/// * `w`: Tensor with shape `(out_channel, channel, filter_len)`
+///
+/// Returns a tensor with shape `(batch, out_channel, out_len)`
+///
+/// where
+///
+///   * `out_len` = `(len + 2 * pad - filter_len) / stride + 1`
+///
+pub fn conv1d<A, B>(x: A, w: B, pad: usize, stride: usize) -> Tensor
+where
+    A: AsRef<Tensor>,
+    B: AsRef<Tensor>,
+{
+    Tensor::builder()
+        .set_inputs(vec![x.as_ref(), w.as_ref()])
+        .build(conv_ops::conv_nd::ConvND {
+            ndim: 1,
+            pad,
+            stride,
+            dilation: 1,
+        })
 }
 
-/// Returns zeros with given shape.
+/// 1D convolution with dilation.
 ///
-/// ```
-/// extern crate ndarray;
-/// extern crate autograd as ag;
+/// * `x`: Tensor with shape `(batch, channel, len)`
+/// * `w`: Tensor with shape `(out_channel, in_channel, filter_len)`
 ///
-/// let a = ag::zeros(&[4, 2]);
-/// assert_eq!(a.eval(&[]), Some(ndarray::Array2::<f32>::zeros((4, 2)).into_dyn()));
-/// ```
-pub fn zeros<T: ArrayLike>(shape: &T) -> Tensor {
+/// Returns a tensor with shape `(batch, out_channel, out_len)`
+///
+/// where
+///
+///   * `out_len` = `(len + 2 * pad - (dilate * (filter_len - 1) + 1)) / stride + 1`
+///
+pub fn dilated_conv1d<A, B>(x: A, w: B, pad: usize, stride: usize, dilate: usize) -> Tensor
+where
+    A: AsRef<Tensor>,
+    B: AsRef<Tensor>,
+{
     Tensor::builder()
-        .set_input(&shape.as_tensor())
-        .build(const_gen_ops::Zeros)
+        .set_inputs(vec![x.as_ref(), w.as_ref()])
+        .build(conv_ops::conv_nd::ConvND {
+            ndim: 1,
+            pad,
+            stride,
+            dilation: dilate,
+        })
 }
 
-/// Returns ones with given shape.
+/// 1D transposed convolution.
+///
+/// * `x`: Tensor with shape `(batch, in_channel, len)`
+/// * `w`: Tensor with shape `(in_channel, out_channel, filter_len)`
+///
+/// Returns a tensor with shape `(batch, out_channel, out_len)`
+///
+/// where
+///
+///   * `out_len` = `stride * (len - 1) - 2 * pad + filter_len`
 ///
 /// ```
-/// extern crate ndarray;
 /// extern crate autograd as ag;
 ///
-/// let a = ag::ones(&[4, 2]);
-/// assert_eq!(a.eval(&[]), Some(ndarray::Array2::<f32>::from_elem((4, 2), 1.).into_dyn()));
+/// let ref x = ag::ones(&[2, 4, 5]);
+/// let ref w = ag::ones(&[4, 3, 3]);
+/// let ref y = ag::conv1d_transpose(x, w, 1, 1);
+///
+/// assert_eq!(y.eval(&[]).unwrap().shape(), &[2, 3, 5]);
 /// ```
-pub fn ones<T: ArrayLike>(shape: &T) -> Tensor {
+pub fn conv1d_transpose<A, B>(x: A, w: B, pad: usize, stride: usize) -> Tensor
+where
+    A: AsRef<Tensor>,
+    B: AsRef<Tensor>,
+{
     Tensor::builder()
-        .set_input(&shape.as_tensor())
-        .build(const_gen_ops::Ones)
+        .set_inputs(vec![x.as_ref(), w.as_ref()])
+        .build(conv_ops::conv_nd::ConvNDTranspose {
+            ndim: 1,
+            pad,
+            stride,
+            dilation: 1,
+        })
 }
 
-/// Returns a range.
+/// 1D transposed convolution with dilation.
 ///
-/// ```
-/// extern crate ndarray;
-/// extern crate autograd as ag;
+/// * `x`: Tensor with shape `(batch, in_channel, len)`
+/// * `w`: Tensor with shape `(in_channel, out_channel, filter_len)`
 ///
-/// let ref start = ag::scalar(0.);
-/// let ref end = ag::scalar(5.);
-/// let ref step = ag::scalar(1.);
-/// let ref z = ag::range(start, end, step);
+/// Returns a tensor with shape `(batch, out_channel, out_len)`
 ///
-/// assert_eq!(z.eval(&[]), Some(ndarray::Array1::range(0., 5., 1.).into_dyn()));
-/// ```
-pub fn range<T: ArrayLike>(start: &T, end: &T, step: &T) -> Tensor {
+/// where
+///
+///   * `out_len` = `stride * (len - 1) - 2 * pad + (dilate * (filter_len - 1) + 1)`
+///
+pub fn dilated_conv1d_transpose<A, B>(
+    x: A,
+    w: B,
+    pad: usize,
+    stride: usize,
+    dilate: usize,
+) -> Tensor
+where
+    A: AsRef<Tensor>,
+    B: AsRef<Tensor>,
+{
     Tensor::builder()
-        .set_inputs(vec![
-            &start.as_tensor(),
-            &end.as_tensor(),
-            &step.as_tensor(),
-        ])
-        .build(const_gen_ops::Range)
+        .set_inputs(vec![x.as_ref(), w.as_ref()])
+        .build(conv_ops::conv_nd::ConvNDTranspose {
+            ndim: 1,
+            pad,
+            stride,
+            dilation: dilate,
+        })
 }
 
-/// 2D convolution.
+/// 3D convolution.
 ///
-/// * `x`: Tensor with shape `(batch, channel, h, w)`
-/// * `w`: Tensor with shape `(out_channel, channel, filter_h, filter_w)`
+/// * `x`: Tensor with shape `(batch, channel, d, h, w)`
+/// * `w`: Tensor with shape `(out_channel, channel, filter_d, filter_h, filter_w)`
 ///
-/// Returns a tensor with shape `(batch, out_channel, out_h, out_w)`
+/// Returns a tensor with shape `(batch, out_channel, out_d, out_h, out_w)`
 ///
 /// where
 ///
+///   * `out_d` = `(d + 2 * pad - filter_d) / stride + 1`
 ///   * `out_h` = `(h + 2 * pad - filter_h) / stride + 1`
 ///   * `out_w` = `(w + 2 * pad - filter_w) / stride + 1`
 ///
-pub fn conv2d<A, B>(x: A, w: B, pad: usize, stride: usize) -> Tensor
+pub fn conv3d<A, B>(x: A, w: B, pad: usize, stride: usize) -> Tensor
 where
     A: AsRef<Tensor>,
     B: AsRef<Tensor>,
 {
     Tensor::builder()
         .set_inputs(vec![x.as_ref(), w.as_ref()])
-        .build(conv_ops::conv2d::Conv2D {
+        .build(conv_ops::conv_nd::ConvND {
+            ndim: 3,
             pad,
             stride,
             dilation: 1,
         })
 }
 
-/// 2D convolution with dilation.
+/// 3D convolution with dilation.
 ///
-/// * `x`: Tensor with shape `(batch, channel, h, w)`
-/// * `w`: Tensor with shape `(out_channel, in_channel, filter_h, filter_w)`
+/// * `x`: Tensor with shape `(batch, channel, d, h, w)`
+/// * `w`: Tensor with shape `(out_channel, in_channel, filter_d, filter_h, filter_w)`
 ///
-/// Returns a tensor with shape `(batch, out_channel, out_h, out_w)`
+/// Returns a tensor with shape `(batch, out_channel, out_d, out_h, out_w)`
 ///
 /// where
 ///
-///   * `out_h` = `(h + 2 * pad - (dilate * (filter - 1) + 1)) / stride + 1`
-///   * `out_w` = `(w + 2 * pad - (dilate * (filter - 1) + 1)) / stride + 1`
+///   * `out_d` = `(d + 2 * pad - (dilate * (filter_d - 1) + 1)) / stride + 1`
+///   * `out_h` = `(h + 2 * pad - (dilate * (filter_h - 1) + 1)) / stride + 1`
+///   * `out_w` = `(w + 2 * pad - (dilate * (filter_w - 1) + 1)) / stride + 1`
 ///
-pub fn dilated_conv2d<A, B>(x: A, w: B, pad: usize, stride: usize, dilate: usize) -> Tensor
+pub fn dilated_conv3d<A, B>(x: A, w: B, pad: usize, stride: usize, dilate: usize) -> Tensor
 where
     A: AsRef<Tensor>,
     B: AsRef<Tensor>,
 {
     Tensor::builder()
         .set_inputs(vec![x.as_ref(), w.as_ref()])
-        .build(conv_ops::conv2d::Conv2D {
+        .build(conv_ops::conv_nd::ConvND {
+            ndim: 3,
             pad,
             stride,
             dilation: dilate,
         })
 }
 
-/// 2D transposed convolution.
+/// 3D transposed convolution.
 ///
-/// * `x`: Tensor with shape `(batch, in_channel, h, w)`
-/// * `w`: Tensor with shape `(in_channel, out_channel, filter_h, filter_w)`
+/// * `x`: Tensor with shape `(batch, in_channel, d, h, w)`
+/// * `w`: Tensor with shape `(in_channel, out_channel, filter_d, filter_h, filter_w)`
 ///
-/// Returns a tensor with shape `(batch, out_channel, out_h, out_w)`
+/// Returns a tensor with shape `(batch, out_channel, out_d, out_h, out_w)`
 ///
 /// where
 ///
-///   * `out_h` = `stride * (h - 1) - pad + filter_h`
-///   * `out_w` = `stride * (w - 1) - pad + filter_w`
+///   * `out_d` = `stride * (d - 1) - 2 * pad + filter_d`
+///   * `out_h` = `stride * (h - 1) - 2 * pad + filter_h`
+///   * `out_w` = `stride * (w - 1) - 2 * pad + filter_w`
 ///
-pub fn conv2d_transpose<A, B>(x: A, w: B, pad: usize, stride: usize) -> Tensor
+pub fn conv3d_transpose<A, B>(x: A, w: B, pad: usize, stride: usize) -> Tensor
 where
     A: AsRef<Tensor>,
     B: AsRef<Tensor>,
 {
     Tensor::builder()
         .set_inputs(vec![x.as_ref(), w.as_ref()])
-        .build(conv_ops::conv2d_transpose::Conv2DTranspose {
+        .build(conv_ops::conv_nd::ConvNDTranspose {
+            ndim: 3,
             pad,
             stride,
             dilation: 1,
         })
 }
 
-/// 2D transposed convolution with dilation.
+/// 3D transposed convolution with dilation.
 ///
-/// * `x`: Tensor with shape `(batch, in_channel, h, w)`
-/// * `w`: Tensor with shape `(in_channel, out_channel, filter_h, filter_w)`
+/// * `x`: Tensor with shape `(batch, in_channel, d, h, w)`
+/// * `w`: Tensor with shape `(in_channel, out_channel, filter_d, filter_h, filter_w)`
 ///
-/// Returns a tensor with shape `(batch, out_channel, out_h, out_w)`
+/// Returns a tensor with shape `(batch, out_channel, out_d, out_h, out_w)`
 ///
 /// where
 ///
-///   * `out_h` = `stride * (h - 1) - pad + (dilate * (filter_h - 1) + 1)`
-///   * `out_w` = `stride * (w - 1) - pad + (dilate * (filter_w - 1) + 1)`
+///   * `out_d` = `stride * (d - 1) - 2 * pad + (dilate * (filter_d - 1) + 1)`
+///   * `out_h` = `stride * (h - 1) - 2 * pad + (dilate * (filter_h - 1) + 1)`
+///   * `out_w` = `stride * (w - 1) - 2 * pad + (dilate * (filter_w - 1) + 1)`
 ///
-pub fn dilated_conv2d_transpose<A, B>(
+pub fn dilated_conv3d_transpose<A, B>(
     x: A,
     w: B,
     pad: usize,
@@ -2053,7 +3608,8 @@ where
 {
     Tensor::builder()
         .set_inputs(vec![x.as_ref(), w.as_ref()])
-        .build(conv_ops::conv2d_transpose::Conv2DTranspose {
+        .build(conv_ops::conv_nd::ConvNDTranspose {
+            ndim: 3,
             pad,
             stride,
             dilation: dilate,
@@ -2075,8 +3631,309 @@ pub fn max_pool2d<A: AsRef<Tensor>>(x: A, pool_size: usize, pad: usize, stride:
     Tensor::builder()
         .set_input(x.as_ref())
         .build(conv_ops::max_pool2d::MaxPool2D {
-            pad,
+            padding: Padding::Explicit(pad, pad, pad, pad),
+            stride: (stride, stride),
+            size: conv_ops::max_pool2d::PoolSize::Window(pool_size, pool_size),
+            format: DataFormat::NCHW,
+        })
+}
+
+/// 2D max pooling with per-axis parameters.
+///
+/// Padded elements are never selected.
+///
+/// * `x`: Tensor with shape `(batch, channel, h, w)`, or `(batch, h, w, channel)` in NHWC
+/// * `pool_size`: `(pool_h, pool_w)`
+/// * `padding`: `Padding::Valid`, `Padding::Same` or `Padding::Explicit(top, bottom, left, right)`
+/// * `stride`: `(stride_h, stride_w)`
+/// * `data_format`: `DataFormat::NCHW` or `DataFormat::NHWC`
+///
+/// Returns a tensor with shape `(batch, channel, out_h, out_w)`, or
+/// `(batch, out_h, out_w, channel)` in NHWC
+///
+/// where
+///
+///   * `out_h` = `(h + top + bottom - pool_h) / stride_h + 1`
+///   * `out_w` = `(w + left + right - pool_w) / stride_w + 1`
+///
+/// With `Padding::Same`, `out_h` = `ceil(h / stride_h)` and `out_w` = `ceil(w / stride_w)`,
+/// as in TensorFlow.
+///
+/// ```
+/// extern crate autograd as ag;
+/// use ag::{DataFormat, Padding};
+///
+/// let ref x = ag::ones(&[2, 3, 5, 6]);
+/// let ref y = ag::max_pool2d_with_padding(x, (3, 2), Padding::Same, (2, 2), DataFormat::NCHW);
+///
+/// assert_eq!(y.eval(&[]).unwrap().shape(), &[2, 3, 3, 3]);
+/// ```
+pub fn max_pool2d_with_padding<A: AsRef<Tensor>>(
+    x: A,
+    pool_size: (usize, usize),
+    padding: Padding,
+    stride: (usize, usize),
+    data_format: DataFormat,
+) -> Tensor {
+    Tensor::builder()
+        .set_input(x.as_ref())
+        .build(conv_ops::max_pool2d::MaxPool2D {
+            padding,
             stride,
-            size: pool_size,
+            size: conv_ops::max_pool2d::PoolSize::Window(pool_size.0, pool_size.1),
+            format: data_format,
+        })
+}
+
+/// 2D global max pooling.
+///
+/// * `x`: Tensor with shape `(batch, channel, h, w)`, or `(batch, h, w, channel)` in NHWC
+/// * `data_format`: `DataFormat::NCHW` or `DataFormat::NHWC`
+///
+/// Returns a tensor with shape `(batch, channel, 1, 1)`, or `(batch, 1, 1, channel)` in NHWC.
+pub fn global_max_pool2d<A: AsRef<Tensor>>(x: A, data_format: DataFormat) -> Tensor {
+    Tensor::builder()
+        .set_input(x.as_ref())
+        .build(conv_ops::max_pool2d::MaxPool2D {
+            padding: Padding::Valid,
+            stride: (1, 1),
+            size: conv_ops::max_pool2d::PoolSize::Global,
+            format: data_format,
+        })
+}
+
+/// 2D average pooling.
+///
+/// If `count_include_pad` is true, padded zeros are counted in the averages.
+///
+/// * `x`: Tensor with shape `(batch, channel, h, w)`
+///
+/// Returns a tensor with shape `(batch, channel, out_h, out_w)`
+///
+/// where
+///
+///   * `out_h` = `(h + 2 * pad - pool_size) / stride + 1`
+///   * `out_w` = `(w + 2 * pad - pool_size) / stride + 1`
+///
+/// ```
+/// extern crate ndarray;
+/// extern crate autograd as ag;
+///
+/// let ref x = ag::constant(ndarray::arr2(&[[1., 2.], [3., 4.]]).into_shape((1, 1, 2, 2)).unwrap());
+/// let ref y = ag::avg_pool2d(x, 2, 1, 2, false);
+/// let ref z = ag::avg_pool2d(x, 2, 1, 2, true);
+///
+/// assert_eq!(y.eval(&[]).unwrap().as_slice().unwrap(), &[1., 2., 3., 4.]);
+/// assert_eq!(z.eval(&[]).unwrap().as_slice().unwrap(), &[0.25, 0.5, 0.75, 1.]);
+/// ```
+pub fn avg_pool2d<A: AsRef<Tensor>>(
+    x: A,
+    pool_size: usize,
+    pad: usize,
+    stride: usize,
+    count_include_pad: bool,
+) -> Tensor {
+    avg_pool2d_with_padding(
+        x,
+        (pool_size, pool_size),
+        Padding::Explicit(pad, pad, pad, pad),
+        (stride, stride),
+        count_include_pad,
+        DataFormat::NCHW,
+    )
+}
+
+/// 2D average pooling with per-axis parameters.
+///
+/// If `count_include_pad` is true, padded zeros are counted in the averages.
+///
+/// * `x`: Tensor with shape `(batch, channel, h, w)`, or `(batch, h, w, channel)` in NHWC
+/// * `pool_size`: `(pool_h, pool_w)`
+/// * `padding`: `Padding::Valid`, `Padding::Same` or `Padding::Explicit(top, bottom, left, right)`
+/// * `stride`: `(stride_h, stride_w)`
+/// * `data_format`: `DataFormat::NCHW` or `DataFormat::NHWC`
+///
+/// Returns a tensor with shape `(batch, channel, out_h, out_w)`, or
+/// `(batch, out_h, out_w, channel)` in NHWC
+///
+/// where
+///
+///   * `out_h` = `(h + top + bottom - pool_h) / stride_h + 1`
+///   * `out_w` = `(w + left + right - pool_w) / stride_w + 1`
+///
+/// With `Padding::Same`, `out_h` = `ceil(h / stride_h)` and `out_w` = `ceil(w / stride_w)`,
+/// as in TensorFlow (whose `"SAME"` average pooling doesn't count padded zeros).
+pub fn avg_pool2d_with_padding<A: AsRef<Tensor>>(
+    x: A,
+    pool_size: (usize, usize),
+    padding: Padding,
+    stride: (usize, usize),
+    count_include_pad: bool,
+    data_format: DataFormat,
+) -> Tensor {
+    assert!(
+        pool_size.0 > 0 && pool_size.1 > 0,
+        "ag::avg_pool2d: pool_size must be positive"
+    );
+    Tensor::builder()
+        .set_input(x.as_ref())
+        .build(conv_ops::avg_pool2d::AvgPool2D {
+            windows: conv_ops::avg_pool2d::AvgPoolWindows::Strided {
+                padding,
+                stride,
+                size: pool_size,
+                count_include_pad,
+            },
+            format: data_format,
+        })
+}
+
+/// 2D global average pooling.
+///
+/// * `x`: Tensor with shape `(batch, channel, h, w)`, or `(batch, h, w, channel)` in NHWC
+/// * `data_format`: `DataFormat::NCHW` or `DataFormat::NHWC`
+///
+/// Returns a tensor with shape `(batch, channel, 1, 1)`, or `(batch, 1, 1, channel)` in NHWC.
+pub fn global_avg_pool2d<A: AsRef<Tensor>>(x: A, data_format: DataFormat) -> Tensor {
+    adaptive_avg_pool2d(x, (1, 1), data_format)
+}
+
+/// 2D adaptive average pooling.
+///
+/// Splits each `(h, w)` plane of `x` into `output_size` windows as evenly as possible,
+/// and averages each of them.
+/// The `i`th window along an axis of length `len` covers `floor(i * len / out)` to
+/// `ceil((i + 1) * len / out)` (exclusive), as in PyTorch.
+///
+/// * `x`: Tensor with shape `(batch, channel, h, w)`, or `(batch, h, w, channel)` in NHWC
+/// * `output_size`: `(out_h, out_w)`
+/// * `data_format`: `DataFormat::NCHW` or `DataFormat::NHWC`
+///
+/// Returns a tensor with shape `(batch, channel, out_h, out_w)`, or
+/// `(batch, out_h, out_w, channel)` in NHWC.
+///
+/// ```
+/// extern crate autograd as ag;
+///
+/// let ref x = ag::ones(&[2, 7, 5, 3]);
+/// let ref y = ag::adaptive_avg_pool2d(x, (3, 2), ag::DataFormat::NHWC);
+///
+/// assert_eq!(y.eval(&[]).unwrap(), ag::ndarray_ext::ones(&[2, 3, 2, 3]));
+/// ```
+pub fn adaptive_avg_pool2d<A: AsRef<Tensor>>(
+    x: A,
+    output_size: (usize, usize),
+    data_format: DataFormat,
+) -> Tensor {
+    assert!(
+        output_size.0 > 0 && output_size.1 > 0,
+        "ag::adaptive_avg_pool2d: output_size must be positive"
+    );
+    Tensor::builder()
+        .set_input(x.as_ref())
+        .build(conv_ops::avg_pool2d::AvgPool2D {
+            windows: conv_ops::avg_pool2d::AvgPoolWindows::Adaptive(output_size.0, output_size.1),
+            format: data_format,
+        })
+}
+
+/// Resizes images with nearest neighbor interpolation.
+///
+/// Output pixel `(i, j)` is input pixel `(floor(i * h / out_h), floor(j * w / out_w))`.
+///
+/// * `x`: Tensor with shape `(batch, channel, h, w)`
+/// * `size`: `(out_h, out_w)`
+///
+/// Returns a tensor with shape `(batch, channel, out_h, out_w)`.
+///
+/// ```
+/// extern crate ndarray;
+/// extern crate autograd as ag;
+///
+/// let ref x = ag::constant(ndarray::arr2(&[[1., 2.], [3., 4.]]).into_shape((1, 1, 2, 2)).unwrap());
+/// let ref y = ag::resize_nearest(x, (2, 4));
+///
+/// assert_eq!(y.eval(&[]).unwrap().as_slice().unwrap(), &[1., 1., 2., 2., 3., 3., 4., 4.]);
+/// ```
+pub fn resize_nearest<A: AsRef<Tensor>>(x: A, size: (usize, usize)) -> Tensor {
+    assert!(
+        size.0 > 0 && size.1 > 0,
+        "ag::resize_nearest: size must be positive"
+    );
+    Tensor::builder()
+        .set_input(x.as_ref())
+        .build(conv_ops::resize::Resize2D {
+            size,
+            method: conv_ops::resize::ResizeMethod::Nearest,
+        })
+}
+
+/// Resizes images with bilinear interpolation.
+///
+/// If `align_corners` is true, the corner pixels of the input and output are aligned, i.e.
+/// output pixel `i` samples input position `i * (h - 1) / (out_h - 1)`.
+/// Otherwise the centers of pixels are aligned, i.e. it samples `(i + 0.5) * h / out_h - 0.5`
+/// (clamped to `0`), as PyTorch's default.
+///
+/// * `x`: Tensor with shape `(batch, channel, h, w)`
+/// * `size`: `(out_h, out_w)`
+///
+/// Returns a tensor with shape `(batch, channel, out_h, out_w)`.
+///
+/// ```
+/// extern crate ndarray;
+/// extern crate autograd as ag;
+///
+/// let ref x = ag::constant(ndarray::arr2(&[[0., 3.]]).into_shape((1, 1, 1, 2)).unwrap());
+/// let ref y = ag::resize_bilinear(x, (1, 4), true);
+/// let ref z = ag::resize_bilinear(x, (1, 4), false);
+///
+/// assert_eq!(y.eval(&[]).unwrap().as_slice().unwrap(), &[0., 1., 2., 3.]);
+/// assert_eq!(z.eval(&[]).unwrap().as_slice().unwrap(), &[0., 0.75, 2.25, 3.]);
+/// ```
+pub fn resize_bilinear<A: AsRef<Tensor>>(
+    x: A,
+    size: (usize, usize),
+    align_corners: bool,
+) -> Tensor {
+    assert!(
+        size.0 > 0 && size.1 > 0,
+        "ag::resize_bilinear: size must be positive"
+    );
+    Tensor::builder()
+        .set_input(x.as_ref())
+        .build(conv_ops::resize::Resize2D {
+            size,
+            method: conv_ops::resize::ResizeMethod::Bilinear { align_corners },
+        })
+}
+
+/// Rearranges channels into spatial blocks (a.k.a. depth to space).
+///
+/// `y[b, c, i * r + di, j * r + dj]` = `x[b, c * r * r + di * r + dj, i, j]`
+/// where `r` is `upscale_factor`, as in PyTorch.
+///
+/// * `x`: Tensor with shape `(batch, channel * r * r, h, w)`
+///
+/// Returns a tensor with shape `(batch, channel, h * r, w * r)`.
+///
+/// ```
+/// extern crate autograd as ag;
+///
+/// let ref x = ag::ones(&[2, 12, 3, 4]);
+/// let ref y = ag::pixel_shuffle(x, 2);
+///
+/// assert_eq!(y.eval(&[]).unwrap().shape(), &[2, 3, 6, 8]);
+/// ```
+pub fn pixel_shuffle<A: AsRef<Tensor>>(x: A, upscale_factor: usize) -> Tensor {
+    assert_ne!(
+        upscale_factor, 0,
+        "ag::pixel_shuffle: upscale_factor must be positive"
+    );
+    Tensor::builder()
+        .set_input(x.as_ref())
+        .build(conv_ops::resize::PixelShuffle {
+            factor: upscale_factor,
+            inverse: false,
         })
 }
@@ -0,0 +1,530 @@
+use ndarray;
+use ndarray_ext::{as_contiguous, NdArray};
+use op;
+use tensor::Tensor;
+
+/// Batch normalization over all the axes but `axis`, with running statistics.
+///
+/// Inputs are `x`, `scale`, `shift`, `running_mean` and `running_var`, where the last four
+/// have `x.shape[axis]` elements.
+/// Outputs `y`, and the mean and inverse standard deviation used for the gradient.
+pub struct BatchNorm {
+    pub axis: isize,
+    pub momentum: f32,
+    pub eps: f32,
+}
+
+// Inputs are `gy`, `x`, `scale`, `shift`, `mean` and `inv_std`;
+// outputs are gradients of `x`, `scale` and `shift`.
+pub struct BatchNormGrad {
+    axis: isize,
+}
+
+/// Layer normalization over the axes from `axis` to the last, per sample.
+///
+/// Inputs are `x`, `scale` and `shift`, where the last two have as many elements as
+/// `x.shape[axis..]` and are applied elementwise.
+/// Outputs `y`, and the mean and inverse standard deviation of each sample.
+pub struct LayerNorm {
+    pub axis: isize,
+    pub eps: f32,
+}
+
+/// Group normalization of `(batch, channels, ...)` over each group of channels, per sample.
+///
+/// Inputs are `x`, `scale` and `shift`, where the last two have `channels` elements.
+/// Outputs `y`, and the mean and inverse standard deviation of each sample and group.
+pub struct GroupNorm {
+    pub groups: usize,
+    pub eps: f32,
+}
+
+/// Instance normalization of `(batch, channels, ...)` over each channel, per sample.
+///
+/// Inputs are `x`, `scale` and `shift`, where the last two have `channels` elements.
+/// Outputs `y`, and the mean and inverse standard deviation of each sample and channel.
+pub struct InstanceNorm {
+    pub eps: f32,
+}
+
+// Inputs are `gy`, `x`, `scale`, `shift`, `mean` and `inv_std`;
+// outputs are gradients of `x`, `scale` and `shift`.
+pub struct RowNormGrad {
+    layout: RowLayout,
+}
+
+/// How `LayerNorm`, `GroupNorm` and `InstanceNorm` see their input.
+#[derive(Clone, Copy)]
+enum RowLayout {
+    Layer(isize),
+    Group(usize),
+    Instance,
+}
+
+/// `x` viewed as `rows` contiguous rows of `len` elements normalized independently.
+///
+/// The element `p` of row `r` is transformed with the affine parameter
+/// `((r % groups) * len + p) / repeat`.
+struct Rows {
+    rows: usize,
+    len: usize,
+    groups: usize,
+    repeat: usize,
+}
+
+impl Rows {
+    #[inline]
+    fn num_params(&self) -> usize {
+        self.groups * self.len / self.repeat
+    }
+
+    #[inline]
+    fn param_index(&self, r: usize, p: usize) -> usize {
+        ((r % self.groups) * self.len + p) / self.repeat
+    }
+}
+
+/// `(outer, channels, inner)` sizes of `shape` split at `axis`.
+fn split_at_axis(shape: &[usize], axis: isize, name: &str) -> (usize, usize, usize) {
+    let axis = if axis < 0 {
+        shape.len() as isize + axis
+    } else {
+        axis
+    } as usize;
+    assert!(
+        axis < shape.len(),
+        "ag::{}: Invalid axis {:?} for {:?}",
+        name,
+        axis,
+        shape
+    );
+    (
+        shape[..axis].iter().product(),
+        shape[axis],
+        shape[axis + 1..].iter().product(),
+    )
+}
+
+/// Calls `f(channel, row)` for each contiguous row of a `(outer, c, inner)` array.
+#[inline]
+fn for_each_row<F: FnMut(usize, &[f32])>(x: &[f32], c: usize, inner: usize, mut f: F) {
+    if inner == 0 {
+        return;
+    }
+    for (i, row) in x.chunks(inner).enumerate() {
+        f(i % c, row);
+    }
+}
+
+fn check_channels(op: &str, name: &str, a: &NdArray, c: usize) {
+    assert_eq!(
+        a.len(),
+        c,
+        "ag::{}: `{}` must have {} elements (got {:?})",
+        op,
+        name,
+        c,
+        a.shape()
+    );
+}
+
+impl op::Op for BatchNorm {
+    fn name(&self) -> &str {
+        "BatchNorm"
+    }
+
+    fn compute(&self, mut ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        let training = ctx.is_training();
+        #[cfg(feature = "half")]
+        let stat_nodes = [
+            ctx.grab_input_node(3).clone(),
+            ctx.grab_input_node(4).clone(),
+        ];
+        let xs = unsafe { ctx.grab_assignable_inputs() };
+        let (xs, stats) = xs.split_at_mut(3);
+        let x_shape = xs[0].shape().to_vec();
+        let (_, c, inner) = split_at_axis(&x_shape, self.axis, "batch_norm");
+        for (name, a) in ["scale", "shift"].iter().zip(xs[1..].iter()) {
+            check_channels("batch_norm", name, a, c);
+        }
+        for (name, a) in ["running_mean", "running_var"].iter().zip(stats.iter()) {
+            check_channels("batch_norm", name, a, c);
+        }
+        let x = as_contiguous(xs[0]);
+
+        let (mean, var) = if training {
+            let n = (x.len() / c) as f32;
+            let mut mean = vec![0.; c];
+            for_each_row(&x, c, inner, |ch, row| {
+                mean[ch] += row.iter().sum::<f32>();
+            });
+            mean.iter_mut().for_each(|m| *m /= n);
+            let mut var = vec![0.; c];
+            for_each_row(&x, c, inner, |ch, row| {
+                var[ch] += row
+                    .iter()
+                    .map(|a| (a - mean[ch]) * (a - mean[ch]))
+                    .sum::<f32>();
+            });
+            var.iter_mut().for_each(|v| *v /= n);
+
+            // Running variance is unbiased.
+            let correction = if n > 1. { n / (n - 1.) } else { 1. };
+            let momentum = self.momentum;
+            for (r, &m) in stats[0].iter_mut().zip(&mean) {
+                *r = momentum * *r + (1. - momentum) * m;
+            }
+            for (r, &v) in stats[1].iter_mut().zip(&var) {
+                *r = momentum * *r + (1. - momentum) * v * correction;
+            }
+            #[cfg(feature = "half")]
+            {
+                for (node, stat) in stat_nodes.iter().zip(stats.iter()) {
+                    if let Some(arr) = unsafe { node.get_half_array_mut() } {
+                        arr.assign_f32(stat);
+                    }
+                }
+            }
+            (mean, var)
+        } else {
+            (
+                stats[0].iter().cloned().collect(),
+                stats[1].iter().cloned().collect(),
+            )
+        };
+        let inv_std = var
+            .iter()
+            .map(|v| 1. / (v + self.eps).sqrt())
+            .collect::<Vec<f32>>();
+
+        let (scale, shift) = (as_contiguous(xs[1]), as_contiguous(xs[2]));
+        let mut y = Vec::with_capacity(x.len());
+        for_each_row(&x, c, inner, |ch, row| {
+            let a = scale[ch] * inv_std[ch];
+            let b = shift[ch] - mean[ch] * a;
+            y.extend(row.iter().map(|x| x * a + b));
+        });
+        let y = NdArray::from_shape_vec(x_shape, y).unwrap();
+        let mean = NdArray::from_shape_vec(ndarray::IxDyn(&[c]), mean).unwrap();
+        let inv_std = NdArray::from_shape_vec(ndarray::IxDyn(&[c]), inv_std).unwrap();
+        vec![Ok(y), Ok(mean), Ok(inv_std)]
+    }
+
+    fn grad(&self, gy: &Tensor, xs: &[&Tensor], y: &Tensor) -> Vec<Option<Tensor>> {
+        let mean = ::ops::nth_tensor(y, 1);
+        let inv_std = ::ops::nth_tensor(y, 2);
+        let g = Tensor::builder()
+            .set_inputs(vec![gy, xs[0], xs[1], xs[2], &mean, &inv_std])
+            .build(BatchNormGrad { axis: self.axis });
+        vec![
+            Some(::ops::nth_tensor(&g, 0)),
+            Some(::ops::nth_tensor(&g, 1)),
+            Some(::ops::nth_tensor(&g, 2)),
+            None,
+            None,
+        ]
+    }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape())
+    }
+}
+
+impl op::Op for BatchNormGrad {
+    fn name(&self) -> &str {
+        "BatchNormGrad"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        let xs = ctx.grab_inputs();
+        let (gy, x) = (as_contiguous(xs[0]), as_contiguous(xs[1]));
+        let (scale, mean, inv_std) = (
+            as_contiguous(xs[2]),
+            as_contiguous(xs[4]),
+            as_contiguous(xs[5]),
+        );
+        let (_, c, inner) = split_at_axis(xs[1].shape(), self.axis, "batch_norm");
+        // `x` is empty if `inner` is 0, and `chunks` only needs a positive size.
+        let inner = inner.max(1);
+        let n = (x.len() / c) as f32;
+
+        // gshift = sum(gy), gscale = sum(gy * x_hat)
+        let mut gshift = vec![0.; c];
+        let mut gscale = vec![0.; c];
+        for (i, (gy, x)) in gy.chunks(inner).zip(x.chunks(inner)).enumerate() {
+            let ch = i % c;
+            for (&g, &x) in gy.iter().zip(x) {
+                gshift[ch] += g;
+                gscale[ch] += g * (x - mean[ch]) * inv_std[ch];
+            }
+        }
+
+        let mut gx = Vec::with_capacity(x.len());
+        for (i, (gy, x)) in gy.chunks(inner).zip(x.chunks(inner)).enumerate() {
+            let ch = i % c;
+            let a = scale[ch] * inv_std[ch];
+            if ctx.is_training() {
+                // The batch statistics depend on `x` too.
+                let (gshift, gscale) = (gshift[ch] / n, gscale[ch] / n);
+                gx.extend(
+                    gy.iter()
+                        .zip(x)
+                        .map(|(&g, &x)| a * (g - gshift - (x - mean[ch]) * inv_std[ch] * gscale)),
+                );
+            } else {
+                gx.extend(gy.iter().map(|&g| a * g));
+            }
+        }
+
+        let gx = NdArray::from_shape_vec(xs[1].shape(), gx).unwrap();
+        let gscale = NdArray::from_shape_vec(xs[2].shape(), gscale).unwrap();
+        let gshift = NdArray::from_shape_vec(xs[3].shape(), gshift).unwrap();
+        vec![Ok(gx), Ok(gscale), Ok(gshift)]
+    }
+
+    fn grad(&self, _: &Tensor, _: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
+        vec![None; 6]
+    }
+}
+
+impl RowLayout {
+    fn name(&self) -> &'static str {
+        match *self {
+            RowLayout::Layer(_) => "layer_norm",
+            RowLayout::Group(_) => "group_norm",
+            RowLayout::Instance => "instance_norm",
+        }
+    }
+
+    fn rows(&self, shape: &[usize]) -> Rows {
+        match *self {
+            RowLayout::Layer(axis) => {
+                let (rows, c, inner) = split_at_axis(shape, axis, self.name());
+                Rows {
+                    rows,
+                    len: c * inner,
+                    groups: 1,
+                    repeat: 1,
+                }
+            }
+            RowLayout::Group(_) | RowLayout::Instance => {
+                assert!(
+                    shape.len() >= 2,
+                    "ag::{}: Input must be at least 2D (got {:?})",
+                    self.name(),
+                    shape
+                );
+                let (batch, c) = (shape[0], shape[1]);
+                let groups = match *self {
+                    RowLayout::Group(groups) => {
+                        assert_ne!(groups, 0, "ag::group_norm: groups must be positive");
+                        groups
+                    }
+                    _ => c,
+                };
+                assert_eq!(
+                    c % groups,
+                    0,
+                    "ag::{}: Number of channels ({:?}) must be divisible by groups ({:?})",
+                    self.name(),
+                    c,
+                    groups
+                );
+                let inner = shape[2..].iter().product::<usize>();
+                Rows {
+                    rows: batch * groups,
+                    len: c / groups * inner,
+                    groups,
+                    repeat: inner,
+                }
+            }
+        }
+    }
+
+    /// Returns `y`, and the mean and inverse standard deviation of each row.
+    fn compute(&self, xs: &[&NdArray], eps: f32) -> op::ComputeResult {
+        let x_shape = xs[0].shape();
+        let rows = self.rows(x_shape);
+        for (name, a) in ["scale", "shift"].iter().zip(xs[1..].iter()) {
+            check_channels(self.name(), name, a, rows.num_params());
+        }
+        let (x, scale, shift) = (
+            as_contiguous(xs[0]),
+            as_contiguous(xs[1]),
+            as_contiguous(xs[2]),
+        );
+
+        let mut mean = Vec::with_capacity(rows.rows);
+        let mut inv_std = Vec::with_capacity(rows.rows);
+        let mut y = Vec::with_capacity(x.len());
+        for (r, row) in x.chunks(rows.len).enumerate() {
+            let n = row.len() as f32;
+            let m = row.iter().sum::<f32>() / n;
+            let var = row.iter().map(|a| (a - m) * (a - m)).sum::<f32>() / n;
+            let s = 1. / (var + eps).sqrt();
+            y.extend(row.iter().enumerate().map(|(p, a)| {
+                let q = rows.param_index(r, p);
+                (a - m) * s * scale[q] + shift[q]
+            }));
+            mean.push(m);
+            inv_std.push(s);
+        }
+
+        let y = NdArray::from_shape_vec(x_shape, y).unwrap();
+        let mean = NdArray::from_shape_vec(ndarray::IxDyn(&[rows.rows]), mean).unwrap();
+        let inv_std = NdArray::from_shape_vec(ndarray::IxDyn(&[rows.rows]), inv_std).unwrap();
+        vec![Ok(y), Ok(mean), Ok(inv_std)]
+    }
+
+    fn grad(&self, gy: &Tensor, xs: &[&Tensor], y: &Tensor) -> Vec<Option<Tensor>> {
+        let mean = ::ops::nth_tensor(y, 1);
+        let inv_std = ::ops::nth_tensor(y, 2);
+        let g = Tensor::builder()
+            .set_inputs(vec![gy, xs[0], xs[1], xs[2], &mean, &inv_std])
+            .build(RowNormGrad { layout: *self });
+        vec![
+            Some(::ops::nth_tensor(&g, 0)),
+            Some(::ops::nth_tensor(&g, 1)),
+            Some(::ops::nth_tensor(&g, 2)),
+        ]
+    }
+}
+
+impl op::Op for LayerNorm {
+    fn name(&self) -> &str {
+        "LayerNorm"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        RowLayout::Layer(self.axis).compute(&ctx.grab_inputs(), self.eps)
+    }
+
+    fn grad(&self, gy: &Tensor, xs: &[&Tensor], y: &Tensor) -> Vec<Option<Tensor>> {
+        RowLayout::Layer(self.axis).grad(gy, xs, y)
+    }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape())
+    }
+}
+
+impl op::Op for GroupNorm {
+    fn name(&self) -> &str {
+        "GroupNorm"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        RowLayout::Group(self.groups).compute(&ctx.grab_inputs(), self.eps)
+    }
+
+    fn grad(&self, gy: &Tensor, xs: &[&Tensor], y: &Tensor) -> Vec<Option<Tensor>> {
+        RowLayout::Group(self.groups).grad(gy, xs, y)
+    }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape())
+    }
+}
+
+impl op::Op for InstanceNorm {
+    fn name(&self) -> &str {
+        "InstanceNorm"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        RowLayout::Instance.compute(&ctx.grab_inputs(), self.eps)
+    }
+
+    fn grad(&self, gy: &Tensor, xs: &[&Tensor], y: &Tensor) -> Vec<Option<Tensor>> {
+        RowLayout::Instance.grad(gy, xs, y)
+    }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape())
+    }
+}
+
+impl op::Op for RowNormGrad {
+    fn name(&self) -> &str {
+        "RowNormGrad"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        let xs = ctx.grab_inputs();
+        let rows = self.layout.rows(xs[1].shape());
+        let (gy, x, scale) = (
+            as_contiguous(xs[0]),
+            as_contiguous(xs[1]),
+            as_contiguous(xs[2]),
+        );
+        let (mean, inv_std) = (as_contiguous(xs[4]), as_contiguous(xs[5]));
+
+        let mut gscale = vec![0.; rows.num_params()];
+        let mut gshift = vec![0.; rows.num_params()];
+        let mut gx = Vec::with_capacity(x.len());
+        let mut gx_hat = vec![0.; rows.len];
+        for (r, (gy, x)) in gy.chunks(rows.len).zip(x.chunks(rows.len)).enumerate() {
+            let (m, s) = (mean[r], inv_std[r]);
+            // Means of gx_hat and gx_hat * x_hat over the row
+            let (mut g_mean, mut gx_mean) = (0., 0.);
+            for (p, (&g, &x)) in gy.iter().zip(x).enumerate() {
+                let q = rows.param_index(r, p);
+                let x_hat = (x - m) * s;
+                gscale[q] += g * x_hat;
+                gshift[q] += g;
+                gx_hat[p] = g * scale[q];
+                g_mean += gx_hat[p];
+                gx_mean += gx_hat[p] * x_hat;
+            }
+            let n = rows.len as f32;
+            let (g_mean, gx_mean) = (g_mean / n, gx_mean / n);
+            gx.extend(
+                gx_hat
+                    .iter()
+                    .zip(x)
+                    .map(|(&g, &x)| s * (g - g_mean - (x - m) * s * gx_mean)),
+            );
+        }
+
+        let gx = NdArray::from_shape_vec(xs[1].shape(), gx).unwrap();
+        let gscale = NdArray::from_shape_vec(xs[2].shape(), gscale).unwrap();
+        let gshift = NdArray::from_shape_vec(xs[3].shape(), gshift).unwrap();
+        vec![Ok(gx), Ok(gscale), Ok(gshift)]
+    }
+
+    fn grad(&self, _: &Tensor, _: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
+        vec![None; 6]
+    }
+}
+
+#[test]
+fn test_group_norm_param_index() {
+    // (batch: 2, channels: 4, spatial: 3) in 2 groups
+    let rows = RowLayout::Group(2).rows(&[2, 4, 3]);
+    assert_eq!((rows.rows, rows.len, rows.num_params()), (4, 6, 4));
+    let channels = (0..rows.rows)
+        .map(|r| {
+            (0..rows.len)
+                .map(|p| rows.param_index(r, p))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(channels[0], [0, 0, 0, 1, 1, 1]);
+    assert_eq!(channels[1], [2, 2, 2, 3, 3, 3]);
+    assert_eq!(channels[2], channels[0]);
+
+    // Instance normalization
+    let rows = RowLayout::Instance.rows(&[2, 4, 3]);
+    assert_eq!((rows.rows, rows.len, rows.num_params()), (8, 3, 4));
+    assert_eq!(rows.param_index(5, 2), 1);
+
+    let rows = RowLayout::Layer(-2).rows(&[2, 4, 3]);
+    assert_eq!((rows.rows, rows.len, rows.num_params()), (2, 12, 12));
+    assert_eq!(rows.param_index(1, 7), 7);
+}
+
+#[test]
+#[should_panic(expected = "ag::group_norm: groups must be positive")]
+fn test_group_norm_rejects_zero_groups() {
+    RowLayout::Group(0).rows(&[2, 4, 3]);
+}
@@ -1,5 +1,6 @@
 use ndarray_ext::{self, ArrRng};
 use op;
+use ops;
 use rand::Rng;
 use tensor::Tensor;
 
@@ -62,6 +63,21 @@ impl<R> Bernoulli<R> {
     }
 }
 
+/// Zeroes elements of `x` with probability `rate` and scales the others by `1 / (1 - rate)`
+/// in training mode, or outputs `x` as is in inference mode.
+///
+/// The second output is the mask that `x` was multiplied by.
+pub struct Dropout<R> {
+    pub arr_rng: ArrRng<R>,
+    pub rate: f64,
+}
+
+impl<R> Dropout<R> {
+    pub fn new(arr_rng: ArrRng<R>, rate: f64) -> Self {
+        Self { arr_rng, rate }
+    }
+}
+
 pub struct Exponential<R> {
     pub arr_rng: ArrRng<R>,
     pub lambda: f64,
@@ -123,6 +139,10 @@ impl<R: Rng> op::Op for RandomNormal<R> {
     fn grad(&self, _: &Tensor, _: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
         vec![None]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(ops::const_gen_ops::static_shape_from_shape_tensor(xs[0]))
+    }
 }
 
 impl<R: Rng> op::Op for RandomUniform<R> {
@@ -143,6 +163,10 @@ impl<R: Rng> op::Op for RandomUniform<R> {
     fn grad(&self, _: &Tensor, _: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
         vec![None]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(ops::const_gen_ops::static_shape_from_shape_tensor(xs[0]))
+    }
 }
 
 impl<R: Rng> op::Op for StandardNormal<R> {
@@ -159,6 +183,10 @@ impl<R: Rng> op::Op for StandardNormal<R> {
     fn grad(&self, _: &Tensor, _: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
         vec![None]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(ops::const_gen_ops::static_shape_from_shape_tensor(xs[0]))
+    }
 }
 
 impl<R: Rng> op::Op for StandardUniform<R> {
@@ -175,6 +203,10 @@ impl<R: Rng> op::Op for StandardUniform<R> {
     fn grad(&self, _: &Tensor, _: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
         vec![None]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(ops::const_gen_ops::static_shape_from_shape_tensor(xs[0]))
+    }
 }
 
 impl<R: Rng> op::Op for Bernoulli<R> {
@@ -191,6 +223,35 @@ impl<R: Rng> op::Op for Bernoulli<R> {
     fn grad(&self, _: &Tensor, _: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
         vec![None]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(ops::const_gen_ops::static_shape_from_shape_tensor(xs[0]))
+    }
+}
+
+impl<R: Rng> op::Op for Dropout<R> {
+    fn name(&self) -> &str {
+        "Dropout"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        let x = ctx.grab_inputs()[0];
+        if !ctx.is_training() || self.rate == 0. {
+            return vec![Ok(x.clone()), Ok(ndarray_ext::array_gen::ones(x.shape()))];
+        }
+        let keep = 1. - self.rate;
+        let mut mask = self.arr_rng.bernoulli(x.shape(), keep);
+        mask.mapv_inplace(|m| m / keep as f32);
+        vec![Ok(x * &mask), Ok(mask)]
+    }
+
+    fn grad(&self, gy: &Tensor, _: &[&Tensor], y: &Tensor) -> Vec<Option<Tensor>> {
+        vec![Some(gy * ops::nth_tensor(y, 1))]
+    }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape())
+    }
 }
 
 impl<R: Rng> op::Op for Exponential<R> {
@@ -207,6 +268,10 @@ impl<R: Rng> op::Op for Exponential<R> {
     fn grad(&self, _: &Tensor, _: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
         vec![None]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(ops::const_gen_ops::static_shape_from_shape_tensor(xs[0]))
+    }
 }
 
 impl<R: Rng> op::Op for LogNormal<R> {
@@ -227,6 +292,10 @@ impl<R: Rng> op::Op for LogNormal<R> {
     fn grad(&self, _: &Tensor, _: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
         vec![None]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(ops::const_gen_ops::static_shape_from_shape_tensor(xs[0]))
+    }
 }
 
 impl<R: Rng> op::Op for Gamma<R> {
@@ -247,4 +316,8 @@ impl<R: Rng> op::Op for Gamma<R> {
     fn grad(&self, _: &Tensor, _: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
         vec![None]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(ops::const_gen_ops::static_shape_from_shape_tensor(xs[0]))
+    }
 }
@@ -39,6 +39,52 @@ pub struct ArgMax {
     pub keep_dim: bool,
 }
 
+/// Cumulative sum along `axis`.
+///
+/// `exclusive` excludes the current element and `reverse` scans from the end.
+pub struct Cumsum {
+    pub axis: isize,
+    pub exclusive: bool,
+    pub reverse: bool,
+}
+
+/// Cumulative product along `axis`; flags are the same as `Cumsum`.
+pub struct Cumprod {
+    pub axis: isize,
+    pub exclusive: bool,
+    pub reverse: bool,
+}
+
+/// Transposed Jacobian of `Cumprod` applied to `gy` (1st input) at `x` (2nd input).
+///
+/// Computed without dividing by `x`, so zeros in `x` are fine.
+pub struct CumprodGrad {
+    axis: isize,
+    exclusive: bool,
+    reverse: bool,
+}
+
+/// Jacobian of `Cumprod` applied to `v` (1st input) at `x` (2nd input).
+pub struct CumprodGradGrad {
+    axis: isize,
+    exclusive: bool,
+    reverse: bool,
+}
+
+pub struct ArgMin {
+    pub axis: isize,
+    pub keep_dim: bool,
+}
+
+/// Sorts each lane along `axis` and keeps the first `k` (all if `None`).
+///
+/// Outputs are the values and their indices in `x`.
+pub struct Sort {
+    pub axis: isize,
+    pub k: Option<usize>,
+    pub descending: bool,
+}
+
 pub struct ReduceGradCommon {
     pub should_make_broadcast_dims: bool,
     pub sparse_axes: bool,
@@ -265,78 +311,404 @@ fn min_max_grad(
     vec![Some(ops::mul_inplace(eq, &gy)), None]
 }
 
+// cf. https://github.com/tensorflow/compiler/tf2xla/kernels/index_ops.cc
+fn compute_argmax(x: &NdArray, axis: isize, keep_dim: bool) -> NdArray {
+    let axis = ndarray_ext::normalize_negative_axis(axis, x.ndim());
+    let x_shape = x.shape();
+
+    // 1. Make binary mask tensor (maximums are 1s)
+    let mut mask = {
+        let max_fn = f32::max;
+        let maxed = x.fold_axis(ndarray::Axis(axis), f32::MIN, move |&a, &b| max_fn(a, b));
+        let mut mask = x.clone();
+        let mut found = ndarray::Array::<bool, ndarray::IxDyn>::from_elem(maxed.shape(), false);
+        for mut sub in mask.axis_iter_mut(ndarray::Axis(axis)) {
+            ndarray::Zip::from(&mut sub)
+                .and(&mut found)
+                .and(&maxed)
+                .apply(|r, f, m| {
+                    let z = r == m && !*f;
+                    *f = z;
+                    *r = (z as i32) as f32;
+                });
+        }
+        mask
+    };
+
+    // 2. Reshape the mask to 2-ranked. e.g. (2, 3, 4) -> (8, 3) (let `axis` be 1)
+    let mask = {
+        // move the `axis` to first, and put remaining together on the 2nd axis
+        let reduction_len = x_shape[axis];
+        ndarray_ext::roll_axis(&mut mask, ndarray::Axis(0), ndarray::Axis(axis));
+        let shape2d = (reduction_len, mask.len() / reduction_len);
+        let mut mask = mask.into_shape(shape2d).unwrap();
+        mask.swap_axes(0, 1);
+        mask
+    };
+
+    // 3. Make the indices (vertical vector)
+    let indices = {
+        let cols = mask.shape()[1];
+        ndarray::Array::range(0., cols as f32, 1.)
+            .into_shape((cols, 1))
+            .unwrap()
+    };
+
+    // 4. Dot product between mask and index-tensor
+    let mat = mask.dot(&indices);
+
+    // 5. Reshape it
+    let mut final_shape = x_shape.to_vec();
+    if keep_dim {
+        final_shape[axis] = 1;
+    } else {
+        final_shape.remove(axis);
+    }
+    // unwrap is safe (95% confidence...)
+    mat.into_dyn()
+        .into_shape(ndarray::IxDyn(final_shape.as_slice()))
+        .unwrap()
+}
+
+fn arg_reduce_static_shape(xs: &[&Tensor], axis: isize, keep_dim: bool) -> op::StaticShapeResult {
+    let mut shape = match xs[0].static_shape() {
+        Some(a) => a,
+        None => return Ok(None),
+    };
+    let normalized = ndarray_ext::normalize_negative_axis(axis, shape.len());
+    if normalized >= shape.len() {
+        return Err(format!("Axis {} is out of range for {:?}", axis, shape));
+    }
+    if keep_dim {
+        shape[normalized] = 1;
+    } else {
+        shape.remove(normalized);
+    }
+    Ok(Some(shape))
+}
+
 impl op::Op for ArgMax {
     fn name(&self) -> &str {
         "ArgMax"
     }
 
-    // cf. https://github.com/tensorflow/compiler/tf2xla/kernels/index_ops.cc
     fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
-        let xs = ctx.grab_inputs();
-        let x = xs[0];
-        let axis = ndarray_ext::normalize_negative_axis(self.axis, x.ndim());
-        let x_shape = x.shape();
+        let x = ctx.grab_inputs()[0];
+        vec![Ok(compute_argmax(x, self.axis, self.keep_dim))]
+    }
+
+    fn grad(&self, _: &Tensor, _: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
+        vec![None]
+    }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        arg_reduce_static_shape(xs, self.axis, self.keep_dim)
+    }
+}
+
+impl op::Op for ArgMin {
+    fn name(&self) -> &str {
+        "ArgMin"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        let x = ctx.grab_inputs()[0];
+        vec![Ok(compute_argmax(&x.map(|a| -a), self.axis, self.keep_dim))]
+    }
+
+    fn grad(&self, _: &Tensor, _: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
+        vec![None]
+    }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        arg_reduce_static_shape(xs, self.axis, self.keep_dim)
+    }
+}
 
-        // 1. Make binary mask tensor (maximums are 1s)
-        let mut mask = {
-            let max_fn = f32::max;
-            let maxed = x.fold_axis(ndarray::Axis(axis), f32::MIN, move |&a, &b| max_fn(a, b));
-            let mut mask = x.clone();
-            let mut found = ndarray::Array::<bool, ndarray::IxDyn>::from_elem(maxed.shape(), false);
-            for mut sub in mask.axis_iter_mut(ndarray::Axis(axis)) {
-                ndarray::Zip::from(&mut sub)
-                    .and(&mut found)
-                    .and(&maxed)
-                    .apply(|r, f, m| {
-                        let z = r == m && !*f;
-                        *f = z;
-                        *r = (z as i32) as f32;
-                    });
+impl op::Op for Sort {
+    fn name(&self) -> &str {
+        "Sort"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        let x = ctx.grab_inputs()[0];
+        let axis = ndarray_ext::normalize_negative_axis(self.axis, x.ndim());
+        assert!(
+            axis < x.ndim(),
+            "Sort axis {} is out of range for {:?}",
+            self.axis,
+            x.shape()
+        );
+        let len = x.shape()[axis];
+        let k = self.k.unwrap_or(len);
+        assert!(k <= len, "Can't take top {} of the axis of length {}", k, len);
+
+        let mut y_shape = x.shape().to_vec();
+        y_shape[axis] = k;
+        let mut values = NdArray::zeros(y_shape.as_slice());
+        let mut indices = NdArray::zeros(y_shape.as_slice());
+        let axis = ndarray::Axis(axis);
+        let mut order = (0..len).collect::<Vec<_>>();
+        for ((x, mut v), mut i) in x
+            .lanes(axis)
+            .into_iter()
+            .zip(values.lanes_mut(axis))
+            .zip(indices.lanes_mut(axis))
+        {
+            for (n, o) in order.iter_mut().enumerate() {
+                *o = n;
             }
-            mask
-        };
+            // Stable, so ties keep their original order.
+            // NaN comes last, or first if descending.
+            if self.descending {
+                order.sort_by(|&a, &b| ops::cmp_nan_last(&x[b], &x[a]));
+            } else {
+                order.sort_by(|&a, &b| ops::cmp_nan_last(&x[a], &x[b]));
+            }
+            for (j, &o) in order[..k].iter().enumerate() {
+                v[j] = x[o];
+                i[j] = o as f32;
+            }
+        }
+        vec![Ok(values), Ok(indices)]
+    }
 
-        // 2. Reshape the mask to 2-ranked. e.g. (2, 3, 4) -> (8, 3) (let `axis` be 1)
-        let mask = {
-            // move the `axis` to first, and put remaining together on the 2nd axis
-            let reduction_len = x_shape[axis];
-            ndarray_ext::roll_axis(&mut mask, ndarray::Axis(0), ndarray::Axis(axis));
-            let shape2d = (reduction_len, mask.len() / reduction_len);
-            let mut mask = mask.into_shape(shape2d).unwrap();
-            mask.swap_axes(0, 1);
-            mask
-        };
+    fn grad(&self, gy: &Tensor, xs: &[&Tensor], y: &Tensor) -> Vec<Option<Tensor>> {
+        // Each value goes back to the position it was taken from.
+        let gx = Tensor::builder()
+            .set_inputs(vec![&ops::nth_tensor(y, 1), gy, xs[0]])
+            .set_shape(xs[0].shape())
+            .build(ops::array_ops::PutAlongAxis { axis: self.axis });
+        vec![Some(gx)]
+    }
 
-        // 3. Make the indices (vertical vector)
-        let indices = {
-            let cols = mask.shape()[1];
-            ndarray::Array::range(0., cols as f32, 1.)
-                .into_shape((cols, 1))
-                .unwrap()
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        let mut shape = match xs[0].static_shape() {
+            Some(a) => a,
+            None => return Ok(None),
         };
+        let axis = ndarray_ext::normalize_negative_axis(self.axis, shape.len());
+        if axis >= shape.len() {
+            return Err(format!(
+                "Axis {} is out of range for {:?}",
+                self.axis, shape
+            ));
+        }
+        if let Some(k) = self.k {
+            if shape[axis] != -1 && (k as isize) > shape[axis] {
+                return Err(format!(
+                    "Can't take top {} along axis {} of {:?}",
+                    k, self.axis, shape
+                ));
+            }
+            shape[axis] = k as isize;
+        }
+        Ok(Some(shape))
+    }
+}
+
+/// Calls `f(x_lane, y_lane)` for each 1-D lane along `axis` of `xs` and `y`, all of
+/// which have the same shape. Lanes are viewed from the end if `reverse`.
+fn scan_lanes<F>(xs: &[&NdArray], y: &mut NdArray, axis: isize, reverse: bool, mut f: F)
+where
+    F: FnMut(&[ndarray::ArrayView1<f32>], ndarray::ArrayViewMut1<f32>),
+{
+    let axis = ndarray::Axis(ndarray_ext::normalize_negative_axis(axis, y.ndim()));
+    assert!(
+        axis.index() < y.ndim(),
+        "Scan axis {} is out of range for {:?}",
+        axis.index(),
+        y.shape()
+    );
+    let mut xs = xs.iter().map(|x| x.view()).collect::<Vec<_>>();
+    let mut y = y.view_mut();
+    if reverse {
+        for x in xs.iter_mut() {
+            x.invert_axis(axis);
+        }
+        y.invert_axis(axis);
+    }
+    let mut x_lanes = xs
+        .iter()
+        .map(|x| x.lanes(axis).into_iter())
+        .collect::<Vec<_>>();
+    let mut buf = Vec::with_capacity(xs.len());
+    for y_lane in y.lanes_mut(axis) {
+        buf.clear();
+        buf.extend(x_lanes.iter_mut().map(|it| it.next().unwrap()));
+        f(&buf, y_lane);
+    }
+}
+
+macro_rules! impl_scan_forward {
+    ($name:ident, $op:tt, $init:expr) => {
+        fn $name(x: &NdArray, axis: isize, exclusive: bool, reverse: bool) -> NdArray {
+            let mut y = NdArray::zeros(x.shape());
+            scan_lanes(&[x], &mut y, axis, reverse, |x, mut y| {
+                let mut acc = $init;
+                for (y, &x) in y.iter_mut().zip(x[0].iter()) {
+                    if exclusive {
+                        *y = acc;
+                        acc $op x;
+                    } else {
+                        acc $op x;
+                        *y = acc;
+                    }
+                }
+            });
+            y
+        }
+    };
+}
+
+impl_scan_forward!(compute_cumsum, +=, 0.);
+impl_scan_forward!(compute_cumprod, *=, 1.);
+
+impl op::Op for Cumsum {
+    fn name(&self) -> &str {
+        "Cumsum"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        let x = ctx.grab_inputs()[0];
+        vec![Ok(compute_cumsum(x, self.axis, self.exclusive, self.reverse))]
+    }
+
+    fn grad(&self, gy: &Tensor, _: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
+        // Each x contributes to the elements after it, i.e. scans gy the other way.
+        let gx = Tensor::builder()
+            .set_input(gy)
+            .set_shape(gy.shape())
+            .build(Cumsum {
+                axis: self.axis,
+                exclusive: self.exclusive,
+                reverse: !self.reverse,
+            });
+        vec![Some(gx)]
+    }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape())
+    }
+}
+
+impl op::Op for Cumprod {
+    fn name(&self) -> &str {
+        "Cumprod"
+    }
 
-        // 4. Dot product between mask and index-tensor
-        let mat = mask.dot(&indices);
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        let x = ctx.grab_inputs()[0];
+        vec![Ok(compute_cumprod(x, self.axis, self.exclusive, self.reverse))]
+    }
+
+    fn grad(&self, gy: &Tensor, xs: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
+        let gx = Tensor::builder()
+            .set_inputs(vec![gy, xs[0]])
+            .set_shape(xs[0].shape())
+            .build(CumprodGrad {
+                axis: self.axis,
+                exclusive: self.exclusive,
+                reverse: self.reverse,
+            });
+        vec![Some(gx)]
+    }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape())
+    }
+}
 
-        // 5. Reshape it
-        let result = {
-            let mut final_shape = x_shape.to_vec();
-            if self.keep_dim {
-                final_shape[axis] = 1;
+impl op::Op for CumprodGrad {
+    fn name(&self) -> &str {
+        "CumprodGrad"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        let xs = ctx.grab_inputs();
+        let (gy, x) = (xs[0], xs[1]);
+        let mut gx = NdArray::zeros(x.shape());
+        let exclusive = self.exclusive;
+        scan_lanes(&[gy, x], &mut gx, self.axis, self.reverse, |lanes, mut gx| {
+            let (gy, x) = (&lanes[0], &lanes[1]);
+            let n = x.len();
+            // gx[k] = prod(x[..k]) * sum_{i >= k} gy[i] * prod(x[k+1..=i]) (inclusive),
+            // where the suffix sum is accumulated from the end.
+            let mut p = 1.;
+            for k in 0..n {
+                gx[k] = p;
+                p *= x[k];
+            }
+            let mut s = 0.;
+            if exclusive {
+                for k in (0..n).rev() {
+                    gx[k] *= s;
+                    s = gy[k] + x[k] * s;
+                }
             } else {
-                final_shape.remove(axis);
+                let mut x_next = 0.;
+                for k in (0..n).rev() {
+                    s = gy[k] + x_next * s;
+                    gx[k] *= s;
+                    x_next = x[k];
+                }
             }
-            // unwrap is safe (95% confidence...)
-            mat.into_dyn()
-                .into_shape(ndarray::IxDyn(final_shape.as_slice()))
-                .unwrap()
-        };
+        });
+        vec![Ok(gx)]
+    }
 
-        vec![Ok(result)]
+    fn grad(&self, ggx: &Tensor, xs: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
+        // NOTE: The gradient of `x` is not propagated.
+        let ggy = Tensor::builder()
+            .set_inputs(vec![ggx, xs[1]])
+            .set_shape(xs[1].shape())
+            .build(CumprodGradGrad {
+                axis: self.axis,
+                exclusive: self.exclusive,
+                reverse: self.reverse,
+            });
+        vec![Some(ggy), None]
     }
+}
 
-    fn grad(&self, _: &Tensor, _: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
-        vec![None]
+impl op::Op for CumprodGradGrad {
+    fn name(&self) -> &str {
+        "CumprodGradGrad"
+    }
+
+    fn compute(&self, ctx: ::runtime::OpComputeContext) -> op::ComputeResult {
+        let xs = ctx.grab_inputs();
+        let (v, x) = (xs[0], xs[1]);
+        let mut y = NdArray::zeros(x.shape());
+        let exclusive = self.exclusive;
+        scan_lanes(&[v, x], &mut y, self.axis, self.reverse, |lanes, mut y| {
+            let (v, x) = (&lanes[0], &lanes[1]);
+            // Product rule: d(p * x[i]) = dp * x[i] + p * v[i]
+            let (mut p, mut dp) = (1., 0.);
+            for i in 0..x.len() {
+                if exclusive {
+                    y[i] = dp;
+                }
+                dp = dp * x[i] + p * v[i];
+                p *= x[i];
+                if !exclusive {
+                    y[i] = dp;
+                }
+            }
+        });
+        vec![Ok(y)]
+    }
+
+    fn grad(&self, gy: &Tensor, xs: &[&Tensor], _: &Tensor) -> Vec<Option<Tensor>> {
+        let gv = Tensor::builder()
+            .set_inputs(vec![gy, xs[1]])
+            .set_shape(xs[1].shape())
+            .build(CumprodGrad {
+                axis: self.axis,
+                exclusive: self.exclusive,
+                reverse: self.reverse,
+            });
+        vec![Some(gv), None]
     }
 }
 
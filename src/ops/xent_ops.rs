@@ -29,6 +29,10 @@ impl op::Op for LogSoftmax {
         let ref mul = sm * sum;
         vec![Some(gy - mul)]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        Ok(xs[0].static_shape())
+    }
 }
 
 impl op::Op for SigmoidCrossEntropy {
@@ -63,6 +67,14 @@ impl op::Op for SigmoidCrossEntropy {
 
         vec![Some(gx1), Some(gx2)]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        match (xs[0].static_shape(), xs[1].static_shape()) {
+            (Some(a), Some(b)) => ops::array_ops::merge_static_shapes(a, b).map(Some),
+            (a, None) => Ok(a),
+            (None, b) => Ok(b),
+        }
+    }
 }
 
 impl op::Op for SparseSoftmaxCrossEntropy {
@@ -117,6 +129,17 @@ impl op::Op for SparseSoftmaxCrossEntropy {
 
         vec![Some(gx1), Some(gx2)]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        let x_shape = match xs[0].static_shape() {
+            Some(a) => a,
+            None => return Ok(None),
+        };
+        if x_shape.len() != 2 {
+            return Err(format!("First input should be a matrix: {:?}", x_shape));
+        }
+        Ok(Some(vec![x_shape[0], 1]))
+    }
 }
 
 impl op::Op for SparseSoftmaxCrossEntropyGrad {
@@ -181,4 +204,16 @@ impl op::Op for SoftmaxCrossEntropy {
 
         vec![Some(gx1), Some(gx2)]
     }
+
+    fn static_shape(&self, xs: &[&Tensor]) -> op::StaticShapeResult {
+        // `t * log_x` in `compute` broadcasts
+        let x_shape = match (xs[0].static_shape(), xs[1].static_shape()) {
+            (Some(a), Some(b)) => ops::binary_ops::broadcast_static_shapes(a, b)?,
+            _ => return Ok(None),
+        };
+        if x_shape.len() != 2 {
+            return Err(format!("Inputs should be matrices: {:?}", x_shape));
+        }
+        Ok(Some(vec![x_shape[0]]))
+    }
 }
@@ -1,12 +1,41 @@
+use backend::{Backend, CpuBackend};
 use ndarray;
 use ndarray_ext::NdArray;
 use op;
+use std::cell::Cell;
 use std::collections::btree_map::Entry;
 use std::collections::BTreeMap;
 use std::mem;
 use std::rc::Rc;
 use tensor::Tensor;
 
+thread_local!(static DEFAULT_TRAINING: Cell<bool> = Cell::new(true));
+
+/// Sets the training mode of the evaluations that follow in the current thread
+/// (`true` by default).
+///
+/// This switches the whole graph at once, e.g. to run a test set;
+/// `Eval::set_training` overrides it for a single evaluation.
+///
+/// ```
+/// extern crate autograd as ag;
+///
+/// let ref x = ag::ones(&[4, 3]);
+/// let ref y = ag::dropout(x, 0.5);
+///
+/// ag::set_training(false);
+/// assert_eq!(y.eval(&[]), x.eval(&[]));
+/// ag::set_training(true);
+/// ```
+pub fn set_training(training: bool) {
+    DEFAULT_TRAINING.with(|t| t.set(training));
+}
+
+/// Returns the training mode set by `set_training`.
+pub fn is_training() -> bool {
+    DEFAULT_TRAINING.with(|t| t.get())
+}
+
 /// Helper structure for batched evaluation.
 ///
 /// Use this in case `ag::eval` doesn't help.
@@ -27,12 +56,34 @@ use tensor::Tensor;
 /// ```
 pub struct Eval<'a> {
     buf: Vec<&'a Tensor>,
+    backend: &'a dyn Backend,
+    training: bool,
 }
 
 impl<'t> Eval<'t> {
     /// Instantiates a new evaluation session.
     pub fn new() -> Self {
-        Eval { buf: Vec::new() }
+        Eval {
+            buf: Vec::new(),
+            backend: &CpuBackend,
+            training: is_training(),
+        }
+    }
+
+    /// Sets the backend used in this evaluation (`CpuBackend` by default).
+    pub fn set_backend(&mut self, backend: &'t dyn Backend) -> &mut Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Sets whether this evaluation is in training mode (`ag::is_training()` by default).
+    ///
+    /// Some ops behave differently in inference mode;
+    /// e.g. `ag::batch_norm_with_stats` normalizes with its running statistics
+    /// and `ag::dropout` does nothing.
+    pub fn set_training(&mut self, training: bool) -> &mut Self {
+        self.training = training;
+        self
     }
 
     /// Appends a tensor to the back of the evaluation targets.
@@ -57,7 +108,7 @@ impl<'t> Eval<'t> {
     where
         F: IntoIterator<Item = &'tpl (&'tsr Tensor, &'arr ndarray::Array<f32, ndarray::IxDyn>)>,
     {
-        eval(&self.buf, feed)
+        eval_with_backend(&self.buf, feed, self.backend, self.training)
     }
 }
 
@@ -65,12 +116,30 @@ impl<'t> Eval<'t> {
 pub struct OpComputeContext<'a, 'b> {
     node: &'a Tensor,
     xs: Vec<&'b NdArray>,
+    backend: &'b dyn Backend,
+    training: bool,
 }
 
 impl<'a, 'b> OpComputeContext<'a, 'b> {
     #[inline]
     pub fn new(node: &'a Tensor, xs: Vec<&'b NdArray>) -> Self {
-        OpComputeContext { node, xs }
+        OpComputeContext {
+            node,
+            xs,
+            backend: &CpuBackend,
+            training: true,
+        }
+    }
+
+    #[inline]
+    pub fn backend(&self) -> &dyn Backend {
+        self.backend
+    }
+
+    /// Returns `false` if this is evaluated in inference mode (see `Eval::set_training`).
+    #[inline]
+    pub fn is_training(&self) -> bool {
+        self.training
     }
 
     #[inline]
@@ -180,29 +249,43 @@ impl<'a> Tensor {
 /// ```
 // FIXME: Annoying lifetime params
 pub fn eval<'a, 'b: 'a, 'c: 'a, T, U>(tensors: &[T], feeds: U) -> Vec<Option<NdArray>>
+where
+    T: AsRef<Tensor>,
+    U: IntoIterator<Item = &'a (&'b Tensor, &'c ndarray::Array<f32, ndarray::IxDyn>)>,
+{
+    eval_with_backend(tensors, feeds, &CpuBackend, is_training())
+}
+
+fn eval_with_backend<'a, 'b: 'a, 'c: 'a, T, U>(
+    tensors: &[T],
+    feeds: U,
+    backend: &dyn Backend,
+    training: bool,
+) -> Vec<Option<NdArray>>
 where
     T: AsRef<Tensor>,
     U: IntoIterator<Item = &'a (&'b Tensor, &'c ndarray::Array<f32, ndarray::IxDyn>)>,
 {
     // Run graph
     let feeds = feeds.into_iter().collect::<Vec<_>>();
-    let mut output_storage = eval_internal(&tensors.iter().map(|t| t.as_ref()).collect(), &feeds);
+    let mut output_storage = eval_internal(
+        &tensors.iter().map(|t| t.as_ref()).collect(),
+        &feeds,
+        backend,
+        training,
+    );
 
     // Treat in-place or delegation ops
     let creators = tensors
         .iter()
         .map(|x| {
-            let x = x.as_ref();
-            let creator = if x.is_placeholder || x.has_persistent_array() {
-                x
-            } else {
-                let creator = find_resource_creator(&output_storage, x);
+            let (creator, i) = find_resource_creator(&output_storage, x.as_ref(), 0);
+            if !creator.is_placeholder && !creator.has_persistent_array() {
                 output_storage[creator.resource_lookup_key.get()].pending_count += 1;
-                creator
-            };
-            creator
+            }
+            (creator, i)
         })
-        .collect::<Vec<&Tensor>>();
+        .collect::<Vec<(&Tensor, usize)>>();
 
     // Shrink to fit (output_storage is moved)
     let mut key2res: BTreeMap<usize, NodeWithValue> = finalize_resource_store(output_storage);
@@ -210,7 +293,7 @@ where
     // Aggregate return values
     creators
         .iter()
-        .map(|ref creator| {
+        .map(|&(creator, i)| {
             if let Some(per) = creator.get_persistent_array() {
                 // Rarely happens (case that a persistent array given by user is required)
                 Some(per.clone())
@@ -223,14 +306,12 @@ where
                         if ent.get().pending_count == 1 {
                             // move out the resource.
                             let mut got = ent.remove();
-                            // Using first item of the compute result
-                            map_err(got.value.remove(0))
+                            map_err(got.value.swap_remove(i))
                         } else {
                             // "clone" the resource.
                             let mut got = ent.get_mut();
                             got.pending_count -= 1;
-                            // Using first item of the compute result
-                            map_err(got.value[0].clone())
+                            map_err(got.value[i].clone())
                         }
                     }
                     _ => unreachable!(),
@@ -241,14 +322,22 @@ where
         .collect()
 }
 
-// Recursive function which seeks a node holding the x's resource.
+// Recursive function which seeks a node holding the x's `value_index`th resource.
+// Returns the node and the index of the resource in its outputs.
 // Actual recursion "rarely" happens.
-fn find_resource_creator<'a, 'b>(storage: &ResourceStore, x: &'b Tensor) -> &'b Tensor {
-    match storage[x.resource_lookup_key.get()].value[0] {
+fn find_resource_creator<'a, 'b>(
+    storage: &ResourceStore,
+    x: &'b Tensor,
+    value_index: usize,
+) -> (&'b Tensor, usize) {
+    if x.is_placeholder || x.has_persistent_array() {
+        return (x, 0);
+    }
+    match storage[x.resource_lookup_key.get()].value[value_index] {
         Err(::op::ComputeException::Delegate { to: i }) => {
-            find_resource_creator(storage, &x.inputs[i])
+            find_resource_creator(storage, &x.inputs[i], x.input_indices[i])
         }
-        _ => x,
+        _ => (x, value_index),
     }
 }
 
@@ -275,10 +364,18 @@ fn find_fed_resource<'a>(node: &Tensor, feeds: &Vec<&(&Tensor, &'a NdArray)>) ->
     panic!("Placeholder unfilled.");
 }
 
+#[inline]
+fn is_visited(node: &Tensor, res_store: &ResourceStore) -> bool {
+    let k = node.resource_lookup_key.get();
+    k < res_store.len() && Rc::ptr_eq(node, res_store[k].node)
+}
+
 // Evaluates "targets".
 fn eval_internal<'a>(
     targets: &Vec<&'a Tensor>,
     feeds: &Vec<&(&'a Tensor, &NdArray)>,
+    backend: &dyn Backend,
+    training: bool,
 ) -> ResourceStore<'a> {
     let mut res_store = Vec::new();
     let mut feed_store = Vec::new();
@@ -287,6 +384,11 @@ fn eval_internal<'a>(
     // Stack-based depth-first-search is used to avoid stack overflow in explicit recursion.
     let mut dfs_stack: Vec<(&Tensor, bool)> = targets.iter().map(|&x| (x, false)).collect();
     while let Some((node, is_parent)) = dfs_stack.pop() {
+        // A node can be pushed more than once before it is visited (e.g. shared by two targets),
+        // but must be computed only once; random ops wouldn't agree with themselves otherwise.
+        if is_visited(node, &res_store) {
+            continue;
+        }
         if is_parent {
             // Visit this node
             if node.is_placeholder {
@@ -298,7 +400,12 @@ fn eval_internal<'a>(
                     let y = {
                         let ins = OpComputeContext::_grab_inputs(node, &res_store, &feed_store);
                         if let Some(xs) = ins {
-                            node.op.compute(OpComputeContext { node, xs })
+                            node.op.compute(OpComputeContext {
+                                node,
+                                xs,
+                                backend,
+                                training,
+                            })
                         } else {
                             vec![Err(::op::ComputeException::Delegate { to: 0 })]
                         }
@@ -311,11 +418,7 @@ fn eval_internal<'a>(
             dfs_stack.push((node, true));
             // Push children if needed
             for child in &node.inputs {
-                let visited = {
-                    let k = child.resource_lookup_key.get();
-                    k < res_store.len() && Rc::ptr_eq(child, res_store[k].node)
-                };
-                if !visited {
+                if !is_visited(child, &res_store) {
                     dfs_stack.push((child, false));
                 }
             }
@@ -387,7 +490,12 @@ fn test_eval_internal() {
     let ref v = ::ops::placeholder(&[3, 2, 1]);
     let ref z = ::ops::squeeze(v, &[2]);
     let ref g = ::ops::grad_with_default(&[z], &[v], &[&::ones(&z.shape())]);
-    let storage = eval_internal(&vec![&g[0]], &vec![&(v, &::ndarray_ext::ones(&[3, 2, 1]))]);
+    let storage = eval_internal(
+        &vec![&g[0]],
+        &vec![&(v, &::ndarray_ext::ones(&[3, 2, 1]))],
+        &CpuBackend,
+        true,
+    );
 
     assert_eq!(
         storage.iter().map(|x| x.node.op.name()).collect::<Vec<_>>(),
@@ -24,6 +24,11 @@ pub struct TensorCore {
     /// "Symbolic" shape of this tensor.
     pub shape: Option<Tensor>,
 
+    /// Shape of this tensor inferred at graph build time.
+    ///
+    /// Unknown dimensions are `-1`, and this is `None` if even the rank is unknown.
+    static_shape: Option<Vec<isize>>,
+
     /// An optional "persistent" NdArray.
     ///
     /// This is `Some` if this tensor is made from `ag::variable` or `ag::constant`.
@@ -35,7 +40,7 @@ pub struct TensorCore {
     /// This tensor is placeholder or not.
     pub is_placeholder: bool,
 
-    /// This is `True` if this tensor can have gradient for any objectives.
+    /// This is `true` if this tensor can have gradient for any objectives.
     pub is_differentiable: bool,
 
     /// Input indices of arrays used in `compute`
@@ -45,11 +50,28 @@ pub struct TensorCore {
     ///
     /// This is same as `inputs` in most cases.
     pub inputs_on_backprop: Option<Vec<Tensor>>,
+
+    /// Sparse representation of this tensor (e.g. gradient of `ag::gather`).
+    indexed_slices: Option<IndexedSlices>,
+}
+
+/// Sparse representation of a tensor whose non-zero elements are in a few rows.
+///
+/// Row `indices[i]` (along the first axis) of the dense tensor is the sum of
+/// `values[i]` over all `i` pointing to it, and the other rows are zero.
+/// `values` has shape `indices.shape + dense_shape[1..]`.
+/// Optimizers use this to update only those rows.
+#[derive(Clone)]
+pub struct IndexedSlices {
+    pub indices: Tensor,
+    pub values: Tensor,
 }
 
 enum PersistentArray {
     Variable(NdArray),
     Constant(NdArray),
+    #[cfg(feature = "half")]
+    Half(::ndarray_ext::HalfArray),
 }
 
 impl Tensor {
@@ -61,6 +83,8 @@ impl Tensor {
             Some(ref a) => match a {
                 PersistentArray::Variable(ref arr) => Some(arr),
                 PersistentArray::Constant(ref arr) => Some(arr),
+                #[cfg(feature = "half")]
+                PersistentArray::Half(_) => None,
             },
             None => None,
         }
@@ -75,26 +99,69 @@ impl Tensor {
                 .as_ref()
                 .and_then(|inner| match inner {
                     PersistentArray::Variable(arr) => Some(arr),
-                    PersistentArray::Constant(_) => None,
+                    _ => None,
                 }),
         )
     }
 
-    /// Returns `True` if this tensor is made from `ag::variable` or `ag::constant`.
+    /// Returns a reference to the half precision storage.
+    ///
+    /// Returns `Some` if this tensor is made from `ag::variable_half`.
+    #[cfg(feature = "half")]
+    pub fn get_half_array(&self) -> Option<&::ndarray_ext::HalfArray> {
+        match self.persistent_array {
+            Some(PersistentArray::Half(ref arr)) => Some(arr),
+            _ => None,
+        }
+    }
+
+    /// Returns a mutable reference to the half precision storage.
+    ///
+    /// Returns `Some` if this tensor is made from `ag::variable_half`.
+    #[cfg(feature = "half")]
+    pub unsafe fn get_half_array_mut(&self) -> Option<&mut ::ndarray_ext::HalfArray> {
+        mem::transmute(self.get_half_array())
+    }
+
+    /// Returns `true` if this tensor is made from `ag::variable` or `ag::constant`.
+    ///
+    /// Half precision variables are not included since they are decoded in their `compute`.
     #[inline]
     pub fn has_persistent_array(&self) -> bool {
-        self.persistent_array.is_some()
+        match self.persistent_array {
+            Some(PersistentArray::Variable(_)) | Some(PersistentArray::Constant(_)) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns the sparse representation of this tensor if available.
+    ///
+    /// Gradients of `ag::gather` along the first axis have this.
+    #[inline]
+    pub fn get_indexed_slices(&self) -> Option<&IndexedSlices> {
+        self.indexed_slices.as_ref()
+    }
+
+    /// Returns `true` if this tensor is made from `ag::variable` (or `ag::variable_half`).
+    #[inline]
+    pub fn is_variable(&self) -> bool {
+        match self.persistent_array {
+            Some(PersistentArray::Constant(_)) | None => false,
+            _ => true,
+        }
     }
 }
 
 pub struct TensorBuilder {
     shape: Option<Tensor>,
+    static_shape: Option<Vec<isize>>,
     inputs: Vec<Tensor>,
     can_have_gradient: bool,
     is_placeholder: bool,
     persistent_array: Option<PersistentArray>,
     input_indices: Option<Vec<usize>>,
     inputs_on_backprop: Option<Vec<Tensor>>,
+    indexed_slices: Option<IndexedSlices>,
 }
 
 #[test]
@@ -116,6 +183,13 @@ impl TensorBuilder {
         self
     }
 
+    /// Sets the static shape explicitly; otherwise it's inferred with `Op::static_shape`.
+    #[inline]
+    pub fn set_static_shape(mut self, a: Vec<isize>) -> TensorBuilder {
+        self.static_shape = Some(a);
+        self
+    }
+
     #[inline]
     pub fn set_differentiable(mut self, a: bool) -> TensorBuilder {
         self.can_have_gradient = a;
@@ -158,6 +232,13 @@ impl TensorBuilder {
         self
     }
 
+    #[cfg(feature = "half")]
+    #[inline]
+    pub fn set_half_array(mut self, a: ::ndarray_ext::HalfArray) -> TensorBuilder {
+        self.persistent_array = Some(PersistentArray::Half(a));
+        self
+    }
+
     #[inline]
     pub fn set_input_indices(mut self, a: Vec<usize>) -> TensorBuilder {
         self.input_indices = Some(a);
@@ -170,6 +251,13 @@ impl TensorBuilder {
         self
     }
 
+    /// Attaches a sparse representation of the tensor to build.
+    #[inline]
+    pub fn set_indexed_slices(mut self, a: IndexedSlices) -> TensorBuilder {
+        self.indexed_slices = Some(a);
+        self
+    }
+
     #[inline]
     pub fn build<T: op::Op + 'static>(self, op: T) -> Tensor {
         let rank = if self.inputs.len() == 0 {
@@ -190,17 +278,40 @@ impl TensorBuilder {
             vec![0; self.inputs.len()]
         };
 
+        let static_shape = if self.static_shape.is_some() {
+            self.static_shape
+        } else if let Some(ref a) = self.persistent_array {
+            let shape = match a {
+                PersistentArray::Variable(ref arr) => arr.shape(),
+                PersistentArray::Constant(ref arr) => arr.shape(),
+                #[cfg(feature = "half")]
+                PersistentArray::Half(ref arr) => arr.shape(),
+            };
+            Some(shape.iter().map(|&a| a as isize).collect())
+        } else if input_indices.iter().any(|&i| i != 0) {
+            // `op` can't know the shapes of non-first outputs of the inputs.
+            None
+        } else {
+            let xs = self.inputs.iter().collect::<Vec<_>>();
+            match op.static_shape(xs.as_slice()) {
+                Ok(a) => a,
+                Err(msg) => panic!("{}: {}", op.name(), msg),
+            }
+        };
+
         Tensor(Rc::new(TensorCore {
             op: Box::new(op),
             inputs: self.inputs,
             top_rank: rank,
             shape: self.shape,
+            static_shape,
             persistent_array: self.persistent_array,
             is_placeholder: self.is_placeholder,
             resource_lookup_key: Cell::new(!0),
             is_differentiable: self.can_have_gradient,
             input_indices,
             inputs_on_backprop: self.inputs_on_backprop,
+            indexed_slices: self.indexed_slices,
         }))
     }
 }
@@ -210,12 +321,14 @@ impl Tensor {
     pub fn builder() -> TensorBuilder {
         TensorBuilder {
             shape: None,
+            static_shape: None,
             inputs: Vec::new(),
             can_have_gradient: true,
             persistent_array: None,
             is_placeholder: false,
             input_indices: None,
             inputs_on_backprop: None,
+            indexed_slices: None,
         }
     }
 
@@ -237,6 +350,25 @@ impl Tensor {
         ::ops::shape(self)
     }
 
+    /// Returns the shape of this tensor known at graph build time.
+    ///
+    /// Unknown dimensions are `-1` like `placeholder`'s, and this returns `None`
+    /// if even the rank is unknown.
+    ///
+    /// ```
+    /// extern crate autograd as ag;
+    ///
+    /// let ref x = ag::placeholder(&[-1, 3]);
+    /// let ref w = ag::variable(ag::ndarray_ext::zeros(&[3, 4]));
+    /// let ref y = ag::matmul(x, w);
+    ///
+    /// assert_eq!(y.static_shape(), Some(vec![-1, 4]));
+    /// ```
+    #[inline]
+    pub fn static_shape(&self) -> Option<Vec<isize>> {
+        self.0.static_shape.clone()
+    }
+
     /// Returns the (symbolic) rank of this tensor.
     ///
     /// See [rank](../ops/fn.rank.html).
@@ -26,6 +26,13 @@ pub fn check_theoretical_grads<'a, 'b, T>(
                 .expect("This is not a variable")
         };
         let head_ptr: *mut f32 = v_arr.as_mut_ptr();
+        // in the logical (row-major) order, which differs from memory order if transposed
+        let th_grad = th_grad
+            .as_ref()
+            .unwrap()
+            .iter()
+            .cloned()
+            .collect::<Vec<f32>>();
 
         // for each values
         for i in 0..v_arr.len() as isize {
@@ -54,7 +61,7 @@ pub fn check_theoretical_grads<'a, 'b, T>(
             }
 
             let g_num = (obj_pos - obj_neg).scalar_sum() / (2. * eps);
-            let g_th = unsafe { *th_grad.as_ref().unwrap().as_ptr().offset(i) };
+            let g_th = th_grad[i as usize];
 
             // compare
             let diff = (g_num - g_th).abs();
@@ -1,4 +1,5 @@
 mod test_array_gen;
+mod test_backend;
 mod test_binary_ops_eval;
 mod test_binary_ops_grad;
 mod test_core;
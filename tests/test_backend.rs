@@ -0,0 +1,309 @@
+extern crate autograd as ag;
+extern crate ndarray;
+
+use self::ag::backend::{Backend, CpuBackend, ReferenceBackend};
+
+fn eval_with(
+    backend: &dyn Backend,
+    xs: &[&ag::Tensor],
+) -> Vec<ndarray::Array<f32, ndarray::IxDyn>> {
+    ag::Eval::new()
+        .set_backend(backend)
+        .extend(xs)
+        .run(&[])
+        .into_iter()
+        .map(|a| a.unwrap())
+        .collect()
+}
+
+#[test]
+fn cpu_backend_matches_reference() {
+    let ref x = ag::variable(ag::ndarray_ext::standard_normal(&[2, 3, 9, 8]));
+    let ref w = ag::variable(ag::ndarray_ext::standard_normal(&[4, 3, 3, 2]));
+    let ref a = ag::variable(ag::ndarray_ext::standard_normal(&[3, 5, 4]));
+    let ref b = ag::variable(ag::ndarray_ext::standard_normal(&[3, 4, 2]));
+
+    let ref y = ag::dilated_conv2d(x, w, 2, 2, 2);
+    let ref y = ag::max_pool2d(&ag::relu(y), 2, 1, 1);
+    let ref y = ag::conv2d_transpose(&ag::tanh(y), w, 1, 1);
+    let ref z = ag::sigmoid(&ag::batch_matmul(a, b));
+    let ref loss = ag::reduce_sum(&ag::square(y), &[0, 1, 2, 3], false)
+        + ag::reduce_sum(
+            &ag::matmul(&ag::reshape(z, &[15, 2]), &ag::ones(&[2, 3])),
+            &[0, 1],
+            false,
+        );
+
+    let grads = ag::grad(&[loss], &[x, w, a, b]);
+    let mut targets = vec![loss, y, z];
+    targets.extend(&grads);
+
+    let cpu = eval_with(&CpuBackend, &targets);
+    let reference = eval_with(&ReferenceBackend, &targets);
+    for (a, b) in cpu.iter().zip(&reference) {
+        assert_eq!(a.shape(), b.shape());
+        assert!(a.all_close(b, 1e-3), "{:?} vs {:?}", a, b);
+    }
+}
+
+#[test]
+fn conv_nd_matches_reference() {
+    let ref x = ag::variable(ag::ndarray_ext::standard_normal(&[2, 3, 5, 6, 4]));
+    let ref w = ag::variable(ag::ndarray_ext::standard_normal(&[2, 3, 3, 2, 2]));
+    let ref a = ag::variable(ag::ndarray_ext::standard_normal(&[2, 3, 9]));
+    let ref b = ag::variable(ag::ndarray_ext::standard_normal(&[4, 3, 3]));
+
+    let ref y = ag::dilated_conv3d(x, w, 1, 2, 1);
+    let ref y = ag::conv3d_transpose(&ag::tanh(y), w, 1, 1);
+    let ref z = ag::dilated_conv1d(a, b, 2, 1, 2);
+    let ref loss = ag::reduce_sum(&ag::square(y), &[0, 1, 2, 3, 4], false)
+        + ag::reduce_sum(&ag::square(z), &[0, 1, 2], false);
+
+    let grads = ag::grad(&[loss], &[x, w, a, b]);
+    let mut targets = vec![loss, y, z];
+    targets.extend(&grads);
+
+    let cpu = eval_with(&CpuBackend, &targets);
+    let reference = eval_with(&ReferenceBackend, &targets);
+    for (a, b) in cpu.iter().zip(&reference) {
+        assert_eq!(a.shape(), b.shape());
+        assert!(a.all_close(b, 1e-3), "{:?} vs {:?}", a, b);
+    }
+}
+
+#[test]
+fn conv1d_matches_conv2d() {
+    let ref x = ag::variable(ag::ndarray_ext::standard_normal(&[2, 3, 8]));
+    let ref w = ag::variable(ag::ndarray_ext::standard_normal(&[4, 3, 3]));
+    let ref y1 = ag::dilated_conv1d(x, w, 0, 2, 2);
+    let ref y2 = ag::dilated_conv2d(
+        &ag::reshape(x, &[2, 3, 1, 8]),
+        &ag::reshape(w, &[4, 3, 1, 3]),
+        0,
+        2,
+        2,
+    );
+    let ret = ag::eval(&[y1, &ag::reshape(y2, &[2, 4, 2])], &[]);
+    assert!(ret[0]
+        .as_ref()
+        .unwrap()
+        .all_close(ret[1].as_ref().unwrap(), 1e-5));
+}
+
+#[test]
+fn grouped_conv2d_matches_dense_conv2d() {
+    let ref x = ag::variable(ag::ndarray_ext::standard_normal(&[2, 4, 7, 5]));
+    let ref w = ag::variable(ag::ndarray_ext::standard_normal(&[6, 2, 3, 3]));
+    let ref y = ag::grouped_conv2d(x, w, 1, 2, 2);
+    let ref y0 = ag::conv2d(
+        &ag::slice(x, &[0, 0, 0, 0], &[-1, 2, -1, -1]),
+        &ag::slice(w, &[0, 0, 0, 0], &[3, -1, -1, -1]),
+        1,
+        2,
+    );
+    let ref y1 = ag::conv2d(
+        &ag::slice(x, &[0, 2, 0, 0], &[-1, -1, -1, -1]),
+        &ag::slice(w, &[3, 0, 0, 0], &[-1, -1, -1, -1]),
+        1,
+        2,
+    );
+    let ref expected = ag::concat(&[y0, y1], 1);
+
+    let ref loss = ag::reduce_sum(&ag::square(y), &[0, 1, 2, 3], false);
+    let ref expected_loss = ag::reduce_sum(&ag::square(expected), &[0, 1, 2, 3], false);
+    let grads = ag::grad(&[loss], &[x, w]);
+    let expected_grads = ag::grad(&[expected_loss], &[x, w]);
+    let mut targets = vec![y];
+    targets.extend(&grads);
+    let mut expected_targets = vec![expected];
+    expected_targets.extend(&expected_grads);
+
+    let ret = eval_with(&CpuBackend, &targets);
+    let expected = eval_with(&ReferenceBackend, &expected_targets);
+    for (a, b) in ret.iter().zip(&expected) {
+        assert_eq!(a.shape(), b.shape());
+        assert!(a.all_close(b, 1e-3), "{:?} vs {:?}", a, b);
+    }
+}
+
+#[test]
+fn same_padding_matches_explicit_padding() {
+    let ref x = ag::variable(ag::ndarray_ext::standard_normal(&[2, 3, 7, 8]));
+    let ref w = ag::variable(ag::ndarray_ext::standard_normal(&[4, 3, 4, 3]));
+    // Total pads are 3 along h and 1 along w; the extra ones go to the bottom/right.
+    let same = ag::Padding::Same;
+    let explicit = ag::Padding::Explicit(1, 2, 0, 1);
+    let ref y = ag::conv2d_with_padding(x, w, same, (1, 2), (1, 1), 1, ag::DataFormat::NCHW);
+    let ref p = ag::max_pool2d_with_padding(x, (2, 3), same, (2, 2), ag::DataFormat::NCHW);
+    let ref expected_y =
+        ag::conv2d_with_padding(x, w, explicit, (1, 2), (1, 1), 1, ag::DataFormat::NCHW);
+    let ref expected_p = ag::max_pool2d_with_padding(
+        x,
+        (2, 3),
+        ag::Padding::Explicit(0, 1, 0, 1),
+        (2, 2),
+        ag::DataFormat::NCHW,
+    );
+
+    let ref loss = ag::reduce_sum(&ag::square(y), &[0, 1, 2, 3], false)
+        + ag::reduce_sum(&ag::square(p), &[0, 1, 2, 3], false);
+    let ref expected_loss = ag::reduce_sum(&ag::square(expected_y), &[0, 1, 2, 3], false)
+        + ag::reduce_sum(&ag::square(expected_p), &[0, 1, 2, 3], false);
+    let grads = ag::grad(&[loss], &[x, w]);
+    let expected_grads = ag::grad(&[expected_loss], &[x, w]);
+    let mut targets = vec![y, p];
+    targets.extend(&grads);
+    let mut expected_targets = vec![expected_y, expected_p];
+    expected_targets.extend(&expected_grads);
+
+    let ret = eval_with(&CpuBackend, &targets);
+    assert_eq!(ret[0].shape(), &[2, 4, 7, 4]);
+    assert_eq!(ret[1].shape(), &[2, 3, 4, 4]);
+    for backend in &[&CpuBackend as &dyn Backend, &ReferenceBackend] {
+        let expected = eval_with(*backend, &expected_targets);
+        for (a, b) in ret.iter().zip(&expected) {
+            assert_eq!(a.shape(), b.shape());
+            assert!(a.all_close(b, 1e-3), "{:?} vs {:?}", a, b);
+        }
+    }
+}
+
+#[test]
+fn pooling_matches_reference() {
+    let ref x = ag::variable(ag::ndarray_ext::standard_normal(&[2, 3, 7, 6]));
+    let ref y = ag::avg_pool2d(x, 3, 1, 2, true)
+        + ag::avg_pool2d_with_padding(
+            x,
+            (3, 3),
+            ag::Padding::Same,
+            (2, 2),
+            false,
+            ag::DataFormat::NCHW,
+        );
+    let ref z = ag::adaptive_avg_pool2d(&ag::square(x), (4, 5), ag::DataFormat::NCHW);
+    let ref loss = ag::reduce_sum(&ag::square(y), &[0, 1, 2, 3], false)
+        + ag::reduce_sum(&ag::square(z), &[0, 1, 2, 3], false);
+
+    let grads = ag::grad(&[loss], &[x]);
+    let mut targets = vec![loss, y, z];
+    targets.extend(&grads);
+
+    let cpu = eval_with(&CpuBackend, &targets);
+    let reference = eval_with(&ReferenceBackend, &targets);
+    for (a, b) in cpu.iter().zip(&reference) {
+        assert_eq!(a.shape(), b.shape());
+        assert!(a.all_close(b, 1e-3), "{:?} vs {:?}", a, b);
+    }
+}
+
+#[test]
+fn global_pooling_matches_reduction() {
+    let ref x = ag::variable(ag::ndarray_ext::standard_normal(&[2, 3, 5, 4]));
+    let targets = [
+        ag::global_avg_pool2d(x, ag::DataFormat::NCHW),
+        ag::reduce_mean(x, &[2, 3], true),
+        ag::global_max_pool2d(x, ag::DataFormat::NCHW),
+        ag::reduce_max(x, &[2, 3], true),
+    ];
+    let ret = eval_with(
+        &CpuBackend,
+        &[&targets[0], &targets[1], &targets[2], &targets[3]],
+    );
+    assert_eq!(ret[0].shape(), &[2, 3, 1, 1]);
+    assert!(ret[0].all_close(&ret[1], 1e-5));
+    assert_eq!(ret[2], ret[3]);
+}
+
+#[test]
+fn nhwc_matches_nchw() {
+    use self::ag::{DataFormat, Padding};
+    let ref x = ag::variable(ag::ndarray_ext::standard_normal(&[2, 7, 6, 4]));
+    let ref w = ag::variable(ag::ndarray_ext::standard_normal(&[3, 2, 2, 6]));
+    let ref wt = ag::variable(ag::ndarray_ext::standard_normal(&[3, 3, 5, 6]));
+    let nchw = |a: &ag::Tensor| ag::transpose(a, &[0, 3, 1, 2]);
+    let nhwc = |a: &ag::Tensor| ag::transpose(a, &[0, 2, 3, 1]);
+    let oihw = |a: &ag::Tensor| ag::transpose(a, &[3, 2, 0, 1]);
+
+    let ops = |x: &ag::Tensor, w: &ag::Tensor, wt: &ag::Tensor, format| {
+        let y = ag::conv2d_with_padding(x, w, Padding::Same, (2, 1), (1, 2), 2, format);
+        let t = ag::conv2d_transpose_with_padding(
+            &ag::tanh(&y),
+            wt,
+            Padding::Explicit(1, 0, 0, 1),
+            (2, 2),
+            (1, 1),
+            1,
+            format,
+        );
+        let p = ag::max_pool2d_with_padding(&y, (3, 2), Padding::Same, (2, 2), format);
+        let q = ag::avg_pool2d_with_padding(
+            &t,
+            (2, 2),
+            Padding::Explicit(1, 1, 0, 1),
+            (2, 1),
+            true,
+            format,
+        );
+        let r = ag::adaptive_avg_pool2d(x, (3, 4), format);
+        vec![y, t, p, q, r]
+    };
+    let ys = ops(x, w, wt, DataFormat::NHWC);
+    let expected_ys = ops(&nchw(x), &oihw(w), &oihw(wt), DataFormat::NCHW)
+        .iter()
+        .map(nhwc)
+        .collect::<Vec<_>>();
+
+    let sum_squares = |ys: &[ag::Tensor]| {
+        ys.iter()
+            .map(|y| ag::reduce_sum(&ag::square(y), &[0, 1, 2, 3], false))
+            .fold(ag::scalar(0.), |acc, y| acc + y)
+    };
+    let (ref loss, ref expected_loss) = (sum_squares(&ys), sum_squares(&expected_ys));
+    let grads = ag::grad(&[loss], &[x, w, wt]);
+    let expected_grads = ag::grad(&[expected_loss], &[x, w, wt]);
+    let targets = ys.iter().chain(&grads).collect::<Vec<_>>();
+    let expected_targets = expected_ys
+        .iter()
+        .chain(&expected_grads)
+        .collect::<Vec<_>>();
+
+    let expected = eval_with(&CpuBackend, &expected_targets);
+    assert_eq!(expected[0].shape(), &[2, 4, 6, 6]);
+    assert_eq!(expected[1].shape(), &[2, 8, 12, 5]);
+    for backend in &[&CpuBackend as &dyn Backend, &ReferenceBackend] {
+        let ret = eval_with(*backend, &targets);
+        for (a, b) in ret.iter().zip(&expected) {
+            assert_eq!(a.shape(), b.shape());
+            assert!(a.all_close(b, 1e-3), "{:?} vs {:?}", a, b);
+        }
+    }
+}
+
+struct BackendName;
+
+impl ag::op::Op for BackendName {
+    fn name(&self) -> &str {
+        "BackendName"
+    }
+
+    fn compute(&self, ctx: ag::runtime::OpComputeContext) -> ag::op::ComputeResult {
+        let is_reference = (ctx.backend().name() == "ReferenceBackend") as i32 as f32;
+        vec![Ok(ndarray::arr0(is_reference).into_dyn())]
+    }
+
+    fn grad(&self, _: &ag::Tensor, _: &[&ag::Tensor], _: &ag::Tensor) -> Vec<Option<ag::Tensor>> {
+        vec![None]
+    }
+}
+
+#[test]
+fn backend_is_selected_per_eval() {
+    let ref y = ag::Tensor::builder()
+        .set_input(&ag::zeros(&[1]))
+        .build(BackendName);
+    assert_eq!(y.eval(&[]), Some(ndarray::arr0(0.).into_dyn()));
+    assert_eq!(
+        eval_with(&ReferenceBackend, &[y])[0],
+        ndarray::arr0(1.).into_dyn()
+    );
+}
@@ -1,4 +1,5 @@
 extern crate autograd as ag;
+extern crate ndarray;
 
 struct MultiOutputOp;
 
@@ -25,3 +26,242 @@ fn test_nth_tensor() {
     let ref c = ag::exp(b);
     ag::eval(&[c], &[]);
 }
+
+#[test]
+fn test_eval_nth_tensor() {
+    let ref a = ag::Tensor::builder().build(MultiOutputOp);
+    let ref b = ag::nth_tensor(a, 1);
+    let ref v = ag::variable(ag::ndarray_ext::ones(&[2]));
+    let ref w = ag::identity(v);
+    let ret = ag::eval(&[b, a, w], &[]);
+    assert_eq!(ret[0].as_ref().unwrap().shape(), &[1, 3]);
+    assert_eq!(ret[1].as_ref().unwrap().shape(), &[2, 3]);
+    assert_eq!(ret[2], Some(ag::ndarray_ext::ones(&[2])));
+}
+
+#[test]
+fn test_trainable_variables() {
+    let ref x = ag::placeholder(&[-1, 2]);
+    let ref w = ag::variable(ag::ndarray_ext::zeros(&[2, 3]));
+    let ref b = ag::variable(ag::ndarray_ext::zeros(&[1, 3]));
+    let ref frozen = ag::variable(ag::ndarray_ext::zeros(&[1, 3]));
+    let ref c = ag::constant(ag::ndarray_ext::zeros(&[1, 3]));
+    let ref h = ag::matmul(x, w) + b;
+    // `w` and `b` are reachable twice
+    let ref z = h * h + ag::stop_gradient(frozen) + c;
+    let ref loss = ag::reduce_sum(z, &[0, 1], false);
+    let ref vars = ag::trainable_variables(&[loss]);
+    assert_eq!(vars, &[w, b]);
+
+    // usable with optimizers
+    let ref grads = ag::grad(&[loss], vars);
+    let mut sgd = ag::gradient_descent_ops::SGD { lr: 0.1 };
+    let ref updates = sgd.compute_updates(vars, grads);
+    assert_eq!(updates.len(), 2);
+}
+
+#[test]
+fn test_static_shape() {
+    let ref x = ag::placeholder(&[-1, 3]);
+    let ref w = ag::variable(ag::ndarray_ext::zeros(&[3, 4]));
+    let ref b = ag::variable(ag::ndarray_ext::zeros(&[1, 4]));
+    assert_eq!(x.static_shape(), Some(vec![-1, 3]));
+    assert_eq!(w.static_shape(), Some(vec![3, 4]));
+
+    let ref y = ag::matmul(x, w) + b;
+    assert_eq!(y.static_shape(), Some(vec![-1, 4]));
+    let ref z = ag::concat(&[y, y], 1);
+    assert_eq!(z.static_shape(), Some(vec![-1, 8]));
+
+    let ref img = ag::placeholder(&[-1, 3, 8, 8]);
+    let ref filter = ag::variable(ag::ndarray_ext::zeros(&[5, 3, 3, 3]));
+    let ref conv = ag::conv2d(img, filter, 1, 1);
+    assert_eq!(conv.static_shape(), Some(vec![-1, 5, 8, 8]));
+    let ref pool = ag::max_pool2d(conv, 2, 0, 2);
+    assert_eq!(pool.static_shape(), Some(vec![-1, 5, 4, 4]));
+}
+
+#[test]
+fn test_static_shape_softmax_cross_entropy() {
+    let ref x = ag::placeholder(&[-1, 3]);
+    let ref t = ag::placeholder(&[1, 3]);
+    let ref loss = ag::softmax_cross_entropy(x, t);
+    assert_eq!(loss.static_shape(), Some(vec![-1]));
+    let ref x = ag::placeholder(&[1, 3]);
+    let ref t = ag::placeholder(&[4, 3]);
+    let ref loss = ag::softmax_cross_entropy(x, t);
+    assert_eq!(loss.static_shape(), Some(vec![4]));
+}
+
+#[test]
+#[should_panic]
+fn test_static_shape_matmul_mismatch() {
+    let ref x = ag::placeholder(&[-1, 3]);
+    let ref w = ag::variable(ag::ndarray_ext::zeros(&[2, 4]));
+    ag::matmul(x, w);
+}
+
+#[test]
+#[should_panic]
+fn test_static_shape_concat_mismatch() {
+    let ref a = ag::constant(ag::ndarray_ext::zeros(&[2, 3]));
+    let ref b = ag::constant(ag::ndarray_ext::zeros(&[3, 3]));
+    ag::concat(&[a, b], 1);
+}
+
+#[test]
+fn test_dynamic_loss_scale() {
+    let ref w = ag::variable(ndarray::arr1(&[1., 2.]));
+    let ref loss = ag::reduce_sum(&(w * w), &[0], false);
+    let mut loss_scale = ag::gradient_descent_ops::DynamicLossScale::new(2f32.powi(127));
+    loss_scale.growth_interval = 2;
+    let ref grads = ag::grad(&[&loss_scale.scale_loss(loss)], &[w]);
+    let (ref grads, ref finite) = loss_scale.unscale(grads);
+    let mut sgd = ag::gradient_descent_ops::SGD { lr: 0.5 };
+    let mut updates = sgd.compute_updates(&[w], grads);
+    updates.push(loss_scale.update(finite));
+    let scale = || loss_scale.scale.eval(&[]).unwrap()[ndarray::IxDyn(&[])];
+
+    // Gradients overflow: updates are skipped and the scale is reduced.
+    ag::eval(&updates, &[]);
+    ag::eval(&updates, &[]);
+    assert_eq!(w.eval(&[]), Some(ndarray::arr1(&[1., 2.]).into_dyn()));
+    assert_eq!(scale(), 2f32.powi(125));
+
+    // Gradients are finite: the scale grows after `growth_interval` steps.
+    ag::eval(&updates, &[]);
+    assert_eq!(w.eval(&[]), Some(ndarray::arr1(&[0., 0.]).into_dyn()));
+    assert_eq!(scale(), 2f32.powi(125));
+    ag::eval(&updates, &[]);
+    assert_eq!(scale(), 2f32.powi(126));
+}
+
+#[test]
+fn test_dynamic_loss_scale_skips_adam() {
+    let ref w = ag::variable(ndarray::arr1(&[1., 2.]));
+    let ref loss = ag::reduce_sum(&(w * w), &[0], false);
+    let loss_scale = ag::gradient_descent_ops::DynamicLossScale::new(2f32.powi(127));
+    let ref grads = ag::grad(&[&loss_scale.scale_loss(loss)], &[w]);
+    let (ref grads, ref finite) = loss_scale.unscale(grads);
+    let adam = ag::gradient_descent_ops::Adam::default();
+    let ref states = ag::gradient_descent_ops::Adam::vars_with_states(&[w]);
+    let mut updates = adam.compute_updates(states, grads);
+    updates.push(loss_scale.update(finite));
+    let t = || states[0].state.t.eval(&[]).unwrap()[ndarray::IxDyn(&[])];
+
+    // Neither the variable nor the moments are touched while the gradients overflow.
+    ag::eval(&updates, &[]);
+    assert_eq!(w.eval(&[]), Some(ndarray::arr1(&[1., 2.]).into_dyn()));
+    assert_eq!(t(), 1.);
+    ag::eval(&updates, &[]);
+    assert_eq!(t(), 1.);
+    ag::eval(&updates, &[]);
+    assert_eq!(t(), 2.);
+    assert!(w.eval(&[]).unwrap()[0] < 1.);
+}
+
+#[cfg(feature = "half")]
+#[test]
+fn test_adam_half_variable() {
+    use self::ag::ndarray_ext::HalfType;
+
+    for &ty in &[HalfType::F16, HalfType::BF16] {
+        let ref w = ag::variable_half(ndarray::arr1(&[1., -1.]), ty);
+        let ref w_ref = ag::variable(ndarray::arr1(&[1., -1.]));
+        let ref loss = ag::reduce_sum(&(w * w), &[0], false);
+        let ref loss_ref = ag::reduce_sum(&(w_ref * w_ref), &[0], false);
+        assert!(ag::trainable_variables(&[loss]) == &[w]);
+
+        let adam = ag::gradient_descent_ops::Adam {
+            alpha: 1e-4,
+            ..Default::default()
+        };
+        let ref states = ag::gradient_descent_ops::Adam::vars_with_states(&[w, w_ref]);
+        let mut updates = adam.compute_updates(&states[..1], &ag::grad(&[loss], &[w]));
+        updates.extend(adam.compute_updates(&states[1..], &ag::grad(&[loss_ref], &[w_ref])));
+
+        // Each step is below the half precision resolution around 1.0,
+        // but accumulates in the master weights.
+        for _ in 0..100 {
+            ag::eval(&updates, &[]);
+        }
+        let master = states[0].state.master.as_ref().unwrap().eval(&[]).unwrap();
+        let w_ref = w_ref.eval(&[]).unwrap();
+        let w = w.eval(&[]).unwrap();
+        assert!((master[0] - w_ref[0]).abs() < 1e-3);
+        assert_eq!(w[0], ty.round(master[0]));
+        assert!(w[0] < 1.);
+    }
+}
+
+#[test]
+fn test_sparse_gather_grad() {
+    let ref table = ag::variable(ag::ndarray_ext::standard_normal(&[5, 3]));
+    let ref y1 = ag::gather(table, &ag::constant(ndarray::arr1(&[1., 3.])), 0);
+    let ref y2 = ag::gather(table, &ag::constant(ndarray::arr2(&[[1.], [0.]])), 0);
+    let ref loss = ag::reduce_sum(y1, &[0, 1], false) + ag::reduce_sum(y2, &[0, 1, 2], false);
+    let ref g = ag::grad(&[loss], &[table])[0];
+
+    // Two gathers: the sparse representations are concatenated.
+    let slices = g.get_indexed_slices().unwrap();
+    let indices = slices.indices.eval(&[]).unwrap();
+    let values = slices.values.eval(&[]).unwrap();
+    assert_eq!(indices.shape(), &[4]);
+    assert_eq!(values, ag::ndarray_ext::ones(&[4, 3]));
+
+    let mut dense = ndarray::Array2::<f32>::zeros((5, 3));
+    for (&i, v) in indices.iter().zip(values.outer_iter()) {
+        let mut row = dense.row_mut(i as usize);
+        row += &v;
+    }
+    assert_eq!(g.eval(&[]), Some(dense.into_dyn()));
+}
+
+#[test]
+fn test_sparse_sgd() {
+    let ref table = ag::variable(ag::ndarray_ext::zeros(&[4, 2]));
+    let ref y = ag::gather(table, &ag::constant(ndarray::arr1(&[1., 3., 1.])), 0);
+    let ref loss = ag::reduce_sum(y, &[0, 1], false);
+    let ref grads = ag::grad(&[loss], &[table]);
+    let mut sgd = ag::gradient_descent_ops::SGD { lr: 0.5 };
+    ag::eval(&sgd.compute_updates(&[table], grads), &[]);
+    assert_eq!(
+        table.eval(&[]),
+        Some(ndarray::arr2(&[[0., 0.], [-1., -1.], [0., 0.], [-0.5, -0.5]]).into_dyn())
+    );
+}
+
+#[test]
+fn test_sparse_adam() {
+    let init = ag::ndarray_ext::standard_normal(&[6, 3]);
+    let ref sparse = ag::variable(init.clone());
+    let ref dense = ag::variable(init.clone());
+    let ref indices = ag::constant(ndarray::arr1(&[4., 0., 4.]));
+    let ref loss_sparse =
+        ag::reduce_sum(&ag::square(&ag::gather(sparse, indices, 0)), &[0, 1], false);
+    let ref loss_dense =
+        ag::reduce_sum(&ag::square(&ag::gather(dense, indices, 0)), &[0, 1], false);
+    let grad_sparse = ag::grad(&[loss_sparse], &[sparse]);
+    // Drops the sparse representation
+    let grad_dense = [ag::identity(&ag::grad(&[loss_dense], &[dense])[0])];
+    assert!(grad_sparse[0].get_indexed_slices().is_some());
+    assert!(grad_dense[0].get_indexed_slices().is_none());
+
+    let adam = ag::gradient_descent_ops::Adam::default();
+    let ref states = ag::gradient_descent_ops::Adam::vars_with_states(&[sparse, dense]);
+    let mut updates = adam.compute_updates(&states[..1], &grad_sparse);
+    updates.extend(adam.compute_updates(&states[1..], &grad_dense));
+
+    // Dense Adam doesn't move rows whose moments are still zero,
+    // so the first step is the same as the lazy one.
+    ag::eval(&updates, &[]);
+    let sparse = sparse.eval(&[]).unwrap();
+    assert!(sparse.all_close(&dense.eval(&[]).unwrap(), 1e-6));
+    for &i in &[1, 2, 3, 5] {
+        assert_eq!(
+            sparse.subview(ndarray::Axis(0), i),
+            init.subview(ndarray::Axis(0), i)
+        );
+    }
+    assert!(sparse[[4, 0]] != init[[4, 0]]);
+}
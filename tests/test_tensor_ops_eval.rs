@@ -1,5 +1,6 @@
 extern crate autograd as ag;
 extern crate ndarray;
+extern crate rand;
 
 #[test]
 fn reduce_prod() {
@@ -23,6 +24,42 @@ fn argmax_with_multi_max_args() {
     assert_eq!(2., y.eval(&[]).unwrap()[ndarray::IxDyn(&[])]);
 }
 
+#[test]
+fn argmin() {
+    let ref x = ag::constant(ndarray::arr2(&[[3., 4.], [6., 5.]]));
+    let ref y = ag::argmin(x, 0, true);
+    assert_eq!(y.eval(&[]), Some(ndarray::arr2(&[[0., 0.]]).into_dyn()));
+}
+
+#[test]
+fn top_k_grad_scatters_into_selected_positions() {
+    let ref x = ag::variable(ndarray::arr2(&[[1., 4., 2., 3.], [8., 5., 6., 7.]]));
+    let (values, indices) = ag::top_k(x, 2, -1);
+    let ref g = ag::grad_with_default(&[&values], &[x], &[&ag::ones(&values.shape())])[0];
+    assert_eq!(
+        g.eval(&[]),
+        Some(ndarray::arr2(&[[0., 1., 0., 1.], [1., 0., 0., 1.]]).into_dyn())
+    );
+    assert_eq!(indices.eval(&[]), Some(ndarray::arr2(&[[1., 3.], [0., 3.]]).into_dyn()));
+}
+
+#[test]
+fn sort_puts_nan_last() {
+    let ref x = ag::constant(ndarray::arr1(&[1., std::f32::NAN, 0.]));
+    let ascending = ag::sort(x, 0, false).eval(&[]).unwrap();
+    let descending = ag::sort(x, 0, true).eval(&[]).unwrap();
+    assert_eq!(&ascending.as_slice().unwrap()[..2], &[0., 1.]);
+    assert!(ascending[2].is_nan());
+    assert!(descending[0].is_nan());
+    assert_eq!(&descending.as_slice().unwrap()[1..], &[1., 0.]);
+    assert_eq!(
+        ag::argsort(x, 0, false).eval(&[]),
+        Some(ndarray::arr1(&[2., 0., 1.]).into_dyn())
+    );
+    let (_, indices) = ag::top_k(x, 1, 0);
+    assert_eq!(indices.eval(&[]), Some(ndarray::arr1(&[1.]).into_dyn()));
+}
+
 #[test]
 fn reduce_mean() {
     let ref v = ag::variable(ndarray::arr1(&[2., 3., 4.]));
@@ -37,3 +74,389 @@ fn reduce_grad() {
     let ref g = ag::grad(&[z], &[v])[0];
     assert_eq!(g.eval(&[]).unwrap().shape(), &[3]);
 }
+
+#[test]
+fn matmul_t_with_non_contiguous_inputs() {
+    let a = ag::ndarray_ext::standard_normal(&[3, 2]);
+    let b = ag::ndarray_ext::standard_normal(&[4, 3]);
+    // `transpose` outputs arrays in non-standard layout.
+    let ref x = ag::transpose(&ag::constant(a.clone()), &[1, 0]);
+    let ref w = ag::constant(b.clone());
+    let ref y = ag::matmul_t(x, w, false, true);
+    let expected = ag::ndarray_ext::into_mat(a)
+        .reversed_axes()
+        .dot(&ag::ndarray_ext::into_mat(b).reversed_axes());
+    let y = y.eval(&[]).unwrap();
+    assert_eq!(y.shape(), &[2, 4]);
+    assert!(y.all_close(&expected.into_dyn(), 1e-5));
+}
+
+#[test]
+fn batch_matmul_with_non_contiguous_inputs() {
+    let a = ag::ndarray_ext::standard_normal(&[2, 3, 2, 4]);
+    let b = ag::ndarray_ext::standard_normal(&[2, 3, 5, 4]);
+    let ref bt = ag::transpose(&ag::constant(b.clone()), &[0, 1, 3, 2]);
+    let ref y = ag::batch_matmul(&ag::constant(a.clone()), bt);
+    let y = y.eval(&[]).unwrap();
+    assert_eq!(y.shape(), &[2, 3, 2, 5]);
+    for i in 0..2 {
+        for j in 0..3 {
+            let mat = |x: &ndarray::Array<f32, ndarray::IxDyn>| {
+                let x = x.subview(ndarray::Axis(0), i);
+                ag::ndarray_ext::into_mat(x.subview(ndarray::Axis(0), j).to_owned())
+            };
+            let expected = mat(&a).dot(&mat(&b).reversed_axes()).into_dyn();
+            assert!(mat(&y).into_dyn().all_close(&expected, 1e-5));
+        }
+    }
+}
+
+#[test]
+fn reshape_with_non_contiguous_input() {
+    let ref x = ag::constant(ndarray::arr2(&[[0., 1., 2.], [3., 4., 5.]]));
+    let ref y = ag::reshape(&ag::transpose(x, &[1, 0]), &[6]);
+    assert_eq!(
+        y.eval(&[]),
+        Some(ndarray::arr1(&[0., 3., 1., 4., 2., 5.]).into_dyn())
+    );
+}
+
+#[test]
+fn conv2d_same_padding_pads_bottom_right() {
+    // Total pads of each axis is 1, which goes to the bottom/right as in TensorFlow.
+    let ref x = ag::ones(&[1, 1, 3, 3]);
+    let ref w = ag::ones(&[1, 1, 2, 2]);
+    let ref y = ag::conv2d_with_padding(
+        x,
+        w,
+        ag::Padding::Same,
+        (1, 1),
+        (1, 1),
+        1,
+        ag::DataFormat::NCHW,
+    );
+    assert_eq!(
+        y.eval(&[]).unwrap().as_slice().unwrap(),
+        &[4., 4., 2., 4., 4., 2., 2., 2., 1.]
+    );
+}
+
+#[test]
+#[should_panic(expected = "must be a multiple of input channels (3)")]
+fn depthwise_conv2d_rejects_filter_of_other_channels() {
+    let ref x = ag::ones(&[1, 3, 4, 4]);
+    let ref w = ag::ones(&[4, 1, 3, 3]);
+    ag::depthwise_conv2d(x, w, 1, 1).eval(&[]);
+}
+
+#[test]
+fn max_pool2d_ignores_padding() {
+    let ref x = ag::constant(ndarray::Array::from_elem(
+        ndarray::IxDyn(&[1, 1, 3, 3]),
+        -1.,
+    ));
+    let ref y = ag::max_pool2d_with_padding(
+        x,
+        (2, 2),
+        ag::Padding::Explicit(1, 0, 0, 1),
+        (2, 2),
+        ag::DataFormat::NCHW,
+    );
+    assert_eq!(y.eval(&[]).unwrap().shape(), &[1, 1, 2, 2]);
+    assert_eq!(y.eval(&[]).unwrap().as_slice().unwrap(), &[-1.; 4]);
+}
+
+#[test]
+#[should_panic(expected = "ag::max_pool2d: pool_size must be positive")]
+fn max_pool2d_rejects_zero_pool_size() {
+    let ref x = ag::ones(&[1, 1, 3, 3]);
+    ag::max_pool2d(x, 0, 0, 1).eval(&[]);
+}
+
+#[test]
+fn pixel_shuffle_moves_channels_into_blocks() {
+    let ref x = ag::constant(
+        ndarray::Array::range(0., 8., 1.)
+            .into_shape(ndarray::IxDyn(&[1, 4, 1, 2]))
+            .unwrap(),
+    );
+    let ref y = ag::pixel_shuffle(x, 2);
+    assert_eq!(y.eval(&[]).unwrap().shape(), &[1, 1, 2, 4]);
+    assert_eq!(
+        y.eval(&[]).unwrap().as_slice().unwrap(),
+        &[0., 2., 1., 3., 4., 6., 5., 7.]
+    );
+}
+
+#[test]
+fn batch_norm_with_stats_updates_and_uses_running_stats() {
+    let ref x = ag::constant(
+        ndarray::arr2(&[[1., 10.], [3., 20.], [5., 30.], [7., 40.]])
+            .into_shape(ndarray::IxDyn(&[4, 2]))
+            .unwrap(),
+    );
+    let ref scale = ag::variable(ndarray::arr1(&[1., 2.]));
+    let ref shift = ag::variable(ndarray::arr1(&[0., 1.]));
+    let ref mean = ag::variable(ndarray::arr1(&[0., 0.]));
+    let ref var = ag::variable(ndarray::arr1(&[1., 1.]));
+    let ref y = ag::batch_norm_with_stats(x, scale, shift, mean, var, 1, 0.5, 0.);
+
+    // Training mode normalizes with the batch statistics and updates the running ones.
+    let ret = ag::Eval::new().push(y).run(&[]);
+    let y_train = ret[0].as_ref().unwrap();
+    let inv_std = 1. / 5f32.sqrt();
+    assert!(y_train.all_close(
+        &ndarray::arr2(&[
+            [-3. * inv_std, -3. * inv_std * 2. + 1.],
+            [-1. * inv_std, -1. * inv_std * 2. + 1.],
+            [1. * inv_std, 1. * inv_std * 2. + 1.],
+            [3. * inv_std, 3. * inv_std * 2. + 1.],
+        ])
+        .into_dyn(),
+        1e-5
+    ));
+    assert_eq!(
+        mean.eval(&[]).unwrap(),
+        ndarray::arr1(&[2., 12.5]).into_dyn()
+    );
+    // Unbiased variances are 20 / 3 and 500 / 3.
+    assert!(var.eval(&[]).unwrap().all_close(
+        &ndarray::arr1(&[0.5 + 10. / 3., 0.5 + 250. / 3.]).into_dyn(),
+        1e-4
+    ));
+
+    // Inference mode only uses the running statistics.
+    let (mean, var) = (mean.eval(&[]).unwrap(), var.eval(&[]).unwrap());
+    let ret = ag::Eval::new().set_training(false).push(y).run(&[]);
+    let y_infer = ret[0].as_ref().unwrap();
+    let x = x.eval(&[]).unwrap();
+    for i in 0..4 {
+        for j in 0..2 {
+            let expected = (x[[i, j]] - mean[[j]]) / var[[j]].sqrt() * [1., 2.][j] + [0., 1.][j];
+            assert!((y_infer[[i, j]] - expected).abs() < 1e-4);
+        }
+    }
+    // and leaves them unchanged.
+    assert_eq!(
+        ag::Eval::new().set_training(false).push(y).run(&[])[0].as_ref(),
+        Some(y_infer)
+    );
+}
+
+#[test]
+fn batch_norm_with_stats_accepts_empty_inner_axes() {
+    let ref x = ag::variable(ag::ndarray_ext::zeros(&[2, 3, 0]));
+    let ref scale = ag::variable(ag::ndarray_ext::ones(&[3]));
+    let ref shift = ag::variable(ag::ndarray_ext::zeros(&[3]));
+    let ref mean = ag::variable(ag::ndarray_ext::zeros(&[3]));
+    let ref var = ag::variable(ag::ndarray_ext::ones(&[3]));
+    let ref y = ag::batch_norm_with_stats(x, scale, shift, mean, var, 1, 0.5, 1e-5);
+    let ref g = ag::grad(&[y], &[x, scale])[0];
+
+    let ret = ag::Eval::new().set_training(false).push(y).push(g).run(&[]);
+    assert_eq!(ret[0].as_ref().unwrap().shape(), &[2, 3, 0]);
+    assert_eq!(ret[1].as_ref().unwrap().shape(), &[2, 3, 0]);
+}
+
+#[test]
+fn group_norm_matches_normalize() {
+    let ref x = ag::constant(ag::ndarray_ext::standard_normal(&[2, 4, 3]));
+    let ref scale = ag::constant(ag::ndarray_ext::standard_normal(&[4]));
+    let ref shift = ag::constant(ag::ndarray_ext::standard_normal(&[4]));
+    let ref y = ag::group_norm(x, scale, shift, 2, 1e-5);
+
+    // Each group of 2 channels is normalized over its channels and spatial axis.
+    let ref grouped = ag::reshape(x, &[2, 2, 6]);
+    let ref expected = ag::reshape(ag::normalize(grouped, &[2]), &[2, 4, 3])
+        * ag::reshape(scale, &[1, 4, 1])
+        + ag::reshape(shift, &[1, 4, 1]);
+    let ret = ag::eval(&[y, expected], &[]);
+    assert!(ret[0]
+        .as_ref()
+        .unwrap()
+        .all_close(ret[1].as_ref().unwrap(), 1e-4));
+}
+
+#[test]
+fn dropout_grad_is_mask() {
+    let ref x = ag::variable(ag::ndarray_ext::ones(&[10, 20]));
+    let ref y = ag::dropout(x, 0.3);
+    let ref g = ag::grad(&[y], &[x])[0];
+    let ret = ag::eval(&[y, g], &[]);
+    let (y, g) = (ret[0].as_ref().unwrap(), ret[1].as_ref().unwrap());
+    assert_eq!(y, g);
+    assert!(y.iter().all(|&a| a == 0. || (a - 1. / 0.7).abs() < 1e-6));
+    assert!(y.iter().any(|&a| a == 0.) && y.iter().any(|&a| a != 0.));
+}
+
+#[test]
+fn dropout_rng_is_reproducible() {
+    use self::rand::SeedableRng;
+    let ref x = ag::ones(&[10, 20]);
+    let rng = || ag::ndarray_ext::ArrRng::new(rand::XorShiftRng::from_seed([1, 2, 3, 4]));
+    let ret = ag::eval(
+        &[
+            ag::dropout_rng(rng(), x, 0.5),
+            ag::dropout_rng(rng(), x, 0.5),
+        ],
+        &[],
+    );
+    assert_eq!(ret[0], ret[1]);
+}
+
+#[test]
+fn dropout_inference_is_identity() {
+    let ref x = ag::variable(ag::ndarray_ext::standard_normal(&[4, 3]));
+    let ref y = ag::dropout(x, 0.9);
+    let ref g = ag::grad(&[y], &[x])[0];
+    let ret = ag::Eval::new().set_training(false).push(y).push(g).run(&[]);
+    assert_eq!(ret[0], x.eval(&[]));
+    assert_eq!(ret[1], Some(ag::ndarray_ext::ones(&[4, 3])));
+}
+
+#[test]
+fn select_does_not_leak_nan() {
+    let ref x = ag::constant(ndarray::arr1(&[-4., 0., 4.]));
+    // NaN, -Inf and ln(4)
+    let ref a = ag::log(x, std::f32::consts::E);
+    let ref b = ag::zeros(&[3]);
+    let ref y = ag::select(ag::greater(x, ag::scalar(0.)), a, b);
+    let ref g = ag::grad(&[y], &[a, b]);
+    let ret = ag::eval(&[y, &g[0], &g[1]], &[]);
+    assert!(ret[0]
+        .as_ref()
+        .unwrap()
+        .all_close(&ndarray::arr1(&[0., 0., 4f32.ln()]), 1e-6));
+    assert_eq!(ret[1], Some(ndarray::arr1(&[0., 0., 1.]).into_dyn()));
+    assert_eq!(ret[2], Some(ndarray::arr1(&[1., 1., 0.]).into_dyn()));
+}
+
+#[test]
+fn pad_reflects_repeatedly() {
+    let ref x = ag::constant(ndarray::arr1(&[1., 2., 3.]));
+    let ref y = ag::pad(x, &[(5, 4)], ag::PadMode::Reflect);
+    assert_eq!(
+        y.eval(&[]),
+        Some(ndarray::arr1(&[2., 1., 2., 3., 2., 1., 2., 3., 2., 1., 2., 3.]).into_dyn())
+    );
+}
+
+#[test]
+fn cumprod_grad_with_zero() {
+    let ref x = ag::variable(ndarray::arr1(&[2., 0., 3.]));
+    let ref y = ag::reduce_sum(ag::cumprod(x, 0, false, false), &[0], false);
+    let ref g = ag::grad(&[y], &[x])[0];
+    // y = x0 + x0x1 + x0x1x2
+    assert_eq!(g.eval(&[]), Some(ndarray::arr1(&[1., 8., 0.]).into_dyn()));
+}
+
+#[test]
+fn einsum() {
+    let a = ag::ndarray_ext::standard_normal(&[2, 3, 4]);
+    let b = ag::ndarray_ext::standard_normal(&[2, 4, 5]);
+    let ref x = ag::constant(a.clone());
+    let ref y = ag::constant(b.clone());
+    let ret = ag::eval(
+        &[
+            &ag::einsum("bij,bjk->bik", &[x, y]),
+            &ag::batch_matmul(x, y),
+            &ag::einsum("bij->jb", &[x]),
+            &ag::einsum("i,j", &[&ag::constant(ndarray::arr1(&[1., 2.])), &ag::constant(ndarray::arr1(&[3., 4., 5.]))]),
+        ],
+        &[],
+    );
+    assert!(ret[0].as_ref().unwrap().all_close(ret[1].as_ref().unwrap(), 1e-5));
+    let sum = a.sum_axis(ndarray::Axis(1)).reversed_axes();
+    assert!(ret[2].as_ref().unwrap().all_close(&sum, 1e-5));
+    assert_eq!(
+        ret[3],
+        Some(ndarray::arr2(&[[3., 4., 5.], [6., 8., 10.]]).into_dyn())
+    );
+}
+
+#[test]
+fn linalg_batched() {
+    let a = ndarray::arr3(&[[[4., 2.], [2., 10.]], [[9., 3.], [3., 5.]]]);
+    let ref x = ag::constant(a.clone());
+    let ref l = ag::cholesky(x);
+    let ref inv = ag::matrix_inverse(x);
+    let ret = ag::eval(
+        &[
+            &ag::batch_matmul(l, ag::transpose(l, &[0, 2, 1])),
+            &ag::batch_matmul(x, inv),
+            &ag::log_det(x),
+        ],
+        &[],
+    );
+    assert!(ret[0].as_ref().unwrap().all_close(&a, 1e-5));
+    let eye = ndarray::arr3(&[[[1., 0.], [0., 1.]], [[1., 0.], [0., 1.]]]);
+    assert!(ret[1].as_ref().unwrap().all_close(&eye, 1e-5));
+    assert!(ret[2].as_ref().unwrap().all_close(&ndarray::arr1(&[36f32.ln(), 36f32.ln()]), 1e-5));
+}
+
+#[test]
+fn decompositions_reconstruct() {
+    let a = ag::ndarray_ext::standard_normal(&[2, 4, 3]);
+    let ref x = ag::constant(a.clone());
+    let (ref q, ref r) = ag::qr(x);
+    let (ref u, ref s, ref v) = ag::svd(x);
+    let ref gram = ag::batch_matmul(ag::transpose(x, &[0, 2, 1]), x);
+    let (ref e, ref vecs) = ag::eigh(gram);
+    let ret = ag::eval(
+        &[
+            &ag::batch_matmul(q, r),
+            &ag::batch_matmul(ag::transpose(q, &[0, 2, 1]), q),
+            &ag::batch_matmul(u * ag::expand_dims(s, &[1]), ag::transpose(v, &[0, 2, 1])),
+            &ag::batch_matmul(vecs * ag::expand_dims(e, &[1]), ag::transpose(vecs, &[0, 2, 1])),
+            gram,
+            s,
+            e,
+        ],
+        &[],
+    );
+    let eye = ndarray::Array::eye(3).into_shape((1, 3, 3)).unwrap();
+    assert!(ret[0].as_ref().unwrap().all_close(&a, 1e-4));
+    assert!(ret[1].as_ref().unwrap().all_close(&eye, 1e-4));
+    assert!(ret[2].as_ref().unwrap().all_close(&a, 1e-4));
+    assert!(ret[3].as_ref().unwrap().all_close(ret[4].as_ref().unwrap(), 1e-3));
+    // eigenvalues of x^T x are the squared singular values
+    let s = ret[5].as_ref().unwrap();
+    let e = ret[6].as_ref().unwrap();
+    for i in 0..2 {
+        for j in 0..3 {
+            assert!((s[[i, j]] * s[[i, j]] - e[[i, 2 - j]]).abs() < 1e-3);
+        }
+    }
+}
+
+#[test]
+fn fft_of_real_signals() {
+    let a = ag::ndarray_ext::standard_normal(&[3, 7]);
+    let ref x = ag::constant(a.clone());
+    let complex =
+        ndarray::Array::from_shape_fn((3, 7, 2), |(i, j, k)| if k == 0 { a[[i, j]] } else { 0. });
+    // the 2nd frame starts at 2 and is windowed by the periodic Hann window
+    let window = [0., 0.5, 1., 0.5];
+    let frame = ndarray::Array::from_shape_fn((3, 4), |(i, j)| a[[i, 2 + j]] * window[j]);
+    let ret = ag::eval(
+        &[
+            &ag::rfft(x),
+            &ag::fft(ag::constant(complex)),
+            &ag::irfft(ag::rfft(x), 7),
+            &ag::stft(x, 4, 2),
+            &ag::rfft(ag::constant(frame)),
+        ],
+        &[],
+    );
+    let (half, full) = (ret[0].as_ref().unwrap(), ret[1].as_ref().unwrap());
+    assert_eq!(half.shape(), &[3, 4, 2]);
+    for (idx, &y) in half.indexed_iter() {
+        assert!((y - full[idx]).abs() < 1e-4);
+    }
+    assert!(ret[2].as_ref().unwrap().all_close(&a, 1e-5));
+    let (stft, expected) = (ret[3].as_ref().unwrap(), ret[4].as_ref().unwrap());
+    assert_eq!(stft.shape(), &[3, 2, 3, 2]);
+    for (idx, &y) in expected.indexed_iter() {
+        assert!((y - stft[[idx[0], 1, idx[1], idx[2]]]).abs() < 1e-5);
+    }
+}
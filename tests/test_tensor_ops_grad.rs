@@ -1,5 +1,6 @@
 extern crate autograd as ag;
 extern crate ndarray;
+extern crate rand;
 
 #[test]
 fn get() {
@@ -274,6 +275,251 @@ fn reduce_prod() {
     ag::test_helper::check_theoretical_grads(z, g.as_slice(), &[v], &[], 1e-3, 1e-3);
 }
 
+#[test]
+fn cumsum() {
+    for &(exclusive, reverse) in [(false, false), (true, false), (false, true), (true, true)].iter() {
+        let ref v = ag::variable(ag::ndarray_ext::standard_normal(&[3, 4, 2]));
+        let ref c = ag::constant(ag::ndarray_ext::standard_normal(&[3, 4, 2]));
+        let ref z = ag::cumsum(v, -2, exclusive, reverse) * c;
+        let ref g = ag::grad_with_default(&[z], &[v], &[&ag::ones(&z.shape())]);
+        ag::test_helper::check_theoretical_grads(z, g.as_slice(), &[v], &[], 1e-3, 1e-3);
+    }
+}
+
+#[test]
+fn cumprod() {
+    for &(exclusive, reverse) in [(false, false), (true, false), (false, true), (true, true)].iter() {
+        let ref v = ag::variable(ag::ndarray_ext::random_uniform(&[2, 4, 2], -1., 1.));
+        let ref c = ag::constant(ag::ndarray_ext::random_uniform(&[2, 4, 2], -1., 1.));
+        let ref z = ag::cumprod(v, 1, exclusive, reverse) * c;
+        let ref g = ag::grad_with_default(&[z], &[v], &[&ag::ones(&z.shape())]);
+        ag::test_helper::check_theoretical_grads(z, g.as_slice(), &[v], &[], 1e-3, 1e-3);
+    }
+}
+
+#[test]
+fn cumprod_with_zeros() {
+    for &(exclusive, reverse) in [(false, false), (true, false), (false, true), (true, true)].iter() {
+        let ref v = ag::variable(ndarray::arr2(&[[1.5, 0., -2., 0.5], [0., 0., 3., 1.2]]));
+        let ref c = ag::constant(ag::ndarray_ext::standard_normal(&[2, 4]));
+        let ref z = ag::cumprod(v, 1, exclusive, reverse) * c;
+        let ref g = ag::grad_with_default(&[z], &[v], &[&ag::ones(&z.shape())]);
+        ag::test_helper::check_theoretical_grads(z, g.as_slice(), &[v], &[], 1e-3, 1e-3);
+    }
+}
+
+#[test]
+fn top_k() {
+    // Well separated values, so that perturbations never reorder them.
+    let ref v = ag::variable(
+        ndarray::arr1(&[
+            2.2, 0.7, 1.5, 0.1, 2.9, 1.1, 0.4, 2.6, 1.8, 0.0, 2.0, 1.3, 0.9, 2.4, 0.2, 1.7, 2.8,
+            0.5, 1.2, 2.1, 0.8, 1.9, 0.3, 2.7, 1.4, 0.6, 2.5, 1.0, 2.3, 1.6,
+        ])
+        .into_shape(ndarray::IxDyn(&[3, 5, 2]))
+        .unwrap(),
+    );
+    let ref c = ag::constant(ag::ndarray_ext::standard_normal(&[3, 2, 2]));
+    let (values, _) = ag::top_k(v, 2, 1);
+    let ref z = values * c;
+    let ref g = ag::grad_with_default(&[z], &[v], &[&ag::ones(&z.shape())]);
+    ag::test_helper::check_theoretical_grads(z, g.as_slice(), &[v], &[], 1e-3, 1e-3);
+}
+
+#[test]
+fn sort() {
+    // Well separated values, so that perturbations never reorder them.
+    let ref v = ag::variable(ndarray::arr2(&[
+        [0.7, 0.1, 1.1, 0.4],
+        [0.0, 0.9, 0.5, 0.2],
+        [1.0, 0.3, 0.6, 0.8],
+    ]));
+    let ref c = ag::constant(ag::ndarray_ext::standard_normal(&[3, 4]));
+    let ref z = ag::sort(v, -1, false) * c;
+    let ref g = ag::grad_with_default(&[z], &[v], &[&ag::ones(&z.shape())]);
+    ag::test_helper::check_theoretical_grads(z, g.as_slice(), &[v], &[], 1e-3, 1e-3);
+}
+
+#[test]
+fn einsum() {
+    use self::rand::SeedableRng;
+    // Products of three operands may exceed the tolerance for unlucky inputs.
+    let rng = ag::ndarray_ext::ArrRng::new(rand::XorShiftRng::from_seed([1, 2, 3, 4]));
+    let ref a = ag::variable(rng.random_uniform(&[2, 3, 4], -1., 1.));
+    let ref b = ag::variable(rng.random_uniform(&[2, 4, 2], -1., 1.));
+    let ref c = ag::variable(rng.random_uniform(&[3, 2], -1., 1.));
+    let ref z = ag::einsum("bij,bjk,ik->kb", &[a, b, c]);
+    let ref g = ag::grad_with_default(&[z], &[a, b, c], &[&ag::ones(&z.shape())]);
+    ag::test_helper::check_theoretical_grads(z, g.as_slice(), &[a, b, c], &[], 1e-3, 1e-3);
+}
+
+#[test]
+fn matrix_inverse() {
+    let ref v = ag::variable(ag::ndarray_ext::random_uniform(&[2, 3, 3], -0.5, 0.5));
+    let ref x = v + ag::constant(ndarray::Array::eye(3).into_shape((1, 3, 3)).unwrap() * 2.);
+    let ref c = ag::constant(ag::ndarray_ext::standard_normal(&[2, 3, 3]));
+    let ref z = ag::matrix_inverse(x) * c;
+    let ref g = ag::grad_with_default(&[z], &[v], &[&ag::ones(&z.shape())]);
+    ag::test_helper::check_theoretical_grads(z, g.as_slice(), &[v], &[], 1e-3, 1e-3);
+}
+
+#[test]
+fn determinant() {
+    let ref v = ag::variable(ag::ndarray_ext::random_uniform(&[2, 3, 3], -0.5, 0.5));
+    let ref x = v + ag::constant(ndarray::Array::eye(3).into_shape((1, 3, 3)).unwrap());
+    let ref z = ag::determinant(x);
+    let ref g = ag::grad_with_default(&[z], &[v], &[&ag::ones(&z.shape())]);
+    ag::test_helper::check_theoretical_grads(z, g.as_slice(), &[v], &[], 1e-3, 1e-3);
+}
+
+#[test]
+fn log_det() {
+    let ref v = ag::variable(ag::ndarray_ext::random_uniform(&[2, 3, 3], -0.5, 0.5));
+    let ref x = v - ag::constant(ndarray::Array::eye(3).into_shape((1, 3, 3)).unwrap() * 2.);
+    let ref z = ag::log_det(x);
+    let ref g = ag::grad_with_default(&[z], &[v], &[&ag::ones(&z.shape())]);
+    ag::test_helper::check_theoretical_grads(z, g.as_slice(), &[v], &[], 1e-3, 1e-3);
+}
+
+#[test]
+fn solve() {
+    let ref v = ag::variable(ag::ndarray_ext::random_uniform(&[2, 3, 3], -0.5, 0.5));
+    let ref a = v + ag::constant(ndarray::Array::eye(3).into_shape((1, 3, 3)).unwrap() * 2.);
+    let ref b = ag::variable(ag::ndarray_ext::standard_normal(&[2, 3, 2]));
+    let ref c = ag::constant(ag::ndarray_ext::standard_normal(&[2, 3, 2]));
+    let ref z = ag::solve(a, b) * c;
+    let ref g = ag::grad_with_default(&[z], &[v, b], &[&ag::ones(&z.shape())]);
+    ag::test_helper::check_theoretical_grads(z, g.as_slice(), &[v, b], &[], 1e-3, 1e-3);
+}
+
+#[test]
+fn triangular_solve() {
+    for &(lower, adjoint) in [(true, false), (true, true), (false, false), (false, true)].iter() {
+        let ref v = ag::variable(ag::ndarray_ext::random_uniform(&[2, 3, 3], -0.5, 0.5));
+        let ref a = v + ag::constant(ndarray::Array::eye(3).into_shape((1, 3, 3)).unwrap() * 2.);
+        let ref b = ag::variable(ag::ndarray_ext::standard_normal(&[2, 3, 2]));
+        let ref c = ag::constant(ag::ndarray_ext::standard_normal(&[2, 3, 2]));
+        let ref z = ag::triangular_solve(a, b, lower, adjoint) * c;
+        let ref g = ag::grad_with_default(&[z], &[v, b], &[&ag::ones(&z.shape())]);
+        ag::test_helper::check_theoretical_grads(z, g.as_slice(), &[v, b], &[], 1e-3, 1e-3);
+    }
+}
+
+#[test]
+fn cholesky() {
+    let ref v = ag::variable(ag::ndarray_ext::random_uniform(&[2, 3, 3], -0.5, 0.5));
+    // symmetric positive definite
+    let ref eye = ag::constant(ndarray::Array::eye(3).into_shape((1, 3, 3)).unwrap());
+    let ref x = ag::batch_matmul(v, ag::transpose(v, &[0, 2, 1])) + eye;
+    let ref c = ag::constant(ag::ndarray_ext::standard_normal(&[2, 3, 3]));
+    let ref z = ag::cholesky(x) * c;
+    let ref g = ag::grad_with_default(&[z], &[v], &[&ag::ones(&z.shape())]);
+    ag::test_helper::check_theoretical_grads(z, g.as_slice(), &[v], &[], 1e-3, 1e-3);
+}
+
+#[test]
+fn qr() {
+    let spread = ndarray::arr2(&[[2., 0., 0.], [0., 2., 0.], [0., 0., 2.], [0., 0., 0.]]);
+    let ref v = ag::variable(ag::ndarray_ext::random_uniform(&[2, 4, 3], -0.5, 0.5));
+    let ref x = v + ag::constant(spread.into_shape((1, 4, 3)).unwrap());
+    let (ref q, ref r) = ag::qr(x);
+    let ref c = ag::constant(ag::ndarray_ext::random_uniform(&[2, 4, 3], -0.5, 0.5));
+    let ref d = ag::constant(ag::ndarray_ext::random_uniform(&[2, 3, 3], -0.5, 0.5));
+    let ref z = ag::reduce_sum(q * c, &[0, 1, 2], false) + ag::reduce_sum(r * d, &[0, 1, 2], false);
+    let ref g = ag::grad(&[z], &[v]);
+    ag::test_helper::check_theoretical_grads(z, g.as_slice(), &[v], &[], 1e-3, 1e-3);
+}
+
+#[test]
+fn svd() {
+    let spread = ndarray::arr2(&[[3., 0., 0.], [0., 2., 0.], [0., 0., 1.], [0., 0., 0.]]);
+    let ref spread = ag::constant(spread.into_shape((1, 4, 3)).unwrap());
+    for &wide in [false, true].iter() {
+        let ref v = ag::variable(ag::ndarray_ext::random_uniform(&[2, 4, 3], -0.3, 0.3));
+        let ref x = if wide {
+            ag::transpose(v + spread, &[0, 2, 1])
+        } else {
+            v + spread
+        };
+        // sign-invariant in the singular vectors
+        let (ref u, ref s, ref v_) = ag::svd(x);
+        let ref a = ag::constant(ag::ndarray_ext::random_uniform(&[1, 3], -0.5, 0.5));
+        let ref b = ag::constant(ag::ndarray_ext::random_uniform(&[1, 1, 3], -0.5, 0.5));
+        let ref z = ag::reduce_sum(s * a, &[0, 1], false)
+            + ag::reduce_sum(ag::square(u) * b, &[0, 1, 2], false)
+            + ag::reduce_sum(ag::square(v_) * b, &[0, 1, 2], false);
+        let ref g = ag::grad(&[z], &[v]);
+        ag::test_helper::check_theoretical_grads(z, g.as_slice(), &[v], &[], 1e-3, 1e-3);
+    }
+}
+
+#[test]
+fn eigh() {
+    let ref v = ag::variable(ag::ndarray_ext::random_uniform(&[2, 3, 3], -0.3, 0.3));
+    let spread = ndarray::arr2(&[[-2., 0., 0.], [0., 0., 0.], [0., 0., 2.]]);
+    // not symmetric, but only the lower triangle is read
+    let ref x = v + ag::constant(spread.into_shape((1, 3, 3)).unwrap());
+    let (ref e, ref vecs) = ag::eigh(x);
+    // sign-invariant in the eigenvectors
+    let ref a = ag::constant(ag::ndarray_ext::random_uniform(&[1, 3], -0.5, 0.5));
+    let ref b = ag::constant(ag::ndarray_ext::random_uniform(&[1, 1, 3], -0.5, 0.5));
+    let ref z = ag::reduce_sum(e * a, &[0, 1], false)
+        + ag::reduce_sum(ag::square(vecs) * b, &[0, 1, 2], false);
+    let ref g = ag::grad(&[z], &[v]);
+    ag::test_helper::check_theoretical_grads(z, g.as_slice(), &[v], &[], 1e-3, 1e-3);
+}
+
+#[test]
+fn fft() {
+    use self::rand::SeedableRng;
+    // Sums of many products may exceed the tolerance for unlucky inputs.
+    let rng = ag::ndarray_ext::ArrRng::new(rand::XorShiftRng::from_seed([1, 2, 3, 4]));
+    let ref x = ag::variable(rng.random_uniform(&[2, 6, 2], -1., 1.));
+    let ref c = ag::constant(rng.random_uniform(&[2, 6, 2], -1., 1.));
+    for y in [ag::fft(x), ag::ifft(x)].iter() {
+        let ref z = y * c;
+        let ref g = ag::grad_with_default(&[z], &[x], &[&ag::ones(&z.shape())]);
+        ag::test_helper::check_theoretical_grads(z, g.as_slice(), &[x], &[], 1e-3, 1e-3);
+    }
+}
+
+#[test]
+fn rfft() {
+    use self::rand::SeedableRng;
+    let rng = ag::ndarray_ext::ArrRng::new(rand::XorShiftRng::from_seed([1, 2, 3, 4]));
+    for &n in [6, 7].iter() {
+        let ref x = ag::variable(rng.random_uniform(&[2, n], -1., 1.));
+        let ref c = ag::constant(rng.random_uniform(&[2, n / 2 + 1, 2], -1., 1.));
+        let ref z = ag::rfft(x) * c;
+        let ref g = ag::grad_with_default(&[z], &[x], &[&ag::ones(&z.shape())]);
+        ag::test_helper::check_theoretical_grads(z, g.as_slice(), &[x], &[], 1e-3, 1e-3);
+    }
+}
+
+#[test]
+fn irfft() {
+    use self::rand::SeedableRng;
+    let rng = ag::ndarray_ext::ArrRng::new(rand::XorShiftRng::from_seed([1, 2, 3, 4]));
+    for &n in [6, 7].iter() {
+        let ref x = ag::variable(rng.random_uniform(&[2, n / 2 + 1, 2], -1., 1.));
+        let ref c = ag::constant(rng.random_uniform(&[2, n], -1., 1.));
+        let ref z = ag::irfft(x, n) * c;
+        let ref g = ag::grad_with_default(&[z], &[x], &[&ag::ones(&z.shape())]);
+        ag::test_helper::check_theoretical_grads(z, g.as_slice(), &[x], &[], 1e-3, 1e-3);
+    }
+}
+
+#[test]
+fn stft() {
+    use self::rand::SeedableRng;
+    let rng = ag::ndarray_ext::ArrRng::new(rand::XorShiftRng::from_seed([1, 2, 3, 4]));
+    let ref x = ag::variable(rng.random_uniform(&[2, 10], -1., 1.));
+    let ref c = ag::constant(rng.random_uniform(&[2, 3, 3, 2], -1., 1.));
+    let ref z = ag::stft(x, 4, 3) * c;
+    let ref g = ag::grad_with_default(&[z], &[x], &[&ag::ones(&z.shape())]);
+    ag::test_helper::check_theoretical_grads(z, g.as_slice(), &[x], &[], 1e-3, 1e-3);
+}
+
 #[test]
 fn maximum() {
     let ref v1 = ag::variable(ndarray::arr1(&[1., 2., 3.]));
@@ -341,6 +587,18 @@ fn reshape_after_transpose() {
     ag::test_helper::check_theoretical_grads(z, g.as_slice(), &[v], &[], 1e-3, 1e-3);
 }
 
+#[test]
+fn transpose_with_non_uniform_gy() {
+    // The theoretical gradient is in non-standard layout.
+    let ref v = ag::variable(ag::ndarray_ext::standard_normal(&[2, 3]));
+    let ref z = ag::transpose(v, &[1, 0]);
+    // Small enough for the f32 finite differences of `obj` to stay within the tolerance.
+    let ref gy = ag::constant(ndarray::arr2(&[[0.1, 0.2], [0.3, 0.4], [0.5, 0.6]]));
+    let ref g = ag::grad_with_default(&[z], &[v], &[gy]);
+    let ref obj = ag::reduce_sum(&(z * gy), &[0, 1], false);
+    ag::test_helper::check_theoretical_grads(obj, g.as_slice(), &[v], &[], 1e-3, 1e-3);
+}
+
 #[test]
 fn add_inplace() {
     let a = ag::ones(&[2, 2]) + ag::zeros(&[2, 2]);
@@ -461,15 +719,91 @@ fn gather() {
     ag::test_helper::check_theoretical_grads(z, g.as_slice(), &[v], &[], 1e-3, 1e-3);
 }
 
+#[test]
+fn scatter_add() {
+    let ref x = ag::variable(ag::ndarray_ext::standard_normal(&[2, 5, 3]));
+    let ref u = ag::variable(ag::ndarray_ext::standard_normal(&[2, 2, 2, 3]));
+    let ref indices = ag::constant(ndarray::arr2(&[[4., 0.], [4., 2.]]));
+    let ref c = ag::constant(ag::ndarray_ext::standard_normal(&[2, 5, 3]));
+    let ref y = ag::scatter_add(x, indices, u, 1) * c;
+    let ref g = ag::grad_with_default(&[y], &[x, u], &[&ag::ones(&y.shape())]);
+    ag::test_helper::check_theoretical_grads(y, g, &[x, u], &[], 1e-3, 1e-3);
+}
+
+#[test]
+fn scatter() {
+    let ref x = ag::variable(ag::ndarray_ext::standard_normal(&[2, 5, 3]));
+    let ref u = ag::variable(ag::ndarray_ext::standard_normal(&[2, 3, 3]));
+    let ref c = ag::constant(ag::ndarray_ext::standard_normal(&[2, 5, 3]));
+    let ref y = ag::scatter(x, &[4., 1., 4.], u, -2) * c;
+    let ref g = ag::grad_with_default(&[y], &[x, u], &[&ag::ones(&y.shape())]);
+    ag::test_helper::check_theoretical_grads(y, g, &[x, u], &[], 1e-3, 1e-3);
+}
+
+#[test]
+fn gather_nd() {
+    let ref x = ag::variable(ag::ndarray_ext::standard_normal(&[4, 3, 2]));
+    let ref indices = ag::constant(ndarray::arr2(&[[3., 1.], [0., 2.], [3., 1.]]));
+    let ref c = ag::constant(ag::ndarray_ext::standard_normal(&[3, 2]));
+    let ref y = ag::gather_nd(x, indices) * c;
+    let ref g = ag::grad_with_default(&[y], &[x], &[&ag::ones(&y.shape())]);
+    ag::test_helper::check_theoretical_grads(y, g, &[x], &[], 1e-3, 1e-3);
+}
+
+#[test]
+fn scatter_nd() {
+    let ref u = ag::variable(ag::ndarray_ext::standard_normal(&[3, 2]));
+    let ref indices = ag::constant(ndarray::arr2(&[[3.], [0.], [3.]]));
+    let ref c = ag::constant(ag::ndarray_ext::standard_normal(&[4, 2]));
+    let ref y = ag::scatter_nd(indices, u, &[4, 2]) * c;
+    let ref g = ag::grad_with_default(&[y], &[u], &[&ag::ones(&y.shape())]);
+    ag::test_helper::check_theoretical_grads(y, g, &[u], &[], 1e-3, 1e-3);
+}
+
+#[test]
+fn select() {
+    let ref a = ag::variable(ag::ndarray_ext::standard_normal(&[4, 3]));
+    let ref b = ag::variable(ag::ndarray_ext::standard_normal(&[1, 3]));
+    let ref cond = ag::constant(ndarray::arr2(&[[1.], [0.], [0.], [1.]]));
+    let ref c = ag::constant(ag::ndarray_ext::standard_normal(&[4, 3]));
+    let ref y = ag::select(cond, a, b) * c;
+    let ref g = ag::grad_with_default(&[y], &[a, b], &[&ag::ones(&y.shape())]);
+    ag::test_helper::check_theoretical_grads(y, g, &[a, b], &[], 1e-3, 1e-3);
+}
+
+#[test]
+fn boolean_mask() {
+    let ref x = ag::variable(ag::ndarray_ext::standard_normal(&[3, 2, 4]));
+    let ref mask = ag::constant(ndarray::arr2(&[[1., 0.], [0., 0.], [1., 1.]]));
+    let ref c = ag::constant(ag::ndarray_ext::standard_normal(&[3, 4]));
+    let ref y = ag::boolean_mask(x, mask) * c;
+    let ref g = ag::grad_with_default(&[y], &[x], &[&ag::ones(&y.shape())]);
+    ag::test_helper::check_theoretical_grads(y, g, &[x], &[], 1e-3, 1e-3);
+}
+
+#[test]
+fn pad() {
+    let modes = [
+        ag::PadMode::Constant(0.5),
+        ag::PadMode::Reflect,
+        ag::PadMode::Replicate,
+    ];
+    for &mode in modes.iter() {
+        let ref x = ag::variable(ag::ndarray_ext::standard_normal(&[2, 3, 2]));
+        let ref c = ag::constant(ag::ndarray_ext::standard_normal(&[3, 8, 3]));
+        let ref y = ag::pad(x, &[(1, 0), (4, 1), (0, 1)], mode) * c;
+        let ref g = ag::grad_with_default(&[y], &[x], &[&ag::ones(&y.shape())]);
+        ag::test_helper::check_theoretical_grads(y, g, &[x], &[], 1e-3, 1e-3);
+    }
+}
+
 #[test]
 fn concat() {
     let ref v1 = ag::variable(ag::ndarray_ext::standard_normal(&[1, 2]));
     let ref v2 = ag::variable(ag::ndarray_ext::standard_normal(&[1, 2]));
     let ref z = ag::concat(&[v1, v2], 1);
-    let ref g = ag::grad_with_default(&[z], &[v1], &[&ag::ones(&z.shape())]);
-    ag::test_helper::check_theoretical_grads(z, g.as_slice(), &[v1], &[], 1e-3, 1e-3);
-    // FIXME: uncommenting below causes SEGV
-    // ag::helper::gradient_check(z, &[v1, v2], g.as_slice(), &ag::Input::new(), 1e-3, 1e-3);
+    let ref g = ag::grad_with_default(&[z], &[v1, v2], &[&ag::ones(&z.shape())]);
+    ag::test_helper::check_theoretical_grads(z, g.as_slice(), &[v1, v2], &[], 1e-3, 1e-3);
 }
 
 #[test]
@@ -586,6 +920,234 @@ fn conv2d() {
     ag::test_helper::check_theoretical_grads(y, g, &[x, w], &[], 1e-3, 1e-2);
 }
 
+#[test]
+fn conv2d_strided() {
+    let ref x = ag::variable(ag::ndarray_ext::standard_normal(&[2, 3, 7, 7]));
+    let ref w = ag::variable(ag::ndarray_ext::standard_normal(&[2, 3, 3, 3]));
+    let ref y = ag::conv2d(x, w, 1, 2);
+    let ref g = ag::grad_with_default(&[y], &[x, w], &[&ag::ones(&y.shape())]);
+    ag::test_helper::check_theoretical_grads(y, g, &[x, w], &[], 1e-3, 1e-2);
+}
+
+#[test]
+fn conv2d_same_padding() {
+    let ref x = ag::variable(ag::ndarray_ext::standard_normal(&[2, 3, 7, 6]));
+    let ref w = ag::variable(ag::ndarray_ext::standard_normal(&[2, 3, 3, 2]));
+    let ref y = ag::conv2d_with_padding(
+        x,
+        w,
+        ag::Padding::Same,
+        (2, 1),
+        (1, 2),
+        1,
+        ag::DataFormat::NCHW,
+    );
+    assert_eq!(y.eval(&[]).unwrap().shape(), &[2, 2, 4, 6]);
+    let ref g = ag::grad_with_default(&[y], &[x, w], &[&ag::ones(&y.shape())]);
+    ag::test_helper::check_theoretical_grads(y, g, &[x, w], &[], 1e-3, 1e-2);
+}
+
+#[test]
+fn conv2d_asymmetric_padding_xw_grad() {
+    let ref x = ag::variable(ag::ndarray_ext::standard_normal(&[2, 3, 5, 6]));
+    let ref w = ag::variable(ag::ndarray_ext::standard_normal(&[2, 3, 2, 3]));
+    let padding = ag::Padding::Explicit(0, 1, 2, 1);
+    let ref y = ag::conv2d_with_padding(x, w, padding, (1, 2), (1, 1), 1, ag::DataFormat::NCHW);
+    let ref g = ag::grad_with_default(&[y], &[w], &[&ag::ones(&y.shape())])[0];
+    let ref gg = ag::grad_with_default(&[g], &[x], &[&ag::ones(&g.shape())]);
+    ag::test_helper::check_theoretical_grads(g, gg, &[x], &[], 1e-3, 1e-2);
+}
+
+#[test]
+fn conv2d_transpose_same_padding() {
+    let ref x = ag::variable(ag::ndarray_ext::standard_normal(&[2, 2, 3, 4]));
+    let ref w = ag::variable(ag::ndarray_ext::standard_normal(&[2, 3, 3, 2]));
+    let ref y = ag::conv2d_transpose_with_padding(
+        x,
+        w,
+        ag::Padding::Same,
+        (2, 2),
+        (1, 1),
+        1,
+        ag::DataFormat::NCHW,
+    );
+    assert_eq!(y.eval(&[]).unwrap().shape(), &[2, 3, 6, 8]);
+    let ref g = ag::grad_with_default(&[y], &[x, w], &[&ag::ones(&y.shape())]);
+    ag::test_helper::check_theoretical_grads(y, g, &[x, w], &[], 1e-3, 1e-2);
+}
+
+#[test]
+fn conv2d_nhwc() {
+    let ref x = ag::variable(ag::ndarray_ext::standard_normal(&[2, 7, 6, 4]));
+    let ref w = ag::variable(ag::ndarray_ext::standard_normal(&[3, 2, 2, 6]));
+    let ref y = ag::conv2d_with_padding(
+        x,
+        w,
+        ag::Padding::Same,
+        (2, 1),
+        (1, 2),
+        2,
+        ag::DataFormat::NHWC,
+    );
+    assert_eq!(y.eval(&[]).unwrap().shape(), &[2, 4, 6, 6]);
+    let ref g = ag::grad_with_default(&[y], &[x, w], &[&ag::ones(&y.shape())]);
+    ag::test_helper::check_theoretical_grads(y, g, &[x, w], &[], 1e-3, 1e-2);
+}
+
+#[test]
+fn conv2d_nhwc_xw_grad() {
+    let ref x = ag::variable(ag::ndarray_ext::standard_normal(&[2, 5, 6, 4]));
+    let ref w = ag::variable(ag::ndarray_ext::standard_normal(&[2, 3, 2, 4]));
+    let padding = ag::Padding::Explicit(0, 1, 2, 1);
+    let ref y = ag::conv2d_with_padding(x, w, padding, (1, 2), (1, 1), 2, ag::DataFormat::NHWC);
+    let ref g = ag::grad_with_default(&[y], &[w], &[&ag::ones(&y.shape())])[0];
+    let ref gg = ag::grad_with_default(&[g], &[x], &[&ag::ones(&g.shape())]);
+    ag::test_helper::check_theoretical_grads(g, gg, &[x], &[], 1e-3, 1e-2);
+}
+
+#[test]
+fn conv2d_transpose_nhwc() {
+    let ref x = ag::variable(ag::ndarray_ext::standard_normal(&[2, 3, 4, 2]));
+    let ref w = ag::variable(ag::ndarray_ext::standard_normal(&[3, 2, 3, 2]));
+    let ref y = ag::conv2d_transpose_with_padding(
+        x,
+        w,
+        ag::Padding::Same,
+        (2, 2),
+        (1, 1),
+        1,
+        ag::DataFormat::NHWC,
+    );
+    assert_eq!(y.eval(&[]).unwrap().shape(), &[2, 6, 8, 3]);
+    let ref g = ag::grad_with_default(&[y], &[x, w], &[&ag::ones(&y.shape())]);
+    ag::test_helper::check_theoretical_grads(y, g, &[x, w], &[], 1e-3, 1e-2);
+}
+
+#[test]
+fn grouped_conv2d() {
+    let ref x = ag::variable(ag::ndarray_ext::standard_normal(&[2, 4, 6, 6]));
+    let ref w = ag::variable(ag::ndarray_ext::standard_normal(&[6, 2, 2, 2]));
+    let ref y = ag::grouped_conv2d(x, w, 1, 2, 2);
+    let ref g = ag::grad_with_default(&[y], &[x, w], &[&ag::ones(&y.shape())]);
+    ag::test_helper::check_theoretical_grads(y, g, &[x, w], &[], 1e-3, 1e-2);
+}
+
+#[test]
+fn grouped_conv2d_xw_grad() {
+    let ref x = ag::variable(ag::ndarray_ext::standard_normal(&[2, 4, 5, 5]));
+    let ref w = ag::variable(ag::ndarray_ext::standard_normal(&[6, 2, 2, 2]));
+    let ref y = ag::grouped_conv2d(x, w, 0, 1, 2);
+    let ref g = ag::grad_with_default(&[y], &[w], &[&ag::ones(&y.shape())])[0];
+    let ref gg = ag::grad_with_default(&[g], &[x], &[&ag::ones(&g.shape())]);
+    ag::test_helper::check_theoretical_grads(g, gg, &[x], &[], 1e-3, 1e-2);
+}
+
+#[test]
+fn depthwise_conv2d() {
+    let ref x = ag::variable(ag::ndarray_ext::standard_normal(&[2, 3, 5, 5]));
+    let ref w = ag::variable(ag::ndarray_ext::standard_normal(&[6, 1, 3, 3]));
+    let ref y = ag::depthwise_conv2d(x, w, 1, 1);
+    let ref g = ag::grad_with_default(&[y], &[x, w], &[&ag::ones(&y.shape())]);
+    ag::test_helper::check_theoretical_grads(y, g, &[x, w], &[], 1e-3, 1e-2);
+}
+
+#[test]
+fn depthwise_conv2d_grad() {
+    let ref x = ag::variable(ag::ndarray_ext::standard_normal(&[2, 3, 5, 5]));
+    let ref w = ag::variable(ag::ndarray_ext::standard_normal(&[3, 1, 2, 2]));
+    let ref y = ag::depthwise_conv2d(x, w, 0, 1);
+    let ref gy = ag::variable(ag::ndarray_ext::ones(&[2, 3, 4, 4]));
+    let ref g = ag::grad_with_default(&[y], &[x], &[gy])[0];
+    let ref gg = ag::grad_with_default(&[g], &[gy], &[&ag::ones(&g.shape())])[0];
+    ag::test_helper::check_theoretical_grads(g, &[gg], &[gy], &[], 1e-3, 1e-2);
+}
+
+#[test]
+fn grouped_conv2d_transpose() {
+    let ref x = ag::variable(ag::ndarray_ext::standard_normal(&[2, 4, 3, 3]));
+    let ref w = ag::variable(ag::ndarray_ext::standard_normal(&[4, 3, 2, 2]));
+    let ref y = ag::grouped_conv2d_transpose(x, w, 0, 1, 2);
+    let ref g = ag::grad_with_default(&[y], &[x, w], &[&ag::ones(&y.shape())]);
+    ag::test_helper::check_theoretical_grads(y, g, &[x, w], &[], 1e-3, 1e-2);
+}
+
+#[test]
+fn grouped_conv2d_transpose_xw_grad() {
+    let ref x = ag::variable(ag::ndarray_ext::standard_normal(&[2, 4, 3, 3]));
+    let ref w = ag::variable(ag::ndarray_ext::standard_normal(&[4, 3, 2, 2]));
+    let ref y = ag::grouped_conv2d_transpose(x, w, 0, 1, 2);
+    let ref g = ag::grad_with_default(&[y], &[w], &[&ag::ones(&y.shape())])[0];
+    let ref gg = ag::grad_with_default(&[g], &[x], &[&ag::ones(&g.shape())]);
+    ag::test_helper::check_theoretical_grads(g, gg, &[x], &[], 1e-3, 1e-2);
+}
+
+#[test]
+fn conv1d() {
+    let ref x = ag::variable(ag::ndarray_ext::standard_normal(&[2, 3, 7]));
+    let ref w = ag::variable(ag::ndarray_ext::standard_normal(&[2, 3, 3]));
+    let ref y = ag::dilated_conv1d(x, w, 1, 2, 2);
+    let ref g = ag::grad_with_default(&[y], &[x, w], &[&ag::ones(&y.shape())]);
+    ag::test_helper::check_theoretical_grads(y, g, &[x, w], &[], 1e-3, 1e-2);
+}
+
+#[test]
+fn conv1d_transpose() {
+    let ref x = ag::variable(ag::ndarray_ext::standard_normal(&[2, 2, 3]));
+    let ref w = ag::variable(ag::ndarray_ext::standard_normal(&[2, 3, 2]));
+    let ref y = ag::conv1d_transpose(x, w, 1, 2);
+    let ref g = ag::grad_with_default(&[y], &[x, w], &[&ag::ones(&y.shape())]);
+    ag::test_helper::check_theoretical_grads(y, g, &[x, w], &[], 1e-3, 1e-2);
+}
+
+#[test]
+fn conv3d() {
+    let ref x = ag::variable(ag::ndarray_ext::standard_normal(&[2, 2, 4, 5, 3]));
+    let ref w = ag::variable(ag::ndarray_ext::standard_normal(&[3, 2, 2, 3, 2]));
+    let ref y = ag::conv3d(x, w, 1, 1);
+    let ref g = ag::grad_with_default(&[y], &[x, w], &[&ag::ones(&y.shape())]);
+    ag::test_helper::check_theoretical_grads(y, g, &[x, w], &[], 1e-3, 1e-2);
+}
+
+#[test]
+fn conv3d_transpose() {
+    let ref x = ag::variable(ag::ndarray_ext::standard_normal(&[2, 2, 2, 3, 2]));
+    let ref w = ag::variable(ag::ndarray_ext::standard_normal(&[2, 3, 2, 2, 3]));
+    let ref y = ag::dilated_conv3d_transpose(x, w, 0, 1, 2);
+    let ref g = ag::grad_with_default(&[y], &[x, w], &[&ag::ones(&y.shape())]);
+    ag::test_helper::check_theoretical_grads(y, g, &[x, w], &[], 1e-3, 1e-2);
+}
+
+#[test]
+fn conv3d_grad() {
+    let ref x = ag::variable(ag::ndarray_ext::standard_normal(&[2, 2, 4, 4, 3]));
+    let ref w = ag::variable(ag::ndarray_ext::standard_normal(&[2, 2, 2, 2, 2]));
+    let ref y = ag::conv3d(x, w, 0, 1);
+    let ref gy = ag::variable(ag::ndarray_ext::ones(&[2, 2, 3, 3, 2]));
+    let ref g = ag::grad_with_default(&[y], &[x], &[gy])[0];
+    let ref gg = ag::grad_with_default(&[g], &[gy], &[&ag::ones(&g.shape())])[0];
+    ag::test_helper::check_theoretical_grads(g, &[gg], &[gy], &[], 1e-3, 1e-2);
+}
+
+#[test]
+fn conv3d_xw_grad() {
+    let ref x = ag::variable(ag::ndarray_ext::standard_normal(&[2, 2, 4, 4, 3]));
+    let ref w = ag::variable(ag::ndarray_ext::standard_normal(&[2, 2, 2, 2, 2]));
+    let ref y = ag::conv3d(x, w, 0, 1);
+    let ref g = ag::grad_with_default(&[y], &[w], &[&ag::ones(&y.shape())])[0];
+    let ref gg = ag::grad_with_default(&[g], &[x], &[&ag::ones(&g.shape())]);
+    ag::test_helper::check_theoretical_grads(g, gg, &[x], &[], 1e-3, 1e-2);
+}
+
+#[test]
+fn conv1d_transpose_xw_grad() {
+    let ref x = ag::variable(ag::ndarray_ext::standard_normal(&[2, 2, 4]));
+    let ref w = ag::variable(ag::ndarray_ext::standard_normal(&[2, 3, 3]));
+    let ref y = ag::conv1d_transpose(x, w, 0, 1);
+    let ref g = ag::grad_with_default(&[y], &[w], &[&ag::ones(&y.shape())])[0];
+    let ref gg = ag::grad_with_default(&[g], &[x], &[&ag::ones(&g.shape())]);
+    ag::test_helper::check_theoretical_grads(g, gg, &[x], &[], 1e-3, 1e-2);
+}
+
 #[test]
 fn max_pool2d() {
     let arr_x = ndarray::Array::from_iter(0..2 * 2 * 3 * 3)
@@ -613,6 +1175,177 @@ fn max_pool2d_grad() {
     ag::test_helper::check_theoretical_grads(g, &[gg], &[gy], &[], 1e-3, 1e-2);
 }
 
+#[test]
+fn max_pool2d_same_padding() {
+    let arr_x = ndarray::Array::from_iter(0..2 * 2 * 5 * 4)
+        .into_shape(ndarray::IxDyn(&[2, 2, 5, 4]))
+        .unwrap();
+    let ref x = ag::variable(arr_x.map(|a| (*a * 7 % 80) as f32));
+    let ref y =
+        ag::max_pool2d_with_padding(x, (3, 2), ag::Padding::Same, (2, 1), ag::DataFormat::NCHW);
+    assert_eq!(y.eval(&[]).unwrap().shape(), &[2, 2, 3, 4]);
+    let ref g = ag::grad_with_default(&[y], &[x], &[&ag::ones(&y.shape())]);
+    ag::test_helper::check_theoretical_grads(y, g, &[x], &[], 1e-3, 1e-2);
+}
+
+#[test]
+fn avg_pool2d() {
+    let ref x = ag::variable(ag::ndarray_ext::standard_normal(&[2, 3, 5, 5]));
+    let ref y = ag::avg_pool2d(x, 3, 1, 2, false) + ag::avg_pool2d(x, 2, 1, 2, true);
+    let ref g = ag::grad_with_default(&[y], &[x], &[&ag::ones(&y.shape())]);
+    ag::test_helper::check_theoretical_grads(y, g, &[x], &[], 1e-3, 1e-2);
+}
+
+#[test]
+fn avg_pool2d_grad() {
+    let ref x = ag::variable(ag::ndarray_ext::standard_normal(&[2, 2, 5, 4]));
+    let ref y = ag::avg_pool2d_with_padding(
+        x,
+        (3, 2),
+        ag::Padding::Same,
+        (2, 1),
+        false,
+        ag::DataFormat::NCHW,
+    );
+    let ref gy = ag::variable(ag::ndarray_ext::standard_normal(&[2, 2, 3, 4]));
+    let ref g = ag::grad_with_default(&[y], &[x], &[gy])[0];
+    let ref gg = ag::grad_with_default(&[g], &[gy], &[&ag::ones(&g.shape())])[0];
+    ag::test_helper::check_theoretical_grads(g, &[gg], &[gy], &[], 1e-3, 1e-2);
+}
+
+#[test]
+fn adaptive_avg_pool2d() {
+    let ref x = ag::variable(ag::ndarray_ext::standard_normal(&[2, 3, 7, 5]));
+    let ref y = ag::adaptive_avg_pool2d(x, (3, 2), ag::DataFormat::NCHW);
+    let ref g = ag::grad_with_default(&[y], &[x], &[&ag::ones(&y.shape())]);
+    ag::test_helper::check_theoretical_grads(y, g, &[x], &[], 1e-3, 1e-2);
+}
+
+#[test]
+fn adaptive_avg_pool2d_grad() {
+    let ref x = ag::variable(ag::ndarray_ext::standard_normal(&[2, 2, 5, 3]));
+    let ref y = ag::adaptive_avg_pool2d(x, (3, 4), ag::DataFormat::NCHW);
+    let ref gy = ag::variable(ag::ndarray_ext::standard_normal(&[2, 2, 3, 4]));
+    let ref g = ag::grad_with_default(&[y], &[x], &[gy])[0];
+    let ref gg = ag::grad_with_default(&[g], &[gy], &[&ag::ones(&g.shape())])[0];
+    ag::test_helper::check_theoretical_grads(g, &[gg], &[gy], &[], 1e-3, 1e-2);
+}
+
+#[test]
+fn global_avg_pool2d() {
+    let ref x = ag::variable(ag::ndarray_ext::standard_normal(&[2, 3, 4, 5]));
+    let ref y = ag::global_avg_pool2d(x, ag::DataFormat::NCHW);
+    let ref g = ag::grad_with_default(&[y], &[x], &[&ag::ones(&y.shape())]);
+    ag::test_helper::check_theoretical_grads(y, g, &[x], &[], 1e-3, 1e-2);
+}
+
+#[test]
+fn global_max_pool2d() {
+    let arr_x = ndarray::Array::from_iter(0..2 * 2 * 3 * 4)
+        .into_shape(ndarray::IxDyn(&[2, 2, 3, 4]))
+        .unwrap();
+    let ref x = ag::variable(arr_x.map(|a| (*a * 5 % 48) as f32));
+    let ref y = ag::global_max_pool2d(x, ag::DataFormat::NCHW);
+    let ref g = ag::grad_with_default(&[y], &[x], &[&ag::ones(&y.shape())]);
+    ag::test_helper::check_theoretical_grads(y, g, &[x], &[], 1e-3, 1e-2);
+}
+
+#[test]
+fn resize_nearest() {
+    let ref x = ag::variable(ag::ndarray_ext::standard_normal(&[2, 3, 4, 5]));
+    let ref c = ag::constant(ag::ndarray_ext::standard_normal(&[2, 3, 7, 3]));
+    let ref y = ag::resize_nearest(x, (7, 3)) * c;
+    let ref g = ag::grad_with_default(&[y], &[x], &[&ag::ones(&y.shape())]);
+    ag::test_helper::check_theoretical_grads(y, g, &[x], &[], 1e-3, 1e-2);
+}
+
+#[test]
+fn resize_bilinear() {
+    let ref x = ag::variable(ag::ndarray_ext::standard_normal(&[2, 3, 4, 5]));
+    let ref c = ag::constant(ag::ndarray_ext::standard_normal(&[2, 3, 7, 3]));
+    let ref y = (ag::resize_bilinear(x, (7, 3), true) + ag::resize_bilinear(x, (7, 3), false)) * c;
+    let ref g = ag::grad_with_default(&[y], &[x], &[&ag::ones(&y.shape())]);
+    ag::test_helper::check_theoretical_grads(y, g, &[x], &[], 1e-3, 1e-2);
+}
+
+#[test]
+fn resize_bilinear_grad() {
+    let ref x = ag::variable(ag::ndarray_ext::standard_normal(&[2, 2, 3, 4]));
+    let ref y = ag::resize_bilinear(x, (5, 6), false);
+    let ref gy = ag::variable(ag::ndarray_ext::standard_normal(&[2, 2, 5, 6]));
+    let ref g = ag::grad_with_default(&[y], &[x], &[gy])[0];
+    let ref gg = ag::grad_with_default(&[g], &[gy], &[&ag::ones(&g.shape())])[0];
+    ag::test_helper::check_theoretical_grads(g, &[gg], &[gy], &[], 1e-3, 1e-2);
+}
+
+#[test]
+fn pixel_shuffle() {
+    let ref x = ag::variable(ag::ndarray_ext::standard_normal(&[2, 8, 3, 2]));
+    let ref c = ag::constant(ag::ndarray_ext::standard_normal(&[2, 2, 6, 4]));
+    let ref y = ag::pixel_shuffle(x, 2) * c;
+    let ref g = ag::grad_with_default(&[y], &[x], &[&ag::ones(&y.shape())]);
+    ag::test_helper::check_theoretical_grads(y, g, &[x], &[], 1e-3, 1e-2);
+}
+
+#[test]
+fn batch_norm_with_stats() {
+    let ref x = ag::variable(ag::ndarray_ext::standard_normal(&[4, 3, 2, 2]));
+    let ref scale = ag::variable(ag::ndarray_ext::standard_normal(&[3]));
+    let ref shift = ag::variable(ag::ndarray_ext::standard_normal(&[3]));
+    let ref mean = ag::variable(ag::ndarray_ext::zeros(&[3]));
+    let ref var = ag::variable(ag::ndarray_ext::ones(&[3]));
+    let ref c = ag::constant(ag::ndarray_ext::standard_normal(&[4, 3, 2, 2]));
+    let ref y = ag::batch_norm_with_stats(x, scale, shift, mean, var, 1, 0.9, 1e-3) * c;
+    let ref g = ag::grad_with_default(&[y], &[x, scale, shift], &[&ag::ones(&y.shape())]);
+    ag::test_helper::check_theoretical_grads(y, g, &[x, scale, shift], &[], 1e-3, 1e-2);
+}
+
+#[test]
+fn batch_norm_with_stats_last_axis() {
+    let ref x = ag::variable(ag::ndarray_ext::standard_normal(&[3, 2, 4]));
+    let ref scale = ag::variable(ag::ndarray_ext::standard_normal(&[1, 4]));
+    let ref shift = ag::variable(ag::ndarray_ext::standard_normal(&[1, 4]));
+    let ref mean = ag::variable(ag::ndarray_ext::zeros(&[4]));
+    let ref var = ag::variable(ag::ndarray_ext::ones(&[4]));
+    let ref c = ag::constant(ag::ndarray_ext::standard_normal(&[3, 2, 4]));
+    let ref y = ag::batch_norm_with_stats(x, scale, shift, mean, var, -1, 0.9, 1e-3) * c;
+    let ref g = ag::grad_with_default(&[y], &[x, scale, shift], &[&ag::ones(&y.shape())]);
+    ag::test_helper::check_theoretical_grads(y, g, &[x, scale, shift], &[], 1e-3, 1e-2);
+}
+
+#[test]
+fn layer_norm() {
+    let ref x = ag::variable(ag::ndarray_ext::standard_normal(&[3, 2, 4]));
+    let ref scale = ag::variable(ag::ndarray_ext::standard_normal(&[2, 4]));
+    let ref shift = ag::variable(ag::ndarray_ext::standard_normal(&[2, 4]));
+    let ref c = ag::constant(ag::ndarray_ext::standard_normal(&[3, 2, 4]));
+    let ref y = ag::layer_norm(x, scale, shift, 1, 1e-3) * c;
+    let ref g = ag::grad_with_default(&[y], &[x, scale, shift], &[&ag::ones(&y.shape())]);
+    ag::test_helper::check_theoretical_grads(y, g, &[x, scale, shift], &[], 1e-3, 1e-2);
+}
+
+#[test]
+fn group_norm() {
+    let ref x = ag::variable(ag::ndarray_ext::standard_normal(&[2, 4, 3, 2]));
+    let ref scale = ag::variable(ag::ndarray_ext::standard_normal(&[4]));
+    let ref shift = ag::variable(ag::ndarray_ext::standard_normal(&[4]));
+    let ref c = ag::constant(ag::ndarray_ext::standard_normal(&[2, 4, 3, 2]));
+    let ref y = ag::group_norm(x, scale, shift, 2, 1e-3) * c;
+    let ref g = ag::grad_with_default(&[y], &[x, scale, shift], &[&ag::ones(&y.shape())]);
+    ag::test_helper::check_theoretical_grads(y, g, &[x, scale, shift], &[], 1e-3, 1e-2);
+}
+
+#[test]
+fn instance_norm() {
+    let ref x = ag::variable(ag::ndarray_ext::standard_normal(&[2, 3, 4]));
+    let ref scale = ag::variable(ag::ndarray_ext::standard_normal(&[1, 3]));
+    let ref shift = ag::variable(ag::ndarray_ext::standard_normal(&[1, 3]));
+    let ref c = ag::constant(ag::ndarray_ext::standard_normal(&[2, 3, 4]));
+    let ref y = ag::instance_norm(x, scale, shift, 1e-3) * c;
+    let ref g = ag::grad_with_default(&[y], &[x, scale, shift], &[&ag::ones(&y.shape())]);
+    ag::test_helper::check_theoretical_grads(y, g, &[x, scale, shift], &[], 1e-3, 1e-2);
+}
+
 #[test]
 fn primitive_back_propagation_through_time() {
     let max_sent = 3;